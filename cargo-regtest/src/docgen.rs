@@ -0,0 +1,109 @@
+//! `cargo regtest docgen`: render the regression data into Markdown pages, so
+//! that golden outputs can double as documentation of the behavior.
+
+use crate::project::{self, Package};
+use regression_test::{RegEntry, RegType, SnapshotFile};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Directory to write the pages to
+    dir: PathBuf,
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    std::fs::create_dir_all(&args.dir)?;
+
+    let mut index = String::from("# Regression outputs\n\n");
+    for package in packages {
+        // Data files by source file, i.e. module
+        let mut modules: BTreeMap<PathBuf, Vec<(String, SnapshotFile)>> = BTreeMap::new();
+        for file in package.existing_data_files()? {
+            let snapshot = SnapshotFile::load(&file).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("{}: {}", project::display(&file), e))
+            })?;
+            let test_path = package.test_path(&file);
+            let test = test_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let module = test_path.parent().unwrap_or(Path::new("")).to_path_buf();
+            modules.entry(module).or_default().push((test, snapshot));
+        }
+        if modules.is_empty() {
+            continue;
+        }
+
+        let page = format!("{}.md", package.name);
+        std::fs::write(args.dir.join(&page), render_page(&package.name, &modules))?;
+        let _ = writeln!(index, "- [{}]({})", package.name, page);
+        println!("Wrote {}", project::display(&args.dir.join(&page)));
+    }
+
+    std::fs::write(args.dir.join("index.md"), index)
+}
+
+/// The page of the package `name`, with a section per module and test.
+fn render_page(name: &str, modules: &BTreeMap<PathBuf, Vec<(String, SnapshotFile)>>) -> String {
+    let mut page = format!(
+        "# {}\n\nRegression outputs of `{}`, generated by `cargo regtest docgen`.\n",
+        name, name
+    );
+    for (module, tests) in modules {
+        let _ = write!(page, "\n## `{}`\n", module.display());
+        for (test, snapshot) in tests {
+            let _ = write!(page, "\n### `{}`\n", test);
+            if let Some(path) = snapshot.test() {
+                let _ = write!(page, "\nRecorded by `{}`.\n", path);
+            }
+
+            let mut section = None;
+            for entry in snapshot.entries() {
+                if entry.section() != section {
+                    section = entry.section();
+                    if let Some(section) = section {
+                        let _ = write!(page, "\n#### Subtest `{}`\n", section);
+                    }
+                }
+                if entry.reg_type() == RegType::Header {
+                    // A level below the test or subtest
+                    let level = if section.is_some() { "#####" } else { "####" };
+                    let _ = write!(page, "\n{} {}\n", level, entry.message());
+                    continue;
+                }
+                render_entry(&mut page, entry);
+            }
+        }
+    }
+    page
+}
+
+fn render_entry(page: &mut String, entry: &RegEntry) {
+    match (entry.note(), entry.key()) {
+        (Some(note), _) => {
+            let _ = write!(page, "\n{}\n", note);
+        }
+        (None, Some(key)) => {
+            let _ = write!(page, "\n`{}`:\n", key);
+        }
+        (None, None) => {}
+    }
+
+    let language = match entry.reg_type() {
+        RegType::Value | RegType::Json | RegType::Http => "json",
+        RegType::Markup => "xml",
+        RegType::Custom(kind) => return code_block(page, entry.message(), &kind),
+        _ => "text",
+    };
+    code_block(page, entry.message(), language);
+}
+
+/// Appends `text` as a fenced code block, fenced by more backticks than it
+/// contains in a row.
+fn code_block(page: &mut String, text: &str, language: &str) {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let _ = write!(page, "\n{}{}\n{}\n{}\n", fence, language, text, fence);
+}
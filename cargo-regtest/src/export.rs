@@ -0,0 +1,40 @@
+//! `cargo regtest export`: write the messages of all regression data files to
+//! plain text files, for tools which don't read data files.
+
+use crate::project::{self, Package};
+use regression_test::SnapshotFile;
+use std::path::PathBuf;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Directory to write the files to
+    dir: PathBuf,
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    let mut files = 0;
+    let mut entries = 0;
+    for package in packages {
+        for file in package.existing_data_files()? {
+            let out = args.dir.join(&package.name).join(package.test_path(&file));
+            // Entries removed since the last export don't linger
+            match std::fs::remove_dir_all(&out) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            let snapshot = SnapshotFile::load(&file).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("{}: {}", project::display(&file), e))
+            })?;
+            entries += snapshot.export(&out)?.len();
+            files += 1;
+        }
+    }
+
+    println!(
+        "Exported {} entries of {} data file(s) to {}",
+        entries,
+        files,
+        project::display(&args.dir)
+    );
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! `cargo regtest import-insta`: convert insta snapshot files into regression
+//! data files, see [`regression_test::import_insta`].
+
+use crate::project::{self, Package};
+use regression_test::RegType;
+use std::path::PathBuf;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Directory holding the `.snap` files, e.g. `src/snapshots`
+    dir: PathBuf,
+    /// Import the snapshots as `regtest_dbg` entries, for
+    /// `assert_debug_snapshot!`
+    #[arg(long)]
+    debug: bool,
+    /// Replace existing data files
+    #[arg(long)]
+    force: bool,
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    let dir = regression_test::logical_path(&args.dir)?;
+    // The innermost package, for nested packages
+    let package = packages
+        .iter()
+        .filter(|package| dir.starts_with(&package.root))
+        .max_by_key(|package| package.root.components().count())
+        .ok_or_else(|| {
+            std::io::Error::other(format!(
+                "{} is not in any package of the workspace",
+                args.dir.display()
+            ))
+        })?;
+
+    let reg_type = if args.debug {
+        RegType::Debug
+    } else {
+        RegType::Display
+    };
+    let tests = regression_test::import_insta(&dir, &package.config, reg_type)?;
+    if tests.is_empty() {
+        println!("No insta snapshot files found.");
+        return Ok(());
+    }
+
+    let mut skipped = 0;
+    for test in &tests {
+        if test.data_file.exists() && !args.force {
+            println!(
+                "Skipped {}, which already exists",
+                project::display(&test.data_file)
+            );
+            skipped += 1;
+            continue;
+        }
+        test.save()?;
+        println!(
+            "Imported {} snapshot(s) into {}",
+            test.snapshots.len(),
+            project::display(&test.data_file)
+        );
+        if test.named {
+            println!(
+                "  note: no test function matches the snapshot name; rename the file after the test recording it"
+            );
+        }
+    }
+
+    if skipped > 0 {
+        println!(
+            "\nSkipped {} existing data file(s). Run with --force to replace them.",
+            skipped
+        );
+    }
+    Ok(())
+}
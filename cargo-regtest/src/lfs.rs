@@ -0,0 +1,160 @@
+//! `cargo regtest lfs`: track the LFS directories of the packages (see
+//! `lfs_dir` in `regtest.toml`) with git LFS.
+//!
+//! Each directory is selected in the `.gitattributes` file at the root of the
+//! repository containing it:
+//!
+//! ```text
+//! regtest_lfs/** filter=lfs diff=lfs merge=lfs -text
+//! ```
+//!
+//! Patterns already there are left alone, so running it again only adds
+//! those of new directories.
+
+use crate::project::{self, Package};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Only check that `.gitattributes` has the patterns, failing if not
+    #[arg(long)]
+    check: bool,
+}
+
+/// The line of `.gitattributes` selecting the directory `relative` (to the
+/// root of the repository) for git LFS.
+fn pattern(relative: &Path) -> String {
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| {
+            // Patterns end at whitespace
+            part.as_os_str()
+                .to_string_lossy()
+                .replace(' ', "[[:space:]]")
+        })
+        .collect();
+    format!("{}/** filter=lfs diff=lfs merge=lfs -text", parts.join("/"))
+}
+
+/// The `patterns` which the `.gitattributes` file `contents` lacks.
+fn missing<'a>(contents: &str, patterns: &'a [String]) -> Vec<&'a str> {
+    patterns
+        .iter()
+        .filter(|pattern| !contents.lines().any(|line| line.trim() == pattern.as_str()))
+        .map(String::as_str)
+        .collect()
+}
+
+/// The `.gitattributes` file `contents` with `patterns` appended, each on a
+/// line of its own.
+fn append(contents: &str, patterns: &[&str]) -> String {
+    let mut contents = contents.to_string();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    for pattern in patterns {
+        contents.push_str(pattern);
+        contents.push('\n');
+    }
+    contents
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    let dirs: BTreeSet<PathBuf> = packages
+        .iter()
+        .filter_map(|package| package.config.lfs_dir.as_deref())
+        .map(regression_test::logical_path)
+        .collect::<std::io::Result<_>>()?;
+    if dirs.is_empty() {
+        return Err(std::io::Error::other(
+            "no package sets `lfs_dir` in its regtest.toml",
+        ));
+    }
+
+    // The patterns of each `.gitattributes` file
+    let mut files: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for dir in &dirs {
+        let repo = dir
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .ok_or_else(|| {
+                std::io::Error::other(format!(
+                    "{} is not in a git repository",
+                    project::display(dir)
+                ))
+            })?;
+        files
+            .entry(repo.join(".gitattributes"))
+            .or_default()
+            .push(pattern(dir.strip_prefix(repo).unwrap_or(dir)));
+    }
+
+    let mut tracked = true;
+    for (file, patterns) in &files {
+        let contents = match std::fs::read_to_string(file) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            contents => contents?,
+        };
+        let missing = missing(&contents, patterns);
+        if missing.is_empty() {
+            continue;
+        }
+        tracked = false;
+
+        if args.check {
+            for pattern in &missing {
+                println!("{}: missing `{}`", project::display(file), pattern);
+            }
+        } else {
+            std::fs::write(file, append(&contents, &missing))?;
+            println!(
+                "Added {} pattern(s) to {}",
+                missing.len(),
+                project::display(file)
+            );
+        }
+    }
+
+    if tracked {
+        println!("The LFS directories are already tracked in .gitattributes.");
+    } else if args.check {
+        return Err(std::io::Error::other(
+            "LFS directories aren't tracked in .gitattributes; run `cargo regtest lfs`",
+        ));
+    } else {
+        println!("Run `git lfs install` once per clone if git LFS isn't set up yet.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_select_the_directories() {
+        assert_eq!(
+            pattern(Path::new("crates/app/golden lfs")),
+            "crates/app/golden[[:space:]]lfs/** filter=lfs diff=lfs merge=lfs -text"
+        );
+    }
+
+    #[test]
+    fn only_missing_patterns_are_appended() {
+        let patterns = [pattern(Path::new("a")), pattern(Path::new("b"))];
+
+        let contents = append("*.rs text", &missing("*.rs text", &patterns));
+        assert_eq!(
+            contents,
+            "*.rs text\n\
+             a/** filter=lfs diff=lfs merge=lfs -text\n\
+             b/** filter=lfs diff=lfs merge=lfs -text\n"
+        );
+        assert!(missing(&contents, &patterns).is_empty());
+
+        let contents = "  a/** filter=lfs diff=lfs merge=lfs -text  \n";
+        assert_eq!(missing(contents, &patterns), [patterns[1].as_str()]);
+        assert_eq!(append("", &["x"]), "x\n");
+    }
+}
@@ -0,0 +1,153 @@
+//! `cargo regtest list`: list the regression data files with statistics, and
+//! flag the suspicious ones.
+
+use crate::project::{self, Package};
+use crate::prune;
+use regression_test::SnapshotFile;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Size above which a data file is flagged as huge, unless the package sets
+/// `max_file_bytes`.
+const HUGE_BYTES: u64 = 1024 * 1024;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Only list the suspicious files: empty, huge, orphaned or unreadable
+    #[arg(long)]
+    suspicious: bool,
+}
+
+/// A data file, with its statistics.
+struct Listed {
+    file: PathBuf,
+    /// Number of entries, or why the file couldn't be read.
+    entries: Result<usize, String>,
+    /// Size of the file and of the messages stored apart from it.
+    size: u64,
+    modified: Option<String>,
+    test: Option<String>,
+    flags: Vec<&'static str>,
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    let mut listed = Vec::new();
+    for package in packages {
+        let orphaned = prune::orphaned_files(package)?;
+        let huge = package.config.max_file_bytes.unwrap_or(HUGE_BYTES);
+        for file in package.existing_data_files()? {
+            let snapshot = SnapshotFile::load(&file);
+            let metadata = std::fs::metadata(&file)?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| regression_test::format_timestamp(since.as_secs()));
+            let size = metadata.len() + dir_size(&regression_test::entry_dir(&file));
+
+            let mut flags = Vec::new();
+            match &snapshot {
+                Ok(snapshot) if snapshot.entries().is_empty() => flags.push("empty"),
+                Ok(_) => {}
+                Err(_) => flags.push("unreadable"),
+            }
+            if size > huge {
+                flags.push("huge");
+            }
+            if orphaned.contains(&file) {
+                flags.push("orphaned");
+            }
+
+            listed.push(Listed {
+                test: snapshot
+                    .as_ref()
+                    .ok()
+                    .and_then(|s| s.test().map(str::to_string)),
+                entries: snapshot
+                    .map(|s| s.entries().len())
+                    .map_err(|e| e.to_string()),
+                file,
+                size,
+                modified,
+                flags,
+            });
+        }
+    }
+
+    if listed.is_empty() {
+        println!("No regression data files found.");
+        return Ok(());
+    }
+
+    println!(
+        "{:>7} {:>10}  {:<20}  file (test)",
+        "entries", "size", "modified"
+    );
+    for file in listed
+        .iter()
+        .filter(|file| !args.suspicious || !file.flags.is_empty())
+    {
+        let entries = match &file.entries {
+            Ok(count) => count.to_string(),
+            Err(_) => "?".to_string(),
+        };
+        let mut line = format!(
+            "{:>7} {:>10}  {:<20}  {}",
+            entries,
+            human_size(file.size),
+            file.modified.as_deref().unwrap_or("-"),
+            project::display(&file.file)
+        );
+        if let Some(test) = &file.test {
+            line.push_str(&format!(" ({})", test));
+        }
+        if !file.flags.is_empty() {
+            line.push_str(&format!(" [{}]", file.flags.join(", ")));
+        }
+        if let Err(e) = &file.entries {
+            line.push_str(&format!(": {}", e));
+        }
+        println!("{}", line);
+    }
+
+    let entries: usize = listed.iter().filter_map(|f| f.entries.as_ref().ok()).sum();
+    let size: u64 = listed.iter().map(|f| f.size).sum();
+    let flagged = |flag| listed.iter().filter(|f| f.flags.contains(&flag)).count();
+    println!(
+        "\n{} data file(s), {} entries, {}; {} empty, {} huge, {} orphaned, {} unreadable.",
+        listed.len(),
+        entries,
+        human_size(size),
+        flagged("empty"),
+        flagged("huge"),
+        flagged("orphaned"),
+        flagged("unreadable")
+    );
+    Ok(())
+}
+
+/// Total size of the files below `dir`, if it exists.
+fn dir_size(dir: &Path) -> u64 {
+    let mut size = 0;
+    if dir.is_dir() {
+        let _ = project::walk(dir, &mut |path| {
+            size += std::fs::metadata(path).map_or(0, |m| m.len());
+        });
+    }
+    size
+}
+
+/// `bytes`, in the largest binary unit which keeps it at least 1.
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
@@ -0,0 +1,105 @@
+//! # cargo-regtest
+//!
+//! Cargo subcommand for managing the data of `regression-test` tests.
+//!
+//! ```text
+//! cargo regtest list             # list data files with their statistics
+//! cargo regtest prune            # list data files whose test no longer exists
+//! cargo regtest prune --delete   # ... and delete them
+//! cargo regtest accept           # replace baselines with their pending files
+//! cargo regtest reject           # delete all pending files
+//! cargo regtest review           # accept or reject them one by one
+//! cargo regtest merge %O %A %B %P  # git merge driver for data files
+//! cargo regtest import-insta src/snapshots  # convert insta snapshots
+//! cargo regtest export golden    # write all messages to plain files
+//! cargo regtest docgen docs      # render them into Markdown pages
+//! cargo regtest lfs              # track the LFS directories in .gitattributes
+//! ```
+
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+mod docgen;
+mod export;
+mod import_insta;
+mod lfs;
+mod list;
+mod merge;
+mod pending;
+mod project;
+mod prune;
+mod review;
+
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cargo {
+    Regtest(Regtest),
+}
+
+/// Manage regression test data
+#[derive(clap::Args)]
+#[command(version)]
+struct Regtest {
+    /// Path to the Cargo.toml of the package or workspace
+    #[arg(long, global = true)]
+    manifest_path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List regression data files with statistics, flagging suspicious ones
+    List(list::Args),
+    /// List (or delete) regression data files whose test no longer exists
+    Prune(prune::Args),
+    /// Replace baselines with the pending files written by failed tests
+    Accept,
+    /// Delete the pending files written by failed tests
+    Reject,
+    /// Go through the pending files, accepting or rejecting each of them
+    Review,
+    /// Merge two versions of a data file, as a git merge driver
+    Merge(merge::Args),
+    /// Convert insta snapshot files into regression data files
+    ImportInsta(import_insta::Args),
+    /// Write the message of every entry to a plain text file
+    Export(export::Args),
+    /// Render the entries, with their notes, into Markdown pages
+    Docgen(docgen::Args),
+    /// Add the LFS directories to `.gitattributes`, to track them with git LFS
+    Lfs(lfs::Args),
+}
+
+fn main() -> ExitCode {
+    let Cargo::Regtest(args) = Cargo::parse();
+
+    // Merging works on the files given, outside of any package
+    let result = match &args.command {
+        Command::Merge(merge_args) => merge::run(merge_args),
+        command => {
+            project::packages(args.manifest_path.as_deref()).and_then(|packages| match command {
+                Command::List(list_args) => list::run(&packages, list_args),
+                Command::Prune(prune_args) => prune::run(&packages, prune_args),
+                Command::Accept => pending::run(&packages, pending::Action::Accept),
+                Command::Reject => pending::run(&packages, pending::Action::Reject),
+                Command::Review => review::run(&packages),
+                Command::ImportInsta(import_args) => import_insta::run(&packages, import_args),
+                Command::Export(export_args) => export::run(&packages, export_args),
+                Command::Docgen(docgen_args) => docgen::run(&packages, docgen_args),
+                Command::Lfs(lfs_args) => lfs::run(&packages, lfs_args),
+                Command::Merge(_) => unreachable!("merged above"),
+            })
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
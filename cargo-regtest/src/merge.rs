@@ -0,0 +1,64 @@
+//! `cargo regtest merge`: a git merge driver for regression data files.
+//!
+//! Registered in `.git/config` (or `~/.gitconfig`) and selected for the data
+//! files in `.gitattributes`:
+//!
+//! ```text
+//! [merge "regtest"]
+//!     name = regression-test data files
+//!     driver = cargo regtest merge %O %A %B %P
+//!
+//! regtest_data/** merge=regtest
+//! ```
+//!
+//! git then merges the entries of data files changed on both branches, rather
+//! than their lines, and only reports a conflict when both branches changed
+//! the same entry.
+
+use crate::project;
+use std::path::PathBuf;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The common ancestor of both versions (`%O`)
+    base: PathBuf,
+    /// Our version, replaced with the merged file (`%A`)
+    ours: PathBuf,
+    /// Their version (`%B`)
+    theirs: PathBuf,
+    /// Path of the data file in the repository (`%P`), whose extension
+    /// decides the format of the merged file
+    path: Option<PathBuf>,
+}
+
+pub fn run(args: &Args) -> std::io::Result<()> {
+    let merged = regression_test::merge_files(&args.base, &args.ours, &args.theirs)?;
+    let name = args.path.as_ref().unwrap_or(&args.ours);
+
+    if merged.conflicts().is_empty() {
+        return std::fs::write(&args.ours, merged.to_bytes(name)?);
+    }
+
+    // git keeps our version, and reports the file as conflicted
+    for conflict in merged.conflicts() {
+        let entry = match conflict.key() {
+            Some(key) => format!("entry `{}` #{}", key, conflict.index),
+            None => format!("entry #{}", conflict.index),
+        };
+        let section = conflict
+            .section()
+            .map(|section| format!(" of subtest `{}`", section))
+            .unwrap_or_default();
+        eprintln!("{}{} was changed on both sides:", entry, section);
+        for (side, version) in [("ours", &conflict.ours), ("theirs", &conflict.theirs)] {
+            let message = version
+                .as_ref()
+                .map_or("(removed)", |entry| entry.message());
+            eprintln!("  {}: {}", side, message.replace('\n', "\n    "));
+        }
+    }
+    Err(std::io::Error::other(format!(
+        "both sides changed the same entries of {}; resolve them by hand, or regenerate the baseline",
+        project::display(name)
+    )))
+}
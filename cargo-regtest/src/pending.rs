@@ -0,0 +1,56 @@
+//! `cargo regtest accept` and `cargo regtest reject`: resolve the pending
+//! files written when tests record entries past the end of their baseline.
+
+use crate::project::{self, Package};
+use std::path::{Path, PathBuf};
+
+/// What to do with the pending files.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Replace each baseline with its pending file.
+    Accept,
+    /// Delete the pending files, keeping the baselines.
+    Reject,
+}
+
+pub fn run(packages: &[Package], action: Action) -> std::io::Result<()> {
+    let mut count = 0;
+    for package in packages {
+        for file in package.pending_files()? {
+            match action {
+                Action::Accept => {
+                    let baseline = accept(&file)?;
+                    println!("Accepted {}", project::display(&baseline));
+                }
+                Action::Reject => {
+                    reject(&file)?;
+                    println!("Rejected {}", project::display(&file));
+                }
+            }
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        println!("No pending regression data files found.");
+    }
+    Ok(())
+}
+
+/// Replaces the baseline of the pending file `file` with it, and returns the
+/// path of the baseline.
+pub fn accept(file: &Path) -> std::io::Result<PathBuf> {
+    let baseline = regression_test::accepted_path(file);
+    std::fs::rename(file, &baseline)?;
+    // Pending files hold their messages, whatever the layout
+    let entries = regression_test::entry_dir(&baseline);
+    if entries.is_dir() {
+        std::fs::remove_dir_all(&entries)?;
+    }
+    Ok(baseline)
+}
+
+/// Deletes the pending file `file`, keeping its baseline.
+pub fn reject(file: &Path) -> std::io::Result<()> {
+    std::fs::remove_file(file)
+}
@@ -0,0 +1,366 @@
+//! Discovery of the packages of a workspace and of their regression tests.
+
+use regression_test::Config;
+use regression_test::config::{Placement, RUN_ORDER_DIR_NAME, SNAPSHOTS_DIR_NAME};
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use syn::visit::Visit;
+
+/// A package of the workspace.
+pub struct Package {
+    pub name: String,
+    /// Directory containing the package's `Cargo.toml`.
+    pub root: PathBuf,
+    /// Regression test configuration of the package.
+    pub config: Config,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<MetadataPackage>,
+}
+
+#[derive(Deserialize)]
+struct MetadataPackage {
+    name: String,
+    manifest_path: PathBuf,
+}
+
+fn other_error(message: String) -> std::io::Error {
+    std::io::Error::other(message)
+}
+
+/// Lists the packages of the workspace containing `manifest_path` (or the
+/// current directory), using `cargo metadata`.
+pub fn packages(manifest_path: Option<&Path>) -> std::io::Result<Vec<Package>> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let mut command = Command::new(cargo);
+    command.args(["metadata", "--format-version", "1", "--no-deps"]);
+    if let Some(manifest_path) = manifest_path {
+        command.arg("--manifest-path").arg(manifest_path);
+    }
+
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(other_error(format!(
+            "cargo metadata failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+
+    let metadata: Metadata = serde_json::from_slice(&output.stdout)?;
+    metadata
+        .packages
+        .into_iter()
+        .map(|package| {
+            let root = regression_test::logical_path(
+                package
+                    .manifest_path
+                    .parent()
+                    .unwrap_or_else(|| Path::new("")),
+            )?;
+            let mut config = Config::discover(&root)?;
+            config.apply_env();
+
+            Ok(Package {
+                name: package.name,
+                root,
+                config,
+            })
+        })
+        .collect()
+}
+
+impl Package {
+    /// Directory holding this package's regression data.
+    pub fn data_dir(&self) -> PathBuf {
+        self.config.data_dir(&self.root)
+    }
+
+    /// The directories holding this package's data files: the
+    /// [data directory](Package::data_dir), and the `__snapshots__`
+    /// directories next to the sources of tests placed there, see
+    /// [Placement::NextToSource].
+    pub fn data_dirs(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut dirs = vec![self.data_dir()];
+        for dir in self.config.target_dirs.keys() {
+            find_snapshot_dirs(&self.root.join(dir), &mut dirs)?;
+        }
+        Ok(dirs)
+    }
+
+    /// All Rust source files which may contain regression tests.
+    pub fn source_files(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for dir in self.config.target_dirs.keys() {
+            let dir = self.root.join(dir);
+            if dir.is_dir() {
+                walk(&dir, &mut |path| {
+                    if path.extension().is_some_and(|ext| ext == "rs") {
+                        files.push(path.to_path_buf());
+                    }
+                })?;
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Regression data files of all `#[regtest]` functions, `regtest!()`
+    /// calls and tests calling `RegTest::for_current_test()` of this package.
+    pub fn expected_data_files(&self) -> std::io::Result<BTreeSet<PathBuf>> {
+        let mut expected = BTreeSet::new();
+        for file in self.source_files()? {
+            let visitor = regtest_functions(&file)?;
+            if let Some((binary, modules)) = self.test_binary(&file) {
+                for test in visitor.current_tests {
+                    let test: Vec<&str> =
+                        modules.iter().map(String::as_str).chain([&*test]).collect();
+                    expected.insert(self.config.current_test_path(
+                        &self.root,
+                        &binary,
+                        &test.join("::"),
+                    ));
+                }
+            }
+            for (test, placement) in visitor.names {
+                let path = match placement {
+                    Some(placement) => Config {
+                        placement,
+                        ..self.config.clone()
+                    }
+                    .snapshot_path(&file, &test),
+                    None => self.config.snapshot_path(&file, &test),
+                };
+                expected.insert(path);
+            }
+        }
+        Ok(expected)
+    }
+
+    /// The test binary whose tests are defined in the source file `file`,
+    /// and the module path of `file` in it, as named at runtime: `src/a/b.rs`
+    /// is the module `a::b` of the library (or main) crate, `tests/it/a.rs`
+    /// the module `a` of the integration test `it`. `None` for files outside
+    /// of `src` and `tests`.
+    fn test_binary(&self, file: &Path) -> Option<(String, Vec<String>)> {
+        let parts: Vec<String> = file
+            .strip_prefix(&self.root)
+            .ok()?
+            .iter()
+            .map(|part| part.to_string_lossy().into_owned())
+            .collect();
+        let strip = |name: &str| name.strip_suffix(".rs").unwrap_or(name).to_string();
+        let (binary, rest) = match parts.as_slice() {
+            [src, bin, name, rest @ ..] if src == "src" && bin == "bin" => (strip(name), rest),
+            [src, rest @ ..] if src == "src" => (self.name.replace('-', "_"), rest),
+            [tests, name, rest @ ..] if tests == "tests" => (strip(name), rest),
+            _ => return None,
+        };
+        let mut modules: Vec<String> = rest.iter().map(|part| strip(part)).collect();
+        // The crate root and `mod.rs` files are named after their directory
+        if modules.last().is_some_and(|last| {
+            last == "mod" || (modules.len() == 1 && (last == "lib" || last == "main"))
+        }) {
+            modules.pop();
+        }
+        Some((binary, modules))
+    }
+
+    /// All regression data files currently stored for this package.
+    pub fn existing_data_files(&self) -> std::io::Result<BTreeSet<PathBuf>> {
+        let mut existing = BTreeSet::new();
+        let run_order = self.data_dir().join(RUN_ORDER_DIR_NAME);
+        for dir in self.data_dirs()? {
+            if dir.is_dir() {
+                walk(&dir, &mut |path| {
+                    if regression_test::is_data_path(path) && !path.starts_with(&run_order) {
+                        existing.insert(path.to_path_buf());
+                    }
+                })?;
+            }
+        }
+        Ok(existing)
+    }
+
+    /// The path of the data file `file` in the data directory, without
+    /// extensions, e.g. `src/lib/parses@big-endian`: the source file and the
+    /// test (and variant) it belongs to. Files next to the sources are named
+    /// after their path in the package, e.g. `src/parser/parses`.
+    pub fn test_path(&self, file: &Path) -> PathBuf {
+        let relative = match file.strip_prefix(self.data_dir()) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => file
+                .strip_prefix(&self.root)
+                .unwrap_or(file)
+                .iter()
+                .filter(|part| *part != SNAPSHOTS_DIR_NAME)
+                .collect(),
+        };
+        let name = relative
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let stem = name.split('.').next().unwrap_or_default();
+        relative.with_file_name(stem)
+    }
+
+    /// All pending files of this package, waiting to be accepted or rejected.
+    pub fn pending_files(&self) -> std::io::Result<BTreeSet<PathBuf>> {
+        let mut pending = BTreeSet::new();
+        for dir in self.data_dirs()? {
+            if dir.is_dir() {
+                walk(&dir, &mut |path| {
+                    if regression_test::is_pending_path(path) {
+                        pending.insert(path.to_path_buf());
+                    }
+                })?;
+            }
+        }
+        Ok(pending)
+    }
+}
+
+/// Collects the `__snapshots__` directories below `dir`.
+fn find_snapshot_dirs(dir: &Path, dirs: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.ends_with(SNAPSHOTS_DIR_NAME) {
+            dirs.push(path);
+        } else {
+            find_snapshot_dirs(&path, dirs)?;
+        }
+    }
+    Ok(())
+}
+
+/// Calls `f` for every file below `dir`, recursively.
+pub fn walk(dir: &Path, f: &mut dyn FnMut(&Path)) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, f)?;
+        } else {
+            f(&path);
+        }
+    }
+    Ok(())
+}
+
+/// The regression tests of `file`: see [RegtestVisitor].
+fn regtest_functions(file: &Path) -> std::io::Result<RegtestVisitor> {
+    let text = std::fs::read_to_string(file)?;
+    let syntax = syn::parse_file(&text)
+        .map_err(|e| other_error(format!("Failed to parse {}: {}", file.display(), e)))?;
+
+    let mut visitor = RegtestVisitor::default();
+    visitor.visit_file(&syntax);
+    Ok(visitor)
+}
+
+#[derive(Default)]
+struct RegtestVisitor {
+    /// Names of the functions annotated with `#[regtest]`, with the placement
+    /// given to the attribute, if any, or calling `regtest!()`, and the names
+    /// given to `regtest!("name")`.
+    names: Vec<(String, Option<Placement>)>,
+    /// The functions calling `RegTest::for_current_test()`, by their module
+    /// path in the file, e.g. `tests::parses`.
+    current_tests: Vec<String>,
+    /// The function being visited, whose data file `regtest!()` opens.
+    function: Option<String>,
+    /// The inline modules being visited.
+    modules: Vec<String>,
+}
+
+impl<'ast> Visit<'ast> for RegtestVisitor {
+    fn visit_item_fn(&mut self, item: &'ast syn::ItemFn) {
+        let regtest = item.attrs.iter().find(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "regtest")
+        });
+        if let Some(attr) = regtest {
+            self.names
+                .push((item.sig.ident.to_string(), attribute_placement(attr)));
+        }
+        let outer = self.function.replace(item.sig.ident.to_string());
+        syn::visit::visit_item_fn(self, item);
+        self.function = outer;
+    }
+
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.modules.push(item.ident.to_string());
+        syn::visit::visit_item_mod(self, item);
+        self.modules.pop();
+    }
+
+    fn visit_expr_call(&mut self, call: &'ast syn::ExprCall) {
+        let is_current_test = matches!(&*call.func, syn::Expr::Path(path)
+            if path.path.segments.last().is_some_and(|segment| segment.ident == "for_current_test"));
+        if is_current_test && let Some(function) = &self.function {
+            let mut test = self.modules.clone();
+            test.push(function.clone());
+            self.current_tests.push(test.join("::"));
+        }
+        syn::visit::visit_expr_call(self, call);
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        let is_regtest = mac
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "regtest");
+        if is_regtest {
+            if mac.tokens.is_empty() {
+                self.names
+                    .extend(self.function.clone().map(|name| (name, None)));
+            } else if let Ok(name) = mac.parse_body::<syn::LitStr>() {
+                self.names.push((name.value(), None));
+            }
+        }
+        syn::visit::visit_macro(self, mac);
+    }
+}
+
+/// The placement given to a `#[regtest(placement = "...")]` attribute.
+fn attribute_placement(attr: &syn::Attribute) -> Option<Placement> {
+    let args = attr
+        .parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+        .ok()?;
+    args.iter().find_map(|arg| match arg {
+        syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(name),
+                    ..
+                }),
+            ..
+        }) if path.is_ident("placement") => Placement::from_name(&name.value()),
+        _ => None,
+    })
+}
+
+/// Renders `path` relative to the current directory when possible.
+pub fn display(path: &Path) -> String {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| {
+            path.strip_prefix(regression_test::logical_path(cwd).ok()?)
+                .ok()
+        })
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
@@ -0,0 +1,170 @@
+//! `cargo regtest prune`: find regression data whose test no longer exists.
+//!
+//! Only the data files of `#[regtest]` functions, of `regtest!` calls and of
+//! tests calling `RegTest::for_current_test()` can be attributed to a test, so
+//! files of hand-made `RegTest` instances (or of `regtest!()` calls in helpers)
+//! are reported as orphaned too.
+
+use crate::project::{self, Package};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Delete the orphaned files instead of only listing them
+    #[arg(long)]
+    delete: bool,
+}
+
+/// Regression data files of `package` which don't belong to any existing
+/// `#[regtest]` function, or to any variant of one.
+pub fn orphaned_files(package: &Package) -> std::io::Result<Vec<PathBuf>> {
+    let expected = package.expected_data_files()?;
+    Ok(package
+        .existing_data_files()?
+        .into_iter()
+        .filter(|file| {
+            !expected.contains(file) && !expected.contains(&regression_test::without_variant(file))
+        })
+        .collect())
+}
+
+pub fn run(packages: &[Package], args: &Args) -> std::io::Result<()> {
+    let mut orphaned = Vec::new();
+    for package in packages {
+        for file in orphaned_files(package)? {
+            orphaned.push((package, file));
+        }
+    }
+
+    if orphaned.is_empty() {
+        println!("No orphaned regression data files found.");
+        return Ok(());
+    }
+
+    for (package, file) in &orphaned {
+        if args.delete {
+            std::fs::remove_file(file)?;
+            let entries = regression_test::entry_dir(file);
+            if entries.is_dir() {
+                std::fs::remove_dir_all(&entries)?;
+            }
+            let data_dirs = package.data_dirs()?;
+            if let Some(root) = data_dirs.iter().find(|dir| file.starts_with(dir)) {
+                remove_empty_parents(file, root);
+            }
+            for sidecar in sidecars(package, file)? {
+                if sidecar.is_dir() {
+                    std::fs::remove_dir_all(&sidecar)?;
+                } else {
+                    std::fs::remove_file(&sidecar)?;
+                }
+                let mut roots = package.config.lfs_dir.iter().chain(&data_dirs);
+                if let Some(root) = roots.find(|dir| sidecar.starts_with(dir)) {
+                    remove_empty_parents(&sidecar, root);
+                }
+            }
+            println!("Deleted {}", project::display(file));
+        } else {
+            println!("{} ({})", project::display(file), package.name);
+        }
+    }
+
+    if !args.delete {
+        println!(
+            "\nFound {} orphaned regression data file(s). Run with --delete to remove them.",
+            orphaned.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// The files written beside the data file `file`, in the
+/// [LFS directory](regression_test::Config::lfs_dir) if it has one: its full
+/// message directory and its images, `<test>.<name>.png`. Blobs are left
+/// alone, as other data files may refer to them.
+fn sidecars(package: &Package, file: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let config = &package.config;
+    let mut sidecars = vec![config.lfs_path(regression_test::full_message_dir(file))];
+    let name = file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let prefix = format!("{}.", name.split('.').next().unwrap_or_default());
+    let dir = config.lfs_path(file.parent().unwrap_or(Path::new("")));
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(&dir)? {
+            let image = entry?.file_name().to_string_lossy().into_owned();
+            if image.starts_with(&prefix) && image.ends_with(".png") {
+                sidecars.push(dir.join(image));
+            }
+        }
+    }
+    sidecars.retain(|sidecar| sidecar.exists());
+    sidecars.sort();
+    Ok(sidecars)
+}
+
+/// Removes the now empty directories between `file` and `root`.
+fn remove_empty_parents(file: &Path, root: &Path) {
+    for dir in file.ancestors().skip(1) {
+        if dir == root || !dir.starts_with(root) || std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regression_test::Config;
+
+    #[test]
+    fn keeps_the_data_of_tests_without_macros() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../example-lib");
+        let root = regression_test::logical_path(root).unwrap();
+        let package = Package {
+            name: "example-lib".to_string(),
+            config: Config::discover(&root).unwrap(),
+            root,
+        };
+        let data = package
+            .root
+            .join("regtest_data/src/tests/without_macros.json");
+        assert!(data.is_file());
+        assert!(package.expected_data_files().unwrap().contains(&data));
+        assert!(!orphaned_files(&package).unwrap().contains(&data));
+    }
+
+    #[test]
+    fn deletes_the_sidecars_in_the_lfs_dir() {
+        let root = std::env::temp_dir().join(format!("regtest-prune-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let data = root.join("regtest_data/src/lib");
+        let lfs = root.join("lfs/regtest_data/src/lib");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::create_dir_all(lfs.join("gone.full")).unwrap();
+        std::fs::write(root.join("regtest.toml"), "lfs_dir = \"lfs\"\n").unwrap();
+        for file in [
+            data.join("gone.json"),
+            lfs.join("gone.full/0123.txt"),
+            lfs.join("gone.0.png"),
+            lfs.join("gone@variant.0.png"),
+        ] {
+            std::fs::write(file, "").unwrap();
+        }
+
+        let package = Package {
+            name: "gone".to_string(),
+            config: Config::discover(&root).unwrap(),
+            root: root.clone(),
+        };
+        run(&[package], &Args { delete: true }).unwrap();
+
+        assert!(!data.join("gone.json").exists());
+        assert!(!lfs.join("gone.full").exists());
+        assert!(!lfs.join("gone.0.png").exists());
+        assert!(lfs.join("gone@variant.0.png").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,449 @@
+//! `cargo regtest review`: go through the pending files in a terminal UI, like
+//! `cargo insta review`, showing how each differs from its baseline and
+//! accepting or rejecting it.
+
+use crate::pending;
+use crate::project::{self, Package};
+use regression_test::{FileDiff, RegEntry, RegType};
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+/// What became of a pending file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+/// A pending file, with how it differs from its baseline.
+struct Item {
+    package: String,
+    file: PathBuf,
+    /// The differences, or why they couldn't be found.
+    diff: Result<FileDiff, String>,
+    status: Status,
+}
+
+/// A line of the side-by-side diff.
+enum Row {
+    /// Introduces the lines of an entry.
+    Heading(String),
+    /// A line of the baseline entry and of the pending one, if they have it.
+    Sides(Option<String>, Option<String>),
+}
+
+/// A key pressed in the UI.
+enum Key {
+    Next,
+    Previous,
+    ScrollDown,
+    ScrollUp,
+    Accept,
+    Reject,
+    Quit,
+    Other,
+}
+
+const HELP: &str = "j/k: next/previous  J/K: scroll  a: accept  r: reject  q: quit";
+
+pub fn run(packages: &[Package]) -> std::io::Result<()> {
+    let mut items = Vec::new();
+    for package in packages {
+        for file in package.pending_files()? {
+            let baseline = regression_test::accepted_path(&file);
+            items.push(Item {
+                package: package.name.clone(),
+                diff: regression_test::diff_files(&baseline, &file).map_err(|e| e.to_string()),
+                file,
+                status: Status::Pending,
+            });
+        }
+    }
+
+    if items.is_empty() {
+        println!("No pending regression data files found.");
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(std::io::Error::other(
+            "`cargo regtest review` needs a terminal; use `cargo regtest accept` or `cargo regtest reject` instead",
+        ));
+    }
+
+    let terminal = terminal::Terminal::enter()?;
+    let mut stdin = std::io::stdin().lock();
+    let mut stdout = std::io::stdout().lock();
+    let mut selected = 0;
+    let mut scroll = 0;
+    let mut message = None;
+    loop {
+        let (width, height) = terminal.size();
+        let rows = rows(&items[selected]);
+        let screen = draw(
+            &items,
+            selected,
+            &rows,
+            scroll,
+            message.as_deref(),
+            width,
+            height,
+        );
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()?;
+
+        let page = (height / 2).max(1);
+        message = None;
+        match read_key(&mut stdin, &terminal)? {
+            Key::Next if selected + 1 < items.len() => {
+                selected += 1;
+                scroll = 0;
+            }
+            Key::Previous if selected > 0 => {
+                selected -= 1;
+                scroll = 0;
+            }
+            Key::ScrollDown => scroll = (scroll + page).min(rows.len().saturating_sub(1)),
+            Key::ScrollUp => scroll = scroll.saturating_sub(page),
+            key @ (Key::Accept | Key::Reject) => {
+                let item = &mut items[selected];
+                if item.status != Status::Pending {
+                    continue;
+                }
+                let resolved = match key {
+                    Key::Accept => pending::accept(&item.file).map(|_| Status::Accepted),
+                    _ => pending::reject(&item.file).map(|()| Status::Rejected),
+                };
+                match resolved {
+                    Ok(status) => {
+                        item.status = status;
+                        if let Some(next) = next_pending(&items, selected) {
+                            selected = next;
+                            scroll = 0;
+                        }
+                    }
+                    Err(e) => message = Some(format!("error: {}", e)),
+                }
+            }
+            Key::Quit => break,
+            _ => {}
+        }
+    }
+    drop(terminal);
+
+    let count = |status| items.iter().filter(|item| item.status == status).count();
+    println!(
+        "Accepted {}, rejected {} and kept {} pending regression data file(s).",
+        count(Status::Accepted),
+        count(Status::Rejected),
+        count(Status::Pending)
+    );
+    Ok(())
+}
+
+/// The first pending item after `selected`, wrapping around.
+fn next_pending(items: &[Item], selected: usize) -> Option<usize> {
+    (1..items.len())
+        .map(|offset| (selected + offset) % items.len())
+        .find(|&i| items[i].status == Status::Pending)
+}
+
+fn read_key(stdin: &mut impl BufRead, terminal: &terminal::Terminal) -> std::io::Result<Key> {
+    let mut byte = [0];
+    stdin.read_exact(&mut byte)?;
+    Ok(match byte[0] {
+        b'j' => Key::Next,
+        b'k' => Key::Previous,
+        b'J' | b' ' => Key::ScrollDown,
+        b'K' => Key::ScrollUp,
+        b'a' => Key::Accept,
+        b'r' => Key::Reject,
+        // Ctrl-C, as signals are off in raw mode
+        b'q' | 3 => Key::Quit,
+        // Arrow keys: ESC [ A to D. The rest of the sequence comes with the
+        // ESC, while a lone Esc key isn't followed by anything for a while,
+        // and the keys pressed after it are not part of it
+        0x1b => {
+            let mut sequence = Vec::new();
+            terminal.with_timeout(|| stdin.take(2).read_to_end(&mut sequence))?;
+            match sequence[..] {
+                [b'[', b'B'] => Key::Next,
+                [b'[', b'A'] => Key::Previous,
+                _ => Key::Other,
+            }
+        }
+        _ => Key::Other,
+    })
+}
+
+/// The side-by-side diff of `item`: the baseline on the left, the pending
+/// file on the right.
+fn rows(item: &Item) -> Vec<Row> {
+    let diff = match &item.diff {
+        Ok(diff) => diff,
+        Err(e) => return vec![Row::Heading(format!("failed to compare: {}", e))],
+    };
+
+    let mut rows = Vec::new();
+    for change in &diff.changed {
+        rows.push(Row::Heading(heading("changed", &change.after)));
+        let before: Vec<&str> = change.before.message().lines().collect();
+        let after: Vec<&str> = change.after.message().lines().collect();
+        for i in 0..before.len().max(after.len()) {
+            rows.push(Row::Sides(
+                before.get(i).map(|line| line.to_string()),
+                after.get(i).map(|line| line.to_string()),
+            ));
+        }
+    }
+    for entry in &diff.removed {
+        rows.push(Row::Heading(heading("removed", entry)));
+        rows.extend(
+            entry
+                .message()
+                .lines()
+                .map(|line| Row::Sides(Some(line.to_string()), None)),
+        );
+    }
+    for entry in &diff.added {
+        rows.push(Row::Heading(heading("added", entry)));
+        rows.extend(
+            entry
+                .message()
+                .lines()
+                .map(|line| Row::Sides(None, Some(line.to_string()))),
+        );
+    }
+    if rows.is_empty() {
+        rows.push(Row::Heading(
+            "same entries as the baseline (only metadata differs)".to_string(),
+        ));
+    }
+    rows
+}
+
+fn heading(what: &str, entry: &RegEntry) -> String {
+    let kind = match entry.reg_type() {
+        RegType::Header => "header",
+        _ => "entry",
+    };
+    let mut heading = format!("{} {}", what, kind);
+    if let Some(key) = entry.key() {
+        heading.push_str(&format!(" `{}`", key));
+    }
+    if let Some(section) = entry.section() {
+        heading.push_str(&format!(" of subtest `{}`", section));
+    }
+    if let Some(note) = entry.note() {
+        heading.push_str(&format!(" ({})", note));
+    }
+    heading
+}
+
+/// The whole screen, `width` columns by `height` lines.
+fn draw(
+    items: &[Item],
+    selected: usize,
+    rows: &[Row],
+    scroll: usize,
+    message: Option<&str>,
+    width: usize,
+    height: usize,
+) -> String {
+    let mut screen = String::from("\x1b[H\x1b[2J");
+    let line = |screen: &mut String, text: &str, style: &str| {
+        screen.push_str(&styled(fit(text, width), style));
+        screen.push_str("\r\n");
+    };
+
+    let left = items
+        .iter()
+        .filter(|item| item.status == Status::Pending)
+        .count();
+    line(
+        &mut screen,
+        &format!(
+            "{} pending regression data file(s), {} left",
+            items.len(),
+            left
+        ),
+        "1",
+    );
+
+    // The list, scrolled to keep the selected file in view
+    let list_height = items.len().min((height / 4).max(3));
+    let first = (selected + 1).saturating_sub(list_height);
+    for (i, item) in items.iter().enumerate().skip(first).take(list_height) {
+        let (status, style) = match item.status {
+            Status::Pending => ("pending ", ""),
+            Status::Accepted => ("accepted", "32"),
+            Status::Rejected => ("rejected", "31"),
+        };
+        let text = format!(
+            "{} {} {} ({})",
+            if i == selected { '>' } else { ' ' },
+            status,
+            project::display(&item.file),
+            item.package
+        );
+        line(&mut screen, &text, if i == selected { "7" } else { style });
+    }
+
+    let column = width.saturating_sub(3) / 2;
+    line(
+        &mut screen,
+        &format!("{} │ {}", fit("baseline", column), "pending"),
+        "1",
+    );
+
+    let diff_height = height.saturating_sub(list_height + 3);
+    for row in rows.iter().skip(scroll).take(diff_height) {
+        match row {
+            Row::Heading(text) => line(&mut screen, text, "36"),
+            Row::Sides(before, after) => {
+                let (left_style, right_style) = match (before, after) {
+                    (Some(before), Some(after)) if before == after => ("", ""),
+                    _ => ("31", "32"),
+                };
+                let side = |text: &Option<String>, style: &str| {
+                    styled(fit(text.as_deref().unwrap_or(""), column), style)
+                };
+                screen.push_str(&format!(
+                    "{} │ {}\r\n",
+                    side(before, left_style),
+                    side(after, right_style)
+                ));
+            }
+        }
+    }
+
+    screen.push_str(&format!("\x1b[{};1H", height));
+    let footer = match message {
+        Some(message) => styled(fit(message, width), "31"),
+        None => styled(fit(HELP, width), "2"),
+    };
+    screen.push_str(&footer);
+    screen
+}
+
+/// `text` with the SGR attributes `style`, if any.
+fn styled(text: String, style: &str) -> String {
+    if style.is_empty() {
+        text
+    } else {
+        format!("\x1b[{}m{}\x1b[0m", style, text)
+    }
+}
+
+/// `text` on one line, cut or padded to `width` columns.
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text
+        .replace('\t', "    ")
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .take(width)
+        .collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::io::Write;
+
+    /// The terminal, in raw mode and showing the alternate screen until
+    /// dropped.
+    pub struct Terminal {
+        original: libc::termios,
+        /// The settings of raw mode.
+        raw: libc::termios,
+    }
+
+    impl Terminal {
+        pub fn enter() -> std::io::Result<Self> {
+            let mut original = std::mem::MaybeUninit::uninit();
+            // SAFETY: tcgetattr initializes the settings when it succeeds
+            if unsafe { libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let original = unsafe { original.assume_init() };
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: `raw` is a valid copy of the current settings
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+
+            // Alternate screen, hidden cursor
+            let mut stdout = std::io::stdout();
+            stdout.write_all(b"\x1b[?1049h\x1b[?25l")?;
+            stdout.flush()?;
+            Ok(Terminal { original, raw })
+        }
+
+        /// Runs `f` with reads from the terminal giving up, as if at its end,
+        /// when no input comes within a tenth of a second.
+        pub fn with_timeout<R>(&self, f: impl FnOnce() -> R) -> R {
+            let mut settings = self.raw;
+            settings.c_cc[libc::VMIN] = 0;
+            settings.c_cc[libc::VTIME] = 1;
+            // SAFETY: `settings` is a valid copy of the raw mode settings
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) };
+            let result = f();
+            // SAFETY: restores the raw mode settings
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.raw) };
+            result
+        }
+
+        /// Columns and lines of the terminal.
+        pub fn size(&self) -> (usize, usize) {
+            // SAFETY: winsize is plain data, filled in by the ioctl
+            let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+            if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } == 0
+                && size.ws_col > 0
+                && size.ws_row > 0
+            {
+                (usize::from(size.ws_col), usize::from(size.ws_row))
+            } else {
+                (80, 24)
+            }
+        }
+    }
+
+    impl Drop for Terminal {
+        fn drop(&mut self) {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+            let _ = stdout.flush();
+            // SAFETY: restores the settings read when entering
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    pub struct Terminal;
+
+    impl Terminal {
+        pub fn enter() -> std::io::Result<Self> {
+            Err(std::io::Error::other(
+                "`cargo regtest review` only supports Unix terminals; use `cargo regtest accept` or `cargo regtest reject` instead",
+            ))
+        }
+
+        pub fn with_timeout<R>(&self, f: impl FnOnce() -> R) -> R {
+            f()
+        }
+
+        pub fn size(&self) -> (usize, usize) {
+            (80, 24)
+        }
+    }
+}
@@ -5,15 +5,25 @@ pub fn add(left: u64, right: u64) -> u64 {
 }
 
 pub fn random_number() -> u64 {
-    use rand::Rng;
-    let mut rng = rand::rng();
+    random_number_with(&mut rand::rng())
+}
+
+pub fn random_number_with<R: rand::Rng>(rng: &mut R) -> u64 {
     rng.random_range(0..100)
 }
 
+pub fn greeting() -> &'static str {
+    if cfg!(feature = "fancy") {
+        "~ Hello ~"
+    } else {
+        "Hello"
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use regression_test::RegTest;
-    use regression_test_macros::regtest;
+    use regression_test::{RegTest, expect};
+    use regression_test_macros::{RegSnapshot, regtest};
 
     use super::*;
 
@@ -24,10 +34,60 @@ mod tests {
         r.regtest(result);
     }
 
+    #[regtest]
+    fn it_works_inline(mut r: RegTest) {
+        r.regtest_inline(add(2, 2), expect!["4"]);
+        r.regtest_inline(
+            format!("{}\n{}", add(1, 1), add(2, 3)),
+            expect![
+                r#"
+            2
+            5
+        "#
+            ],
+        );
+    }
+
+    #[regtest(features = ["fancy"])]
+    fn greeting_test(mut r: RegTest) {
+        r.regtest(greeting());
+    }
+
+    #[derive(RegSnapshot)]
+    struct Draw {
+        label: &'static str,
+        #[regsnapshot(redact = "[random]")]
+        number: u64,
+        #[regsnapshot(skip)]
+        _attempts: u32,
+    }
+
+    #[regtest]
+    fn snapshot_test(mut r: RegTest) {
+        let draw = Draw {
+            label: "lucky",
+            number: random_number(),
+            _attempts: 1,
+        };
+        r.regtest_snapshot(&draw);
+    }
+
     #[regtest]
     fn random_number_test(mut r: RegTest) {
-        let result = random_number();
+        let result = random_number_with(&mut r.rng::<rand::rngs::StdRng>());
         assert!(result < 100);
         r.regtest(result);
     }
+
+    #[test]
+    fn without_attribute() {
+        let mut r = regression_test::regtest!();
+        r.regtest(add(20, 22));
+    }
+
+    #[test]
+    fn without_macros() {
+        let mut r = RegTest::for_current_test().unwrap();
+        r.regtest(add(40, 2));
+    }
 }
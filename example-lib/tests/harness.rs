@@ -0,0 +1,17 @@
+use regression_test::RegTest;
+use regression_test_macros::regtest;
+
+#[regtest(tags("arithmetic"))]
+fn adds(mut r: RegTest) {
+    r.regtest(example_lib::add(2, 3));
+    r.regtest(example_lib::add(40, 2));
+}
+
+#[regtest(features = ["fancy"])]
+fn greets(mut r: RegTest) {
+    r.regtest(example_lib::greeting());
+}
+
+fn main() {
+    regression_test::harness::main();
+}
@@ -15,8 +15,11 @@ fn my_integration_test(mut r: RegTest) {
 
 #[regtest]
 fn another_integration_test(mut r: RegTest) {
-    // Another integration test that checks a random number
-    let result = example_lib::random_number();
+    // Another integration test that checks a random number, made
+    // reproducible by seeding it
+    let seed = r.seed();
+    let mut rng = <rand::rngs::StdRng as rand::SeedableRng>::seed_from_u64(seed);
+    let result = example_lib::random_number_with(&mut rng);
     assert!(result < 100);
     r.regtest(result);
 }
@@ -28,7 +28,128 @@
 //! via the `RegTest` argument.
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{ItemFn, parse_macro_input};
+use syn::spanned::Spanned;
+use syn::{ItemFn, LitStr, parse_macro_input};
+
+/// Arguments of the `regtest` attribute.
+#[derive(Default)]
+struct Args {
+    /// Fixed variant of the test.
+    variant: Option<LitStr>,
+    /// Cargo features whose enabled subset is part of the variant.
+    features: Vec<LitStr>,
+    /// Why the test is expected not to match its baseline.
+    xfail: Option<LitStr>,
+    /// Environment variable which must be set for the test to run.
+    requires_env: Option<LitStr>,
+    /// Tags of the test.
+    tags: Vec<LitStr>,
+    /// Where the data file is placed, overriding the configuration.
+    placement: Option<syn::Ident>,
+    /// Most entries the test may record when writing its baseline.
+    max_entries: Option<syn::LitInt>,
+}
+
+impl Args {
+    fn parse(attr: TokenStream) -> syn::Result<Self> {
+        let mut args = Args::default();
+        let parser = syn::meta::parser(|meta| {
+            if meta.path.is_ident("variant") {
+                let variant: LitStr = meta.value()?.parse()?;
+                check_variant(&variant)?;
+                args.variant = Some(variant);
+                Ok(())
+            } else if meta.path.is_ident("features") {
+                let list: syn::ExprArray = meta.value()?.parse()?;
+                for element in list.elems {
+                    match element {
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(feature),
+                            ..
+                        }) => {
+                            check_variant(&feature)?;
+                            args.features.push(feature);
+                        }
+                        other => {
+                            return Err(syn::Error::new_spanned(
+                                other,
+                                "Expected the name of a feature, as a string literal.",
+                            ));
+                        }
+                    }
+                }
+                Ok(())
+            } else if meta.path.is_ident("xfail") {
+                args.xfail = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("placement") {
+                let name: LitStr = meta.value()?.parse()?;
+                let placement = regression_test::config::Placement::from_name(&name.value())
+                    .ok_or_else(|| {
+                        syn::Error::new_spanned(
+                            &name,
+                            "Unknown placement, expected `mirrored`, `central` or `next_to_source`.",
+                        )
+                    })?;
+                args.placement = Some(syn::Ident::new(placement.variant_name(), name.span()));
+                Ok(())
+            } else if meta.path.is_ident("max_entries") {
+                let max: syn::LitInt = meta.value()?.parse()?;
+                max.base10_parse::<usize>()?;
+                args.max_entries = Some(max);
+                Ok(())
+            } else if meta.path.is_ident("requires_env") {
+                args.requires_env = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("tags") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                let tags = content
+                    .parse_terminated(<LitStr as syn::parse::Parse>::parse, syn::Token![,])?;
+                for tag in tags {
+                    check_tag(&tag)?;
+                    args.tags.push(tag);
+                }
+                Ok(())
+            } else {
+                Err(meta.error(
+                    "Unknown regtest argument, expected `variant`, `features`, `xfail`, `requires_env`, `tags`, `placement` or `max_entries`.",
+                ))
+            }
+        });
+        syn::parse::Parser::parse(parser, attr)?;
+        Ok(args)
+    }
+}
+
+/// Checks that `tag` can be listed in `REGTEST_UPDATE_TAGS`.
+fn check_tag(tag: &LitStr) -> syn::Result<()> {
+    let value = tag.value();
+    if value.is_empty() || value.contains(|c: char| c == ',' || c.is_whitespace()) {
+        return Err(syn::Error::new_spanned(
+            tag,
+            "Tags may not be empty, nor contain ',' or whitespace.",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `variant` can be part of a file name, see
+/// `regression_test::variant_path`.
+fn check_variant(variant: &LitStr) -> syn::Result<()> {
+    let value = variant.value();
+    if value.is_empty()
+        || !value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+'))
+    {
+        return Err(syn::Error::new_spanned(
+            variant,
+            "Variants may only contain ASCII letters, digits, '-', '_' and '+'.",
+        ));
+    }
+    Ok(())
+}
 
 /// Attribute macro for regression tests.
 ///
@@ -54,8 +175,88 @@ use syn::{ItemFn, parse_macro_input};
 ///
 /// The macro will inject code to determine the appropriate file path for the regression data,
 /// create the file if necessary, and pass a `RegTest` instance to the test function.
+///
+/// # Variants
+/// Tests whose output depends on the configuration can keep one baseline per
+/// variant (see `regression_test::variant_path`), either fixed or derived
+/// from the Cargo features of the crate being tested which are enabled:
+///
+/// ```rust,ignore
+/// #[regtest(variant = "with-simd")]
+/// fn fixed(rt: RegTest) { /* ... */ }
+///
+/// // `features_only@simd+avx2.json` with both features, `features_only.json`
+/// // with none of them
+/// #[regtest(features = ["simd", "avx2"])]
+/// fn features_only(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// With both, the variant comes first, followed by the enabled features.
+///
+/// # Expected failures
+/// Tests known not to match their baseline, e.g. while work changing their
+/// behavior lands before the baselines are regenerated, can be marked with
+/// the reason (see `RegTest::expect_failure`): a mismatch is then reported
+/// as an expected failure, and a test matching its baseline fails.
+///
+/// ```rust,ignore
+/// #[regtest(xfail = "tracking-issue-123")]
+/// fn changing(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// # Tags
+/// Tests can be tagged, so that only the baselines of the tests with a given
+/// tag are regenerated, with `REGTEST_UPDATE_TAGS=parser` (see
+/// `RegTest::with_tags`):
+///
+/// ```rust,ignore
+/// #[regtest(tags("parser", "slow"))]
+/// fn tagged(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// # Placement
+/// A test can place its data file differently from the configuration of the
+/// crate (see `regression_test::config::Placement`), e.g. next to its source
+/// file:
+///
+/// ```rust,ignore
+/// #[regtest(placement = "next_to_source")]
+/// fn beside(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// # Entry limit
+/// A test can limit the number of entries it records when writing its
+/// baseline (see `RegTest::limit_entries`), so that recording an entry
+/// inside a loop by accident fails the test before a bloated baseline is
+/// written:
+///
+/// ```rust,ignore
+/// #[regtest(max_entries = 50)]
+/// fn bounded(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// # Opt-in tests
+/// Slow tests can be skipped unless an environment variable is set (to
+/// anything but an empty string), e.g. on nightly CI only. Skipped tests
+/// return before creating their `RegTest`, so they pass without touching
+/// their baseline:
+///
+/// ```rust,ignore
+/// #[regtest(requires_env = "RUN_REGTESTS")]
+/// fn slow(rt: RegTest) { /* ... */ }
+/// ```
+///
+/// # Custom harness
+/// With the `harness` feature of `regression-test`, the functions (except
+/// `async` ones) also register themselves with `regression_test::harness`,
+/// which runs them in test targets with `harness = false`, honoring
+/// `#[ignore]`.
 #[proc_macro_attribute]
-pub fn regtest(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn regtest(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Args::parse(attr) {
+        Ok(args) => args,
+        Err(e) => return e.to_compile_error().into(),
+    };
     let input_fn = parse_macro_input!(item as ItemFn);
     let fn_name = &input_fn.sig.ident;
     let fn_attrs = &input_fn.attrs;
@@ -103,7 +304,7 @@ pub fn regtest(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 &pat_type.ty,
                 format!(
                     "Expected the first argument to be of type RegTest, but found a different type: {}.",
-                    quote!(#pat_type.ty).to_string()
+                    quote!(#pat_type.ty)
                 )
             ).to_compile_error().into();
         }
@@ -112,106 +313,299 @@ pub fn regtest(_attr: TokenStream, item: TokenStream) -> TokenStream {
             first_arg,
             format!(
                 "Expected the first argument to be a typed argument (e.g., arg: RegTest), but found: `{}`.",
-                quote!(#first_arg).to_string()
+                quote!(#first_arg)
             )
         ).to_compile_error().into();
     };
 
+    // The variant is only known at run time, once features are resolved
+    let variant_parts = args
+        .variant
+        .iter()
+        .map(|variant| quote!(::core::option::Option::Some(#variant)))
+        .chain(
+            args.features
+                .iter()
+                .map(|feature| quote!(cfg!(feature = #feature).then_some(#feature))),
+        )
+        .collect::<Vec<_>>();
+    let variant_quote = if variant_parts.is_empty() {
+        quote! {
+            let __regtest_variant = ::std::string::String::new();
+        }
+    } else {
+        quote! {
+            let __regtest_variant: ::std::vec::Vec<&str> =
+                [#(#variant_parts),*].into_iter().flatten().collect();
+            let __regtest_variant = __regtest_variant.join("+");
+        }
+    };
+
+    let tags = &args.tags;
+    let skip_quote = args.requires_env.map(|var| {
+        quote! {
+            if ::std::env::var_os(#var).is_none_or(|value| value.is_empty()) {
+                eprintln!(
+                    "note: skipping regression test `{}`: set {} to run it",
+                    stringify!(#fn_name),
+                    #var
+                );
+                return;
+            }
+        }
+    });
+
+    let xfail_quote = args.xfail.map(|reason| {
+        quote! {
+            let mut __regtest = __regtest;
+            __regtest.expect_failure(#reason);
+        }
+    });
+    let max_entries_quote = args.max_entries.map(|max| {
+        quote! {
+            let mut __regtest = __regtest;
+            __regtest.limit_entries(#max);
+        }
+    });
+
     // Try to get the local file path, but handle rust-analyzer bug where local_file() returns None
     let file_path_opt = proc_macro::Span::call_site().local_file();
 
-    let regtest_path_quote = if let Some(full_file_path_buf) = file_path_opt {
-        let full_file_path_buf = full_file_path_buf
-            .canonicalize()
-            .expect("Failed to canonicalize the file path");
+    let regtest_init_quote = if let Some(full_file_path_buf) = file_path_opt {
+        // Symbolic links are kept, so that the data stays in the tree the
+        // sources were given in
+        let full_file_path_buf = regression_test::logical_path(full_file_path_buf)
+            .expect("Failed to make the file path absolute");
 
         let full_file_path = full_file_path_buf
             .to_str()
             .expect("Failed to convert the file path to a string")
             .to_string();
 
-        // Path computation quote
-        quote! {
-            // Determine the file path for the regression test data
-            let __regtest_file_path = {
-                use std::path::{Path, PathBuf};
-
-                let file = #full_file_path;
-                let test_name = stringify!(#fn_name);
-                let path = Path::new(file);
-
-                // Helper to get the relative path after "src" or "tests"
-                fn relative_mod_path(path: &std::path::Path) -> std::path::PathBuf {
-                    let mut components = path.components().peekable();
-                    let mut found = false;
-                    let mut rel = PathBuf::new();
-                    while let Some(comp) = components.next() {
-                        if found {
-                            rel.push(comp.as_os_str());
-                        }
-                        if comp.as_os_str() == "src" || comp.as_os_str() == "tests" {
-                            found = true;
-                        }
-                    }
-                    rel
-                }
-
-                let mut base = {
-                    // Check if this is an integration test (in "tests" folder)
-                    if path.components().any(|c| c.as_os_str() == "tests") {
-                        // Place the file next to the test file, preserving subfolders after "tests"
-                        let ancestor = path.ancestors().find(|a| a.ends_with("tests")).unwrap_or_else(|| Path::new(""));
-                        let rel = relative_mod_path(path);
-                        let mut p = ancestor.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
-                        p.push("regtest_data");
-                        p.push("tests");
-                        if let Some(parent) = rel.parent() {
-                            p.push(parent);
-                        }
-                        p
-                    } else {
-                        // Place the file in "unit_tests" at the same level as "src"
-                        let ancestor = path.ancestors().find(|a| a.ends_with("src")).unwrap_or_else(|| Path::new(""));
-                        let rel = relative_mod_path(path);
-                        let mut p = ancestor.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
-                        p.push("regtest_data");
-                        p.push("src");
-                        if let Some(parent) = rel.parent() {
-                            p.push(parent);
-                        }
-                        p
-                    }
-                };
+        // Lets the tests find their data when run from elsewhere, e.g. from a
+        // nextest archive
+        let relative_file_path = std::env::var_os("CARGO_MANIFEST_DIR")
+            .and_then(|dir| regression_test::logical_path(dir).ok())
+            .and_then(|dir| {
+                let relative = full_file_path_buf.strip_prefix(dir).ok()?;
+                Some(relative.to_str()?.to_string())
+            })
+            .unwrap_or_default();
 
-                // Add the file stem as a directory
-                if let Some(file_stem) = path.file_stem() {
-                    base.push(file_stem);
-                }
-
-                // Create the directory if it doesn't exist
-                std::fs::create_dir_all(&base).ok();
+        let source_file_quote =
+            quote!(::regression_test::__source_file(#full_file_path, #relative_file_path));
+        let path_quote = match &args.placement {
+            Some(placement) => quote! {{
+                let mut __regtest_config = ::regression_test::Config::current().clone();
+                __regtest_config.placement = ::regression_test::config::Placement::#placement;
+                __regtest_config.snapshot_path(#source_file_quote, stringify!(#fn_name))
+            }},
+            None => quote! {
+                ::regression_test::snapshot_path(#source_file_quote, stringify!(#fn_name))
+            },
+        };
 
-                // Add the test name as the file
-                base.push(format!("{}.json", test_name));
-                base
-            };
+        quote! {
+            // Determine the file path for the regression test data
+            let mut __regtest_file_path = #path_quote;
+            #variant_quote
+            if !__regtest_variant.is_empty() {
+                __regtest_file_path =
+                    ::regression_test::variant_path(__regtest_file_path, &__regtest_variant);
+            }
+            let __regtest = RegTest::with_tags(__regtest_file_path, &[#(#tags),*])
+                .expect("Failed to create or open regression test file");
+            #xfail_quote
+            #max_entries_quote
+            let #arg_pat = __regtest;
         }
     } else {
-        // rust-analyzer fallback
+        // rust-analyzer fallback: the data is kept in memory, so that
+        // evaluating the test never creates files
         quote! {
-            let __regtest_file_path = "./rust-analyzer-dummy.json".to_string();
+            #variant_quote
+            let mut __regtest_name =
+                ::std::string::String::from(concat!(module_path!(), "::", stringify!(#fn_name)));
+            if !__regtest_variant.is_empty() {
+                __regtest_name = format!("{}@{}", __regtest_name, __regtest_variant);
+            }
+            let __regtest = RegTest::with_backend(
+                __regtest_name,
+                ::regression_test::Config::current().clone(),
+                ::regression_test::Backend::InMemory,
+            )
+            .expect("Failed to create in-memory regression test");
+            #xfail_quote
+            #max_entries_quote
+            let #arg_pat = __regtest;
         }
     };
 
+    // The harness runs a copy of the test, since `#[test]` functions only
+    // exist in targets using libtest
+    let ignored = fn_attrs.iter().any(|attr| attr.path().is_ident("ignore"));
+    let register_quote = fn_async.is_none().then(|| {
+        quote! {
+            ::regression_test::__register_regtest! {
+                concat!(module_path!(), "::", stringify!(#fn_name)),
+                &[#(#tags),*],
+                #ignored,
+                {
+                    #skip_quote
+                    #regtest_init_quote
+                    #fn_block
+                }
+            }
+        }
+    });
+
     let fn_quote = quote! {
         #[test]
         #(#fn_attrs)*
         #fn_vis #fn_async fn #fn_name() {
-            #regtest_path_quote
-            let #arg_pat = RegTest::new(__regtest_file_path).expect("Failed to create or open regression test file");
+            #skip_quote
+            #regtest_init_quote
             #fn_block
         }
+
+        #register_quote
     };
 
     TokenStream::from(fn_quote)
 }
+
+/// How a field is shown by `#[derive(RegSnapshot)]`.
+enum FieldMode {
+    Debug,
+    Skip,
+    Redact(LitStr),
+    Nested,
+}
+
+impl FieldMode {
+    fn parse(field: &syn::Field) -> syn::Result<Self> {
+        let mut mode = FieldMode::Debug;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("regsnapshot"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if !matches!(mode, FieldMode::Debug) {
+                    return Err(meta.error("Only one of `skip`, `redact` and `nested` is allowed."));
+                }
+                if meta.path.is_ident("skip") {
+                    mode = FieldMode::Skip;
+                } else if meta.path.is_ident("nested") {
+                    mode = FieldMode::Nested;
+                } else if meta.path.is_ident("redact") {
+                    mode = FieldMode::Redact(if meta.input.peek(syn::Token![=]) {
+                        meta.value()?.parse()?
+                    } else {
+                        LitStr::new("[redacted]", meta.path.span())
+                    });
+                } else {
+                    return Err(meta.error(
+                        "Unknown regsnapshot argument, expected `skip`, `redact` or `nested`.",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(mode)
+    }
+}
+
+/// Derive macro for `regression_test::RegSnapshot`, for structs.
+///
+/// The fields are listed in declaration order, one per line, with their
+/// `Debug` output, unless annotated with `#[regsnapshot(...)]`:
+///
+/// - `skip`: the field is left out, e.g. for caches,
+/// - `redact`: the field is shown as `[redacted]`, or as the given text with
+///   `redact = "[timestamp]"`, e.g. for identifiers which change between runs,
+/// - `nested`: the field is shown with its own `RegSnapshot` representation.
+///
+/// ```rust
+/// use regression_test::RegSnapshot;
+/// use regression_test_macros::RegSnapshot;
+///
+/// #[derive(RegSnapshot)]
+/// struct User {
+///     name: String,
+///     #[regsnapshot(redact)]
+///     id: u64,
+///     #[regsnapshot(skip)]
+///     cache: Vec<u8>,
+/// }
+///
+/// let user = User { name: "ada".to_string(), id: 7, cache: Vec::new() };
+/// assert_eq!(user.reg_snapshot(), "User {\n    name: \"ada\"\n    id: [redacted]\n}");
+/// ```
+#[proc_macro_derive(RegSnapshot, attributes(regsnapshot))]
+pub fn derive_reg_snapshot(item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::DeriveInput);
+    let fields = match &input.data {
+        syn::Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "RegSnapshot can only be derived for structs.",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let mut pushes = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let mode = match FieldMode::parse(field) {
+            Ok(mode) => mode,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let (member, name) = match &field.ident {
+            Some(ident) => (quote!(#ident), ident.to_string()),
+            None => {
+                let index = syn::Index::from(i);
+                (quote!(#index), i.to_string())
+            }
+        };
+        let value = match mode {
+            FieldMode::Skip => continue,
+            FieldMode::Debug => quote!(&format!("{:?}", &self.#member)),
+            // the field is still read, so that it isn't reported as dead code
+            FieldMode::Redact(text) => quote!({
+                let _ = &self.#member;
+                #text
+            }),
+            FieldMode::Nested => {
+                quote!(&::regression_test::RegSnapshot::reg_snapshot(&self.#member))
+            }
+        };
+        pushes.push(quote! {
+            ::regression_test::__push_field(&mut __regsnapshot_out, #name, #value);
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let body = if matches!(fields, syn::Fields::Unit) {
+        quote!(::std::string::String::from(stringify!(#name)))
+    } else {
+        quote! {
+            let mut __regsnapshot_out =
+                ::std::string::String::from(concat!(stringify!(#name), " {\n"));
+            #(#pushes)*
+            __regsnapshot_out.push('}');
+            __regsnapshot_out
+        }
+    };
+
+    TokenStream::from(quote! {
+        impl #impl_generics ::regression_test::RegSnapshot for #name #ty_generics #where_clause {
+            fn reg_snapshot(&self) -> ::std::string::String {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,168 @@
+//! Allocation tracking for
+//! [`RegTest::regtest_alloc`](crate::RegTest::regtest_alloc).
+//!
+//! Allocations are only seen when [`TrackingAllocator`] is installed as the
+//! global allocator of the test binary, e.g. in `tests/alloc.rs`:
+//!
+//! ```rust
+//! use regression_test::alloc::TrackingAllocator;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: TrackingAllocator = TrackingAllocator::system();
+//! ```
+//!
+//! Allocations are counted per thread, so that tests running in parallel
+//! don't see each other's.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// A global allocator forwarding to `A` while counting the allocations of
+/// each thread.
+#[derive(Debug, Default)]
+pub struct TrackingAllocator<A = System> {
+    inner: A,
+}
+
+impl TrackingAllocator<System> {
+    /// Tracks the allocations of the system allocator.
+    pub const fn system() -> Self {
+        TrackingAllocator { inner: System }
+    }
+}
+
+impl<A> TrackingAllocator<A> {
+    /// Tracks the allocations of `inner`.
+    pub const fn new(inner: A) -> Self {
+        TrackingAllocator { inner }
+    }
+}
+
+/// Whether a [TrackingAllocator] has allocated anything, i.e. is installed.
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+/// Allocations of the current thread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Stats {
+    /// Bytes currently allocated, less those freed (which may have been
+    /// allocated by other threads).
+    current: i64,
+    /// The highest value of `current`.
+    peak: i64,
+    /// Bytes allocated.
+    pub(crate) total: u64,
+    /// Number of allocations.
+    pub(crate) count: u64,
+}
+
+impl Stats {
+    /// The peak of the bytes allocated at once.
+    pub(crate) fn peak(&self) -> u64 {
+        self.peak.max(0) as u64
+    }
+}
+
+thread_local! {
+    static STATS: Cell<Stats> = const {
+        Cell::new(Stats { current: 0, peak: 0, total: 0, count: 0 })
+    };
+}
+
+fn record(allocated: usize, freed: usize) {
+    // Threads being torn down no longer have their counters
+    let _ = STATS.try_with(|stats| {
+        let mut s = stats.get();
+        s.current = s.current + allocated as i64 - freed as i64;
+        s.peak = s.peak.max(s.current);
+        if allocated > 0 {
+            s.total += allocated as u64;
+            s.count += 1;
+        }
+        stats.set(s);
+    });
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for TrackingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        INSTALLED.store(true, Ordering::Relaxed);
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record(layout.size(), 0);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { self.inner.dealloc(ptr, layout) };
+        record(0, layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            record(new_size, layout.size());
+        }
+        new
+    }
+}
+
+/// What `f` allocated on the current thread: the peak of the bytes allocated
+/// at once, above those already allocated when it started, and the totals.
+///
+/// # Panics
+/// Panics if no [TrackingAllocator] is installed.
+pub(crate) fn measure<R>(f: impl FnOnce() -> R) -> (R, Stats) {
+    let start = STATS.with(|stats| {
+        let mut s = stats.get();
+        s.peak = s.current;
+        stats.set(s);
+        s
+    });
+    let result = f();
+    let end = STATS.with(Cell::get);
+    if !INSTALLED.load(Ordering::Relaxed) {
+        panic!(
+            "regtest_alloc requires regression_test::alloc::TrackingAllocator to be the global allocator"
+        );
+    }
+    let stats = Stats {
+        current: end.current - start.current,
+        peak: end.peak - start.current,
+        total: end.total - start.total,
+        count: end.count - start.count,
+    };
+    (result, stats)
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator::system();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_measured() {
+        let (_, stats) = measure(|| {
+            let a = vec![0u8; 1000];
+            drop(a);
+            let mut b = Vec::<u8>::with_capacity(500);
+            b.reserve_exact(1500);
+            b
+        });
+        assert_eq!(stats.peak(), 1500);
+        assert_eq!(stats.total, 3000);
+        assert_eq!(stats.count, 3);
+    }
+}
@@ -0,0 +1,112 @@
+//! Anonymization of the absolute paths of the machine running the tests, see
+//! [`Config::anonymize_paths`](crate::Config::anonymize_paths).
+
+use regex::Regex;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Stands for the root of the Cargo workspace in anonymized output.
+const WORKSPACE: &str = "<WORKSPACE>";
+/// Stands for the home directory of the user in anonymized output.
+const HOME: &str = "<HOME>";
+
+/// The machine-specific prefixes, and what replaces them, in order.
+static PREFIXES: OnceLock<Vec<(Regex, &'static str)>> = OnceLock::new();
+
+/// `message` with the workspace and home directories replaced.
+pub(crate) fn anonymize(message: &str) -> Cow<'_, str> {
+    let prefixes = PREFIXES.get_or_init(|| prefixes(workspace_root(), home_dir()));
+    replace(message, prefixes)
+}
+
+fn replace<'a>(message: &'a str, prefixes: &[(Regex, &'static str)]) -> Cow<'a, str> {
+    let mut message = Cow::Borrowed(message);
+    for (pattern, replacement) in prefixes {
+        if let Cow::Owned(replaced) = pattern.replace_all(&message, *replacement) {
+            message = Cow::Owned(replaced);
+        }
+    }
+    message
+}
+
+/// Patterns matching the spellings of `workspace` and `home`: as is, with
+/// the backslashes of Windows paths escaped (as `Debug` prints them) or
+/// turned into slashes, and resolved if they go through symbolic links. The
+/// workspace comes first, as it is usually inside the home directory.
+fn prefixes(workspace: Option<PathBuf>, home: Option<PathBuf>) -> Vec<(Regex, &'static str)> {
+    [(workspace, WORKSPACE), (home, HOME)]
+        .into_iter()
+        .filter_map(|(dir, replacement)| {
+            let dir = dir.filter(|dir| dir.parent().is_some())?;
+            let mut spellings = Vec::new();
+            for dir in [Some(dir.clone()), std::fs::canonicalize(&dir).ok()]
+                .into_iter()
+                .flatten()
+            {
+                let dir = crate::normalize_path(&dir).to_string_lossy().into_owned();
+                let dir = dir.trim_end_matches(['/', '\\']).to_string();
+                spellings.push(dir.replace('\\', "\\\\"));
+                spellings.push(dir.replace('\\', "/"));
+                spellings.push(dir);
+            }
+            // Longest first, so that alternatives don't cut each other short
+            spellings.sort_by_key(|spelling| std::cmp::Reverse(spelling.len()));
+            spellings.dedup();
+            let alternatives: Vec<String> = spellings.iter().map(|s| regex::escape(s)).collect();
+            // Not a prefix of a longer name, e.g. `/home/al` of `/home/alice`
+            let pattern = format!(r"(?:{})(?:\b|$)", alternatives.join("|"));
+            Some((Regex::new(&pattern).ok()?, replacement))
+        })
+        .collect()
+}
+
+/// The root of the Cargo workspace of the crate under test: the nearest
+/// ancestor of its manifest directory whose `Cargo.toml` declares a
+/// workspace, or else the manifest directory.
+fn workspace_root() -> Option<PathBuf> {
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR")?);
+    let declares_workspace = |dir: &Path| {
+        std::fs::read_to_string(dir.join("Cargo.toml"))
+            .is_ok_and(|manifest| manifest.lines().any(|line| line.trim() == "[workspace]"))
+    };
+    let root = manifest_dir
+        .ancestors()
+        .find(|dir| declares_workspace(dir))
+        .unwrap_or(&manifest_dir);
+    Some(root.to_path_buf())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var)
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_workspace_and_home_prefixes() {
+        let unix = prefixes(Some("/home/me/work/proj/".into()), Some("/home/me".into()));
+        let anonymize = |message| replace(message, &unix).into_owned();
+        assert_eq!(
+            anonymize("panicked at /home/me/work/proj/src/lib.rs:3"),
+            "panicked at <WORKSPACE>/src/lib.rs:3"
+        );
+        assert_eq!(
+            anonymize("/home/me/.cargo/registry and /home/me"),
+            "<HOME>/.cargo/registry and <HOME>"
+        );
+        assert_eq!(anonymize("/home/me2/x"), "/home/me2/x");
+
+        let windows = prefixes(Some(r"C:\work\proj".into()), None);
+        let anonymize = |message| replace(message, &windows).into_owned();
+        assert_eq!(
+            anonymize(r#"path: "C:\\work\\proj\\src", C:/work/proj/src"#),
+            r#"path: "<WORKSPACE>\\src", <WORKSPACE>/src"#
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Canonical `Debug` output, see [`RegTest::regtest_sorted_dbg`].
+//!
+//! [`RegTest::regtest_sorted_dbg`]: crate::RegTest::regtest_sorted_dbg
+
+/// Sorts the items of the maps and sets in `debug`, the compact (`{:?}`)
+/// `Debug` output of a value, by their text.
+///
+/// Maps and sets are the braces which don't follow a name: `{1, 2}` and
+/// `{"a": 1}` are sorted, while the fields of `Point { x: 1, y: 2 }` keep
+/// their order.
+pub(crate) fn sort_debug(debug: &str) -> String {
+    let chars: Vec<char> = debug.chars().collect();
+    let mut pos = 0;
+    let mut items = Vec::new();
+    // Text past an unbalanced closing bracket is kept as is
+    loop {
+        items.push(group(&chars, &mut pos, None).join(","));
+        match chars.get(pos) {
+            Some(&c) => {
+                items.push(c.to_string());
+                pos += 1;
+            }
+            None => break,
+        }
+    }
+    items.concat()
+}
+
+/// Reads the comma-separated items up to `close` (excluded), canonicalizing
+/// the nested groups.
+fn group(chars: &[char], pos: &mut usize, close: Option<char>) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+
+    while let Some(&c) = chars.get(*pos) {
+        match c {
+            _ if Some(c) == close => break,
+            ')' | ']' | '}' => break,
+            ',' => {
+                items.push(std::mem::take(&mut item));
+                *pos += 1;
+            }
+            '"' | '\'' => literal(chars, pos, &mut item),
+            '(' | '[' | '{' => {
+                let closing = match c {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                let sorted = c == '{'
+                    && !item
+                        .trim_end()
+                        .ends_with(|c: char| c.is_alphanumeric() || c == '_');
+                *pos += 1;
+                let mut inner = group(chars, pos, Some(closing));
+                if sorted {
+                    let mut trimmed: Vec<&str> = inner.iter().map(|i| i.trim()).collect();
+                    trimmed.sort_unstable();
+                    inner = vec![trimmed.join(", ")];
+                }
+                item.push(c);
+                item.push_str(&inner.join(","));
+                if chars.get(*pos) == Some(&closing) {
+                    item.push(closing);
+                    *pos += 1;
+                }
+            }
+            _ => {
+                item.push(c);
+                *pos += 1;
+            }
+        }
+    }
+
+    items.push(item);
+    items
+}
+
+/// Copies the string or char literal at `pos` to `item`.
+fn literal(chars: &[char], pos: &mut usize, item: &mut String) {
+    let quote = chars[*pos];
+    item.push(quote);
+    *pos += 1;
+    while let Some(&c) = chars.get(*pos) {
+        item.push(c);
+        *pos += 1;
+        if c == '\\' {
+            if let Some(&escaped) = chars.get(*pos) {
+                item.push(escaped);
+                *pos += 1;
+            }
+        } else if c == quote {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_maps_and_sets_only() {
+        assert_eq!(sort_debug("{3, 1, 2}"), "{1, 2, 3}");
+        assert_eq!(sort_debug(r#"{"b": 2, "a": 1}"#), r#"{"a": 1, "b": 2}"#);
+        assert_eq!(sort_debug("{}"), "{}");
+        assert_eq!(
+            sort_debug("Point { y: 2, x: {2, 1} }"),
+            "Point { y: 2, x: {1, 2} }"
+        );
+        assert_eq!(
+            sort_debug(r#"[Some({"z, }": 'x', "a{": '}'})]"#),
+            r#"[Some({"a{": '}', "z, }": 'x'})]"#
+        );
+        assert_eq!(sort_debug(r#"{"\"b": 1, "a": 2}"#), r#"{"\"b": 1, "a": 2}"#);
+        assert_eq!(sort_debug("a) {2, 1}"), "a) {1, 2}");
+    }
+}
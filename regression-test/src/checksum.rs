@@ -0,0 +1,134 @@
+//! Checksums of data files, telling baselines written by the tests from
+//! hand-edited ones. See [`Config::checksums`](crate::Config::checksums).
+
+use crate::{DataFile, RegEntry};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// The checksum of `entries`, as stored in the header of a data file: the
+/// SHA-256 digest of the entries in compact JSON, with the messages stored
+/// apart from the file (see [Layout::Directory](crate::config::Layout::Directory))
+/// in place.
+pub(crate) fn of(entries: &[RegEntry]) -> String {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        let entry = RegEntry {
+            file: None,
+            blob: None,
+            ..entry.clone()
+        };
+        serde_json::to_writer(&mut hasher, &entry).expect("entries serialize to JSON");
+    }
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256:{}", digest)
+}
+
+/// Checks that the entries of `data`, read from `path`, match its checksum.
+/// Files without a checksum only fail if it is `required`.
+pub(crate) fn verify(path: &Path, data: &DataFile, required: bool) -> std::io::Result<()> {
+    if data.header.checksum.is_none() && !required {
+        return Ok(());
+    }
+    let actual = of(&data.entries);
+    if data.header.checksum.as_ref() == Some(&actual) {
+        return Ok(());
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        TamperedSnapshot {
+            path: path.to_path_buf(),
+            expected: data.header.checksum.clone(),
+            actual,
+        },
+    ))
+}
+
+/// The error of reading a data file whose entries don't match the checksum
+/// in its header, i.e. which was edited by hand (or by another tool) since a
+/// test wrote it.
+///
+/// Returned inside an [`std::io::Error`] of kind
+/// [`InvalidData`](std::io::ErrorKind::InvalidData), like
+/// [CorruptSnapshot](crate::CorruptSnapshot). With
+/// [`Config::checksums`](crate::Config::checksums), data files without a
+/// checksum are rejected the same way.
+#[derive(Debug, Clone)]
+pub struct TamperedSnapshot {
+    /// The data file.
+    pub path: PathBuf,
+    /// The checksum in the header of the file, if any.
+    pub expected: Option<String>,
+    /// The checksum of the entries of the file.
+    pub actual: String,
+}
+
+impl std::fmt::Display for TamperedSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.expected {
+            Some(expected) => write!(
+                f,
+                "{} was edited by hand: its entries have the checksum {}, not {} as when it \
+                 was generated. Revert the edit, or regenerate the file by running the tests \
+                 with REGTEST_UPDATE=always.",
+                self.path.display(),
+                self.actual,
+                expected
+            ),
+            None => write!(
+                f,
+                "{} has no checksum, which `checksums = true` requires, so it may have been \
+                 written by hand. Regenerate it by running the tests with REGTEST_UPDATE=always.",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TamperedSnapshot {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegType;
+
+    #[test]
+    fn detects_edited_entries() {
+        let path = Path::new("regtest_data/t.json");
+        let mut data = DataFile::new(
+            vec![RegEntry {
+                reg_type: RegType::Display,
+                message: "generated".to_string(),
+                section: None,
+                key: None,
+                location: None,
+                note: None,
+                value_type: None,
+                file: None,
+                blob: None,
+            }],
+            None,
+            Vec::new(),
+        );
+        assert!(verify(path, &data, false).is_ok());
+        assert!(verify(path, &data, true).is_err());
+
+        data.seal();
+        // Messages stored apart don't change the checksum
+        data.entries[0].file = Some("0000.txt".to_string());
+        let read = DataFile::read(data.encode(path).unwrap().as_slice(), path).unwrap();
+        assert!(verify(path, &read, true).is_ok());
+
+        data.entries[0].message = "edited".to_string();
+        let error = verify(path, &data, false).unwrap_err();
+        let tampered = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<TamperedSnapshot>())
+            .unwrap();
+        assert_eq!(tampered.expected, read.header.checksum);
+        assert_ne!(Some(&tampered.actual), tampered.expected.as_ref());
+    }
+}
@@ -0,0 +1,60 @@
+//! A clock for code whose output depends on the current time, see
+//! [`RegTest::clock`](crate::RegTest::clock).
+//!
+//! ```rust
+//! use regression_test::RegTest;
+//! use regression_test::clock::Clock;
+//! use std::time::Duration;
+//!
+//! fn greeting(clock: &Clock) -> String {
+//!     format!("Generated at {}", clock.rfc3339())
+//! }
+//!
+//! # let path = std::env::temp_dir().join("regtest-clock-doc.json");
+//! let mut rt = RegTest::new(path).unwrap();
+//! let clock = rt.clock();
+//! rt.regtest(greeting(&clock));
+//! clock.advance(Duration::from_secs(60));
+//! rt.regtest(greeting(&clock));
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A clock which only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl Clock {
+    /// A clock frozen at `time`.
+    pub fn fixed(time: SystemTime) -> Self {
+        Clock {
+            now: Arc::new(Mutex::new(time)),
+        }
+    }
+
+    /// The time of the clock.
+    pub fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// The time of the clock, in whole seconds since the Unix epoch.
+    pub fn unix_seconds(&self) -> u64 {
+        self.now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+
+    /// The time of the clock in RFC 3339 format (UTC), to the second, e.g.
+    /// `2024-05-01T12:30:00Z`.
+    pub fn rfc3339(&self) -> String {
+        crate::format_timestamp(self.unix_seconds())
+    }
+}
@@ -0,0 +1,185 @@
+//! Comparators deciding whether recorded output is equivalent to the baseline
+//! even though the text differs.
+//!
+//! ```rust
+//! use regression_test::RegTest;
+//! use regression_test::compare::{Paths, Urls};
+//!
+//! # let path = std::env::temp_dir().join("regtest-compare-doc.json");
+//! let mut rt = RegTest::new(path).unwrap();
+//! rt.regtest_with("GET https://example.com/api?a=1&b=2", Urls);
+//! rt.regtest_with("wrote ./target//out/../report.txt", Paths);
+//! rt.regtest_with("exact", |expected: &str, actual: &str| {
+//!     expected.eq_ignore_ascii_case(actual)
+//! });
+//! ```
+//!
+//! The built-in comparators are token-aware: they find the tokens they know
+//! about (version numbers, URLs, paths), normalize them, and compare the
+//! remaining text exactly. The baseline always stores the output as recorded.
+
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+/// Decides whether an entry recorded in read mode matches the baseline.
+///
+/// Implemented for closures taking the expected and the actual text.
+pub trait Comparator {
+    /// Whether `actual` is equivalent to `expected`. Only called when the two
+    /// texts differ.
+    fn equivalent(&self, expected: &str, actual: &str) -> bool;
+}
+
+impl<F: Fn(&str, &str) -> bool> Comparator for F {
+    fn equivalent(&self, expected: &str, actual: &str) -> bool {
+        self(expected, actual)
+    }
+}
+
+/// Compares version numbers (`1.2.3`, `2.0.0-rc.1+build.5`) as units: numeric
+/// components ignore leading zeros, trailing `.0` components are optional and
+/// build metadata is ignored. So `1.2` matches `1.2.0` and `01.2.0+abc`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Versions;
+
+impl Comparator for Versions {
+    fn equivalent(&self, expected: &str, actual: &str) -> bool {
+        static VERSION: OnceLock<Regex> = OnceLock::new();
+        let regex = VERSION.get_or_init(|| {
+            Regex::new(r"\b(\d+(?:\.\d+)+)(-[0-9A-Za-z.-]+)?(?:\+[0-9A-Za-z.-]+)?\b").unwrap()
+        });
+
+        let normalize = |caps: &Captures| {
+            let mut components: Vec<String> = caps[1]
+                .split('.')
+                .map(|c| c.trim_start_matches('0'))
+                .map(|c| if c.is_empty() { "0" } else { c }.to_string())
+                .collect();
+            while components.len() > 1 && components.last().is_some_and(|c| c == "0") {
+                components.pop();
+            }
+            let pre_release = caps.get(2).map_or("", |m| m.as_str());
+            format!("{}{}", components.join("."), pre_release)
+        };
+
+        regex.replace_all(expected, normalize) == regex.replace_all(actual, normalize)
+    }
+}
+
+/// Compares URLs ignoring the order of query parameters and the case of the
+/// scheme and host.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Urls;
+
+impl Comparator for Urls {
+    fn equivalent(&self, expected: &str, actual: &str) -> bool {
+        static URL: OnceLock<Regex> = OnceLock::new();
+        let regex =
+            URL.get_or_init(|| Regex::new(r#"[A-Za-z][A-Za-z0-9+.-]*://[^\s"'<>]+"#).unwrap());
+
+        let normalize = |caps: &Captures| {
+            let url = &caps[0];
+            let (url, fragment) = match url.split_once('#') {
+                Some((url, fragment)) => (url, format!("#{}", fragment)),
+                None => (url, String::new()),
+            };
+            let (url, query) = match url.split_once('?') {
+                Some((url, query)) => {
+                    let mut params: Vec<_> = query.split('&').collect();
+                    params.sort_unstable();
+                    (url, format!("?{}", params.join("&")))
+                }
+                None => (url, String::new()),
+            };
+            // The scheme and authority end at the first `/` after `://`
+            let scheme_end = url.find("://").map_or(0, |i| i + 3);
+            let authority_end = url[scheme_end..]
+                .find('/')
+                .map_or(url.len(), |i| scheme_end + i);
+            format!(
+                "{}{}{}{}",
+                url[..authority_end].to_lowercase(),
+                &url[authority_end..],
+                query,
+                fragment
+            )
+        };
+
+        regex.replace_all(expected, normalize) == regex.replace_all(actual, normalize)
+    }
+}
+
+/// Compares file paths after lexical normalization: `\` and `/` are
+/// equivalent, repeated separators and `.` components are dropped, and `..`
+/// components are resolved.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Paths;
+
+impl Comparator for Paths {
+    fn equivalent(&self, expected: &str, actual: &str) -> bool {
+        static PATH: OnceLock<Regex> = OnceLock::new();
+        let regex = PATH
+            .get_or_init(|| Regex::new(r"(?:[A-Za-z]:)?[\\/]*(?:[\w.-]+[\\/]+)+[\w.-]*").unwrap());
+
+        let normalize = |caps: &Captures| {
+            let path = caps[0].replace('\\', "/");
+            let absolute = path.starts_with('/');
+
+            let mut components: Vec<&str> = Vec::new();
+            for component in path.split('/') {
+                match component {
+                    "" | "." => {}
+                    ".." if components.last().is_some_and(|c| *c != "..") => {
+                        components.pop();
+                    }
+                    _ => components.push(component),
+                }
+            }
+
+            let normalized = components.join("/");
+            if absolute {
+                format!("/{}", normalized)
+            } else {
+                normalized
+            }
+        };
+
+        regex.replace_all(expected, normalize) == regex.replace_all(actual, normalize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert!(Versions.equivalent(
+            "depends on 1.2 and 3.0.1",
+            "depends on 1.2.0 and 03.0.1+git"
+        ));
+        assert!(Versions.equivalent("2.0.0-rc.1", "2.0-rc.1"));
+        assert!(!Versions.equivalent("2.0.0-rc.1", "2.0.0-rc.2"));
+        assert!(!Versions.equivalent("1.2.0", "1.2.1"));
+        assert!(!Versions.equivalent("version 1.2", "release 1.2"));
+    }
+
+    #[test]
+    fn urls() {
+        assert!(Urls.equivalent(
+            "see HTTPS://Example.com/Path?b=2&a=1#top.",
+            "see https://example.com/Path?a=1&b=2#top."
+        ));
+        assert!(!Urls.equivalent("https://example.com/Path", "https://example.com/path"));
+        assert!(!Urls.equivalent("https://a.com/?a=1", "https://a.com/?a=2"));
+    }
+
+    #[test]
+    fn paths() {
+        assert!(Paths.equivalent("wrote out/report.txt", "wrote ./out//tmp/../report.txt"));
+        assert!(Paths.equivalent("C:/data/file", r"C:\data\file"));
+        assert!(Paths.equivalent("/usr/lib/", "/usr/./lib"));
+        assert!(!Paths.equivalent("/usr/lib", "usr/lib"));
+        assert!(!Paths.equivalent("wrote a/b", "read a/b"));
+    }
+}
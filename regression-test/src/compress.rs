@@ -0,0 +1,115 @@
+//! Transparent compression of the data files, see [`Compression`].
+//!
+//! The compression of a file being written is given by its extension, while
+//! files being read are recognized from their magic number.
+
+use crate::config::Compression;
+use std::io::{BufRead, Read};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Whether this build supports `compression`.
+pub(crate) fn supported(compression: Compression) -> bool {
+    match compression {
+        Compression::None => true,
+        Compression::Gzip => cfg!(feature = "gzip"),
+        Compression::Zstd => cfg!(feature = "zstd"),
+    }
+}
+
+/// The error of using `compression` without its feature.
+pub(crate) fn unsupported(compression: Compression) -> std::io::Error {
+    let name = match compression {
+        Compression::Gzip => "gzip",
+        _ => "zstd",
+    };
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "{} compression requires the `{}` feature of regression-test",
+            name, name
+        ),
+    )
+}
+
+/// The compression of the data file `path`, from its extension.
+pub(crate) fn of_path(path: &Path) -> Compression {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gz") => Compression::Gzip,
+        Some("zst") => Compression::Zstd,
+        _ => Compression::None,
+    }
+}
+
+/// Compresses `data` with `compression`.
+pub(crate) fn compress(data: Vec<u8>, compression: Compression) -> std::io::Result<Vec<u8>> {
+    if !supported(compression) {
+        return Err(unsupported(compression));
+    }
+    match compression {
+        Compression::None => Ok(data),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::encode_all(data.as_slice(), 0),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!("unsupported compression"),
+    }
+}
+
+/// The decompressed contents of `reader`, which may or may not be
+/// compressed.
+pub(crate) fn decoder<'a, R: BufRead + Send + 'a>(
+    mut reader: R,
+) -> std::io::Result<Box<dyn Read + Send + 'a>> {
+    let head = reader.fill_buf()?;
+    let compression = if head.starts_with(GZIP_MAGIC) {
+        Compression::Gzip
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    };
+
+    if !supported(compression) {
+        return Err(unsupported(compression));
+    }
+    match compression {
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)),
+        _ => Ok(Box::new(reader)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_supported_compressions() {
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = compress(b"[1, 2]".to_vec(), compression);
+            if !supported(compression) {
+                assert!(compressed.is_err());
+                continue;
+            }
+
+            let mut data = String::new();
+            decoder(compressed.unwrap().as_slice())
+                .unwrap()
+                .read_to_string(&mut data)
+                .unwrap();
+            assert_eq!(data, "[1, 2]");
+        }
+    }
+}
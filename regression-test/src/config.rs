@@ -0,0 +1,1269 @@
+//! Project-wide configuration, read from a `regtest.toml` file.
+//!
+//! The configuration file is looked up starting from the crate being tested
+//! (`CARGO_MANIFEST_DIR`, or the current directory when that is not set) and
+//! walking up the directory tree, so it can live either in the crate root or
+//! in the workspace root. The first file found wins.
+//!
+//! ```toml
+//! # Directory holding the regression data, relative to the crate root.
+//! root = "regtest_data"
+//! # The regression data is intentionally ignored by git (local-only baselines).
+//! # Otherwise, a warning is printed when git ignores it.
+//! local_only = false
+//! # Store the data of all crates in a single root next to this file instead,
+//! # with one subdirectory per crate.
+//! shared = false
+//! # Storage format of the regression data: "json", or "jsonl" for one entry
+//! # per line.
+//! format = "json"
+//! # Where data files are placed: "mirrored" (under `root`, in a tree mirroring
+//! # the sources), "central" (directly in `root`, named after the source file
+//! # and the test) or "next_to_source" (in `__snapshots__` directories next to
+//! # the source files).
+//! placement = "mirrored"
+//! # Where messages are stored: "file" (in the data file), or "directory" for one
+//! # file per entry in a `<test>.entries` directory next to the data file.
+//! layout = "file"
+//! # Compress the data files: "none", "gzip" or "zstd" (with the feature of the
+//! # same name). Compressed files are recognized when read, whatever this says.
+//! compression = "none"
+//! # Encrypt the data files with the key in `REGTEST_KEY` (with the
+//! # `encryption` feature). Encrypted files are recognized when read.
+//! encrypt = false
+//! # How mismatches are reported: "lines" or "none".
+//! diff = "lines"
+//! # Render file paths as clickable terminal links: "auto", "always" or "never".
+//! hyperlinks = "auto"
+//! # Print GitHub Actions annotations for mismatches: "auto", "always" or "never".
+//! annotations = "auto"
+//! # With `--nocapture`, print full diffs to stdout and keep panics compact.
+//! echo = true
+//! # Append a JSON line describing every mismatch to this file.
+//! report = "target/regtest-report.jsonl"
+//! # Write a JUnit XML summary of all regression tests to this file.
+//! junit = "target/regtest-junit.xml"
+//! # Write an HTML report with side-by-side diffs of all mismatches to this
+//! # directory.
+//! html_report = "target/regtest-report"
+//! # Write Prometheus metrics (entries compared, recorded, pending, ...) to this
+//! # file, e.g. for the node exporter's textfile collector.
+//! metrics = "target/regtest.prom"
+//! # Push the same metrics to a Prometheus Pushgateway.
+//! pushgateway = "http://localhost:9091"
+//! # When baselines are (re)generated: "missing", "always", "never",
+//! # "mismatched" (only replace the entries which don't match) or "repair"
+//! # (also regenerate corrupt data files).
+//! update = "missing"
+//! # Only regenerate the baselines of tests with one of these tags (see
+//! # `RegTest::with_tags`); other tests only generate missing ones. Usually set
+//! # with `REGTEST_UPDATE_TAGS=parser,lexer`.
+//! update_tags = ["parser"]
+//! # Compare against the data files committed at this git revision instead of
+//! # those in the working tree. Usually set with `REGTEST_BASELINE`.
+//! baseline = "origin/main"
+//! # Only warn about mismatches, which are still reported, instead of failing
+//! # tests. Usually set with `REGTEST_ADVISORY=1`.
+//! advisory = false
+//! # What to do when a test records nothing: "allow", "warn" or "fail".
+//! empty = "warn"
+//! # What to do when a test records more entries than its baseline holds: "fail"
+//! # or "warn", writing them to a `.pending.json` file for review, or "append"
+//! # them to the baseline.
+//! extra_entries = "fail"
+//! # When generating baselines, fail tests recording an entry, or a data file,
+//! # larger than this many bytes.
+//! max_entry_bytes = 1048576
+//! max_file_bytes = 10485760
+//! # Keep only the first and last this many bytes of longer messages, and the
+//! # SHA-256 digest of the rest, so that one runaway dump doesn't make a data
+//! # file unreviewable. With `truncate_sidecar`, the full messages are also
+//! # written next to the data file, to `<test>.full/<digest>.txt`.
+//! truncate_bytes = 4096
+//! truncate_sidecar = false
+//! # Store the messages longer than this many bytes as separate files named
+//! # after their SHA-256 digest, in the `blobs` directory of the data
+//! # directory, keeping only the digest in the data file.
+//! blob_bytes = 65536
+//! # Write images, the full messages of truncated entries and blobs to this
+//! # directory instead of next to the data files, to track it with git LFS.
+//! # `cargo regtest lfs` adds it to `.gitattributes`.
+//! lfs_dir = "regtest_lfs"
+//! # Write data files to this directory instead of the source tree, for
+//! # read-only checkouts (sandboxed CI, Nix builds). Baselines are read from
+//! # it when it has them, and from the source tree otherwise. Usually set with
+//! # `REGTEST_SHADOW_DIR`.
+//! shadow_dir = "target/regtest-shadow"
+//! # Write the outputs of `regtest_hash` which don't match their baseline to this
+//! # directory, for inspection.
+//! hash_dump = "target/regtest-hashed"
+//! # Tolerances of `regtest_image`: the perceptual difference (from 0 to 1)
+//! # above which two pixels count as different, and the fraction of the pixels
+//! # which may differ. Images which don't match their baseline are written to
+//! # `image_diffs`, along with a diff highlighting the differing pixels.
+//! image_threshold = 0.1
+//! image_max_diff = 0.001
+//! image_diffs = "target/regtest-images"
+//! # How many percent more than their baseline the allocations measured by
+//! # `regtest_alloc` may be before they count as a regression.
+//! alloc_slack = 10
+//! # Read baselines incrementally while comparing instead of loading them whole,
+//! # for very large data files.
+//! streaming = false
+//! # In write mode, also append each entry to a `.partial.jsonl` file as it is
+//! # recorded, so that a crash doesn't lose the data recorded until then.
+//! incremental = false
+//! # Record the order in which tests run with the baselines, for tests sharing
+//! # global state: "off", "warn" (when the order differs) or "enforce".
+//! run_order = "off"
+//! # Derive a key from the recorded output: entries with a key are matched by
+//! # key rather than by position. Uses the `key` capture group, or the first one.
+//! key_pattern = "^== (?<key>\\S+) =="
+//! # Keep data files friendly to git merges: keyed entries are sorted by key,
+//! # and when the file was generated and where entries were recorded are left
+//! # out. See `cargo regtest merge` for a merge driver.
+//! merge_friendly = false
+//! # Store a checksum of the entries in each data file, and reject data files
+//! # whose entries don't match it (or which have none), i.e. hand-edited ones.
+//! checksums = false
+//! # Normalize both the baseline and the recorded output before comparing them:
+//! # "line_endings" (CRLF to LF), "trailing_whitespace", "collapse_spaces",
+//! # "nfc" (Unicode normalization), and for locale-dependent output "numbers"
+//! # (`1.234,5` to `1234.5`) and "month_names" (`März` to `March`). The
+//! # baseline keeps the output as recorded.
+//! normalize = ["line_endings", "trailing_whitespace"]
+//! # Replace UUIDs, timestamps, durations (`12.345ms`, `3m 2s`) and sizes
+//! # (`1.2 MiB`) in the recorded output with the `{{uuid}}`, `{{timestamp}}`,
+//! # `{{duration}}` and `{{bytes}}` placeholders. Placeholders in stored
+//! # messages, including `{{any}}` and `{{any:<regex>}}`, match any conforming
+//! # text when comparing.
+//! placeholders = ["uuid", "timestamp"]
+//! # Replace the workspace and home directories in the recorded output with
+//! # `<WORKSPACE>` and `<HOME>`, before the redactions.
+//! anonymize_paths = false
+//! # Round the decimal numbers in the recorded output to this many significant
+//! # digits, so that tiny floating-point differences between targets don't
+//! # fail tests.
+//! float_digits = 10
+//!
+//! # Directories holding the sources of Cargo targets, and the directory of the
+//! # regression data mirroring each of them. Added to (or overriding) these
+//! # defaults, e.g. with `tests = "integration"`.
+//! [target_dirs]
+//! src = "src"
+//! tests = "tests"
+//! examples = "examples"
+//! benches = "benches"
+//!
+//! # Replace volatile fragments before storing and comparing output.
+//! [[redactions]]
+//! pattern = "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}"
+//! replacement = "[uuid]"
+//! ```
+
+use crate::normalize::Normalization;
+use regex::Regex;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the configuration file.
+pub const CONFIG_FILE_NAME: &str = "regtest.toml";
+
+/// Default [`Config::image_diffs`].
+const DEFAULT_IMAGE_DIFFS: &str = "target/regtest-images";
+
+/// Name of the directories holding the data files next to the source files,
+/// with [Placement::NextToSource].
+pub const SNAPSHOTS_DIR_NAME: &str = "__snapshots__";
+
+/// Name of the directory, in the data directory, holding the run-order
+/// manifests (see [Config::run_order]).
+pub const RUN_ORDER_DIR_NAME: &str = "run-order";
+
+/// Name of the directory, in the data directory, holding the blobs of large
+/// entries (see [Config::blob_bytes]).
+pub const BLOBS_DIR_NAME: &str = "blobs";
+
+/// Environment variable overriding [Config::update].
+pub const UPDATE_ENV_VAR: &str = "REGTEST_UPDATE";
+
+/// Environment variable setting [Config::update_tags], as a comma-separated
+/// list.
+pub const UPDATE_TAGS_ENV_VAR: &str = "REGTEST_UPDATE_TAGS";
+
+/// Environment variable setting [Config::report].
+pub const REPORT_ENV_VAR: &str = "REGTEST_REPORT";
+
+/// Environment variable setting [Config::junit].
+pub const JUNIT_ENV_VAR: &str = "REGTEST_JUNIT";
+
+/// Environment variable setting [Config::html_report].
+pub const HTML_REPORT_ENV_VAR: &str = "REGTEST_HTML_REPORT";
+
+/// Environment variable setting [Config::metrics].
+pub const METRICS_ENV_VAR: &str = "REGTEST_METRICS";
+
+/// Environment variable setting [Config::baseline].
+pub const BASELINE_ENV_VAR: &str = "REGTEST_BASELINE";
+
+/// Environment variable overriding [Config::advisory]: `1` or `true`, `0`
+/// or `false`.
+pub const ADVISORY_ENV_VAR: &str = "REGTEST_ADVISORY";
+
+/// Environment variable setting [Config::shadow_dir].
+pub const SHADOW_DIR_ENV_VAR: &str = "REGTEST_SHADOW_DIR";
+
+/// Environment variable holding the key encrypted data files are keyed with,
+/// as 64 hexadecimal digits, see [Config::encrypt].
+pub const KEY_ENV_VAR: &str = "REGTEST_KEY";
+
+/// Environment variable relocating all regression data, overriding
+/// [Config::root]. The directory is shared by all crates, see
+/// [Config::shared].
+pub const DIR_ENV_VAR: &str = "REGTEST_DIR";
+
+/// Storage format of the regression data files.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// A pretty-printed JSON object, holding a header (format version, crate,
+    /// test, generation time) and the array of entries.
+    #[default]
+    Json,
+    /// JSON Lines, in `.jsonl` files: the header on the first line, then one
+    /// entry per line. Recording one more entry changes a single line of the
+    /// file, which keeps diffs of the baselines small.
+    Jsonl,
+}
+
+impl Format {
+    /// Extension of the data files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// How the data files are compressed.
+///
+/// Data files are written with the compression in effect, with its extension
+/// appended (e.g. `.json.zst`). When reading, compressed files are detected
+/// from their contents, so that baselines can be compressed one at a time.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Plain text.
+    #[default]
+    None,
+    /// gzip, in `.gz` files. Requires the `gzip` feature.
+    Gzip,
+    /// Zstandard, in `.zst` files. Requires the `zstd` feature.
+    Zstd,
+}
+
+impl Compression {
+    /// Extension appended to the data files, including the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Where the data files of tests are placed, for a test `parses` in
+/// `src/parser.rs`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Placement {
+    /// Under the [root](Config::root), in a tree mirroring the sources:
+    /// `regtest_data/src/parser/parses.json`.
+    #[default]
+    Mirrored,
+    /// Directly in the [root](Config::root), named after the source file and
+    /// the test: `regtest_data/src__parser__parses.json`.
+    Central,
+    /// In a `__snapshots__` directory next to the source file, as jest does:
+    /// `src/__snapshots__/parser/parses.json`. The [root](Config::root) only
+    /// holds the data which doesn't belong to a test, such as run orders.
+    NextToSource,
+}
+
+impl Placement {
+    /// The placement named `name` in `regtest.toml`, e.g. `next_to_source`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mirrored" => Some(Placement::Mirrored),
+            "central" => Some(Placement::Central),
+            "next_to_source" => Some(Placement::NextToSource),
+            _ => None,
+        }
+    }
+
+    /// The name of this variant in Rust, for the code `#[regtest]`
+    /// generates.
+    #[doc(hidden)]
+    pub fn variant_name(self) -> &'static str {
+        match self {
+            Placement::Mirrored => "Mirrored",
+            Placement::Central => "Central",
+            Placement::NextToSource => "NextToSource",
+        }
+    }
+}
+
+/// Where the recorded messages are stored.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// In the data file, with the rest of the entries.
+    #[default]
+    File,
+    /// One text file per entry, in a directory next to the data file (see
+    /// [`entry_dir`](crate::entry_dir)), named after the entry's key or
+    /// index and placed in a subdirectory per subtest. The data file then
+    /// only refers to them. Each output can be reviewed on its own, and has
+    /// its own history in version control.
+    Directory,
+}
+
+/// How a mismatch between expected and actual output is reported.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStyle {
+    /// Print the expected and actual output, followed by a line diff.
+    #[default]
+    Lines,
+    /// Only print the expected and actual output.
+    None,
+}
+
+/// Whether file paths in reports are rendered as clickable OSC 8 terminal
+/// hyperlinks.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Hyperlinks {
+    /// Use hyperlinks when the terminal is known to support them. Setting the
+    /// `FORCE_HYPERLINK` environment variable to `1` or `0` overrides the
+    /// detection.
+    #[default]
+    Auto,
+    /// Always use hyperlinks.
+    Always,
+    /// Never use hyperlinks.
+    Never,
+}
+
+/// Whether mismatches are reported as GitHub Actions annotations, which show
+/// up inline in pull requests.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Annotations {
+    /// Annotate when running on GitHub Actions (`GITHUB_ACTIONS=true`).
+    #[default]
+    Auto,
+    /// Always print annotations.
+    Always,
+    /// Never print annotations.
+    Never,
+}
+
+/// When regression data is (re)generated.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdatePolicy {
+    /// Generate regression data for tests which don't have any yet, and
+    /// compare against it otherwise.
+    #[default]
+    Missing,
+    /// Always regenerate the regression data, overwriting existing files.
+    Always,
+    /// Never generate regression data. A missing file is an error, which is
+    /// useful on CI.
+    Never,
+    /// Like [Missing](UpdatePolicy::Missing), but the baseline entries which
+    /// don't match the recorded ones are replaced with them, while the others
+    /// are kept as they are, for smaller diffs than regenerating whole files.
+    /// Entries which were added or removed still fail the test.
+    Mismatched,
+    /// Like [Missing](UpdatePolicy::Missing), but data files which can't be
+    /// read as regression data (see [CorruptSnapshot](crate::CorruptSnapshot))
+    /// are backed up next to themselves and regenerated, rather than failing
+    /// the test.
+    Repair,
+}
+
+impl UpdatePolicy {
+    fn from_env_value(value: &str) -> Option<Self> {
+        match value {
+            "missing" => Some(UpdatePolicy::Missing),
+            "always" | "1" => Some(UpdatePolicy::Always),
+            "never" | "0" => Some(UpdatePolicy::Never),
+            "mismatched" => Some(UpdatePolicy::Mismatched),
+            "repair" => Some(UpdatePolicy::Repair),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the order in which tests run is recorded with the baselines and
+/// checked, for suites whose tests share global state affecting their output.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RunOrder {
+    /// Don't record the run order.
+    #[default]
+    Off,
+    /// Record the run order, and warn when a later run starts tests in a
+    /// different order.
+    Warn,
+    /// Like [RunOrder::Warn], but also make each test wait until the tests
+    /// recorded before it have finished.
+    Enforce,
+}
+
+/// What happens when a test finishes without recording any entry.
+///
+/// Tests which intentionally record nothing should say so with
+/// [`RegTest::expect_nothing_recorded`](crate::RegTest::expect_nothing_recorded),
+/// which is not subject to this policy.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmptyPolicy {
+    /// Silently accept the test.
+    Allow,
+    /// Print a warning to stderr.
+    #[default]
+    Warn,
+    /// Fail the test.
+    Fail,
+}
+
+/// What happens when a test records entries past the end of its baseline.
+///
+/// Unless they are appended to it, the baseline with the new entries appended
+/// is written to the [pending file](crate::pending_path) next to it, to be
+/// reviewed and accepted with `cargo regtest accept`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExtraEntries {
+    /// Fail the test.
+    #[default]
+    Fail,
+    /// Print a warning to stderr.
+    Warn,
+    /// Append the new entries to the baseline, and list them on stderr, so
+    /// that growing a test doesn't require regenerating its baseline.
+    Append,
+}
+
+/// A placeholder inserted in the recorded output in place of the fragments it
+/// matches, see [`Config::placeholders`].
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Placeholder {
+    /// `{{uuid}}`, for UUIDs.
+    Uuid,
+    /// `{{timestamp}}`, for RFC 3339 dates and times.
+    Timestamp,
+    /// `{{duration}}`, for durations such as `12.345ms` or `3m 2s`, e.g. of
+    /// performance counters in log-like output.
+    Duration,
+    /// `{{bytes}}`, for sizes such as `512 B` or `1.2 MiB`.
+    Bytes,
+}
+
+/// A pattern replaced in the recorded output before it is stored or compared.
+#[derive(Debug, Clone)]
+pub struct Redaction {
+    /// Regular expression matching the volatile fragment.
+    pub pattern: Regex,
+    /// Text substituted for every match. May reference capture groups, e.g.
+    /// `$1`.
+    pub replacement: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRedaction {
+    pattern: String,
+    replacement: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    root: Option<PathBuf>,
+    local_only: bool,
+    shared: bool,
+    format: Format,
+    placement: Placement,
+    layout: Layout,
+    compression: Compression,
+    encrypt: bool,
+    diff: DiffStyle,
+    hyperlinks: Hyperlinks,
+    annotations: Annotations,
+    echo: Option<bool>,
+    report: Option<PathBuf>,
+    junit: Option<PathBuf>,
+    html_report: Option<PathBuf>,
+    metrics: Option<PathBuf>,
+    pushgateway: Option<String>,
+    update: UpdatePolicy,
+    update_tags: Vec<String>,
+    baseline: Option<String>,
+    advisory: bool,
+    empty: EmptyPolicy,
+    extra_entries: ExtraEntries,
+    max_entry_bytes: Option<u64>,
+    max_file_bytes: Option<u64>,
+    truncate_bytes: Option<u64>,
+    truncate_sidecar: bool,
+    blob_bytes: Option<u64>,
+    lfs_dir: Option<PathBuf>,
+    shadow_dir: Option<PathBuf>,
+    hash_dump: Option<PathBuf>,
+    image_threshold: f64,
+    image_max_diff: f64,
+    image_diffs: Option<PathBuf>,
+    alloc_slack: f64,
+    streaming: bool,
+    incremental: bool,
+    run_order: RunOrder,
+    key_pattern: Option<String>,
+    merge_friendly: bool,
+    checksums: bool,
+    normalize: Vec<Normalization>,
+    placeholders: Vec<Placeholder>,
+    anonymize_paths: bool,
+    float_digits: Option<u32>,
+    target_dirs: BTreeMap<String, PathBuf>,
+    redactions: Vec<RawRedaction>,
+}
+
+/// Configuration shared by all regression tests of a crate.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Directory holding the regression data. Relative paths are resolved
+    /// against the root of the crate owning the test.
+    pub root: PathBuf,
+    /// Whether the regression data is intentionally ignored by git, e.g. for
+    /// baselines which only make sense on one machine. Otherwise, a warning is
+    /// printed when a data file is written to a path git ignores.
+    pub local_only: bool,
+    /// Whether [root](Config::root) is shared by all crates of the workspace.
+    ///
+    /// If set, a relative root is resolved against the directory containing
+    /// the configuration file, and each crate gets its own subdirectory in it,
+    /// named after the crate's path relative to that directory (or after the
+    /// crate's directory name, if it isn't below it).
+    pub shared: bool,
+    /// Storage format of the regression data files.
+    pub format: Format,
+    /// Where the data files of tests are placed. Tests can override it with
+    /// `#[regtest(placement = "central")]`.
+    pub placement: Placement,
+    /// Where the recorded messages are stored.
+    pub layout: Layout,
+    /// How the data files are compressed.
+    pub compression: Compression,
+    /// Whether data files are written encrypted with AES-256-GCM, so that
+    /// baselines holding proprietary data can live in a public repository.
+    /// Requires the `encryption` feature.
+    ///
+    /// The 256-bit key is read from the [KEY_ENV_VAR] environment variable,
+    /// as 64 hexadecimal digits, which reading or writing an encrypted file
+    /// requires. A file whose contents don't change is not encrypted again,
+    /// so that it doesn't change either.
+    /// Encrypted files are recognized when read, whatever this says, and keep
+    /// their name. Doesn't combine with the [directory layout](Layout::Directory),
+    /// [truncate_sidecar](Config::truncate_sidecar) or
+    /// [blob_bytes](Config::blob_bytes), which write plain files, and doesn't
+    /// apply to the images of
+    /// [regtest_image](crate::RegTest::regtest_image).
+    pub encrypt: bool,
+    /// How mismatches are reported.
+    pub diff: DiffStyle,
+    /// Whether file paths are rendered as terminal hyperlinks.
+    pub hyperlinks: Hyperlinks,
+    /// Whether mismatches are reported as GitHub Actions annotations.
+    pub annotations: Annotations,
+    /// Whether, when tests run with `--nocapture`, full diffs are printed to
+    /// stdout while panic messages are kept compact.
+    pub echo: bool,
+    /// File to which a machine-readable description of every mismatch is
+    /// appended, as one JSON object per line. Relative paths are resolved
+    /// against the directory containing the configuration file.
+    ///
+    /// Each object contains the `test` name, the `subtest` (if any), the
+    /// `kind` of mismatch, the regression data `file` (or the source file,
+    /// with `line` and `column`, for inline expectations), the `entry` index,
+    /// and the `expected`, `actual` and `diff` texts.
+    pub report: Option<PathBuf>,
+    /// File to which a JUnit XML summary of the regression tests is written,
+    /// with one test suite per test binary. Relative paths are resolved
+    /// against the directory containing the configuration file.
+    ///
+    /// Failures caused by regression data have the type `regression`, and
+    /// other panics the type `panic`.
+    pub junit: Option<PathBuf>,
+    /// Directory to which an HTML report with side-by-side diffs of all
+    /// mismatches is written, with one page per test binary and an
+    /// `index.html`. Relative paths are resolved against the directory
+    /// containing the configuration file.
+    pub html_report: Option<PathBuf>,
+    /// File to which Prometheus metrics about the regression data of the run
+    /// are written, in the text exposition format: the number of tests,
+    /// entries compared, recorded and pending, tests failed because of their
+    /// regression data, and the size of the data files. Samples are labelled
+    /// with the test binary. Relative paths are resolved against the directory
+    /// containing the configuration file.
+    pub metrics: Option<PathBuf>,
+    /// URL of a Prometheus Pushgateway (`http://host:port`) to which the same
+    /// metrics are pushed, grouped by `job="regtest"` and test binary.
+    pub pushgateway: Option<String>,
+    /// When regression data is (re)generated.
+    pub update: UpdatePolicy,
+    /// Tags selecting the tests whose baselines are regenerated, see
+    /// [`RegTest::with_tags`](crate::RegTest::with_tags). If any are given,
+    /// tests with one of them regenerate their baselines whatever the
+    /// [update policy](Config::update), while other tests generate missing
+    /// baselines at most, so that unrelated data files aren't touched.
+    pub update_tags: Vec<String>,
+    /// Whether mismatches only print a warning instead of failing the test,
+    /// e.g. to see the churn of a large refactoring without failing CI. They
+    /// are still written to the [HTML report](Config::html_report) and to the
+    /// [report](Config::report), flagged as advisory. After its
+    /// first mismatch, a test compares no further entries.
+    pub advisory: bool,
+    /// Git revision (e.g. `origin/main`) whose committed data files are
+    /// compared against, instead of those in the working tree. The working
+    /// tree is never written to, whatever the [update policy](Config::update),
+    /// and tests without a data file at that revision fail.
+    pub baseline: Option<String>,
+    /// What happens when a test records nothing.
+    pub empty: EmptyPolicy,
+    /// What happens when a test records more entries than its baseline holds.
+    /// Only applies outside of subtests, whose extra entries always fail them.
+    pub extra_entries: ExtraEntries,
+    /// In write mode, recording an entry larger than this many bytes fails the
+    /// test.
+    pub max_entry_bytes: Option<u64>,
+    /// In write mode, a data file larger than this many bytes (as written,
+    /// i.e. after compression, and with the messages inline whatever the
+    /// [layout](Config::layout)) fails the test instead of being written.
+    pub max_file_bytes: Option<u64>,
+    /// Messages longer than twice this many bytes are truncated when
+    /// recorded: only their first and last this many bytes are kept, around
+    /// a marker with the SHA-256 digest of the part left out. Changes to that
+    /// part still change the message. Only applies to text, i.e. not to
+    /// serialized values or documents.
+    pub truncate_bytes: Option<u64>,
+    /// Whether the full messages of truncated entries are also written next
+    /// to the data file, in a `.full` directory, when writing the baseline.
+    /// Doesn't combine with [encrypt](Config::encrypt), as the full messages
+    /// are plain files.
+    pub truncate_sidecar: bool,
+    /// Messages longer than this many bytes are stored as blobs: files named
+    /// after the SHA-256 digest of the message, in the
+    /// [`BLOBS_DIR_NAME`] directory of the data directory, which the entries
+    /// of the data file refer to by digest. Keeps the data files small and
+    /// diffable, and the blobs can be kept in git LFS. Blobs are never
+    /// removed, as other data files may refer to them.
+    pub blob_bytes: Option<u64>,
+    /// Directory to which the binary and sidecar files of data files (the
+    /// images of [`regtest_image`](crate::RegTest::regtest_image), the full
+    /// messages of [truncated](Config::truncate_sidecar) entries and
+    /// [blobs](Config::blob_bytes)) are written instead of next to them, at
+    /// their path relative to the directory containing the configuration
+    /// file, so that only this directory needs to be tracked with git LFS.
+    /// Relative paths are resolved against the directory containing the
+    /// configuration file. `cargo regtest lfs` adds the patterns selecting
+    /// it to `.gitattributes`.
+    pub lfs_dir: Option<PathBuf>,
+    /// Directory to which data files are written instead of the source tree,
+    /// for checkouts which are read-only, e.g. in sandboxed CI or Nix builds.
+    /// Baselines are read from it when it has them, and from the source tree
+    /// otherwise. Relative paths are resolved against the directory
+    /// containing the configuration file. See
+    /// [`ShadowStore`](crate::store::ShadowStore).
+    pub shadow_dir: Option<PathBuf>,
+    /// Directory to which the outputs of
+    /// [`regtest_hash`](crate::RegTest::regtest_hash) which don't match their
+    /// baseline are written, as `<test>/<entry index>.bin`. Relative paths are
+    /// resolved against the directory containing the configuration file.
+    pub hash_dump: Option<PathBuf>,
+    /// The perceptual difference between two pixels, from 0 (identical) to 1
+    /// (black and white), above which `regtest_image` counts them as
+    /// different.
+    pub image_threshold: f64,
+    /// The fraction of the pixels of an image, from 0 to 1, which may differ
+    /// from the baseline before `regtest_image` reports a mismatch.
+    pub image_max_diff: f64,
+    /// Directory to which the images recorded by `regtest_image` which don't
+    /// match their baseline are written, along with a diff highlighting the
+    /// differing pixels. Relative paths are resolved against the directory
+    /// containing the configuration file.
+    pub image_diffs: PathBuf,
+    /// How many percent more than their baseline the allocations measured by
+    /// [`regtest_alloc`](crate::RegTest::regtest_alloc) may be before they
+    /// count as a regression.
+    pub alloc_slack: f64,
+    /// Whether baselines are read incrementally while the test compares its
+    /// entries, instead of being loaded whole when it starts. Compared
+    /// entries are released as the test goes, so that very large data files
+    /// don't need to fit in memory. Keyed entries, subtests and
+    /// [`assert_shape`](crate::RegTest::assert_shape) still read the rest of
+    /// the baseline when used. Doesn't apply to [git baselines](Config::baseline).
+    pub streaming: bool,
+    /// Whether, in write mode, each entry is also appended to a partial file
+    /// next to the data file as it is recorded (as JSON Lines, with a
+    /// `.partial.jsonl` extension), so that the entries recorded before a
+    /// crash or `abort()` survive it. The data file is still written when the
+    /// test ends, and the partial file then removed. Only applies to data
+    /// files on the local file system.
+    pub incremental: bool,
+    /// Whether the run order of the tests is recorded with the baselines, in
+    /// `<data dir>/run-order/<test binary>.json`, and checked against.
+    pub run_order: RunOrder,
+    /// Pattern deriving a key from each recorded (and redacted) message.
+    ///
+    /// Entries with a key are matched against the stored entry with the same
+    /// key rather than by position, so outputs which carry a natural
+    /// identifier may be recorded in any order. The key is the capture group
+    /// named `key`, or else the first capture group, or else the whole match.
+    pub key_pattern: Option<Regex>,
+    /// Whether data files are written so that concurrent branches changing
+    /// them rarely conflict: keyed entries are sorted by key (they are
+    /// matched by key anyway), and the time the file was generated and where
+    /// each entry was recorded are left out, so that regenerating a baseline
+    /// or moving code around doesn't change it.
+    pub merge_friendly: bool,
+    /// Whether data files store a checksum of their entries, which is checked
+    /// when they are read, so that baselines are only ever produced by the
+    /// tests: a data file whose entries don't match its checksum, or which
+    /// has none, fails the test with a
+    /// [TamperedSnapshot](crate::TamperedSnapshot) error. Baselines read with
+    /// [streaming](Config::streaming) aren't checked.
+    ///
+    /// Checksums are checked whenever present, even with this off.
+    pub checksums: bool,
+    /// Normalizations applied, in order, to both the baseline and the
+    /// recorded output before they are compared. See the
+    /// [normalize](crate::normalize) module.
+    pub normalize: Vec<Normalization>,
+    /// Placeholders replacing the fragments they match in the recorded
+    /// output, after the [redactions](Config::redactions).
+    ///
+    /// Stored messages may contain placeholders (`{{uuid}}`, `{{timestamp}}`,
+    /// `{{duration}}`, `{{bytes}}`, `{{any}}` or `{{any:<regex>}}`), which
+    /// match any conforming fragment
+    /// of the output they are compared to. Those listed here are inserted
+    /// when recording, so that they need not be written by hand.
+    pub placeholders: Vec<Placeholder>,
+    /// Whether the absolute paths of the machine running the tests are
+    /// replaced in the recorded output, before the
+    /// [redactions](Config::redactions), so that error messages and `Debug`
+    /// output embedding them don't differ between developers: the root of the
+    /// Cargo workspace becomes `<WORKSPACE>`, and the home directory `<HOME>`.
+    ///
+    /// Paths are also recognized with their backslashes escaped, as `Debug`
+    /// prints Windows paths, or turned into slashes.
+    pub anonymize_paths: bool,
+    /// Number of significant digits the decimal numbers in the recorded
+    /// output (e.g. `0.30000000000000004`) are rounded to, if any, before
+    /// it is stored and compared, so that tiny floating-point differences
+    /// across CPU targets don't invalidate baselines. Applies to text and
+    /// `Debug` output, and to serialized values and JSON documents, after
+    /// the [redactions](Config::redactions) and
+    /// [placeholders](Config::placeholders). Tests can override it with
+    /// [`Settings::float_digits`](crate::Settings::float_digits).
+    pub float_digits: Option<u32>,
+    /// Directories of a crate holding the sources of its Cargo targets (`src`
+    /// for the library and binaries, `tests`, `examples` and `benches`), each
+    /// with the directory of the [root](Config::root) mirroring it.
+    ///
+    /// The data of a test is laid out after the nearest of them containing
+    /// its source file, whose parent is taken as the crate root.
+    pub target_dirs: BTreeMap<String, PathBuf>,
+    /// Patterns replaced in the recorded output, in order.
+    pub redactions: Vec<Redaction>,
+    /// The file this configuration was loaded from, if any.
+    pub source: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root: PathBuf::from("regtest_data"),
+            local_only: false,
+            shared: false,
+            format: Format::default(),
+            placement: Placement::default(),
+            layout: Layout::default(),
+            compression: Compression::default(),
+            encrypt: false,
+            diff: DiffStyle::default(),
+            hyperlinks: Hyperlinks::default(),
+            annotations: Annotations::default(),
+            echo: true,
+            report: None,
+            junit: None,
+            html_report: None,
+            metrics: None,
+            pushgateway: None,
+            update: UpdatePolicy::default(),
+            update_tags: Vec::new(),
+            advisory: false,
+            baseline: None,
+            empty: EmptyPolicy::default(),
+            extra_entries: ExtraEntries::default(),
+            max_entry_bytes: None,
+            max_file_bytes: None,
+            truncate_bytes: None,
+            truncate_sidecar: false,
+            blob_bytes: None,
+            lfs_dir: None,
+            shadow_dir: None,
+            hash_dump: None,
+            image_threshold: 0.0,
+            image_max_diff: 0.0,
+            image_diffs: PathBuf::from(DEFAULT_IMAGE_DIFFS),
+            alloc_slack: 0.0,
+            streaming: false,
+            incremental: false,
+            run_order: RunOrder::default(),
+            key_pattern: None,
+            merge_friendly: false,
+            checksums: false,
+            normalize: Vec::new(),
+            placeholders: Vec::new(),
+            anonymize_paths: false,
+            float_digits: None,
+            target_dirs: ["src", "tests", "examples", "benches"]
+                .into_iter()
+                .map(|dir| (dir.to_string(), PathBuf::from(dir)))
+                .collect(),
+            redactions: Vec::new(),
+            source: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from a specific `regtest.toml` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let invalid = |msg: String| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid regression test config {}: {}", path.display(), msg),
+            )
+        };
+
+        let text = std::fs::read_to_string(path)?;
+        let raw: RawConfig = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+
+        let redactions = raw
+            .redactions
+            .into_iter()
+            .map(|r| {
+                Regex::new(&r.pattern)
+                    .map(|pattern| Redaction {
+                        pattern,
+                        replacement: r.replacement,
+                    })
+                    .map_err(|e| invalid(e.to_string()))
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        if !crate::compress::supported(raw.compression) {
+            return Err(invalid(
+                crate::compress::unsupported(raw.compression).to_string(),
+            ));
+        }
+        if raw.encrypt && !crate::encrypt::supported() {
+            return Err(invalid(crate::encrypt::unsupported().to_string()));
+        }
+        if raw.encrypt && raw.layout == Layout::Directory {
+            return Err(invalid(
+                "`encrypt = true` doesn't combine with `layout = \"directory\"`, whose messages are plain files".to_string(),
+            ));
+        }
+
+        for (name, value) in [
+            ("image_threshold", raw.image_threshold),
+            ("image_max_diff", raw.image_max_diff),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(invalid(format!("{} must be between 0 and 1", name)));
+            }
+        }
+
+        if raw
+            .float_digits
+            .is_some_and(|digits| !(1..=17).contains(&digits))
+        {
+            return Err(invalid("float_digits must be between 1 and 17".to_string()));
+        }
+        if raw.encrypt && raw.truncate_sidecar {
+            return Err(invalid(
+                "`encrypt = true` doesn't combine with `truncate_sidecar`, whose full messages are plain files"
+                    .to_string(),
+            ));
+        }
+        if raw.encrypt && raw.blob_bytes.is_some() {
+            return Err(invalid(
+                "`encrypt = true` doesn't combine with `blob_bytes`, whose blobs are plain files"
+                    .to_string(),
+            ));
+        }
+        if raw.truncate_bytes == Some(0) {
+            return Err(invalid("truncate_bytes must be at least 1".to_string()));
+        }
+        if raw.alloc_slack.is_nan() || raw.alloc_slack < 0.0 {
+            return Err(invalid("alloc_slack must be at least 0".to_string()));
+        }
+
+        let key_pattern = raw
+            .key_pattern
+            .map(|pattern| Regex::new(&pattern).map_err(|e| invalid(e.to_string())))
+            .transpose()?;
+
+        if let Some(name) = raw
+            .target_dirs
+            .keys()
+            .find(|name| name.is_empty() || name.contains(['/', '\\']))
+        {
+            return Err(invalid(format!(
+                "target_dirs: `{}` is not the name of a directory",
+                name
+            )));
+        }
+
+        let defaults = Config::default();
+        let mut target_dirs = defaults.target_dirs;
+        target_dirs.extend(raw.target_dirs);
+        Ok(Config {
+            root: raw.root.unwrap_or(defaults.root),
+            local_only: raw.local_only,
+            shared: raw.shared,
+            format: raw.format,
+            placement: raw.placement,
+            layout: raw.layout,
+            compression: raw.compression,
+            encrypt: raw.encrypt,
+            diff: raw.diff,
+            hyperlinks: raw.hyperlinks,
+            annotations: raw.annotations,
+            echo: raw.echo.unwrap_or(defaults.echo),
+            report: raw
+                .report
+                .map(|report| path.parent().unwrap_or(Path::new("")).join(report)),
+            junit: raw
+                .junit
+                .map(|junit| path.parent().unwrap_or(Path::new("")).join(junit)),
+            html_report: raw
+                .html_report
+                .map(|dir| path.parent().unwrap_or(Path::new("")).join(dir)),
+            metrics: raw
+                .metrics
+                .map(|metrics| path.parent().unwrap_or(Path::new("")).join(metrics)),
+            pushgateway: raw.pushgateway,
+            update: raw.update,
+            update_tags: raw.update_tags,
+            advisory: raw.advisory,
+            baseline: raw.baseline,
+            empty: raw.empty,
+            extra_entries: raw.extra_entries,
+            max_entry_bytes: raw.max_entry_bytes,
+            max_file_bytes: raw.max_file_bytes,
+            truncate_bytes: raw.truncate_bytes,
+            truncate_sidecar: raw.truncate_sidecar,
+            blob_bytes: raw.blob_bytes,
+            lfs_dir: raw
+                .lfs_dir
+                .map(|dir| path.parent().unwrap_or(Path::new("")).join(dir)),
+            shadow_dir: raw
+                .shadow_dir
+                .map(|dir| path.parent().unwrap_or(Path::new("")).join(dir)),
+            hash_dump: raw
+                .hash_dump
+                .map(|dir| path.parent().unwrap_or(Path::new("")).join(dir)),
+            image_threshold: raw.image_threshold,
+            image_max_diff: raw.image_max_diff,
+            image_diffs: path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .join(raw.image_diffs.unwrap_or(defaults.image_diffs)),
+            alloc_slack: raw.alloc_slack,
+            streaming: raw.streaming,
+            incremental: raw.incremental,
+            run_order: raw.run_order,
+            key_pattern,
+            merge_friendly: raw.merge_friendly,
+            checksums: raw.checksums,
+            normalize: raw.normalize,
+            placeholders: raw.placeholders,
+            anonymize_paths: raw.anonymize_paths,
+            float_digits: raw.float_digits,
+            target_dirs,
+            redactions,
+            source: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Looks for a `regtest.toml` in `start` and its ancestors, and loads the
+    /// first one found. Returns the default configuration if there is none.
+    pub fn discover<P: AsRef<Path>>(start: P) -> std::io::Result<Self> {
+        match start
+            .as_ref()
+            .ancestors()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .find(|candidate| candidate.is_file())
+        {
+            Some(path) => Config::load(path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// The configuration of the crate currently being tested, including
+    /// overrides from environment variables.
+    ///
+    /// It is discovered once per process, starting from `CARGO_MANIFEST_DIR`.
+    ///
+    /// # Panics
+    /// Panics if the configuration file exists but is invalid, or if an
+    /// environment variable override has an unknown value.
+    pub fn current() -> &'static Config {
+        static CURRENT: OnceLock<Config> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let start = std::env::var_os("CARGO_MANIFEST_DIR")
+                .map(PathBuf::from)
+                .or_else(|| std::env::current_dir().ok())
+                .unwrap_or_default();
+
+            let mut config = Config::discover(&start).unwrap_or_else(|e| panic!("{}", e));
+            config.apply_env();
+            config
+        })
+    }
+
+    /// Applies the overrides from environment variables (`REGTEST_UPDATE`,
+    /// `REGTEST_DIR`, `REGTEST_REPORT`, `REGTEST_JUNIT`, `REGTEST_HTML_REPORT`,
+    /// `REGTEST_UPDATE_TAGS`, `REGTEST_METRICS`, `REGTEST_BASELINE`,
+    /// `REGTEST_ADVISORY`).
+    /// [Config::current] already includes them.
+    ///
+    /// # Panics
+    /// Panics if an environment variable has an unknown value.
+    pub fn apply_env(&mut self) {
+        if let Some(report) = std::env::var_os(REPORT_ENV_VAR).filter(|r| !r.is_empty()) {
+            let report = PathBuf::from(report);
+            self.report = Some(std::path::absolute(&report).unwrap_or(report));
+        }
+
+        if let Some(junit) = std::env::var_os(JUNIT_ENV_VAR).filter(|j| !j.is_empty()) {
+            let junit = PathBuf::from(junit);
+            self.junit = Some(std::path::absolute(&junit).unwrap_or(junit));
+        }
+
+        if let Some(dir) = std::env::var_os(HTML_REPORT_ENV_VAR).filter(|d| !d.is_empty()) {
+            let dir = PathBuf::from(dir);
+            self.html_report = Some(std::path::absolute(&dir).unwrap_or(dir));
+        }
+
+        if let Some(metrics) = std::env::var_os(METRICS_ENV_VAR).filter(|m| !m.is_empty()) {
+            let metrics = PathBuf::from(metrics);
+            self.metrics = Some(std::path::absolute(&metrics).unwrap_or(metrics));
+        }
+
+        if let Some(dir) = std::env::var_os(SHADOW_DIR_ENV_VAR).filter(|d| !d.is_empty()) {
+            let dir = PathBuf::from(dir);
+            self.shadow_dir = Some(std::path::absolute(&dir).unwrap_or(dir));
+        }
+
+        if let Ok(baseline) = std::env::var(BASELINE_ENV_VAR)
+            && !baseline.is_empty()
+        {
+            self.baseline = Some(baseline);
+        }
+
+        if let Some(dir) = std::env::var_os(DIR_ENV_VAR).filter(|dir| !dir.is_empty()) {
+            let dir = PathBuf::from(dir);
+            self.root = std::path::absolute(&dir).unwrap_or(dir);
+            self.shared = true;
+        }
+
+        if let Ok(tags) = std::env::var(UPDATE_TAGS_ENV_VAR) {
+            self.update_tags = tags
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+
+        if let Ok(value) = std::env::var(ADVISORY_ENV_VAR) {
+            self.advisory = match value.as_str() {
+                "1" | "true" => true,
+                "0" | "false" | "" => false,
+                _ => panic!(
+                    "Invalid value for {}: '{}' (expected '1', 'true', '0' or 'false')",
+                    ADVISORY_ENV_VAR, value
+                ),
+            };
+        }
+
+        if let Ok(value) = std::env::var(UPDATE_ENV_VAR) {
+            self.update = UpdatePolicy::from_env_value(&value).unwrap_or_else(|| {
+                panic!(
+                    "Invalid value for {}: '{}' (expected 'missing', 'always', 'never', 'mismatched' or 'repair')",
+                    UPDATE_ENV_VAR, value
+                )
+            });
+        }
+    }
+
+    /// The update policy of a test tagged with `tags`, see
+    /// [Config::update_tags].
+    pub(crate) fn update_policy(&self, tags: &[&str]) -> UpdatePolicy {
+        if self.update_tags.is_empty() {
+            self.update
+        } else if tags
+            .iter()
+            .any(|tag| self.update_tags.iter().any(|t| t == tag))
+        {
+            UpdatePolicy::Always
+        } else if self.update == UpdatePolicy::Always {
+            UpdatePolicy::Missing
+        } else {
+            self.update
+        }
+    }
+
+    /// Directory containing the configuration file, if any.
+    pub fn base_dir(&self) -> Option<&Path> {
+        self.source.as_deref().and_then(Path::parent)
+    }
+
+    /// Computes the regression data file for the test `test_name` defined in
+    /// `source_file` under this configuration. See
+    /// [`snapshot_path`](crate::snapshot_path) for the layout.
+    pub fn snapshot_path<P: AsRef<Path>>(&self, source_file: P, test_name: &str) -> PathBuf {
+        crate::path::snapshot_path_with(self, source_file.as_ref(), test_name)
+    }
+
+    /// Computes the regression data file of the test `test` (named after its
+    /// module path, e.g. `parser::tests::parses`) of the test binary `binary`
+    /// of the crate rooted at `crate_root` under this configuration, as used
+    /// by [`RegTest::for_current_test`](crate::RegTest::for_current_test).
+    pub fn current_test_path<P: AsRef<Path>>(
+        &self,
+        crate_root: P,
+        binary: &str,
+        test: &str,
+    ) -> PathBuf {
+        crate::path::current_test_path(self, crate_root.as_ref(), binary, test)
+    }
+
+    /// Where the binary or sidecar file `path` of a data file (an image, a
+    /// full message or a blob) is written under this configuration: in the
+    /// [LFS directory](Config::lfs_dir), if any, and at `path` otherwise.
+    pub fn lfs_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        crate::path::lfs_path(self, path.as_ref())
+    }
+
+    /// Directory holding all regression data of the crate rooted at
+    /// `crate_root` under this configuration.
+    pub fn data_dir<P: AsRef<Path>>(&self, crate_root: P) -> PathBuf {
+        crate::path::data_dir(self, crate_root.as_ref())
+    }
+
+    /// The key of an entry recording `message`, see [Config::key_pattern].
+    pub fn entry_key(&self, message: &str) -> Option<String> {
+        let captures = self.key_pattern.as_ref()?.captures(message)?;
+        captures
+            .name("key")
+            .or_else(|| captures.get(1))
+            .or_else(|| captures.get(0))
+            .map(|m| m.as_str().to_string())
+    }
+
+    /// Applies all configured redactions to `message`, after anonymizing
+    /// its paths if [anonymize_paths](Config::anonymize_paths) is set.
+    pub fn redact<'a>(&self, message: &'a str) -> Cow<'a, str> {
+        let mut message = if self.anonymize_paths {
+            crate::anonymize::anonymize(message)
+        } else {
+            Cow::Borrowed(message)
+        };
+        for redaction in &self.redactions {
+            if let Cow::Owned(replaced) = redaction
+                .pattern
+                .replace_all(&message, redaction.replacement.as_str())
+            {
+                message = Cow::Owned(replaced);
+            }
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_plain_files_next_to_encrypted_ones() {
+        let dir = std::env::temp_dir().join(format!("regtest-encrypt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&file, "truncate_bytes = 64\ntruncate_sidecar = true\n").unwrap();
+        assert!(Config::load(&file).unwrap().truncate_sidecar);
+
+        std::fs::write(
+            &file,
+            "encrypt = true\ntruncate_bytes = 64\ntruncate_sidecar = true\n",
+        )
+        .unwrap();
+        let error = Config::load(&file).unwrap_err();
+        if crate::encrypt::supported() {
+            assert!(error.to_string().contains("truncate_sidecar"), "{}", error);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parses_and_redacts() {
+        let dir = std::env::temp_dir().join(format!("regtest-config-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(
+            dir.join(CONFIG_FILE_NAME),
+            r#"
+                root = "golden"
+                diff = "none"
+                update = "never"
+
+                [[redactions]]
+                pattern = "took [0-9]+ms"
+                replacement = "took [duration]"
+            "#,
+        )
+        .unwrap();
+
+        let config = Config::discover(dir.join("nested")).unwrap();
+        assert_eq!(config.root, Path::new("golden"));
+        assert_eq!(config.format, Format::Json);
+        assert_eq!(config.diff, DiffStyle::None);
+        assert_eq!(config.update, UpdatePolicy::Never);
+        assert_eq!(config.redact("done, took 15ms"), "done, took [duration]");
+    }
+}
@@ -0,0 +1,115 @@
+//! Data files which can't be read as regression data, e.g. after a botched
+//! merge or a write cut short.
+
+use std::path::{Path, PathBuf};
+
+/// The error of reading a data file which isn't valid regression data.
+///
+/// The [RegTest](crate::RegTest) constructors and
+/// [`SnapshotFile::load`](crate::SnapshotFile::load) return it inside an
+/// [`std::io::Error`] of kind [`InvalidData`](std::io::ErrorKind::InvalidData)
+/// (or [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) for truncated
+/// files):
+///
+/// ```rust,no_run
+/// use regression_test::{CorruptSnapshot, SnapshotFile};
+///
+/// if let Err(e) = SnapshotFile::load("regtest_data/src/lib/parses.json") {
+///     if let Some(corrupt) = e.get_ref().and_then(|e| e.downcast_ref::<CorruptSnapshot>()) {
+///         eprintln!("line {} of {} is broken", corrupt.line, corrupt.path.display());
+///     }
+/// }
+/// ```
+///
+/// With `REGTEST_UPDATE=repair`, tests back corrupt data files up (see
+/// [backup_path](CorruptSnapshot::backup_path)) and regenerate them.
+#[derive(Debug, Clone)]
+pub struct CorruptSnapshot {
+    /// The data file.
+    pub path: PathBuf,
+    /// Line of the data file (from 1) at which reading it failed. Compressed
+    /// files count the lines of their decompressed contents.
+    pub line: usize,
+    /// Column of that line (from 1, in bytes), or 0 at its start.
+    pub column: usize,
+    /// What is wrong there.
+    pub reason: String,
+}
+
+impl CorruptSnapshot {
+    /// The corruption of the data file `path` reported by `error`, if it
+    /// reports one rather than e.g. a failure to read the file.
+    pub(crate) fn from_error(path: &Path, error: &std::io::Error) -> Option<Self> {
+        let json = error.get_ref()?.downcast_ref::<serde_json::Error>()?;
+        if json.is_io() {
+            return None;
+        }
+        let message = json.to_string();
+        let position = format!(" at line {} column {}", json.line(), json.column());
+        Some(CorruptSnapshot {
+            path: path.to_path_buf(),
+            line: json.line(),
+            column: json.column(),
+            reason: message
+                .strip_suffix(&position)
+                .unwrap_or(&message)
+                .to_string(),
+        })
+    }
+
+    /// `error` with the corruption it reports, if any, described as such.
+    pub(crate) fn describe(path: &Path, error: std::io::Error) -> std::io::Error {
+        match CorruptSnapshot::from_error(path, &error) {
+            Some(corrupt) => std::io::Error::new(error.kind(), corrupt),
+            None => error,
+        }
+    }
+
+    /// Where `REGTEST_UPDATE=repair` keeps the corrupt file, e.g.
+    /// `parses.json.corrupt` for `parses.json`.
+    pub fn backup_path(&self) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".corrupt");
+        self.path.with_file_name(name)
+    }
+}
+
+impl std::fmt::Display for CorruptSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is corrupt: {}, at line {}, column {}. Fix it by hand (e.g. resolve the merge \
+             conflict), or run the tests with REGTEST_UPDATE=repair to back it up and \
+             regenerate it.",
+            self.path.display(),
+            self.reason,
+            self.line,
+            self.column
+        )
+    }
+}
+
+impl std::error::Error for CorruptSnapshot {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_where_the_file_is_corrupt() {
+        let path = Path::new("regtest_data/t.json");
+        let error = crate::DataFile::read(&b"{\n  \"version\": 1,\n<<<<<<< HEAD\n"[..], path)
+            .err()
+            .unwrap();
+        let corrupt = CorruptSnapshot::from_error(path, &error).unwrap();
+        assert_eq!((corrupt.line, corrupt.column), (3, 1));
+        assert_eq!(corrupt.reason, "key must be a string");
+        assert_eq!(
+            corrupt.backup_path(),
+            Path::new("regtest_data/t.json.corrupt")
+        );
+
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(CorruptSnapshot::from_error(path, &missing).is_none());
+    }
+}
@@ -0,0 +1,47 @@
+//! Support of `#[derive(RegSnapshot)]`, see [`RegSnapshot`].
+
+/// A stable, canonical text representation of a value for regression data,
+/// recorded with [`RegTest::regtest_snapshot`](crate::RegTest::regtest_snapshot).
+///
+/// Usually derived with `regression_test_macros::RegSnapshot`, which lists
+/// the fields of a struct in declaration order, one per line, with their
+/// `Debug` output. Fields can be annotated with:
+///
+/// - `#[regsnapshot(skip)]`: left out,
+/// - `#[regsnapshot(redact)]`: shown as `[redacted]`, or as the given text
+///   with `#[regsnapshot(redact = "[timestamp]")]`,
+/// - `#[regsnapshot(nested)]`: shown with their own `RegSnapshot`
+///   representation rather than `Debug`.
+pub trait RegSnapshot {
+    /// The representation of `self`.
+    fn reg_snapshot(&self) -> String;
+}
+
+/// Appends the field `name` with the representation `value` to the
+/// representation `out` of a struct, indenting multi-line values. Used by
+/// the derived implementations.
+#[doc(hidden)]
+pub fn __push_field(out: &mut String, name: &str, value: &str) {
+    out.push_str("    ");
+    out.push_str(name);
+    out.push_str(": ");
+    out.push_str(&value.replace('\n', "\n    "));
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_indented() {
+        let mut out = String::from("Outer {\n");
+        __push_field(&mut out, "id", "1");
+        __push_field(&mut out, "inner", "Inner {\n    x: 2\n}");
+        out.push('}');
+        assert_eq!(
+            out,
+            "Outer {\n    id: 1\n    inner: Inner {\n        x: 2\n    }\n}"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Formatting of the values recorded as text, see [`RegDisplay`].
+
+use std::fmt::{self, Display};
+
+/// How a value is rendered into regression data by
+/// [`RegTest::regtest`](crate::RegTest::regtest) and the other methods
+/// recording text.
+///
+/// Implemented for all `Display` types. Types without a `Display`
+/// implementation, or whose `Debug` output isn't stable enough to be a
+/// baseline (e.g. because it comes from a third-party crate), can implement
+/// it to control exactly how they are recorded:
+///
+/// ```rust
+/// use regression_test::{RegDisplay, RegTest};
+/// use std::fmt;
+///
+/// struct Rgb(u8, u8, u8);
+///
+/// impl RegDisplay for Rgb {
+///     fn reg_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+///     }
+/// }
+///
+/// # let path = std::env::temp_dir().join("regtest-display-doc.json");
+/// let mut rt = RegTest::new(path).unwrap();
+/// rt.regtest(Rgb(255, 128, 0));
+/// ```
+///
+/// Values are taken by value, so implement it for `&Rgb` too to record
+/// borrowed values.
+pub trait RegDisplay {
+    /// Formats `self` for regression data.
+    fn reg_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+impl<T: Display + ?Sized> RegDisplay for T {
+    fn reg_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+/// The text recorded for `value`.
+pub(crate) fn render<T: RegDisplay + ?Sized>(value: &T) -> String {
+    struct Rendered<'a, T: ?Sized>(&'a T);
+
+    impl<T: RegDisplay + ?Sized> Display for Rendered<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.reg_fmt(f)
+        }
+    }
+
+    Rendered(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Version(u32, u32);
+
+    impl RegDisplay for Version {
+        fn reg_fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "v{}.{}", self.0, self.1)
+        }
+    }
+
+    #[test]
+    fn custom_and_display_formats() {
+        assert_eq!(render(&Version(1, 2)), "v1.2");
+        assert_eq!(render(&42), "42");
+        assert_eq!(render("text"), "text");
+    }
+}
@@ -0,0 +1,206 @@
+//! Transparent encryption of the data files, see
+//! [`Config::encrypt`](crate::Config::encrypt).
+//!
+//! Encrypted files start with a magic line, followed by the nonce and the
+//! AES-256-GCM ciphertext of the (possibly compressed) data file. Like
+//! compressed ones, they are recognized from their contents when read.
+//!
+//! The key is a random 256-bit one rather than a passphrase, since the
+//! ciphertext is meant to be published: a passphrase could be guessed
+//! offline. And since each encryption draws a new nonce, a file whose contents
+//! don't change is kept as it is rather than encrypted again.
+
+use crate::config::KEY_ENV_VAR;
+use std::io::BufRead;
+
+const MAGIC: &[u8] = b"regtest-aes256gcm\n";
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+/// Whether this build supports encryption.
+pub(crate) fn supported() -> bool {
+    cfg!(feature = "encryption")
+}
+
+/// The error of using encryption without its feature.
+pub(crate) fn unsupported() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "encrypted data files require the `encryption` feature of regression-test",
+    )
+}
+
+/// The key in [KEY_ENV_VAR].
+fn key() -> std::io::Result<[u8; 32]> {
+    let secret = std::env::var(KEY_ENV_VAR).unwrap_or_default();
+    if secret.trim().is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "encrypted data files need the key in the {} environment variable",
+                KEY_ENV_VAR
+            ),
+        ));
+    }
+    parse_key(secret.trim()).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "{} must hold a 256-bit key as 64 hexadecimal digits, e.g. from `openssl rand -hex 32`",
+                KEY_ENV_VAR
+            ),
+        )
+    })
+}
+
+/// The 256-bit key written as 64 hexadecimal digits in `hex`.
+fn parse_key(hex: &str) -> Option<[u8; 32]> {
+    let digits = hex.as_bytes();
+    if digits.len() != 64 || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    let mut key = [0; 32];
+    for (byte, pair) in key.iter_mut().zip(digits.chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(key)
+}
+
+/// Encrypts the contents of a data file with the key in [KEY_ENV_VAR]. If
+/// the `previous` contents of the file hold the same data, they are returned
+/// as they are.
+pub(crate) fn encrypt(data: Vec<u8>, previous: Option<&[u8]>) -> std::io::Result<Vec<u8>> {
+    if !supported() {
+        return Err(unsupported());
+    }
+    seal_over(&data, previous, &key()?)
+}
+
+/// `data` encrypted with `key`, or the `previous` contents of the file if
+/// they hold `data` already.
+fn seal_over(data: &[u8], previous: Option<&[u8]>, key: &[u8; 32]) -> std::io::Result<Vec<u8>> {
+    match previous {
+        Some(previous)
+            if is_encrypted(previous) && open(previous, key).is_ok_and(|old| old == data) =>
+        {
+            Ok(previous.to_vec())
+        }
+        _ => seal(data, key),
+    }
+}
+
+/// Whether `data` is the contents of an encrypted data file.
+pub(crate) fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// The decrypted contents of `reader`, which may or may not be encrypted.
+/// Encrypted files are authenticated, so they are read whole first.
+pub(crate) fn decrypter<'a, R: BufRead + Send + 'a>(
+    mut reader: R,
+) -> std::io::Result<Box<dyn BufRead + Send + 'a>> {
+    if !reader.fill_buf()?.starts_with(MAGIC) {
+        return Ok(Box::new(reader));
+    }
+    if !supported() {
+        return Err(unsupported());
+    }
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(Box::new(std::io::Cursor::new(open(&data, &key()?)?)))
+}
+
+#[cfg(feature = "encryption")]
+fn cipher(key: &[u8; 32]) -> aes_gcm::Aes256Gcm {
+    use aes_gcm::KeyInit;
+    aes_gcm::Aes256Gcm::new(key.into())
+}
+
+/// `data` encrypted with `key`, as stored.
+#[cfg(feature = "encryption")]
+fn seal(data: &[u8], key: &[u8; 32]) -> std::io::Result<Vec<u8>> {
+    use aes_gcm::aead::{Aead, AeadCore, OsRng};
+    let nonce = aes_gcm::Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key)
+        .encrypt(&nonce, data)
+        .map_err(|_| std::io::Error::other("failed to encrypt the data file"))?;
+    Ok([MAGIC, nonce.as_slice(), &ciphertext].concat())
+}
+
+/// The data stored encrypted with `key` as `stored`.
+#[cfg(feature = "encryption")]
+fn open(stored: &[u8], key: &[u8; 32]) -> std::io::Result<Vec<u8>> {
+    use aes_gcm::aead::Aead;
+    let data = &stored[MAGIC.len()..];
+    if data.len() < NONCE_LEN {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+    cipher(key)
+        .decrypt(aes_gcm::Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "failed to decrypt the data file: the key in {} is wrong, or the file was altered",
+                    KEY_ENV_VAR
+                ),
+            )
+        })
+}
+
+#[cfg(not(feature = "encryption"))]
+fn seal(_: &[u8], _: &[u8; 32]) -> std::io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn open(_: &[u8], _: &[u8; 32]) -> std::io::Result<Vec<u8>> {
+    Err(unsupported())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_with_the_key() {
+        let mut data = String::new();
+        decrypter(&b"[1, 2]"[..])
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, "[1, 2]");
+        let key = [7; 32];
+        if !supported() {
+            assert!(seal(b"[1, 2]", &key).is_err());
+            return;
+        }
+
+        let sealed = seal(b"[1, 2]", &key).unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.windows(4).any(|w| w == b"1, 2"));
+        assert_eq!(open(&sealed, &key).unwrap(), b"[1, 2]");
+
+        assert!(open(&sealed, &[8; 32]).is_err());
+        let mut altered = sealed.clone();
+        *altered.last_mut().unwrap() ^= 1;
+        assert!(open(&altered, &key).is_err());
+
+        // Unchanged files are not encrypted again
+        assert_eq!(seal_over(b"[1, 2]", Some(&sealed), &key).unwrap(), sealed);
+        let changed = seal_over(b"[1, 3]", Some(&sealed), &key).unwrap();
+        assert_eq!(open(&changed, &key).unwrap(), b"[1, 3]");
+        assert_ne!(seal_over(b"[1, 2]", None, &key).unwrap(), sealed);
+    }
+
+    #[test]
+    fn keys_are_256_bits_in_hex() {
+        let hex = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+        let key = parse_key(hex).unwrap();
+        assert_eq!((key[1], key[31]), (0x11, 0xff));
+        assert!(parse_key("correct horse battery staple").is_none());
+        assert!(parse_key(&hex[2..]).is_none());
+        assert!(parse_key(&hex.replace('0', "g")).is_none());
+    }
+}
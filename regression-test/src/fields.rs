@@ -0,0 +1,175 @@
+//! Field-level differences between values serialized as JSON, see
+//! [`RegTest::regtest_eq`](crate::RegTest::regtest_eq) and
+//! [`RegTest::regtest_json`](crate::RegTest::regtest_json).
+
+use crate::report::Change;
+use serde_json::{Map, Value};
+
+/// Paths of the fields which differ between the JSON documents `expected`
+/// and `actual`, e.g. `config.retries` or `items[2].name`. Empty if either
+/// isn't JSON, or if the documents differ as a whole (e.g. two numbers).
+pub(crate) fn differing_fields(expected: &str, actual: &str) -> Vec<String> {
+    let (Ok(expected), Ok(actual)) = (
+        serde_json::from_str::<Value>(expected),
+        serde_json::from_str::<Value>(actual),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut fields = Vec::new();
+    diff("", &expected, &actual, &mut fields);
+    fields.retain(|field| !field.is_empty());
+    fields
+}
+
+fn diff(path: &str, expected: &Value, actual: &Value, fields: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            let mut keys: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                let field = match path {
+                    "" => key.clone(),
+                    _ => format!("{}.{}", path, key),
+                };
+                match (expected.get(key), actual.get(key)) {
+                    (Some(expected), Some(actual)) => diff(&field, expected, actual, fields),
+                    _ => fields.push(field),
+                }
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                diff(&format!("{}[{}]", path, i), expected, actual, fields);
+            }
+        }
+        _ if expected != actual => fields.push(path.to_string()),
+        _ => {}
+    }
+}
+
+/// `value` as compact JSON, with the keys of all objects sorted.
+pub(crate) fn canonical_json(value: &Value) -> String {
+    fn sorted(value: &Value) -> Value {
+        match value {
+            Value::Object(object) => {
+                let mut keys: Vec<&String> = object.keys().collect();
+                keys.sort_unstable();
+                let map: Map<String, Value> = keys
+                    .into_iter()
+                    .map(|key| (key.clone(), sorted(&object[key])))
+                    .collect();
+                Value::Object(map)
+            }
+            Value::Array(items) => Value::Array(items.iter().map(sorted).collect()),
+            other => other.clone(),
+        }
+    }
+    sorted(value).to_string()
+}
+
+/// The changes between the JSON documents `expected` and `actual`, at the
+/// JSON pointers (RFC 6901) of the values which differ. Empty if either isn't
+/// JSON.
+pub(crate) fn json_changes(expected: &str, actual: &str) -> Vec<Change> {
+    let (Ok(expected), Ok(actual)) = (
+        serde_json::from_str::<Value>(expected),
+        serde_json::from_str::<Value>(actual),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut changes = Vec::new();
+    changes_at(String::new(), Some(&expected), Some(&actual), &mut changes);
+    changes
+}
+
+fn changes_at(
+    pointer: String,
+    expected: Option<&Value>,
+    actual: Option<&Value>,
+    changes: &mut Vec<Change>,
+) {
+    let child = |token: &str| {
+        format!(
+            "{}/{}",
+            pointer,
+            token.replace('~', "~0").replace('/', "~1")
+        )
+    };
+    match (expected, actual) {
+        (Some(Value::Object(expected)), Some(Value::Object(actual))) => {
+            let mut keys: Vec<&String> = expected.keys().chain(actual.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                changes_at(child(key), expected.get(key), actual.get(key), changes);
+            }
+        }
+        (Some(Value::Array(expected)), Some(Value::Array(actual))) => {
+            for i in 0..expected.len().max(actual.len()) {
+                changes_at(
+                    child(&i.to_string()),
+                    expected.get(i),
+                    actual.get(i),
+                    changes,
+                );
+            }
+        }
+        _ if expected != actual => changes.push(Change {
+            pointer,
+            expected: expected.cloned(),
+            actual: actual.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_nested_fields() {
+        let expected = r#"{"name": "a", "items": [{"id": 1}, {"id": 2}], "tags": [1], "gone": 0}"#;
+        let actual = r#"{"items": [{"id": 1}, {"id": 3}], "name": "a", "tags": [1, 2], "new": 0}"#;
+        assert_eq!(
+            differing_fields(expected, actual),
+            ["gone", "items[1].id", "new", "tags"]
+        );
+        assert!(differing_fields("1", "2").is_empty());
+        assert!(differing_fields("not json", "{}").is_empty());
+    }
+
+    #[test]
+    fn lists_json_changes() {
+        let value: Value =
+            serde_json::from_str(r#"{"b": [1, {"d": 2, "c": 3}], "a": null}"#).unwrap();
+        assert_eq!(
+            canonical_json(&value),
+            r#"{"a":null,"b":[1,{"c":3,"d":2}]}"#
+        );
+
+        let changes = json_changes(
+            r#"{"a/b": 1, "l": [1, 2], "same": 0}"#,
+            r#"{"a/b": 2, "l": [1], "same": 0, "new": true}"#,
+        );
+        let changes: Vec<(&str, Option<String>, Option<String>)> = changes
+            .iter()
+            .map(|c| {
+                let text = |v: &Option<Value>| v.as_ref().map(Value::to_string);
+                (c.pointer.as_str(), text(&c.expected), text(&c.actual))
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("/a~1b", Some("1".to_string()), Some("2".to_string())),
+                ("/l/1", Some("2".to_string()), None),
+                ("/new", None, Some("true".to_string())),
+            ]
+        );
+        assert_eq!(json_changes("1", "2")[0].pointer, "");
+    }
+}
@@ -0,0 +1,54 @@
+//! Rounding of the floating-point numbers in recorded output, see
+//! [`Config::float_digits`](crate::Config::float_digits).
+
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+/// `text` with its decimal numbers (e.g. `0.30000000000000004` or
+/// `1.5e-7`) rounded to `digits` significant digits. Numbers which already
+/// have no more digits keep their spelling, and dotted sequences such as
+/// versions (`1.2.3`) are left alone.
+pub(crate) fn round(text: &str, digits: u32) -> Cow<'_, str> {
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    let number = NUMBER
+        .get_or_init(|| Regex::new(r"[0-9]+(?:\.[0-9]+)+(?:[eE][-+]?[0-9]+)?").expect("valid"));
+    number.replace_all(text, |c: &Captures| {
+        round_number(&c[0], digits).unwrap_or_else(|| c[0].to_string())
+    })
+}
+
+fn round_number(number: &str, digits: u32) -> Option<String> {
+    if number.matches('.').count() > 1 {
+        return None;
+    }
+    let value: f64 = number.parse().ok()?;
+    let rounded: f64 = format!("{:.*e}", digits.saturating_sub(1) as usize, value)
+        .parse()
+        .ok()?;
+    if rounded == value {
+        return None;
+    }
+    Some(if number.contains(['e', 'E']) {
+        format!("{:e}", rounded)
+    } else {
+        rounded.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_to_significant_digits() {
+        assert_eq!(
+            round("sum: 0.30000000000000004, mean -12.3456789", 6),
+            "sum: 0.3, mean -12.3457"
+        );
+        assert_eq!(round("1.5000000001e-7", 4), "1.5e-7");
+        assert_eq!(round("1.50 and 2.0", 6), "1.50 and 2.0");
+        assert_eq!(round("version 1.2.3456789", 3), "version 1.2.3456789");
+        assert!(matches!(round("no floats: 42", 3), Cow::Borrowed(_)));
+    }
+}
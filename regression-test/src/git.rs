@@ -0,0 +1,79 @@
+//! Reading baselines from another git revision instead of the working tree.
+//!
+//! When a baseline revision is configured (see [`Config::baseline`]), tests
+//! compare against their data files as committed at that revision, read with
+//! `git show`. This tells what changed relative to e.g. `origin/main` even
+//! when the data files of the working tree were already regenerated.
+//!
+//! [`Config::baseline`]: crate::Config::baseline
+
+use std::path::{Component, Path};
+use std::process::Command;
+
+/// Contents of `path` at the git revision `rev`, of the repository containing
+/// it, or `None` if the file doesn't exist at that revision.
+pub(crate) fn show(rev: &str, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    let path = std::path::absolute(path)?;
+    let repo = path
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is not in a git repository", path.display()),
+            )
+        })?;
+
+    // Git wants `/` separators, relative to the repository root
+    let relative: Vec<String> = path
+        .strip_prefix(repo)
+        .unwrap_or(&path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let object = format!("{}:{}", rev, relative.join("/"));
+
+    let git = |args: &[&str]| Command::new("git").arg("-C").arg(repo).args(args).output();
+
+    // Tell an unknown revision apart from a file missing at that revision
+    let commit = git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("{}^{{commit}}", rev),
+    ])?;
+    if !commit.status.success() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("unknown git revision `{}`", rev),
+        ));
+    }
+
+    let output = git(&["show", &object])?;
+    Ok(output.status.success().then_some(output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_committed_files() {
+        // This crate's own manifest, as committed
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        if let Ok(text) = show("HEAD", &manifest) {
+            let text = text.expect("committed manifest");
+            assert!(String::from_utf8_lossy(&text).contains("name = \"regression-test\""));
+            assert!(
+                show("HEAD", &manifest.with_file_name("missing.json"))
+                    .unwrap()
+                    .is_none()
+            );
+        }
+
+        assert!(show("no-such-revision", &manifest).is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Detection of regression data ignored by git.
+//!
+//! Baselines which git ignores are never committed, so every fresh checkout
+//! silently regenerates them instead of comparing against them. When a data
+//! file is written, the ignore files of its repository are checked and a
+//! warning is printed (once per process), unless the configuration declares
+//! the baselines as local-only (see [`Config::local_only`]).
+//!
+//! [`Config::local_only`]: crate::Config::local_only
+
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The ignore rule matching `path`, as `file: pattern`, if git ignores
+/// it.
+///
+/// Only the `.gitignore` files of the repository containing `path` and its
+/// `.git/info/exclude` file are considered. As in git, deeper files take
+/// precedence, and later rules take precedence within a file.
+pub(crate) fn ignoring_rule(path: &Path) -> Option<String> {
+    let path = std::path::absolute(path).ok()?;
+    let repo = path.ancestors().find(|dir| dir.join(".git").exists())?;
+
+    let mut matchers: Vec<Gitignore> = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repo))
+        .filter_map(|dir| build(dir, &dir.join(".gitignore")))
+        .collect();
+    matchers.extend(build(repo, &repo.join(".git/info/exclude")));
+
+    for matcher in &matchers {
+        match matcher.matched_path_or_any_parents(&path, false) {
+            Match::None => continue,
+            Match::Whitelist(_) => return None,
+            Match::Ignore(glob) => {
+                let file = glob
+                    .from()
+                    .map_or_else(|| "<unknown>".to_string(), |f| f.display().to_string());
+                return Some(format!("{}: {}", file, glob.original()));
+            }
+        }
+    }
+
+    None
+}
+
+fn build(root: &Path, file: &Path) -> Option<Gitignore> {
+    if !file.is_file() {
+        return None;
+    }
+    let mut builder = GitignoreBuilder::new(root);
+    builder.add(file);
+    builder.build().ok()
+}
+
+/// Prints a warning if git ignores the regression data file `path`. Only the
+/// first ignored file of the process is reported.
+pub(crate) fn warn_if_ignored(path: &Path) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if WARNED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let Some(rule) = ignoring_rule(path) else {
+        return;
+    };
+    if WARNED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    // Written to stderr directly, so libtest doesn't capture it
+    let _ = writeln!(
+        std::io::stderr(),
+        "\n\
+         ************************************************************************\n\
+         warning: regression data is ignored by git and will not be committed:\n\
+         \x20   {}\n\
+         \x20   (ignored by {})\n\
+         Set `local_only = true` in regtest.toml if this is intended.\n\
+         ************************************************************************\n",
+        path.display(),
+        rule
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_ignoring_rule() {
+        let repo = std::env::temp_dir().join(format!("regtest-git-{}", std::process::id()));
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join("crate")).unwrap();
+        std::fs::write(repo.join(".gitignore"), "target/\nregtest_data/\n").unwrap();
+        std::fs::write(repo.join("crate/.gitignore"), "!regtest_data/\n").unwrap();
+
+        let ignored = repo.join("regtest_data/src/lib/test.json");
+        let rule = ignoring_rule(&ignored).unwrap();
+        assert!(rule.ends_with(".gitignore: regtest_data/"), "{}", rule);
+
+        // A deeper .gitignore takes precedence
+        assert_eq!(ignoring_rule(&repo.join("crate/regtest_data/a.json")), None);
+        assert_eq!(ignoring_rule(&repo.join("data/a.json")), None);
+
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+}
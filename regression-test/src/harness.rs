@@ -0,0 +1,278 @@
+//! A test harness for `#[regtest]` functions, built on
+//! [libtest-mimic](https://docs.rs/libtest-mimic), for test targets which
+//! don't use libtest:
+//!
+//! ```toml
+//! [[test]]
+//! name = "regressions"
+//! harness = false
+//! ```
+//!
+//! ```rust,ignore
+//! use regression_test::RegTest;
+//! use regression_test_macros::regtest;
+//!
+//! #[regtest(tags("parser"))]
+//! fn parses(rt: RegTest) {
+//!     rt.regtest(parse("1 + 2"));
+//! }
+//!
+//! fn main() {
+//!     regression_test::harness::main();
+//! }
+//! ```
+//!
+//! The `#[regtest]` functions of the target register themselves, and run like
+//! they would under libtest, taking the same arguments (filters, `--ignored`,
+//! `--test-threads`, ...), plus:
+//!
+//! - `--tag <TAG>` runs only the tests with the tag, and can be repeated,
+//! - `--update` regenerates the baselines of the tests which run,
+//! - `--review` goes through the pending files written by the run once it is
+//!   done, showing how each differs from its baseline and asking whether to
+//!   accept it.
+//!
+//! `async` functions aren't registered, and `#[should_panic]` isn't supported.
+//! Requires the `harness` feature.
+
+use crate::{ChangedEntry, RegEntry, accepted_path, diff_files, entry_dir};
+use libtest_mimic::{Arguments, Trial};
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[doc(hidden)]
+pub use inventory as __inventory;
+
+/// A `#[regtest]` function, registered by the macro.
+#[doc(hidden)]
+pub struct __Regtest {
+    /// Path of the function, starting with the crate name.
+    pub path: &'static str,
+    pub tags: &'static [&'static str],
+    pub ignore: bool,
+    pub run: fn(),
+}
+
+inventory::collect!(__Regtest);
+
+/// Registers a `#[regtest]` function with the harness, used by the macro.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_regtest {
+    ($path:expr, $tags:expr, $ignore:expr, $run:block) => {
+        const _: () = {
+            fn run() $run
+
+            $crate::harness::__inventory::submit! {
+                $crate::harness::__Regtest {
+                    path: $path,
+                    tags: $tags,
+                    ignore: $ignore,
+                    run,
+                }
+            }
+        };
+    };
+}
+
+thread_local! {
+    /// Name of the test running on this thread.
+    static CURRENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Whether `--update` was given.
+static UPDATE: AtomicBool = AtomicBool::new(false);
+
+/// Pending files written by the tests of this run.
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Name of the test the harness is running on this thread, if any.
+pub(crate) fn current_test_name() -> Option<String> {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+/// Whether the baselines of the tests should be regenerated.
+pub(crate) fn updating() -> bool {
+    UPDATE.load(Ordering::Relaxed)
+}
+
+/// Remembers that a test wrote the pending file `path`, for `--review`.
+pub(crate) fn record_pending(path: PathBuf) {
+    PENDING.lock().unwrap_or_else(|e| e.into_inner()).push(path);
+}
+
+/// The options of the harness, next to those of libtest.
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    tags: Vec<String>,
+    update: bool,
+    review: bool,
+}
+
+/// Splits the harness's own options off the command line `args`, leaving
+/// those of libtest.
+fn parse_args(args: impl IntoIterator<Item = String>) -> (Options, Vec<String>) {
+    let mut options = Options::default();
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--update" => options.update = true,
+            "--review" => options.review = true,
+            "--tag" => match args.next() {
+                Some(tag) => options.tags.push(tag),
+                None => rest.push(arg),
+            },
+            _ => match arg.strip_prefix("--tag=") {
+                Some(tag) => options.tags.push(tag.to_string()),
+                None => rest.push(arg),
+            },
+        }
+    }
+    (options, rest)
+}
+
+/// The name libtest would give the test at `path`, without the crate name.
+fn test_name(path: &str) -> &str {
+    path.split_once("::").map_or(path, |(_, name)| name)
+}
+
+/// Runs the `#[regtest]` functions of the test target with the arguments of
+/// the process, then exits.
+pub fn main() -> ! {
+    let (options, args) = parse_args(std::env::args());
+    let arguments = Arguments::from_iter(args);
+    UPDATE.store(options.update, Ordering::Relaxed);
+
+    let mut trials: Vec<Trial> = inventory::iter::<__Regtest>()
+        .filter(|test| {
+            options.tags.is_empty()
+                || test
+                    .tags
+                    .iter()
+                    .any(|tag| options.tags.iter().any(|t| t == tag))
+        })
+        .map(|test| {
+            let name = test_name(test.path).to_string();
+            let run = test.run;
+            Trial::test(name.clone(), move || {
+                CURRENT.set(Some(name));
+                run();
+                Ok(())
+            })
+            .with_ignored_flag(test.ignore)
+        })
+        .collect();
+    trials.sort_by(|a, b| a.name().cmp(b.name()));
+
+    let conclusion = libtest_mimic::run(&arguments, trials);
+    if options.review {
+        let pending = std::mem::take(&mut *PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+        review(pending);
+    }
+    conclusion.exit()
+}
+
+/// Shows how each of the `pending` files differs from its baseline, and asks
+/// whether to accept it.
+fn review(mut pending: Vec<PathBuf>) {
+    if pending.is_empty() {
+        println!("No pending regression data files to review.");
+        return;
+    }
+    pending.sort();
+
+    let mut stdin = std::io::stdin().lock();
+    for file in pending {
+        let baseline = accepted_path(&file);
+        let diff = match diff_files(&baseline, &file) {
+            Ok(diff) => diff,
+            Err(e) => {
+                eprintln!("warning: failed to compare {}: {}", file.display(), e);
+                continue;
+            }
+        };
+
+        println!("\n{}", file.display());
+        for change in &diff.changed {
+            print_change(change);
+        }
+        for entry in &diff.removed {
+            print_entry('-', entry);
+        }
+        for entry in &diff.added {
+            print_entry('+', entry);
+        }
+
+        print!("Accept? [y/N/q] ");
+        let _ = std::io::stdout().flush();
+        let mut answer = String::new();
+        if stdin.read_line(&mut answer).unwrap_or(0) == 0 {
+            break;
+        }
+        match answer.trim() {
+            "y" | "Y" => match accept(&file, &baseline) {
+                Ok(()) => println!("Accepted {}", baseline.display()),
+                Err(e) => eprintln!("warning: failed to accept {}: {}", file.display(), e),
+            },
+            "q" | "Q" => break,
+            _ => println!("Kept {} for later", file.display()),
+        }
+    }
+}
+
+fn print_change(change: &ChangedEntry) {
+    print_entry('-', &change.before);
+    print_entry('+', &change.after);
+}
+
+fn print_entry(sign: char, entry: &RegEntry) {
+    for line in entry.message().lines() {
+        println!("  {} {}", sign, line);
+    }
+}
+
+/// Replaces `baseline` with the pending file `file`, like
+/// `cargo regtest accept`.
+fn accept(file: &Path, baseline: &Path) -> std::io::Result<()> {
+    std::fs::rename(file, baseline)?;
+    // Pending files hold their messages, whatever the layout
+    let entries = entry_dir(baseline);
+    if entries.is_dir() {
+        std::fs::remove_dir_all(&entries)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harness_options_are_split_off() {
+        let args = [
+            "regressions",
+            "--tag",
+            "parser",
+            "parses",
+            "--update",
+            "--tag=slow",
+            "--test-threads",
+            "1",
+        ];
+        let (options, rest) = parse_args(args.map(String::from));
+        assert_eq!(
+            options,
+            Options {
+                tags: vec!["parser".to_string(), "slow".to_string()],
+                update: true,
+                review: false,
+            }
+        );
+        assert_eq!(rest, ["regressions", "parses", "--test-threads", "1"]);
+        assert_eq!(test_name("my_crate::tests::parses"), "tests::parses");
+    }
+}
@@ -0,0 +1,451 @@
+//! Standalone HTML report with side-by-side diffs of every mismatch of a run.
+//!
+//! When a report directory is configured (see [`Config::html_report`]), each
+//! test binary writes the mismatches it found to `<binary>.html` in it, and
+//! `index.html` links to the pages of all binaries. A binary's page from a
+//! previous run is removed as soon as it starts using regression data again,
+//! so the directory only ever describes the latest run of each binary. Under
+//! nextest, which runs every test in a process of its own, the page keeps the
+//! mismatches of the other tests of the binary instead, and only those of the
+//! test starting are removed.
+//!
+//! [`Config::html_report`]: crate::Config::html_report
+
+use crate::render::escape_markup;
+use crate::report::{Mismatch, MismatchKind};
+use crate::{nextest, registry};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Mutex, Once, OnceLock};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+h2 { font-size: 1.1em; margin-top: 2em; }
+.meta { color: #555; }
+table.diff { border-collapse: collapse; width: 100%; table-layout: fixed; font-family: monospace; }
+table.diff td { padding: 0 0.5em; white-space: pre-wrap; word-break: break-all; vertical-align: top; }
+table.diff td.num { width: 3em; color: #999; text-align: right; user-select: none; }
+table.diff th { text-align: left; padding: 0.3em 0.5em; background: #eee; }
+td.del { background: #ffebe9; }
+td.ins { background: #dafbe1; }
+td.empty { background: #f6f8fa; }
+";
+
+/// The first line of a section, followed by its (escaped) test and ` -->`.
+const SECTION_START: &str = "<!-- regtest-section: ";
+/// The last line of a section.
+const SECTION_END: &str = "<!-- /regtest-section -->";
+
+/// One reported mismatch.
+struct Section {
+    test: String,
+    subtest: Option<String>,
+    file: String,
+    mismatch: Mismatch,
+}
+
+/// Removes this binary's page from a previous run, or under nextest the
+/// mismatches of the test running from it. Called whenever a test starts, but
+/// only acts once per process.
+pub(crate) fn start(dir: &Path) {
+    static START: Once = Once::new();
+    START.call_once(|| {
+        let page = dir.join(page_name());
+        if !nextest::process_per_test() {
+            let _ = std::fs::remove_file(&page);
+        } else if page.exists() {
+            let test = registry::current_test_name();
+            let _ = nextest::rewrite(&page, |existing| {
+                render_page(
+                    &registry::binary_name(),
+                    &other_sections(existing, &[&test]),
+                )
+            });
+        }
+        let _ = write_index(dir);
+    });
+}
+
+/// Adds `mismatch` to this binary's page in `dir`.
+pub(crate) fn record(
+    dir: &Path,
+    test: &str,
+    subtest: Option<&str>,
+    file: &Path,
+    mismatch: &Mismatch,
+) -> std::io::Result<()> {
+    static SECTIONS: OnceLock<Mutex<Vec<Section>>> = OnceLock::new();
+
+    let mut sections = SECTIONS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    sections.push(Section {
+        test: test.to_string(),
+        subtest: subtest.map(str::to_string),
+        file: match &mismatch.position {
+            Some(position) => format!("{}:{}:{}", position.file, position.line, position.column),
+            None => file.display().to_string(),
+        },
+        mismatch: mismatch.clone(),
+    });
+
+    let binary = registry::binary_name();
+    let mut rendered: Vec<String> = sections.iter().map(render_section).collect();
+    if nextest::process_per_test() {
+        // The other tests of this binary ran in other processes
+        let tests: Vec<&str> = sections.iter().map(|s| s.test.as_str()).collect();
+        nextest::rewrite(&dir.join(page_name()), |existing| {
+            let mut merged = other_sections(existing, &tests);
+            merged.append(&mut rendered);
+            render_page(&binary, &merged)
+        })?;
+    } else {
+        std::fs::create_dir_all(dir)?;
+        std::fs::write(dir.join(page_name()), render_page(&binary, &rendered))?;
+    }
+    write_index(dir)
+}
+
+/// The rendered sections of the page `existing` for tests other than
+/// `tests`.
+fn other_sections(existing: &str, tests: &[&str]) -> Vec<String> {
+    // Sections are delimited by comments written by this module only, and
+    // escaping keeps any message from looking like one
+    let mut sections = Vec::new();
+    let mut section: Option<String> = None;
+    for line in existing.lines() {
+        if let Some(test) = line
+            .strip_prefix(SECTION_START)
+            .and_then(|rest| rest.strip_suffix(" -->"))
+        {
+            let other = !tests.iter().any(|t| escape_markup(t) == test);
+            section = other.then(String::new);
+        }
+        if let Some(section) = &mut section {
+            section.push_str(line);
+            section.push('\n');
+        }
+        if line == SECTION_END {
+            sections.extend(section.take());
+        }
+    }
+    sections
+}
+
+fn page_name() -> String {
+    format!("{}.html", registry::binary_name())
+}
+
+/// Rewrites `index.html`, linking to the pages of all binaries.
+fn write_index(dir: &Path) -> std::io::Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut pages: Vec<String> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(".html") && name != "index.html")
+        .collect();
+    pages.sort();
+
+    let mut body = String::from("<h1>Regression test mismatches</h1>\n");
+    if pages.is_empty() {
+        body.push_str("<p>No mismatches.</p>\n");
+    } else {
+        body.push_str("<ul>\n");
+        for page in &pages {
+            let name = page.trim_end_matches(".html");
+            let _ = writeln!(
+                body,
+                "<li><a href=\"{}\">{}</a></li>",
+                escape_markup(page),
+                escape_markup(name)
+            );
+        }
+        body.push_str("</ul>\n");
+    }
+
+    let index = document("Regression test mismatches", &body);
+    if nextest::process_per_test() {
+        nextest::rewrite(&dir.join("index.html"), |_| index)
+    } else {
+        std::fs::write(dir.join("index.html"), index)
+    }
+}
+
+fn document(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_markup(title),
+        STYLE,
+        body
+    )
+}
+
+/// The page of `binary`, holding the [rendered](render_section) `sections`.
+fn render_page(binary: &str, sections: &[String]) -> String {
+    let mut body = format!(
+        "<p><a href=\"index.html\">All binaries</a></p>\n<h1>{} mismatch(es) in {}</h1>\n",
+        sections.len(),
+        escape_markup(binary)
+    );
+    for section in sections {
+        body.push_str(section);
+    }
+    document(&format!("Regression mismatches in {}", binary), &body)
+}
+
+/// Renders `section`, between comments naming its test.
+fn render_section(section: &Section) -> String {
+    let mut body = format!("{}{} -->\n", SECTION_START, escape_markup(&section.test));
+    let mismatch = &section.mismatch;
+    let test = match &section.subtest {
+        Some(subtest) => format!("{} / {}", section.test, subtest),
+        None => section.test.clone(),
+    };
+    let entry = match (&mismatch.key, mismatch.index) {
+        (Some(key), _) => format!(", entry `{}`", key),
+        (None, Some(index)) => format!(", entry #{}", index),
+        (None, None) => String::new(),
+    };
+
+    let _ = writeln!(
+        body,
+        "<h2>{}</h2>\n<p class=\"meta\">{}{}</p>",
+        escape_markup(&test),
+        escape_markup(&section.file),
+        escape_markup(&entry)
+    );
+
+    match mismatch.kind {
+        MismatchKind::Message
+        | MismatchKind::Inline
+        | MismatchKind::Removed
+        | MismatchKind::Added
+        | MismatchKind::Extra => {
+            body.push_str(&side_by_side(&mismatch.expected, &mismatch.actual));
+        }
+        MismatchKind::Type => {
+            let _ = writeln!(
+                body,
+                "<p>Recorded with {} instead of {}.</p>",
+                escape_markup(&mismatch.actual),
+                escape_markup(&mismatch.expected)
+            );
+        }
+        MismatchKind::Incompatible => {
+            let _ = writeln!(
+                body,
+                "<p>The baseline can no longer be read back: {}</p>",
+                escape_markup(&mismatch.actual)
+            );
+            body.push_str(&side_by_side(&mismatch.expected, ""));
+        }
+        MismatchKind::MissingEntry => {
+            body.push_str("<p>The baseline has no entry for this output:</p>\n");
+            body.push_str(&side_by_side("", &mismatch.actual));
+        }
+    }
+    body.push_str(SECTION_END);
+    body.push('\n');
+    body
+}
+
+/// A line of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line diff based on the longest common subsequence. Very large inputs are
+/// compared line by line instead, to bound the cost.
+fn diff<'a>(expected: &'a str, actual: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = expected.lines().collect();
+    let new: Vec<&str> = actual.lines().collect();
+
+    if old.len().saturating_mul(new.len()) > 4_000_000 {
+        let mut lines = Vec::new();
+        for i in 0..old.len().max(new.len()) {
+            match (old.get(i), new.get(i)) {
+                (Some(o), Some(n)) if o == n => lines.push(Line::Same(o)),
+                (o, n) => {
+                    lines.extend(o.map(|o| Line::Removed(o)));
+                    lines.extend(n.map(|n| Line::Added(n)));
+                }
+            }
+        }
+        return lines;
+    }
+
+    // lcs[i][j]: length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(Line::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(Line::Added(new[j]));
+            j += 1;
+        } else {
+            lines.push(Line::Removed(old[i]));
+            i += 1;
+        }
+    }
+    lines
+}
+
+/// Renders a two-column table, pairing up removed and added lines.
+fn side_by_side(expected: &str, actual: &str) -> String {
+    let mut out = String::from(
+        "<table class=\"diff\">\n<tr><th colspan=\"2\">Expected</th><th colspan=\"2\">Actual</th></tr>\n",
+    );
+    let (mut old_num, mut new_num) = (0, 0);
+
+    let lines = diff(expected, actual);
+    let mut rest = lines.as_slice();
+    while let Some(first) = rest.first() {
+        if let Line::Same(line) = first {
+            old_num += 1;
+            new_num += 1;
+            let line = escape_markup(line);
+            let _ = writeln!(
+                out,
+                "<tr><td class=\"num\">{}</td><td>{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+                old_num, line, new_num, line
+            );
+            rest = &rest[1..];
+            continue;
+        }
+
+        // A block of changes: show removed lines next to added ones
+        let len = rest
+            .iter()
+            .position(|l| matches!(l, Line::Same(_)))
+            .unwrap_or(rest.len());
+        let (block, after) = rest.split_at(len);
+        let removed: Vec<_> = block
+            .iter()
+            .filter_map(|l| match l {
+                Line::Removed(line) => Some(*line),
+                _ => None,
+            })
+            .collect();
+        let added: Vec<_> = block
+            .iter()
+            .filter_map(|l| match l {
+                Line::Added(line) => Some(*line),
+                _ => None,
+            })
+            .collect();
+
+        for row in 0..removed.len().max(added.len()) {
+            out.push_str("<tr>");
+            match removed.get(row) {
+                Some(line) => {
+                    old_num += 1;
+                    let _ = write!(
+                        out,
+                        "<td class=\"num\">{}</td><td class=\"del\">{}</td>",
+                        old_num,
+                        escape_markup(line)
+                    );
+                }
+                None => out.push_str("<td class=\"num\"></td><td class=\"empty\"></td>"),
+            }
+            match added.get(row) {
+                Some(line) => {
+                    new_num += 1;
+                    let _ = write!(
+                        out,
+                        "<td class=\"num\">{}</td><td class=\"ins\">{}</td>",
+                        new_num,
+                        escape_markup(line)
+                    );
+                }
+                None => out.push_str("<td class=\"num\"></td><td class=\"empty\"></td>"),
+            }
+            out.push_str("</tr>\n");
+        }
+
+        rest = after;
+    }
+
+    out.push_str("</table>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_keep_the_sections_of_other_tests() {
+        let section = |test: &str, actual: &str| {
+            render_section(&Section {
+                test: test.to_string(),
+                subtest: None,
+                file: "t.json".to_string(),
+                mismatch: Mismatch {
+                    kind: MismatchKind::Message,
+                    index: Some(0),
+                    key: None,
+                    note: None,
+                    heading: None,
+                    fields: Vec::new(),
+                    changes: Vec::new(),
+                    position: None,
+                    caller: None,
+                    expected: "a".to_string(),
+                    actual: actual.to_string(),
+                },
+            })
+        };
+        // A message can't end a section early
+        let first = section("a::t", SECTION_END);
+        let page = render_page("it", &[first.clone(), section("b::t", "x")]);
+        assert!(page.contains("2 mismatch(es) in it"));
+
+        let mut merged = other_sections(&page, &["b::t"]);
+        assert_eq!(merged, [first]);
+        merged.push(section("b::t", "y"));
+        let page = render_page("it", &merged);
+        assert!(page.contains("2 mismatch(es) in it"));
+        assert!(page.contains(">y</td>") && !page.contains(">x</td>"));
+    }
+
+    #[test]
+    fn diff_keeps_common_lines() {
+        let lines = diff("a\nb\nc\nd", "a\nc\nx\nd");
+        assert_eq!(
+            lines,
+            [
+                Line::Same("a"),
+                Line::Removed("b"),
+                Line::Same("c"),
+                Line::Added("x"),
+                Line::Same("d"),
+            ]
+        );
+
+        let table = side_by_side("a\n<b>", "a\nc");
+        assert!(table.contains(
+            "<td class=\"del\">&lt;b&gt;</td><td class=\"num\">2</td><td class=\"ins\">c</td>"
+        ));
+    }
+}
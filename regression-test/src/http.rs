@@ -0,0 +1,560 @@
+//! Recording and replay of the HTTP exchanges of tests, see
+//! [`RegTest::with_http_replay`](crate::RegTest::with_http_replay), and the
+//! minimal HTTP client behind it and the network integrations (metrics push,
+//! remote snapshot stores). Only plain `http://` URLs are supported.
+
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Timeout of each network operation, unless a caller needs another.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Headers which only concern one connection, and are never forwarded.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Response headers carrying credentials, which are not recorded, so that
+/// they stay out of the data file.
+const CREDENTIALS: &[&str] = &[
+    "authentication-info",
+    "proxy-authentication-info",
+    "set-cookie",
+    "set-cookie2",
+    "www-authenticate",
+];
+
+/// A response: the status code, the content type, the other headers and the
+/// body.
+pub(crate) struct Response {
+    pub(crate) status: u16,
+    pub(crate) content_type: Option<String>,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+    pub(crate) fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Sends a `method` request for `url`, with `body` of type `content_type`,
+/// giving up on each network operation after `timeout`.
+///
+/// HTTP/1.0 is used, so that responses are never chunked.
+pub(crate) fn request(
+    method: &str,
+    url: &str,
+    content_type: &str,
+    body: &[u8],
+    timeout: Duration,
+) -> std::io::Result<Response> {
+    let headers = [("Content-Type".to_string(), content_type.to_string())];
+    send(method, url, &headers, body, timeout)
+}
+
+/// Sends a `method` request for `url`, with `headers` (besides `Host` and
+/// `Content-Length`) and `body`, like [request].
+fn send(
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &[u8],
+    timeout: Duration,
+) -> std::io::Result<Response> {
+    let invalid = |message: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        invalid(format!(
+            "unsupported URL {} (only http:// is supported)",
+            url
+        ))
+    })?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let socket = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid(format!("cannot resolve {}", host)))?;
+    let mut stream = TcpStream::connect_timeout(&socket, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut head = format!("{} {} HTTP/1.0\r\nHost: {}\r\n", method, path, host);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let header_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| std::io::Error::other("malformed HTTP response"))?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| std::io::Error::other("malformed HTTP status line"))?;
+
+    Ok(Response {
+        status,
+        content_type: header(&head, "content-type"),
+        headers: all_headers(&head),
+        body: response[header_end + 4..].to_vec(),
+    })
+}
+
+/// The headers of the head of a request or a response, in order.
+fn all_headers(head: &str) -> Vec<(String, String)> {
+    head.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Whether the proxy forwards the request header `name` to the upstream
+/// server: all but the [hop-by-hop](HOP_BY_HOP) ones, `Host`, and
+/// `Content-Length`, which is set anew.
+fn is_forwarded(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    !HOP_BY_HOP.contains(&name.as_str()) && name != "host" && name != "content-length"
+}
+
+/// Whether the proxy records (and replays) the response header `name`: all
+/// but the [hop-by-hop](HOP_BY_HOP) ones, those carrying
+/// [credentials](CREDENTIALS), `Content-Length`, which is set anew,
+/// `Content-Type`, which is recorded apart, and `Date`, which would change
+/// the exchange every time it is recorded.
+fn is_recorded(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    !HOP_BY_HOP.contains(&name.as_str())
+        && !CREDENTIALS.contains(&name.as_str())
+        && !matches!(name.as_str(), "content-length" | "content-type" | "date")
+}
+
+/// The value of the header `name` (lowercase) in the head of a request or a
+/// response.
+fn header(head: &str, name: &str) -> Option<String> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim().to_ascii_lowercase() == name).then(|| value.trim().to_string())
+    })
+}
+
+/// An HTTP request and the response it got, as stored in the regression
+/// data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Exchange {
+    /// The server the request was meant for.
+    pub(crate) upstream: String,
+    pub(crate) method: String,
+    /// The path and query of the request.
+    pub(crate) path: String,
+    #[serde(default, skip_serializing_if = "Body::is_empty")]
+    pub(crate) request: Body,
+    pub(crate) status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) content_type: Option<String>,
+    /// The [recorded](is_recorded) headers of the response, in order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Body::is_empty")]
+    pub(crate) response: Body,
+}
+
+/// A request or response body: text if it is UTF-8, hexadecimal otherwise.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Body {
+    #[default]
+    #[serde(skip)]
+    Empty,
+    Text(String),
+    Hex(String),
+}
+
+impl Body {
+    fn new(bytes: &[u8]) -> Self {
+        if bytes.is_empty() {
+            return Body::Empty;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Body::Text(text.to_string()),
+            Err(_) => Body::Hex(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        matches!(self, Body::Empty)
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Body::Empty => Vec::new(),
+            Body::Text(text) => text.as_bytes().to_vec(),
+            Body::Hex(hex) => (0..hex.len() / 2)
+                .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+                .collect(),
+        }
+    }
+}
+
+/// A local HTTP server standing in for an upstream server while a test
+/// runs, see [`RegTest::with_http_replay`](crate::RegTest::with_http_replay).
+///
+/// When generating the baseline, requests are forwarded to the upstream
+/// server and the exchanges recorded. When comparing, the responses are
+/// replayed from the baseline, without network access.
+pub struct HttpProxy {
+    url: String,
+    upstream: String,
+    stop: Arc<AtomicBool>,
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HttpProxy {
+    /// Starts a proxy for `upstream`, replaying the exchanges `replay` if
+    /// given, or forwarding the requests otherwise.
+    pub(crate) fn start(upstream: &str, replay: Option<Vec<Exchange>>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let upstream = upstream.trim_end_matches('/').to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let exchanges = Arc::new(Mutex::new(Vec::new()));
+
+        let thread = {
+            let (upstream, stop, exchanges) = (upstream.clone(), stop.clone(), exchanges.clone());
+            let mut replay = replay.map(|exchanges| exchanges.into_iter().map(Some).collect());
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    if let Ok(exchange) = serve(stream, &upstream, replay.as_mut()) {
+                        exchanges.lock().unwrap().push(exchange);
+                    }
+                }
+            })
+        };
+
+        Ok(HttpProxy {
+            url,
+            upstream,
+            stop,
+            exchanges,
+            thread: Some(thread),
+        })
+    }
+
+    /// The base URL to send requests to instead of the upstream server, e.g.
+    /// `http://127.0.0.1:41234`.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The upstream server, as given.
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+
+    /// Stops the proxy, returning the exchanges it served, in order.
+    pub(crate) fn finish(mut self) -> Vec<Exchange> {
+        self.shutdown();
+        std::mem::take(&mut *self.exchanges.lock().unwrap())
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            // Wakes the listener up, so that it sees the stop flag
+            let _ = TcpStream::connect(self.url.trim_start_matches("http://"));
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for HttpProxy {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+/// Serves one request received by the proxy: replays the first matching
+/// exchange not replayed yet, if `replay` is given, and forwards the request
+/// to `upstream` otherwise.
+fn serve(
+    mut stream: TcpStream,
+    upstream: &str,
+    replay: Option<&mut Vec<Option<Exchange>>>,
+) -> std::io::Result<Exchange> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        head.push_str(&line);
+    }
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(path)) = (request_line.next(), request_line.next()) else {
+        return Err(std::io::Error::other("malformed HTTP request"));
+    };
+    let length = header(&head, "content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let mut exchange = Exchange {
+        upstream: upstream.to_string(),
+        method: method.to_string(),
+        path: path.to_string(),
+        request: Body::new(&body),
+        status: 502,
+        content_type: Some("text/plain".to_string()),
+        headers: Vec::new(),
+        response: Body::Empty,
+    };
+    match replay {
+        Some(replay) => {
+            let recorded = replay.iter_mut().find(|recorded| {
+                recorded.as_ref().is_some_and(|recorded| {
+                    (&recorded.method, &recorded.path, &recorded.request)
+                        == (&exchange.method, &exchange.path, &exchange.request)
+                })
+            });
+            match recorded.and_then(Option::take) {
+                Some(recorded) => exchange = recorded,
+                None => {
+                    exchange.response = Body::Text(format!(
+                        "No recorded response for {} {}",
+                        exchange.method, exchange.path
+                    ))
+                }
+            }
+        }
+        None => {
+            let forwarded: Vec<(String, String)> = all_headers(&head)
+                .into_iter()
+                .filter(|(name, _)| is_forwarded(name))
+                .collect();
+            let url = format!("{}{}", upstream, path);
+            match send(method, &url, &forwarded, &body, TIMEOUT) {
+                Ok(response) => {
+                    exchange.status = response.status;
+                    exchange.content_type = response.content_type;
+                    exchange.headers = response
+                        .headers
+                        .into_iter()
+                        .filter(|(name, _)| is_recorded(name))
+                        .collect();
+                    exchange.response = Body::new(&response.body);
+                }
+                Err(e) => {
+                    exchange.response = Body::Text(format!("Request to {} failed: {}", url, e))
+                }
+            }
+        }
+    }
+
+    let body = exchange.response.bytes();
+    write!(stream, "HTTP/1.0 {} \r\n", exchange.status)?;
+    if let Some(content_type) = &exchange.content_type {
+        write!(stream, "Content-Type: {}\r\n", content_type)?;
+    }
+    for (name, value) in &exchange.headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    write!(
+        stream,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(exchange)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_forwards_and_replays() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = upstream.accept().unwrap();
+            // Reads the whole request, so that the proxy isn't cut off while
+            // still sending its body
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"hi") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\npong")
+                .unwrap();
+        });
+
+        let proxy = HttpProxy::start(&upstream_url, None).unwrap();
+        let response = request(
+            "POST",
+            &format!("{}/ping", proxy.url()),
+            "text/plain",
+            b"hi",
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(
+            (response.status, response.body.as_slice()),
+            (200, &b"pong"[..])
+        );
+        let exchanges = proxy.finish();
+        server.join().unwrap();
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].request, Body::Text("hi".to_string()));
+
+        // The upstream server is gone: responses come from the recording
+        let proxy = HttpProxy::start(&upstream_url, Some(exchanges.clone())).unwrap();
+        let response = request(
+            "POST",
+            &format!("{}/ping", proxy.url()),
+            "text/plain",
+            b"hi",
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(response.body, b"pong");
+        let response = request(
+            "GET",
+            &format!("{}/other", proxy.url()),
+            "text/plain",
+            &[],
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(response.status, 502);
+        assert_eq!(proxy.finish()[0], exchanges[0]);
+
+        assert_eq!(Body::new(&[0xff, 0x01]).bytes(), [0xff, 0x01]);
+    }
+
+    #[test]
+    fn proxy_forwards_headers() {
+        let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = upstream.accept().unwrap();
+            let mut head = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            // The request has no body: it ends with an empty line
+            while reader.read_line(&mut head).unwrap() > 2 {}
+            stream
+                .write_all(
+                    b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\
+                      Date: Mon, 01 Jan 2024 00:00:00 GMT\r\nETag: \"v1\"\r\n\
+                      Set-Cookie: session=hidden\r\n\r\n{}",
+                )
+                .unwrap();
+            head
+        });
+
+        let proxy = HttpProxy::start(&upstream_url, None).unwrap();
+        let headers = [
+            ("Authorization".to_string(), "Bearer secret".to_string()),
+            ("Accept".to_string(), "application/json".to_string()),
+            ("Connection".to_string(), "keep-alive".to_string()),
+        ];
+        let response = send(
+            "GET",
+            &format!("{}/me", proxy.url()),
+            &headers,
+            &[],
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(response.status, 200);
+        let exchanges = proxy.finish();
+
+        let received = server.join().unwrap();
+        assert_eq!(
+            header(&received, "authorization").as_deref(),
+            Some("Bearer secret")
+        );
+        assert_eq!(
+            header(&received, "accept").as_deref(),
+            Some("application/json")
+        );
+        assert_eq!(header(&received, "connection"), None);
+        assert_eq!(
+            header(&received, "host"),
+            upstream_url.strip_prefix("http://").map(str::to_string)
+        );
+
+        // Request headers aren't stored, and neither are cookies or the date
+        let stored = serde_json::to_string(&exchanges[0]).unwrap();
+        assert!(!stored.contains("secret") && !stored.contains("2024"));
+        assert!(!stored.contains("hidden"));
+        assert_eq!(
+            exchanges[0].headers,
+            [("ETag".to_string(), "\"v1\"".to_string())]
+        );
+
+        // The upstream server is gone: the headers come from the recording
+        let proxy = HttpProxy::start(&upstream_url, Some(exchanges)).unwrap();
+        let response = send(
+            "GET",
+            &format!("{}/me", proxy.url()),
+            &headers,
+            &[],
+            TIMEOUT,
+        )
+        .unwrap();
+        assert_eq!(response.content_type.as_deref(), Some("application/json"));
+        assert!(
+            response
+                .headers
+                .contains(&("ETag".to_string(), "\"v1\"".to_string()))
+        );
+    }
+}
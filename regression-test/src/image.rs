@@ -0,0 +1,364 @@
+//! Images recorded with [`RegTest::regtest_image`](crate::RegTest::regtest_image).
+//!
+//! Images are stored as PNG files next to the data file. Only the PNG
+//! features needed for golden images are supported: non-interlaced images,
+//! with 8 or 16 bits per sample, or a palette.
+
+use flate2::Crc;
+use std::io::{Read, Write};
+
+/// The PNG file signature.
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// An image, as 8-bit RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    /// An image of `width` × `height` pixels, given row by row as RGBA
+    /// samples (4 bytes per pixel).
+    ///
+    /// # Panics
+    /// Panics if `pixels` doesn't hold exactly `width * height` pixels.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "an image of {}x{} pixels needs {} RGBA bytes",
+            width,
+            height,
+            width as usize * height as usize * 4
+        );
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The RGBA samples, row by row.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Decodes the PNG file `data`.
+    pub fn from_png(data: &[u8]) -> std::io::Result<Self> {
+        let invalid = |msg: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid PNG: {}", msg),
+            )
+        };
+        let mut rest = data
+            .strip_prefix(SIGNATURE)
+            .ok_or_else(|| invalid("no signature"))?;
+
+        let mut header = None;
+        let mut palette: &[u8] = &[];
+        let mut transparency: &[u8] = &[];
+        let mut compressed = Vec::new();
+        loop {
+            if rest.len() < 12 {
+                return Err(invalid("truncated chunk"));
+            }
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            let Some(chunk) = rest.get(4..8 + length) else {
+                return Err(invalid("truncated chunk"));
+            };
+            let Some(crc) = rest.get(8 + length..12 + length) else {
+                return Err(invalid("truncated chunk"));
+            };
+            if crc != checksum(chunk).to_be_bytes() {
+                return Err(invalid("corrupted chunk"));
+            }
+            let (kind, body) = chunk.split_at(4);
+            match kind {
+                b"IHDR" if body.len() == 13 => header = Some(body),
+                b"PLTE" => palette = body,
+                b"tRNS" => transparency = body,
+                b"IDAT" => compressed.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+            rest = &rest[12 + length..];
+        }
+
+        let header = header.ok_or_else(|| invalid("no header"))?;
+        let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let (depth, color_type) = (header[8], header[9]);
+        if header[12] != 0 {
+            return Err(invalid("interlaced images are not supported"));
+        }
+        let channels = match (color_type, depth) {
+            (0, 8 | 16) => 1,
+            (2, 8 | 16) => 3,
+            (3, 1 | 2 | 4 | 8) => 1,
+            (4, 8 | 16) => 2,
+            (6, 8 | 16) => 4,
+            _ => {
+                return Err(invalid(&format!(
+                    "color type {} with {} bits per sample is not supported",
+                    color_type, depth
+                )));
+            }
+        };
+
+        let stride = (width as usize * channels * depth as usize).div_ceil(8);
+        let bpp = (channels * depth as usize / 8).max(1);
+        let mut filtered = Vec::new();
+        flate2::read::ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut filtered)?;
+        if filtered.len() < (stride + 1) * height as usize {
+            return Err(invalid("truncated image data"));
+        }
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        let mut previous = vec![0u8; stride];
+        for row in filtered.chunks_exact(stride + 1).take(height as usize) {
+            let mut current = row[1..].to_vec();
+            unfilter(row[0], &mut current, &previous, bpp).map_err(|e| invalid(&e))?;
+            for x in 0..width as usize {
+                let sample = |channel: usize| -> u8 {
+                    match depth {
+                        16 => current[(x * channels + channel) * 2],
+                        8 => current[x * channels + channel],
+                        _ => {
+                            let bit = x * depth as usize;
+                            let shift = 8 - depth as usize - bit % 8;
+                            (current[bit / 8] >> shift) & ((1 << depth) - 1)
+                        }
+                    }
+                };
+                let rgba = match color_type {
+                    0 => [sample(0), sample(0), sample(0), 255],
+                    2 => [sample(0), sample(1), sample(2), 255],
+                    3 => {
+                        let index = sample(0) as usize;
+                        let color = palette
+                            .get(index * 3..index * 3 + 3)
+                            .ok_or_else(|| invalid("palette index out of range"))?;
+                        let alpha = transparency.get(index).copied().unwrap_or(255);
+                        [color[0], color[1], color[2], alpha]
+                    }
+                    4 => [sample(0), sample(0), sample(0), sample(1)],
+                    _ => [sample(0), sample(1), sample(2), sample(3)],
+                };
+                pixels.extend_from_slice(&rgba);
+            }
+            previous = current;
+        }
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Encodes the image as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut filtered = Vec::with_capacity((self.width as usize * 4 + 1) * self.height as usize);
+        for row in self.pixels.chunks_exact((self.width as usize * 4).max(1)) {
+            filtered.push(0);
+            filtered.extend_from_slice(row);
+        }
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(&filtered)
+            .expect("compressing in memory doesn't fail");
+        let compressed = encoder
+            .finish()
+            .expect("compressing in memory doesn't fail");
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bits per sample, RGBA, no interlacing
+        header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+        let mut png = SIGNATURE.to_vec();
+        for (kind, body) in [
+            (b"IHDR", header.as_slice()),
+            (b"IDAT", compressed.as_slice()),
+            (b"IEND", &[]),
+        ] {
+            png.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            let start = png.len();
+            png.extend_from_slice(kind);
+            png.extend_from_slice(body);
+            let crc = checksum(&png[start..]);
+            png.extend_from_slice(&crc.to_be_bytes());
+        }
+        png
+    }
+}
+
+/// Values which can be recorded with
+/// [`RegTest::regtest_image`](crate::RegTest::regtest_image): [`Image`]s,
+/// and PNG files as bytes.
+pub trait ImageSource {
+    fn to_image(&self) -> std::io::Result<Image>;
+}
+
+impl ImageSource for Image {
+    fn to_image(&self) -> std::io::Result<Image> {
+        Ok(self.clone())
+    }
+}
+
+impl ImageSource for [u8] {
+    fn to_image(&self) -> std::io::Result<Image> {
+        Image::from_png(self)
+    }
+}
+
+impl ImageSource for Vec<u8> {
+    fn to_image(&self) -> std::io::Result<Image> {
+        Image::from_png(self)
+    }
+}
+
+/// How an image differs from its baseline.
+pub(crate) struct Comparison {
+    /// The number of pixels which differ.
+    pub(crate) differing: usize,
+    /// The actual image, faded, with the differing pixels in red.
+    pub(crate) diff: Image,
+}
+
+/// Compares the pixels of `expected` and `actual`, counting those whose
+/// perceptual difference is above `threshold` (from 0 to 1). `None` if the
+/// images don't have the same size.
+pub(crate) fn compare(expected: &Image, actual: &Image, threshold: f64) -> Option<Comparison> {
+    if (expected.width, expected.height) != (actual.width, actual.height) {
+        return None;
+    }
+
+    // The largest possible difference, between black and white
+    const MAX_DELTA: f64 = 35215.0;
+    let max_delta = MAX_DELTA * threshold * threshold;
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(actual.pixels.len());
+    for (e, a) in expected
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        if e != a && delta(e, a) > max_delta {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let (y, _, _) = yiq(a);
+            let faded = (255.0 - 0.1 * (255.0 - y)).clamp(0.0, 255.0) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    Some(Comparison {
+        differing,
+        diff: Image {
+            width: actual.width,
+            height: actual.height,
+            pixels: diff,
+        },
+    })
+}
+
+/// The perceptual difference between two RGBA pixels, in the YIQ color
+/// space, after blending them with white.
+fn delta(expected: &[u8], actual: &[u8]) -> f64 {
+    let (y1, i1, q1) = yiq(expected);
+    let (y2, i2, q2) = yiq(actual);
+    let (y, i, q) = (y1 - y2, i1 - i2, q1 - q2);
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+fn yiq(pixel: &[u8]) -> (f64, f64, f64) {
+    let alpha = pixel[3] as f64 / 255.0;
+    let blend = |c: u8| 255.0 + (c as f64 - 255.0) * alpha;
+    let (r, g, b) = (blend(pixel[0]), blend(pixel[1]), blend(pixel[2]));
+    (
+        r * 0.29889531 + g * 0.58662247 + b * 0.11448223,
+        r * 0.59597799 - g * 0.27417610 - b * 0.32180189,
+        r * 0.21147017 - g * 0.52261711 + b * 0.31114694,
+    )
+}
+
+/// Reverses the PNG filter `filter` of the scanline `current`, given the
+/// previous (unfiltered) scanline and the number of bytes per pixel.
+fn unfilter(filter: u8, current: &mut [u8], previous: &[u8], bpp: usize) -> Result<(), String> {
+    for i in 0..current.len() {
+        let left = if i >= bpp { current[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let predicted = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => return Err(format!("unknown filter {}", filter)),
+        };
+        current[i] = current[i].wrapping_add(predicted);
+    }
+    Ok(())
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let p = left as i16 + up as i16 - up_left as i16;
+    let (pa, pb, pc) = (
+        (p - left as i16).abs(),
+        (p - up as i16).abs(),
+        (p - up_left as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        left
+    } else if pb <= pc {
+        up
+    } else {
+        up_left
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_round_trips_and_compares() {
+        let pixels: Vec<u8> = (0..6u8)
+            .flat_map(|i| [i * 40, 255 - i * 40, 7, 255])
+            .collect();
+        let image = Image::new(3, 2, pixels);
+        assert_eq!(Image::from_png(&image.to_png()).unwrap(), image);
+        assert!(Image::from_png(b"not a png").is_err());
+
+        let mut changed = image.clone();
+        changed.pixels[0] = changed.pixels[0].wrapping_add(2);
+        changed.pixels[4..8].copy_from_slice(&[0, 0, 0, 255]);
+        assert_eq!(compare(&image, &changed, 0.0).unwrap().differing, 2);
+        let comparison = compare(&image, &changed, 0.1).unwrap();
+        assert_eq!(comparison.differing, 1);
+        assert_eq!(&comparison.diff.pixels[4..8], &[255, 0, 0, 255]);
+        assert!(compare(&image, &Image::new(1, 1, vec![0; 4]), 0.0).is_none());
+    }
+}
@@ -0,0 +1,343 @@
+//! Inline expectations, stored in the test's source code instead of a
+//! regression data file.
+//!
+//! ```rust
+//! use regression_test::{RegTest, expect};
+//!
+//! # let path = std::env::temp_dir().join("regtest-inline-doc.json");
+//! let mut rt = RegTest::new(path).unwrap();
+//! rt.regtest_inline(2 + 2, expect!["4"]);
+//! rt.regtest_inline("multiple\nlines", expect![[r#"
+//!     multiple
+//!     lines
+//! "#]]);
+//! ```
+//!
+//! When a baseline may be generated (see
+//! [`UpdatePolicy`](crate::config::UpdatePolicy)), an empty `expect![]` is
+//! filled in with the actual output. When baselines are regenerated
+//! (`REGTEST_UPDATE=always`), mismatching literals are rewritten in place.
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Creates an [Expect] from a string literal, remembering where it is written
+/// so it can be updated in place.
+///
+/// A multi-line expectation should start with a newline; its common
+/// indentation and the indentation of the closing line are ignored.
+#[macro_export]
+macro_rules! expect {
+    [[$data:literal]] => { $crate::expect![$data] };
+    [$data:literal] => {
+        $crate::Expect {
+            data: $data,
+            position: $crate::Position {
+                file: file!(),
+                line: line!(),
+                column: column!(),
+            },
+        }
+    };
+    [] => { $crate::expect![""] };
+}
+
+/// Location of an [`expect!`](crate::expect!) invocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// File containing the invocation, as given by `file!()`.
+    pub file: &'static str,
+    /// 1-based line of the invocation.
+    pub line: u32,
+    /// 1-based column of the invocation.
+    pub column: u32,
+}
+
+impl Position {
+    pub(crate) fn from_location(location: &'static Location<'static>) -> Self {
+        Position {
+            file: location.file(),
+            line: location.line(),
+            column: location.column(),
+        }
+    }
+}
+
+/// An expected value written inline in the source code. Created with the
+/// [`expect!`](crate::expect!) macro.
+#[derive(Debug, Clone, Copy)]
+pub struct Expect {
+    /// The literal, as written.
+    pub data: &'static str,
+    /// Where the literal is written.
+    pub position: Position,
+}
+
+impl Expect {
+    /// The expected value, with the formatting of multi-line literals removed.
+    pub fn value(&self) -> String {
+        trim_indent(self.data)
+    }
+}
+
+fn trim_indent(text: &str) -> String {
+    let Some(text) = text.strip_prefix('\n') else {
+        return text.to_string();
+    };
+
+    // Drop the indentation of the closing delimiter's line
+    let text = match text.rfind('\n') {
+        Some(last) if text[last + 1..].trim().is_empty() => &text[..=last],
+        _ => text,
+    };
+
+    let indent = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+
+    let mut value: String = text
+        .lines()
+        .map(|line| line.get(indent..).unwrap_or("").to_string() + "\n")
+        .collect();
+    value.pop();
+    value
+}
+
+/// Rewrites the literal of `expect` with `actual`.
+pub(crate) fn update(expect: &Expect, actual: &str) -> std::io::Result<()> {
+    static PATCHES: OnceLock<Mutex<HashMap<PathBuf, FilePatches>>> = OnceLock::new();
+
+    let file = locate_source(expect.position.file)?;
+    let mut patches = PATCHES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+
+    let entry = match patches.get_mut(&file) {
+        Some(entry) => entry,
+        None => {
+            let original = std::fs::read_to_string(&file)?;
+            patches.entry(file.clone()).or_insert(FilePatches {
+                original,
+                patches: Vec::new(),
+            })
+        }
+    };
+
+    let (range, indent) = find_literal(&entry.original, expect.position).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "Could not find the expect! literal at {}:{}:{}",
+                expect.position.file, expect.position.line, expect.position.column
+            ),
+        )
+    })?;
+
+    let literal = format_literal(actual, &indent);
+    entry.patches.retain(|(r, _)| r.start != range.start);
+    entry.patches.push((range, literal));
+
+    std::fs::write(&file, entry.apply())
+}
+
+/// Pending rewrites of a source file. Ranges refer to the file's content
+/// before any rewrite, so several literals in one file can be updated.
+struct FilePatches {
+    original: String,
+    patches: Vec<(Range<usize>, String)>,
+}
+
+impl FilePatches {
+    fn apply(&mut self) -> String {
+        self.patches.sort_by_key(|(range, _)| range.start);
+
+        let mut text = String::with_capacity(self.original.len());
+        let mut last = 0;
+        for (range, literal) in &self.patches {
+            text.push_str(&self.original[last..range.start]);
+            text.push_str(literal);
+            last = range.end;
+        }
+        text.push_str(&self.original[last..]);
+        text
+    }
+}
+
+/// `file!()` is relative to the workspace root, while tests run from the
+/// crate root.
+fn locate_source(file: &str) -> std::io::Result<PathBuf> {
+    let file = Path::new(file);
+    if file.is_absolute() {
+        return Ok(file.to_path_buf());
+    }
+
+    let start = std::env::var_os("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .map_or_else(std::env::current_dir, Ok)?;
+
+    start
+        .ancestors()
+        .map(|dir| dir.join(file))
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Could not locate source file {}", file.display()),
+            )
+        })
+}
+
+/// Finds the byte range of the literal passed to the `expect!` invocation at
+/// `position` (an empty range for `expect![]`), and the indentation of the
+/// line it is on.
+fn find_literal(text: &str, position: Position) -> Option<(Range<usize>, String)> {
+    let line_start: usize = text
+        .split_inclusive('\n')
+        .take(position.line.saturating_sub(1) as usize)
+        .map(str::len)
+        .sum();
+    let line = text[line_start..].lines().next()?;
+    let indent = line[..line.len() - line.trim_start().len()].to_string();
+
+    let column = line
+        .char_indices()
+        .nth(position.column.saturating_sub(1) as usize)
+        .map_or(0, |(i, _)| i);
+    let macro_start = line_start + column + text[line_start + column..].find("expect!")?;
+
+    let mut offset = macro_start + "expect!".len();
+    let rest = &text[offset..];
+    offset += rest.len() - rest.trim_start().len();
+    let open = text[offset..].chars().next()?;
+    let close = match open {
+        '[' => ']',
+        '(' => ')',
+        '{' => '}',
+        _ => return None,
+    };
+    offset += 1;
+
+    // Skip any whitespace and the inner brackets of `expect![[...]]`
+    loop {
+        let rest = &text[offset..];
+        let trimmed = rest.trim_start();
+        offset += rest.len() - trimmed.len();
+        if trimmed.starts_with('[') {
+            offset += 1;
+        } else {
+            break;
+        }
+    }
+
+    let rest = &text[offset..];
+    if rest.starts_with(close) {
+        return Some((offset..offset, indent));
+    }
+
+    let len = if let Some(raw) = rest.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        let body = 1 + hashes + 1;
+        body + rest[body..].find(&terminator)? + terminator.len()
+    } else if let Some(quoted) = rest.strip_prefix('"') {
+        let mut escaped = false;
+        let end = quoted.char_indices().find_map(|(i, c)| match c {
+            _ if escaped => {
+                escaped = false;
+                None
+            }
+            '\\' => {
+                escaped = true;
+                None
+            }
+            '"' => Some(i),
+            _ => None,
+        })?;
+        end + 2
+    } else {
+        return None;
+    };
+
+    Some((offset..offset + len, indent))
+}
+
+/// Formats `value` as a literal which [trim_indent] turns back into `value`.
+fn format_literal(value: &str, indent: &str) -> String {
+    if !value.contains('\n') {
+        return format!("{:?}", value);
+    }
+
+    let mut hashes = 1;
+    while value.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+    }
+    let hashes = "#".repeat(hashes);
+
+    let mut literal = format!("r{}\"\n", hashes);
+    for line in value.split('\n') {
+        if !line.is_empty() {
+            literal.push_str(indent);
+            literal.push_str("    ");
+            literal.push_str(line);
+        }
+        literal.push('\n');
+    }
+    literal.push_str(indent);
+    literal.push('"');
+    literal.push_str(&hashes);
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_line_literals_roundtrip() {
+        for value in [
+            "single",
+            "two\nlines",
+            "trailing\nnewline\n",
+            "  indented\nnot",
+        ] {
+            let literal = format_literal(value, "        ");
+            let data = literal
+                .trim_start_matches(['r', '#'])
+                .trim_end_matches('#')
+                .trim_matches('"');
+            if value.contains('\n') {
+                assert_eq!(trim_indent(data), value);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_literals() {
+        let text = "fn f() {\n    rt.regtest_inline(1, expect![\"1\"]);\n    rt.regtest_inline(2, expect![]);\n}\n";
+
+        let position = Position {
+            file: "",
+            line: 2,
+            column: 26,
+        };
+        let (range, indent) = find_literal(text, position).unwrap();
+        assert_eq!(&text[range], "\"1\"");
+        assert_eq!(indent, "    ");
+
+        let position = Position {
+            file: "",
+            line: 3,
+            column: 26,
+        };
+        let (range, _) = find_literal(text, position).unwrap();
+        assert!(range.is_empty());
+        assert_eq!(&text[range.start..range.start + 1], "]");
+    }
+}
@@ -0,0 +1,334 @@
+//! Importing [insta](https://insta.rs) snapshot files, for suites moving over
+//! from insta without regenerating their baselines.
+
+use crate::{Config, DataFile, FORMAT_VERSION, Header, RegEntry, RegType};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// The insta snapshots of a test, converted to a regression data file, see
+/// [`import_insta`].
+pub struct InstaTest {
+    /// The regression data file of the test.
+    pub data_file: PathBuf,
+    /// The `.snap` files, in the order of the entries they became.
+    pub snapshots: Vec<PathBuf>,
+    /// Whether the source file defines no test function after which insta
+    /// named the snapshots, as for snapshots named explicitly. The data file
+    /// is then named after the snapshot, and needs renaming after the test
+    /// recording it.
+    pub named: bool,
+    data: DataFile,
+}
+
+impl InstaTest {
+    /// The entries converted from the snapshots.
+    pub fn entries(&self) -> &[RegEntry] {
+        &self.data.entries
+    }
+
+    /// Writes the [data file](InstaTest::data_file), replacing any existing
+    /// one.
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(parent) = self.data_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.data_file, self.data.encode(&self.data_file)?)
+    }
+}
+
+/// A `.snap` file, as written by insta.
+struct Snap {
+    path: PathBuf,
+    /// The header, which is YAML; only its top-level scalars are kept.
+    header: BTreeMap<String, String>,
+    contents: String,
+}
+
+/// Converts the insta snapshot files (`.snap`) found in `dir` and below into
+/// regression data files, laid out according to `config`. Nothing is written
+/// until [`InstaTest::save`] is called.
+///
+/// insta names the snapshots of a test after the test function, without its
+/// `test_` prefix, numbering all but the first (`parses`, `parses-2`, ...), and
+/// keeps them in a `snapshots` directory next to the source file. The
+/// snapshots of a test become the entries of its data file, in order, with
+/// the snapshot's description (or else its expression) as
+/// [note](RegEntry::note). Snapshots named explicitly become data files named
+/// after the snapshot, see [`InstaTest::named`].
+///
+/// Entries are recorded as `reg_type`: [RegType::Display] for snapshots
+/// compared with [regtest](crate::RegTest::regtest), e.g. of
+/// `assert_snapshot!`, or [RegType::Debug] for those of
+/// `assert_debug_snapshot!`, compared with
+/// [regtest_dbg](crate::RegTest::regtest_dbg) and
+/// [pretty_debug](crate::Settings::pretty_debug). Binary snapshots aren't
+/// supported.
+pub fn import_insta<P: AsRef<Path>>(
+    dir: P,
+    config: &Config,
+    reg_type: RegType,
+) -> std::io::Result<Vec<InstaTest>> {
+    let mut files = Vec::new();
+    find_snaps(dir.as_ref(), &mut files)?;
+
+    // Snapshots by source file, module and test, numbered in order
+    let mut tests: BTreeMap<(PathBuf, String, String), Vec<(u32, Snap)>> = BTreeMap::new();
+    for path in files {
+        let snap = parse(&path)?;
+        if snap.header.contains_key("extension") {
+            return Err(invalid(&path, "binary snapshots are not supported"));
+        }
+        let source = snap
+            .header
+            .get("source")
+            .ok_or_else(|| invalid(&path, "the header has no `source`"))?;
+        // insta keeps the snapshots in a directory next to the source file
+        let source_file = path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""))
+            .join(Path::new(source).file_name().unwrap_or_default());
+
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let (module, name) = stem.rsplit_once("__").unwrap_or(("", &stem));
+        let (name, number) = match name.rsplit_once('-') {
+            Some((name, number)) if number.parse::<u32>().is_ok() => {
+                (name, number.parse().unwrap_or(1))
+            }
+            _ => (name, 1),
+        };
+        tests
+            .entry((source_file, module.to_string(), name.to_string()))
+            .or_default()
+            .push((number, snap));
+    }
+
+    let mut imported = Vec::new();
+    for ((source_file, module, name), mut snaps) in tests {
+        snaps.sort_by_key(|(number, _)| *number);
+        let found = function_name(&source_file, &name);
+        let named = found.is_none();
+        let function = found.unwrap_or(name);
+        // The module path starts with the crate name, which tests leave out
+        let test = match module.split_once("__") {
+            Some((_, path)) => format!("{}::{}", path.replace("__", "::"), function),
+            None => function.clone(),
+        };
+
+        let entries = snaps
+            .iter()
+            .map(|(_, snap)| RegEntry {
+                reg_type: reg_type.clone(),
+                message: snap.contents.clone(),
+                section: None,
+                key: None,
+                location: snap
+                    .header
+                    .get("source")
+                    .zip(snap.header.get("assertion_line"))
+                    .map(|(source, line)| format!("{}:{}", source, line)),
+                note: snap
+                    .header
+                    .get("description")
+                    .or(snap.header.get("expression"))
+                    .cloned(),
+                value_type: None,
+                file: None,
+                blob: None,
+            })
+            .collect();
+        imported.push(InstaTest {
+            data_file: config.snapshot_path(&source_file, &function),
+            snapshots: snaps.into_iter().map(|(_, snap)| snap.path).collect(),
+            named,
+            data: DataFile {
+                header: Header {
+                    version: FORMAT_VERSION,
+                    crate_name: module
+                        .split("__")
+                        .next()
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string),
+                    test: Some(test),
+                    generated: Some(crate::timestamp()),
+                    shape: None,
+                    tags: Vec::new(),
+                    checksum: None,
+                    blobs: None,
+                },
+                entries,
+                encrypted: config.encrypt,
+            },
+        });
+    }
+    Ok(imported)
+}
+
+fn find_snaps(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_snaps(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "snap") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+/// The test function whose snapshots insta named `name`, if `source_file`
+/// defines it: insta drops the `test_` prefix of function names.
+fn function_name(source_file: &Path, name: &str) -> Option<String> {
+    let prefixed = format!("test_{}", name);
+    let source = std::fs::read_to_string(source_file).unwrap_or_default();
+    let defines = |function: &str| {
+        source
+            .match_indices(&format!("fn {}", function))
+            .any(|(i, m)| {
+                source[i + m.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c == '(' || c == '<' || c.is_whitespace())
+            })
+    };
+    if defines(name) {
+        Some(name.to_string())
+    } else if defines(&prefixed) {
+        Some(prefixed)
+    } else {
+        None
+    }
+}
+
+fn parse(path: &Path) -> std::io::Result<Snap> {
+    let text = std::fs::read_to_string(path)?;
+    let text = text.replace("\r\n", "\n");
+    let rest = text
+        .strip_prefix("---\n")
+        .ok_or_else(|| invalid(path, "it doesn't start with a `---` header"))?;
+    let (header, contents) = rest
+        .split_once("\n---\n")
+        .ok_or_else(|| invalid(path, "its header isn't closed by `---`"))?;
+
+    let header = header
+        .lines()
+        // Nested values are indented
+        .filter(|line| !line.starts_with([' ', '\t']))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), yaml_scalar(value.trim())))
+        .collect();
+    Ok(Snap {
+        path: path.to_path_buf(),
+        header,
+        // insta ends the contents with a newline of its own
+        contents: contents.strip_suffix('\n').unwrap_or(contents).to_string(),
+    })
+}
+
+/// The value of a YAML scalar, unquoted.
+fn yaml_scalar(value: &str) -> String {
+    if let Some(quoted) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        let mut unescaped = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some(other) => unescaped.push(other),
+                None => {}
+            }
+        }
+        unescaped
+    } else if let Some(quoted) = value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+    {
+        quoted.replace("''", "'")
+    } else {
+        value.to_string()
+    }
+}
+
+fn invalid(path: &Path, reason: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} is not an insta snapshot: {}", path.display(), reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_become_entries_of_their_test() {
+        let root = std::env::temp_dir().join(format!("regtest-insta-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let snapshots = root.join("src").join("snapshots");
+        std::fs::create_dir_all(&snapshots).unwrap();
+        std::fs::write(
+            root.join("src").join("lib.rs"),
+            "mod tests { #[test] fn test_parses() {} }",
+        )
+        .unwrap();
+        let snap = |name: &str, header: &str, contents: &str| {
+            std::fs::write(
+                snapshots.join(format!("krate__tests__{}.snap", name)),
+                format!("---\nsource: src/lib.rs\n{}\n---\n{}\n", header, contents),
+            )
+            .unwrap();
+        };
+        snap("parses-2", "expression: \"parse(\\\"b\\\")\"", "B");
+        snap(
+            "parses",
+            "assertion_line: 7\nexpression: parse(a)",
+            "A\n  indented",
+        );
+        snap("named", "description: a named one", "N");
+
+        let config = Config {
+            root: "data".into(),
+            ..Config::default()
+        };
+        let tests = import_insta(&root, &config, RegType::Display).unwrap();
+        assert_eq!(tests.len(), 2);
+
+        let named = &tests[0];
+        assert_eq!(named.data_file, root.join("data/src/lib/named.json"));
+        assert!(named.named);
+        assert_eq!(named.entries()[0].note(), Some("a named one"));
+
+        let parses = &tests[1];
+        assert_eq!(parses.data_file, root.join("data/src/lib/test_parses.json"));
+        assert!(!parses.named);
+        let entries: Vec<_> = parses
+            .entries()
+            .iter()
+            .map(|e| (e.message(), e.note(), e.location()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("A\n  indented", Some("parse(a)"), Some("src/lib.rs:7")),
+                ("B", Some("parse(\"b\")"), None),
+            ]
+        );
+        assert_eq!(
+            parses.data.header.test.as_deref(),
+            Some("tests::test_parses")
+        );
+
+        parses.save().unwrap();
+        let saved = crate::SnapshotFile::load(&parses.data_file).unwrap();
+        assert_eq!(saved.entries().len(), 2);
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
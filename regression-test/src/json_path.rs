@@ -0,0 +1,178 @@
+//! Paths to values of JSON documents, ignored or replaced before the
+//! documents are recorded, see [`Settings::ignore_path`].
+//!
+//! Paths use a subset of the JSONPath syntax: `$` is the document, followed
+//! by fields (`.name` or `['name']`), array items (`[0]`) and wildcards
+//! matching all fields or items (`.*` or `[*]`), e.g.
+//! `$.items[*].meta.generated_at`.
+//!
+//! [`Settings::ignore_path`]: crate::Settings::ignore_path
+
+use serde_json::Value;
+
+/// A parsed path.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct JsonPath(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+    Any,
+}
+
+/// What happens to the values selected by a path.
+#[derive(Debug, Clone)]
+pub(crate) struct PathRule {
+    pub(crate) path: JsonPath,
+    /// The text replacing the values, or `None` to remove them.
+    pub(crate) replacement: Option<String>,
+}
+
+impl JsonPath {
+    pub(crate) fn parse(path: &str) -> Result<Self, String> {
+        let Some(mut rest) = path.strip_prefix('$') else {
+            return Err("paths start with `$`".to_string());
+        };
+        let mut segments = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix(".*") {
+                segments.push(Segment::Any);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('.') {
+                let end = after.find(['.', '[']).unwrap_or(after.len());
+                if end == 0 {
+                    return Err("expected a field name after `.`".to_string());
+                }
+                segments.push(Segment::Key(after[..end].to_string()));
+                rest = &after[end..];
+            } else if let Some(after) = rest.strip_prefix('[') {
+                let Some(end) = after.find(']') else {
+                    return Err("unclosed `[`".to_string());
+                };
+                let inner = &after[..end];
+                let quoted = inner.len() >= 2
+                    && (inner.starts_with('\'') && inner.ends_with('\'')
+                        || inner.starts_with('"') && inner.ends_with('"'));
+                segments.push(if inner == "*" {
+                    Segment::Any
+                } else if quoted {
+                    Segment::Key(inner[1..inner.len() - 1].to_string())
+                } else {
+                    match inner.parse() {
+                        Ok(index) => Segment::Index(index),
+                        Err(_) => {
+                            return Err(format!(
+                                "expected an index, `*` or a quoted name in `[{}]`",
+                                inner
+                            ));
+                        }
+                    }
+                });
+                rest = &after[end + 1..];
+            } else {
+                return Err(format!("unexpected `{}`", rest));
+            }
+        }
+        Ok(JsonPath(segments))
+    }
+}
+
+impl PathRule {
+    /// Removes or replaces the values of `document` selected by the path.
+    pub(crate) fn apply(&self, document: &mut Value) {
+        let replacement = self
+            .replacement
+            .as_deref()
+            .map(|r| Value::String(r.to_string()));
+        match self.path.0.as_slice() {
+            [] => *document = replacement.unwrap_or(Value::Null),
+            segments => apply(segments, document, replacement.as_ref()),
+        }
+    }
+}
+
+fn apply(segments: &[Segment], value: &mut Value, replacement: Option<&Value>) {
+    let [first, rest @ ..] = segments else {
+        return;
+    };
+    if !rest.is_empty() {
+        let children: Vec<&mut Value> = match (value, first) {
+            (Value::Object(object), Segment::Key(key)) => object.get_mut(key).into_iter().collect(),
+            (Value::Object(object), Segment::Any) => object.values_mut().collect(),
+            (Value::Array(items), Segment::Index(i)) => items.get_mut(*i).into_iter().collect(),
+            (Value::Array(items), Segment::Any) => items.iter_mut().collect(),
+            _ => Vec::new(),
+        };
+        for child in children {
+            apply(rest, child, replacement);
+        }
+        return;
+    }
+
+    match (value, first, replacement) {
+        (Value::Object(object), Segment::Key(key), Some(replacement)) => {
+            if let Some(value) = object.get_mut(key) {
+                *value = replacement.clone();
+            }
+        }
+        (Value::Object(object), Segment::Key(key), None) => {
+            object.remove(key);
+        }
+        (Value::Object(object), Segment::Any, Some(replacement)) => {
+            object
+                .values_mut()
+                .for_each(|value| *value = replacement.clone());
+        }
+        (Value::Object(object), Segment::Any, None) => object.clear(),
+        (Value::Array(items), Segment::Index(i), Some(replacement)) => {
+            if let Some(value) = items.get_mut(*i) {
+                *value = replacement.clone();
+            }
+        }
+        (Value::Array(items), Segment::Index(i), None) if *i < items.len() => {
+            items.remove(*i);
+        }
+        (Value::Array(items), Segment::Any, Some(replacement)) => {
+            items
+                .iter_mut()
+                .for_each(|value| *value = replacement.clone());
+        }
+        (Value::Array(items), Segment::Any, None) => items.clear(),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn paths_ignore_and_replace_values() {
+        let rule = |path: &str, replacement: Option<&str>| PathRule {
+            path: JsonPath::parse(path).unwrap(),
+            replacement: replacement.map(str::to_string),
+        };
+        let mut document = json!({
+            "meta": {"generated_at": "2024-01-01", "version": 1},
+            "items": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}],
+        });
+        rule("$.meta.generated_at", None).apply(&mut document);
+        rule("$.items[*]['id']", Some("[id]")).apply(&mut document);
+        rule("$.items[1].name", None).apply(&mut document);
+        rule("$.missing.field", None).apply(&mut document);
+        assert_eq!(
+            document,
+            json!({
+                "meta": {"version": 1},
+                "items": [{"id": "[id]", "name": "a"}, {"id": "[id]"}],
+            })
+        );
+
+        assert!(JsonPath::parse("meta").is_err());
+        assert!(JsonPath::parse("$.").is_err());
+        assert!(JsonPath::parse("$[x]").is_err());
+        assert!(JsonPath::parse("$[0").is_err());
+    }
+}
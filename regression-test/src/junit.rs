@@ -0,0 +1,228 @@
+//! JUnit XML summary of the regression tests of a run, for CI dashboards.
+//!
+//! When a JUnit file is configured (see [`Config::junit`]), the outcome of
+//! every [`RegTest`](crate::RegTest) is collected process-wide. The file holds
+//! one `<testsuite>` per test binary, which is rewritten as each test finishes,
+//! so it is complete once the binary exits. Suites of other binaries already in
+//! the file are kept. Under nextest, which runs every test in a process of its
+//! own, the cases of other tests of the same binary are kept as well.
+//!
+//! Failures caused by regression data (mismatches, failed subtests, ...) have
+//! the type `regression`, while tests which panicked for another reason (e.g.
+//! a failed `assert!`) have the type `panic`.
+//!
+//! [`Config::junit`]: crate::Config::junit
+
+use crate::render::escape_markup;
+use crate::{nextest, registry};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Why a test failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailureKind {
+    /// The regression data didn't match, or was otherwise rejected.
+    Regression,
+    /// The test panicked for a reason unrelated to its regression data.
+    Panic,
+}
+
+impl FailureKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::Regression => "regression",
+            FailureKind::Panic => "panic",
+        }
+    }
+}
+
+/// The outcome of one test.
+#[derive(Debug, Clone)]
+pub(crate) struct Case {
+    /// Full path of the test, e.g. `tests::it_works`.
+    pub(crate) test: String,
+    pub(crate) time: Duration,
+    pub(crate) failure: Option<(FailureKind, String)>,
+}
+
+/// Adds `case` to the results of this process and rewrites the JUnit file at
+/// `path`.
+pub(crate) fn record(path: &Path, case: Case) -> std::io::Result<()> {
+    static CASES: OnceLock<Mutex<Vec<Case>>> = OnceLock::new();
+
+    let mut cases = CASES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    cases.push(case);
+
+    let suite_name = registry::binary_name();
+    let keep_cases = nextest::process_per_test();
+    nextest::rewrite(path, |existing| {
+        render(&suite_name, &cases, existing, keep_cases)
+    })
+}
+
+/// Renders the whole JUnit file: the suites of `existing` other than
+/// `suite_name`, followed by this process's suite. With `keep_cases`, the
+/// suite also keeps the cases of `existing` for tests not in `cases`.
+fn render(suite_name: &str, cases: &[Case], existing: &str, keep_cases: bool) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+
+    // Suites and cases are delimited by lines written by this module only,
+    // and escaping keeps any message from looking like one
+    let own_start = format!("  <testsuite name=\"{}\"", escape_markup(suite_name));
+    let rendered: Vec<String> = cases
+        .iter()
+        .map(|case| render_case(suite_name, case))
+        .collect();
+    let mut kept = Vec::new();
+    let mut keep = false;
+    let mut own = false;
+    let mut in_case = false;
+    for line in existing.lines() {
+        if line.starts_with("  <testsuite ") {
+            own = line.starts_with(&own_start) && keep_cases;
+            keep = !line.starts_with(&own_start);
+        }
+        if keep {
+            out.push_str(line);
+            out.push('\n');
+        } else if own && (in_case || line.starts_with("    <testcase ")) {
+            if !in_case {
+                kept.push(String::new());
+            }
+            if let Some(case) = kept.last_mut() {
+                case.push_str(line);
+                case.push('\n');
+            }
+            in_case = !line.ends_with("/>") && line != "    </testcase>";
+        }
+        if line == "  </testsuite>" {
+            keep = false;
+            own = false;
+        }
+    }
+
+    // A case is identified by its first line up to the time
+    let identity = |case: &str| case.split(" time=").next().unwrap_or_default().to_string();
+    let replaced: Vec<String> = rendered.iter().map(|case| identity(case)).collect();
+    kept.retain(|case| !replaced.contains(&identity(case)));
+
+    let failures = cases.iter().filter(|c| c.failure.is_some()).count()
+        + kept
+            .iter()
+            .filter(|case| case.contains("<failure "))
+            .count();
+    let time = cases.iter().map(|c| c.time.as_secs_f64()).sum::<f64>()
+        + kept.iter().map(|case| case_time(case)).sum::<f64>();
+    let _ = writeln!(
+        out,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+        escape_markup(suite_name),
+        kept.len() + cases.len(),
+        failures,
+        time
+    );
+
+    for case in kept.iter().chain(&rendered) {
+        out.push_str(case);
+    }
+
+    out.push_str("  </testsuite>\n</testsuites>\n");
+    out
+}
+
+/// Renders the `<testcase>` element of `case`, with a trailing newline.
+fn render_case(suite_name: &str, case: &Case) -> String {
+    let (classname, name) = match case.test.rsplit_once("::") {
+        Some((module, name)) => (format!("{}::{}", suite_name, module), name),
+        None => (suite_name.to_string(), case.test.as_str()),
+    };
+    let mut out = format!(
+        "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"",
+        escape_markup(name),
+        escape_markup(&classname),
+        case.time.as_secs_f64()
+    );
+
+    match &case.failure {
+        None => out.push_str("/>\n"),
+        Some((kind, message)) => {
+            let summary = message.lines().next().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                ">\n      <failure type=\"{}\" message=\"{}\">{}</failure>\n    </testcase>",
+                kind.as_str(),
+                escape_markup(summary),
+                escape_markup(message)
+            );
+        }
+    }
+    out
+}
+
+/// The time of the rendered `<testcase>` element `case`, in seconds.
+fn case_time(case: &str) -> f64 {
+    case.split(" time=\"")
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .and_then(|time| time.parse().ok())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_own_suite_only() {
+        let cases = vec![
+            Case {
+                test: "tests::passes".to_string(),
+                time: Duration::from_millis(2),
+                failure: None,
+            },
+            Case {
+                test: "tests::fails".to_string(),
+                time: Duration::from_millis(1),
+                failure: Some((FailureKind::Regression, "a < b\n  </testsuite>".to_string())),
+            },
+        ];
+
+        let first = render("other", &cases[..1], "", false);
+        let second = render(
+            "mine",
+            &cases,
+            &render("mine", &cases[..1], &first, false),
+            false,
+        );
+
+        assert_eq!(second.matches("<testsuite ").count(), 2);
+        assert!(second.contains("<testsuite name=\"other\" tests=\"1\" failures=\"0\""));
+        assert!(second.contains("<testsuite name=\"mine\" tests=\"2\" failures=\"1\""));
+        assert!(second.contains(
+            "<testcase name=\"fails\" classname=\"mine::tests\" time=\"0.001\">\n      <failure type=\"regression\" message=\"a &lt; b\">a &lt; b\n  &lt;/testsuite&gt;</failure>"
+        ));
+
+        // The escaped message doesn't end the suite early
+        let third = render("mine", &cases[..1], &second, false);
+        assert!(!third.contains("fails"));
+        assert!(third.contains("<testsuite name=\"other\""));
+
+        // With a process per test, the cases of other processes are kept
+        let per_test = render("mine", &cases[..1], &second, true);
+        assert!(
+            per_test.contains("<testsuite name=\"mine\" tests=\"2\" failures=\"1\" time=\"0.003\"")
+        );
+        assert_eq!(
+            per_test
+                .matches("\"passes\" classname=\"mine::tests\"")
+                .count(),
+            1
+        );
+        assert!(per_test.contains("a &lt; b\n  &lt;/testsuite&gt;</failure>\n    </testcase>"));
+    }
+}
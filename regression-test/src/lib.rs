@@ -1,26 +1,821 @@
 //! Regression testing utilities
 
+use regex::Regex;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Display};
-use std::fs::OpenOptions;
-use std::io::{BufWriter, Write};
+use std::io::Write;
+use std::panic::Location;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub mod alloc;
+mod anonymize;
+mod canonical;
+mod checksum;
+pub mod clock;
+pub mod compare;
+mod compress;
+pub mod config;
+mod corrupt;
+mod derive;
+mod display;
+mod encrypt;
+mod fields;
+mod float;
+mod git;
+mod gitignore;
+#[cfg(feature = "harness")]
+pub mod harness;
+mod html;
+pub mod http;
+#[cfg(feature = "image")]
+pub mod image;
+mod inline;
+mod insta;
+mod json_path;
+mod junit;
+mod lock;
+#[cfg(feature = "markup")]
+mod markup;
+mod measure;
+mod merge;
+mod metrics;
+mod nextest;
+pub mod normalize;
+mod order;
+mod path;
+mod placeholder;
+mod registry;
+mod render;
+mod report;
+mod settings;
+mod shared;
+mod snapshot;
+pub mod store;
+mod stream;
+#[cfg(feature = "summary")]
+mod summary;
+mod table;
+mod truncate;
+
+pub use checksum::TamperedSnapshot;
+use compare::Comparator;
+pub use config::Config;
+use config::{EmptyPolicy, ExtraEntries, Layout, UpdatePolicy};
+pub use corrupt::CorruptSnapshot;
+#[doc(hidden)]
+pub use derive::__push_field;
+pub use derive::RegSnapshot;
+pub use display::RegDisplay;
+pub use inline::{Expect, Position};
+pub use insta::{InstaTest, import_insta};
+use json_path::{JsonPath, PathRule};
+pub use merge::{MergeConflict, MergedFile, merge_files};
+use normalize::Normalizer;
+#[doc(hidden)]
+pub use path::{__regtest_path, __source_file};
+pub use path::{
+    accepted_path, entry_dir, full_message_dir, is_data_path, is_partial_path, is_pending_path,
+    logical_path, normalize_path, pending_path, snapshot_path, variant_path, without_variant,
+};
+use render::Renderer;
+use report::{Mismatch, MismatchKind};
+pub use settings::Settings;
+pub use shared::SharedRegTest;
+pub use snapshot::{ChangedEntry, FileDiff, SnapshotFile, diff_files};
+pub use store::Backend;
+
+/// Creates the [RegTest] of the test running, for tests which can't use the
+/// `#[regtest]` attribute, e.g. because of other attributes, and for helpers
+/// called by tests:
+///
+/// ```rust,ignore
+/// #[test]
+/// fn parses() {
+///     let mut rt = regression_test::regtest!();
+///     rt.regtest(parse("a + b"));
+/// }
+/// ```
+///
+/// The data file is found like that of a `#[regtest]` function, from the file
+/// calling the macro and the name of the test, which libtest gives the
+/// thread running it. Helpers called by tests of other modules name the file
+/// after the test and its module. Tests running on the main thread, e.g.
+/// with a custom harness, must give their name: `regtest!("parses")`.
+///
+/// Panics if the data file can't be opened, like `#[regtest]` functions.
+#[macro_export]
+macro_rules! regtest {
+    (@open $name:expr) => {
+        $crate::RegTest::new($crate::__regtest_path(
+            env!("CARGO_MANIFEST_DIR"),
+            file!(),
+            module_path!(),
+            $name,
+        ))
+        .expect("Failed to create or open regression test file")
+    };
+    () => {
+        $crate::regtest!(@open ::core::option::Option::None)
+    };
+    ($name:expr) => {
+        $crate::regtest!(@open ::core::option::Option::Some($name))
+    };
+}
+
+/// Without the `harness` feature, `#[regtest]` functions only run under
+/// libtest.
+#[cfg(not(feature = "harness"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_regtest {
+    ($($tokens:tt)*) => {};
+}
+
+/// How an entry was recorded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
-enum RegType {
+pub enum RegType {
+    /// Recorded with [RegTest::regtest].
     Display,
+    /// Recorded with [RegTest::regtest_dbg].
     Debug,
+    /// Recorded with [RegTest::regtest_hash]: the message is the SHA-256
+    /// digest and the length of the output.
+    Hash,
+    /// Recorded with [RegTest::regtest_matches]: the message is a regular
+    /// expression which the output must match.
+    Pattern,
+    /// Recorded with [RegTest::checkpoint]: the message is the name of the
+    /// checkpoint.
+    Checkpoint,
+    /// Recorded with [RegTest::regtest_eq]: the message is the value
+    /// serialized as JSON.
+    Value,
+    /// Recorded with [RegTest::regtest_json]: the message is the JSON
+    /// document, with sorted keys.
+    Json,
+    /// Recorded with `RegTest::regtest_markup`: the message is the XML or
+    /// HTML markup, canonicalized.
+    Markup,
+    /// Recorded with `RegTest::regtest_image`: the message is the size and
+    /// the SHA-256 digest of the image, and the name of the PNG file holding
+    /// it.
+    Image,
+    /// Recorded with [RegTest::with_http_replay]: the message is an HTTP
+    /// request and its response, as JSON.
+    Http,
+    /// Recorded with [RegTest::seed]: the message is the seed.
+    Seed,
+    /// Recorded with [RegTest::clock]: the message is the time, in seconds
+    /// since the Unix epoch.
+    Clock,
+    /// Recorded with [RegTest::regtest_duration] and its variants: the
+    /// message is the measurement followed by its unit, e.g. `1520000 ns`.
+    Measurement,
+    /// Recorded with [RegTest::header]: the message is the title of the
+    /// entries which follow it.
+    Header,
+    /// Recorded with [RegTest::regtest_custom]: an entry of a kind declared
+    /// by the test, e.g. `sql`, which tools reading the data files may treat
+    /// specially. Compared like [Display](RegType::Display) entries, and only
+    /// with entries of the same kind.
+    Custom(String),
+}
+
+impl RegType {
+    /// Whether entries of this kind are recorded and compared as they are:
+    /// their messages are generated (digests, patterns, serialized values,
+    /// ...) or given by the test (checkpoints, headers) rather than output,
+    /// so they are not redacted, truncated, keyed or normalized, and
+    /// placeholders don't apply to them.
+    fn is_verbatim(&self) -> bool {
+        !matches!(
+            self,
+            RegType::Display | RegType::Debug | RegType::Markup | RegType::Custom(_)
+        )
+    }
+
+    /// Whether the message of entries of this kind is a value serialized as
+    /// JSON, whose floats are rounded like those of output.
+    fn is_serialized(&self) -> bool {
+        matches!(self, RegType::Value | RegType::Json)
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct RegEntry {
+/// An entry of the regression data, as stored in a data file. See
+/// [SnapshotFile] for reading data files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegEntry {
     #[serde(rename = "type")]
     reg_type: RegType,
     message: String,
+    /// Name of the [subtest](RegTest::subtest) which recorded this entry.
+    /// Nested subtests are separated by `/`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
+    /// Identifies the entry independently of its position: in read mode,
+    /// keyed entries are matched by key instead of by position. See
+    /// [`Config::key_pattern`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    /// Where the test recorded the entry, as `file:line`. Only informative:
+    /// it is not compared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    /// What the entry is about, see [RegTest::regtest_with_note]. Only
+    /// informative: it is not compared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    /// Name of the type of the recorded value, e.g. `alloc::string::String`.
+    /// In read mode, recording a value of another type only warns, as type
+    /// names may change between compiler versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value_type: Option<String>,
+    /// With the [directory layout](Layout::Directory), the file holding the
+    /// message, relative to the [entry directory](entry_dir).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    /// For messages stored as blobs (see [Config::blob_bytes]), the digest
+    /// of the message, e.g. `sha256:<hex>`, naming the blob.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blob: Option<String>,
+}
+
+impl RegEntry {
+    /// How the entry was recorded.
+    pub fn reg_type(&self) -> RegType {
+        self.reg_type.clone()
+    }
+
+    /// The recorded (and redacted) output.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Name of the subtest which recorded the entry, with nested subtests
+    /// separated by `/`, or `None` for the test itself.
+    pub fn section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+
+    /// The key of the entry, see [`Config::key_pattern`].
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+
+    /// Where the test recorded the entry, as `file:line`.
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    /// The note describing the entry, if it was recorded with one.
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    /// Name of the type of the recorded value, for entries recorded from a
+    /// value.
+    pub fn value_type(&self) -> Option<&str> {
+        self.value_type.as_deref()
+    }
+
+    /// Loads the message of this entry from the entry directory `dir`, if it
+    /// is stored apart. The entry then holds its message like any other.
+    fn load_apart(
+        &mut self,
+        dir: &Path,
+        load: impl Fn(&Path) -> std::io::Result<Option<Vec<u8>>>,
+    ) -> std::io::Result<()> {
+        let Some(file) = self.file.take() else {
+            return Ok(());
+        };
+        let path = dir.join(file);
+        let data = load(&path)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("missing entry file {}", path.display()),
+            )
+        })?;
+        self.message = String::from_utf8(data).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("entry file {} is not UTF-8", path.display()),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Loads the message of this entry from the blob directory `dir`, if it
+    /// is stored as a blob, see [Config::blob_bytes].
+    fn load_blob(
+        &mut self,
+        dir: &Path,
+        load: impl Fn(&Path) -> std::io::Result<Option<Vec<u8>>>,
+    ) -> std::io::Result<()> {
+        let Some(blob) = self.blob.take() else {
+            return Ok(());
+        };
+        self.file = Some(blob_file_name(&blob));
+        self.load_apart(dir, load)
+    }
+}
+
+/// The blob directory `blobs` of the data file `path`, as stored in its
+/// header: relative to the directory of the data file.
+fn blob_dir(path: &Path, blobs: &str) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    path::join_lexically(parent, Path::new(blobs))
+}
+
+/// The name of the file holding the blob `blob`, e.g. `<hex>.txt` for
+/// `sha256:<hex>`.
+fn blob_file_name(blob: &str) -> String {
+    let digest = blob.strip_prefix("sha256:").unwrap_or(blob);
+    format!("{}.txt", file_name_part(digest))
+}
+
+/// `name` with the characters which aren't safe in file names replaced.
+fn file_name_part(name: &str) -> String {
+    let part: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match part.as_str() {
+        "." | ".." => "_".to_string(),
+        _ => part,
+    }
+}
+
+/// How many entries are looked at past a mismatch to find where the recorded
+/// entries and the baseline agree again.
+const RESYNC_WINDOW: usize = 8;
+
+/// How an entry is recorded, besides its message.
+#[derive(Default)]
+struct Recording<'a> {
+    /// Decides whether a differing message still matches, see
+    /// [RegTest::regtest_with].
+    comparator: Option<&'a dyn Comparator>,
+    /// The output whose digest is the message, see [RegTest::regtest_hash].
+    hashed: Option<&'a [u8]>,
+    /// The pattern stored instead of the message, see
+    /// [RegTest::regtest_matches].
+    pattern: Option<&'a str>,
+    /// Describes the entry, see [RegTest::regtest_with_note].
+    note: Option<&'a str>,
+    /// Checks that the message of the baseline entry can still be read back,
+    /// see [RegTest::regtest_roundtrip].
+    read_back: Option<&'a ReadBack>,
+    /// Name of the type of the recorded value, see [RegEntry::value_type].
+    value_type: Option<&'static str>,
+}
+
+/// Name of the type `T`, without references, which don't change what is
+/// recorded.
+fn value_type<T: ?Sized>() -> &'static str {
+    let mut name = std::any::type_name::<T>();
+    while let Some(rest) = name.strip_prefix('&') {
+        name = rest.strip_prefix("mut ").unwrap_or(rest);
+    }
+    name
+}
+
+/// `message`, as listed among the entries of a report: headers stand out
+/// from the output around them.
+fn listed(reg_type: &RegType, message: &str) -> String {
+    match reg_type {
+        RegType::Header => format!("== {} ==", message),
+        _ => message.to_string(),
+    }
+}
+
+/// Reads a baseline message back, returning why it can't be.
+type ReadBack = dyn Fn(&str) -> Result<(), String>;
+
+/// An entry recorded in read mode, not yet compared.
+struct Recorded {
+    reg_type: RegType,
+    message: String,
+    caller: Option<Position>,
+}
+
+/// Entries recorded after an entry without a key didn't match the baseline.
+struct Divergence {
+    /// Index of the baseline entry which didn't match.
+    index: usize,
+    /// The mismatching entry and the ones recorded after it.
+    recorded: Vec<Recorded>,
+    /// The [description](Settings::description) in effect at the mismatch.
+    description: Option<String>,
+}
+
+/// Structural shape of the entries recorded by a test, see
+/// [RegTest::assert_shape].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Shape {
+    entries: usize,
+    /// Distinct kinds of the entries, sorted.
+    kinds: Vec<RegType>,
+}
+
+impl Shape {
+    fn new(entries: usize, kinds: &[RegType]) -> Self {
+        let mut kinds = kinds.to_vec();
+        kinds.sort();
+        kinds.dedup();
+        Shape { entries, kinds }
+    }
+
+    fn of<'a>(entries: impl Iterator<Item = &'a RegEntry>) -> Self {
+        let entries: Vec<_> = entries.map(|e| e.reg_type.clone()).collect();
+        Shape::new(entries.len(), &entries)
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} entries of kinds {:?}", self.entries, self.kinds)
+    }
+}
+
+/// Version of the regression data file format written by this crate.
+///
+/// Version 0 is the original format: a bare array of entries.
+const FORMAT_VERSION: u32 = 1;
+
+/// Describes a regression data file.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    #[serde(default = "current_format_version")]
+    version: u32,
+    /// Name of the crate owning the test.
+    #[serde(rename = "crate", default, skip_serializing_if = "Option::is_none")]
+    crate_name: Option<String>,
+    /// Full path of the test which generated the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    test: Option<String>,
+    /// When the file was generated, in RFC 3339 format (UTC).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    generated: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shape: Option<Shape>,
+    /// Tags of the test, see [RegTest::with_tags].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Checksum of the entries, see [Config::checksums].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+    /// The directory holding the blobs of the entries, relative to the
+    /// directory of the data file, see [Config::blob_bytes].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blobs: Option<String>,
+}
+
+/// Contents of a regression data file: a header describing the file, and the
+/// entries.
+#[derive(Serialize, Deserialize)]
+struct DataFile {
+    #[serde(flatten)]
+    header: Header,
+    entries: Vec<RegEntry>,
+    /// Whether the file is (to be) stored encrypted, see [Config::encrypt].
+    #[serde(skip)]
+    encrypted: bool,
+}
+
+fn current_format_version() -> u32 {
+    FORMAT_VERSION
+}
+
+impl DataFile {
+    /// A data file generated now by the current test.
+    fn new(entries: Vec<RegEntry>, shape: Option<Shape>, tags: Vec<String>) -> Self {
+        DataFile {
+            header: Header {
+                version: FORMAT_VERSION,
+                crate_name: std::env::var("CARGO_PKG_NAME").ok(),
+                test: Some(registry::current_test_name()),
+                generated: Some(timestamp()),
+                shape,
+                tags,
+                checksum: None,
+                blobs: None,
+            },
+            entries,
+            encrypted: false,
+        }
+    }
+
+    /// Stores the checksum of the entries in the header, see
+    /// [Config::checksums]. Called before messages are moved out of the
+    /// entries by [save_apart](DataFile::save_apart).
+    fn seal(&mut self) {
+        self.header.checksum = Some(checksum::of(&self.entries));
+    }
+
+    /// Keeps this file from changing needlessly between runs, see
+    /// [Config::merge_friendly]: sorts the keyed entries by subtest and key,
+    /// in the places keyed entries take, and leaves out when the file was
+    /// generated and where the entries were recorded.
+    fn make_merge_friendly(&mut self) {
+        self.header.generated = None;
+        let slots: Vec<usize> = (0..self.entries.len())
+            .filter(|&i| self.entries[i].key.is_some())
+            .collect();
+        let mut keyed: Vec<RegEntry> = slots.iter().map(|&i| self.entries[i].clone()).collect();
+        // Stable, so that entries with the same key keep their order
+        keyed.sort_by(|a, b| (&a.section, &a.key).cmp(&(&b.section, &b.key)));
+        for (slot, entry) in slots.into_iter().zip(keyed) {
+            self.entries[slot] = entry;
+        }
+        for entry in &mut self.entries {
+            entry.location = None;
+        }
+    }
+
+    /// Saves this file as `path` to the store of `backend`, as JSON Lines if
+    /// `path` has a `.jsonl` extension, compressed according to any further
+    /// extension (e.g. `.jsonl.zst`), and encrypted if it is
+    /// [encrypted](DataFile::encrypted).
+    fn save(&self, path: &Path, backend: Backend) -> std::io::Result<()> {
+        let previous = if self.encrypted {
+            backend.store().load(path)?
+        } else {
+            None
+        };
+        backend
+            .store()
+            .save(path, &self.encode_over(path, previous.as_deref())?)
+    }
+
+    /// Whether the data file `path` is in JSON Lines format: a header line,
+    /// followed by one entry per line.
+    fn is_lines(path: &Path) -> bool {
+        match compress::of_path(path) {
+            config::Compression::None => path.extension(),
+            _ => path.file_stem().map(Path::new).and_then(Path::extension),
+        }
+        .is_some_and(|ext| ext == "jsonl")
+    }
+
+    /// The contents of this file when saved as `path`.
+    fn encode(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        self.encode_over(path, None)
+    }
+
+    /// The contents of this file when saved as `path` over its `previous`
+    /// contents: those are kept if the file is encrypted and its contents
+    /// don't change, rather than encrypted again with a new nonce.
+    fn encode_over(&self, path: &Path, previous: Option<&[u8]>) -> std::io::Result<Vec<u8>> {
+        let compression = compress::of_path(path);
+        let data = if DataFile::is_lines(path) {
+            let mut lines = serde_json::to_vec(&self.header)?;
+            for entry in &self.entries {
+                lines.push(b'\n');
+                serde_json::to_writer(&mut lines, entry)?;
+            }
+            lines.push(b'\n');
+            lines
+        } else {
+            serde_json::to_vec_pretty(self)?
+        };
+        let data = compress::compress(data, compression)?;
+        if self.encrypted {
+            encrypt::encrypt(data, previous)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Saves this file as `path` with the [directory layout](Layout::Directory):
+    /// the messages go to their own files, which replace the previous ones.
+    fn save_apart(mut self, path: &Path, backend: Backend) -> std::io::Result<()> {
+        let dir = entry_dir(path);
+        if let Some(local) = backend.store().local_path(&dir) {
+            match std::fs::remove_dir_all(local) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        let names = self.entry_file_names();
+        for (entry, name) in self.entries.iter_mut().zip(names) {
+            if entry.blob.is_some() {
+                continue;
+            }
+            backend.store().save(
+                &dir.join(&name),
+                std::mem::take(&mut entry.message).as_bytes(),
+            )?;
+            entry.file = Some(name);
+        }
+        self.save(path, backend)
+    }
+
+    /// The file holding the message of each entry with the
+    /// [directory layout](Layout::Directory), relative to the entry
+    /// directory: named after the key of the entry, or else its index, in a
+    /// directory per subtest.
+    fn entry_file_names(&self) -> Vec<String> {
+        let mut used = HashSet::new();
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let prefix: String = entry
+                    .section
+                    .iter()
+                    .flat_map(|section| section.split('/'))
+                    .map(|name| format!("{}/", file_name_part(name)))
+                    .collect();
+                // Named after the key, or else the index, whichever is free
+                let keyed = entry.key.as_deref().map(file_name_part);
+                keyed
+                    .into_iter()
+                    .chain(std::iter::once(format!("{:04}", index)))
+                    .chain((2..).map(|n| format!("{:04}-{}", index, n)))
+                    .map(|name| format!("{}{}.txt", prefix, name))
+                    .find(|name| used.insert(name.clone()))
+                    .expect("names are endless")
+            })
+            .collect()
+    }
+
+    /// Loads the messages stored apart from the data file `path` (see
+    /// [Layout::Directory]) with `load`.
+    fn load_apart(
+        &mut self,
+        path: &Path,
+        load: impl Fn(&Path) -> std::io::Result<Option<Vec<u8>>>,
+    ) -> std::io::Result<()> {
+        let dir = entry_dir(path);
+        let blobs = self
+            .header
+            .blobs
+            .as_deref()
+            .map(|blobs| blob_dir(path, blobs));
+        for entry in &mut self.entries {
+            entry.load_apart(&dir, &load)?;
+            if let Some(blobs) = &blobs {
+                entry.load_blob(blobs, &load)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the messages larger than `threshold` bytes out of the data file
+    /// `path`, to blobs in `dir` named after their digest, see
+    /// [Config::blob_bytes]. Called after [seal](DataFile::seal), like
+    /// [save_apart](DataFile::save_apart).
+    fn store_blobs(
+        &mut self,
+        path: &Path,
+        dir: &Path,
+        threshold: u64,
+        backend: Backend,
+    ) -> std::io::Result<()> {
+        self.header.blobs = None;
+        for entry in &mut self.entries {
+            if entry.message.len() as u64 <= threshold {
+                continue;
+            }
+            let digest: String = Sha256::digest(entry.message.as_bytes())
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            let blob = format!("sha256:{}", digest);
+            backend.store().save(
+                &dir.join(blob_file_name(&blob)),
+                std::mem::take(&mut entry.message).as_bytes(),
+            )?;
+            entry.blob = Some(blob);
+            if self.header.blobs.is_none() {
+                let parent = path.parent().unwrap_or(Path::new(""));
+                self.header.blobs = Some(path::relative_path(dir, parent)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the data file `path` in any format, compressed or not, encrypted
+    /// or not. Only `.jsonl` files may be in JSON Lines format.
+    fn read<R: std::io::Read + Send>(reader: R, path: &Path) -> std::io::Result<Self> {
+        let mut reader = std::io::BufReader::new(reader);
+        let encrypted = encrypt::is_encrypted(std::io::BufRead::fill_buf(&mut reader)?);
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(
+            &mut compress::decoder(encrypt::decrypter(reader)?)?,
+            &mut bytes,
+        )?;
+
+        // The first value tells the format; each is then parsed again from
+        // the bytes, so that errors tell where in the file they are
+        let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+        let first = serde_json::Value::deserialize(&mut deserializer)?;
+        let mut data = match first {
+            serde_json::Value::Object(header)
+                if DataFile::is_lines(path) && !header.contains_key("entries") =>
+            {
+                let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+                DataFile {
+                    header: Header::deserialize(&mut deserializer)?,
+                    entries: deserializer
+                        .into_iter()
+                        .collect::<Result<_, serde_json::Error>>()?,
+                    encrypted: false,
+                }
+            }
+            serde_json::Value::Array(_) => {
+                let entries = serde_json::from_slice(&bytes)?;
+                DataFile {
+                    header: Header {
+                        version: 0,
+                        crate_name: None,
+                        test: None,
+                        generated: None,
+                        shape: None,
+                        tags: Vec::new(),
+                        checksum: None,
+                        blobs: None,
+                    },
+                    entries,
+                    encrypted: false,
+                }
+            }
+            _ => serde_json::from_slice(&bytes)?,
+        };
+        data.encrypted = encrypted;
+
+        if data.header.version > FORMAT_VERSION {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "format version {} is newer than the supported version {}; update regression-test",
+                    data.header.version, FORMAT_VERSION
+                ),
+            ));
+        }
+        Ok(data)
+    }
+}
+
+/// The current time in RFC 3339 format (UTC). Honors `SOURCE_DATE_EPOCH`, for
+/// reproducible baselines.
+fn timestamp() -> String {
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    format_timestamp(seconds)
+}
+
+/// Formats seconds since the Unix epoch in RFC 3339 format (UTC). Public for
+/// `cargo regtest list`.
+#[doc(hidden)]
+pub fn format_timestamp(seconds: u64) -> String {
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let days = (seconds / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let time = seconds % 86400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
 }
 
 /// Regression test mode
+#[derive(Clone, Copy)]
 enum Mode {
     /// We are currently generating the regression test data, and writing it on
     /// disk when appropriate.
@@ -64,159 +859,4452 @@ pub struct RegTest {
     /// In [Mode::Read], contains all previously generated regression test data,
     /// and is used to compare with current output.
     buffer: Vec<RegEntry>,
-    /// Used in [Mode::Read]. Next regression test to process.
+    /// Used in [Mode::Read]. Previously generated entries of subtests, which
+    /// are handed over to each subtest as it starts.
+    sections: Vec<RegEntry>,
+    /// Used in [Mode::Read] with [Config::streaming]. The rest of the
+    /// baseline, pulled into `buffer` and `sections` as needed.
+    stream: Option<stream::EntryStream>,
+    /// Used with `stream`. The blob directory of the baseline, once read
+    /// from its header.
+    stream_blobs: Option<PathBuf>,
+    /// Used in [Mode::Write] with [Config::incremental]. The partial file, to
+    /// which entries are appended as they are recorded.
+    partial: Option<std::fs::File>,
+    /// Used in [Mode::Read]. Position from which the next entry without a key
+    /// is looked up.
     read_index: usize,
+    /// Used in [Mode::Read]. Number of entries compared so far.
+    compared: usize,
+    /// Used in [Mode::Read]. How many times each key has been recorded, so
+    /// that repeated keys are matched in order.
+    key_occurrences: HashMap<String, usize>,
+    /// Used in [Mode::Read]. Set once an entry doesn't match the baseline,
+    /// until the mismatch is reported.
+    divergence: Option<Divergence>,
+    /// Used in [Mode::Read]. Entries recorded past the end of the baseline,
+    /// with where they were recorded. Only collected outside of subtests.
+    extra: Vec<(RegEntry, Position)>,
+    /// Number of entries written to the pending file.
+    pending: usize,
+    /// Set by [RegTest::expect_nothing_recorded]. Any recorded entry is a test
+    /// failure.
+    expect_empty: bool,
+    /// Set by [RegTest::limit_entries].
+    max_entries: Option<usize>,
+    /// Entries recorded by the enclosing tests before this subtest started,
+    /// which count towards [max_entries](RegTest::max_entries).
+    outer_entries: usize,
+    /// Set by [RegTest::with_tags].
+    tags: Vec<String>,
+    /// Used in [Mode::Read] with [UpdatePolicy::Mismatched]. Baseline entries
+    /// replaced by the recorded ones: their index among the entries of their
+    /// section, which is relative to this test.
+    updates: Vec<(usize, RegEntry)>,
+    /// Used in [Mode::Read]. Whether the baseline was rewritten, see
+    /// [UpdatePolicy::Mismatched] and [ExtraEntries::Append].
+    rewritten: bool,
+    /// Set by [RegTest::expect_failure]: why the test is expected not to
+    /// match its baseline.
+    xfail: Option<String>,
+    /// Number of [inline expectations](RegTest::regtest_inline) checked.
+    inline_count: usize,
+    /// Number of images recorded with `regtest_image`, which names their
+    /// files.
+    #[cfg(feature = "image")]
+    image_count: usize,
+    /// Used in [Mode::Read]. Number of entries compared by subtests.
+    subtest_count: usize,
+    /// Names of the subtests run so far.
+    subtest_names: HashSet<String>,
+    /// Subtests which failed, with their panic message.
+    subtest_failures: Vec<(String, String)>,
+    /// Full name of this subtest (nested subtests are separated by `/`), if
+    /// this is one. Subtest data is handled by the parent.
+    section: Option<String>,
+    /// Shape declared with [RegTest::assert_shape].
+    shape: Option<Shape>,
+    /// Used in [Mode::Read]. Shape stored in the baseline's metadata.
+    baseline_shape: Option<Shape>,
+    /// When the test started.
+    started: Instant,
+    /// The first mismatch reported by this test, for the JUnit summary.
+    regression: OnceCell<String>,
+    /// Where the regression data is kept.
+    backend: Backend,
+    /// Lock on the local data file, keeping other processes from using it
+    /// meanwhile, until this structure is dropped. Subtests use that of their
+    /// parent.
+    _lock: Option<lock::FileLock>,
+    /// Configuration in effect for this test.
+    config: Config,
+    /// Renders the messages reported to the user.
+    renderer: Renderer,
+    /// Normalizers added with [normalize_with](RegTest::normalize_with),
+    /// applied after those of the configuration.
+    normalizers: Vec<Arc<dyn Normalizer>>,
+    /// Whether `Debug` entries are pretty-printed, see [Settings::pretty_debug].
+    pretty_debug: bool,
+    /// Prefix of the mismatches reported, see [Settings::description].
+    description: Option<String>,
+    /// Values removed or replaced from JSON documents, see
+    /// [Settings::ignore_path].
+    json_paths: Vec<PathRule>,
 }
 
 impl RegTest {
     pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
-        let file_path = path.as_ref().to_path_buf();
+        Self::with_config(path, Config::current().clone())
+    }
 
-        if file_path.exists() {
-            // Store all entries in memory
-            let file = OpenOptions::new().read(true).open(&file_path)?;
+    /// Like [RegTest::new], but uses the given configuration instead of the
+    /// one discovered from `regtest.toml`.
+    pub fn with_config<P: AsRef<Path>>(path: P, config: Config) -> std::io::Result<Self> {
+        Self::with_backend(path, config, Backend::Store)
+    }
 
-            let mut reader = std::io::BufReader::new(file);
+    /// Like [RegTest::with_config], but keeps the regression data in
+    /// `backend`. With [Backend::InMemory], nothing is read from or written
+    /// to disk, which suits tools evaluating tests (e.g. rust-analyzer) and
+    /// tests of code built on this crate.
+    pub fn with_backend<P: AsRef<Path>>(
+        path: P,
+        config: Config,
+        backend: Backend,
+    ) -> std::io::Result<Self> {
+        Self::open(path.as_ref(), config, backend, &[])
+    }
 
-            let buffer = match serde_json::from_reader(&mut reader) {
-                Ok(entries) => entries,
-                Err(e) => {
-                    eprintln!(
-                        "Failed to read regression test file {}: {}",
-                        file_path.display(),
-                        e
-                    );
-                    return Err(e.into());
-                }
-            };
+    /// Like [RegTest::new], for a test tagged with `tags` (e.g. `parser`,
+    /// `slow`). The tags are stored in the header of the data file, and
+    /// select the tests whose baselines are regenerated with
+    /// [Config::update_tags]. Usually set with `#[regtest(tags("parser"))]`.
+    pub fn with_tags<P: AsRef<Path>>(path: P, tags: &[&str]) -> std::io::Result<Self> {
+        Self::open(
+            path.as_ref(),
+            Config::current().clone(),
+            Backend::Store,
+            tags,
+        )
+    }
 
-            Ok(RegTest {
-                file_path,
-                mode: Mode::Read,
-                buffer,
-                read_index: 0,
-            })
-        } else {
-            Ok(RegTest {
-                file_path,
-                mode: Mode::Write,
-                buffer: Vec::new(),
-                read_index: 0,
-            })
+    /// Like [RegTest::new], for the test running on this thread, without
+    /// macros: the data file is found from the name libtest gives the thread
+    /// (or that of the test nextest runs, or the harness runs) and the crate
+    /// directory cargo sets in `CARGO_MANIFEST_DIR`.
+    ///
+    /// The source file of the test isn't known at runtime, so data files are
+    /// laid out after the test's module path instead: the data of
+    /// `parser::tests::parses` is in `regtest_data/src/parser/tests/`, and
+    /// that of a test `parses` of the integration test `tests/it.rs` in
+    /// `regtest_data/tests/it/`, as if each module had a file of its own.
+    ///
+    /// Fails if the running test, or the crate directory, isn't known, e.g.
+    /// in a custom harness running tests on the main thread.
+    pub fn for_current_test() -> std::io::Result<Self> {
+        let test = registry::current_test_name();
+        if test == "main" || test == "<unnamed>" {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "can't tell which test is running on this thread",
+            ));
         }
+        let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "CARGO_MANIFEST_DIR isn't set; run the tests with cargo",
+            )
+        })?;
+        let config = Config::current().clone();
+        let path = config.current_test_path(&manifest_dir, &registry::binary_name(), &test);
+        Self::with_config(path, config)
     }
 
-    fn regtest_internal(&mut self, message: String, reg_type: RegType) {
-        match self.mode {
-            Mode::Write => {
-                self.buffer.push(RegEntry { reg_type, message });
+    fn open(
+        file_path: &Path,
+        mut config: Config,
+        backend: Backend,
+        tags: &[&str],
+    ) -> std::io::Result<Self> {
+        let file_path = file_path.to_path_buf();
+        let renderer = Renderer::new(&config);
+        config.update = config.update_policy(tags);
+        #[cfg(feature = "harness")]
+        if harness::updating() {
+            config.update = UpdatePolicy::Always;
+        }
+
+        if let Err(previous) = registry::register(&file_path) {
+            panic!(
+                "Regression test file collision: {} is used by both `{}` and `{}`.\n\
+                 Each test must have its own regression data file.",
+                renderer.path(&file_path),
+                previous,
+                registry::current_test_name()
+            );
+        }
+
+        let lock = match backend.store().local_path(&file_path) {
+            Some(path) => Some(lock::acquire(&path)?),
+            None => None,
+        };
+
+        if let Some(dir) = &config.html_report {
+            html::start(dir);
+        }
+
+        let baseline = config
+            .baseline
+            .as_ref()
+            .filter(|_| backend == Backend::Store);
+        let stored = if baseline.is_none() && config.update != UpdatePolicy::Always {
+            backend.store().open(&file_path)?
+        } else {
+            None
+        };
+
+        let mut stream = None;
+        let (mode, (entries, baseline_shape)) = if let Some(rev) = baseline {
+            // A test without data at that revision compares against nothing
+            let data = git::show(rev, &file_path)
+                .and_then(|blob| match blob {
+                    Some(blob) => {
+                        let mut data = DataFile::read(blob.as_slice(), &file_path)?;
+                        data.load_apart(&file_path, |path| git::show(rev, path))?;
+                        checksum::verify(&file_path, &data, config.checksums)?;
+                        Ok(Some(data))
+                    }
+                    None => Ok(None),
+                })
+                .map_err(|e| {
+                    std::io::Error::new(
+                        e.kind(),
+                        format!(
+                            "Failed to read regression test file {} at {}: {}",
+                            renderer.path(&file_path),
+                            rev,
+                            e
+                        ),
+                    )
+                })?;
+            match data {
+                Some(data) => (Mode::Read, (data.entries, data.header.shape)),
+                None => (Mode::Read, (Vec::new(), None)),
             }
-            Mode::Read => {
-                if self.read_index >= self.buffer.len() {
-                    panic!("No more regression entries in file, but test expected more.");
+        } else if let Some(stored) = stored {
+            if config.streaming {
+                // Entries are pulled as the test compares them
+                stream = Some(stream::EntryStream::new(stored)?);
+                (Mode::Read, (Vec::new(), None))
+            } else {
+                // Store all entries in memory
+                let data = DataFile::read(stored, &file_path).and_then(|mut data| {
+                    data.load_apart(&file_path, |path| backend.store().load(path))?;
+                    checksum::verify(&file_path, &data, config.checksums)?;
+                    Ok(data)
+                });
+                match data.map_err(|e| CorruptSnapshot::describe(&file_path, e)) {
+                    Ok(data) => (Mode::Read, (data.entries, data.header.shape)),
+                    Err(ref e)
+                        if config.update == UpdatePolicy::Repair
+                            && let Some(corrupt) = e
+                                .get_ref()
+                                .and_then(|e| e.downcast_ref::<CorruptSnapshot>()) =>
+                    {
+                        let backup = corrupt.backup_path();
+                        if let Some(data) = backend.store().load(&file_path)? {
+                            backend.store().save(&backup, &data)?;
+                        }
+                        eprintln!(
+                            "note: {} was corrupt ({}, at line {}), regenerating it; the corrupt file was backed up to {}",
+                            renderer.path(&file_path),
+                            corrupt.reason,
+                            corrupt.line,
+                            renderer.path(&backup)
+                        );
+                        (Mode::Write, (Vec::new(), None))
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to read regression test file {}: {}",
+                            file_path.display(),
+                            e
+                        );
+                        return Err(e);
+                    }
                 }
+            }
+        } else if config.update == UpdatePolicy::Never {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "Regression test file {} does not exist, and the update policy forbids generating it.",
+                    renderer.path(&file_path)
+                ),
+            ));
+        } else {
+            (Mode::Write, (Vec::new(), None))
+        };
 
-                let expected = &self.buffer[self.read_index];
-                self.read_index += 1;
+        let (buffer, sections) = entries.into_iter().partition(|e| e.section.is_none());
 
-                if expected.reg_type != reg_type {
-                    panic!(
-                        "Regression data generated in different ways: expected {:?}, got {:?}",
-                        expected.reg_type, reg_type
-                    );
+        let partial = match backend.store().local_path(&file_path) {
+            Some(local) if matches!(mode, Mode::Write) && config.incremental => {
+                let partial = path::partial_path(&local);
+                let created = local
+                    .parent()
+                    .map_or(Ok(()), std::fs::create_dir_all)
+                    .and_then(|()| std::fs::File::create(&partial));
+                match created {
+                    Ok(file) => Some(file),
+                    Err(e) => {
+                        eprintln!(
+                            "warning: failed to create partial regression data {}: {}",
+                            partial.display(),
+                            e
+                        );
+                        None
+                    }
                 }
+            }
+            _ => None,
+        };
 
-                if expected.message != message {
-                    panic!(
-                        "Regression message mismatch:\nExpected: {}\nActual:   {}\n\nDiff:\n{}",
-                        expected.message,
-                        message,
-                        diff_lines(&expected.message, &message)
-                    );
+        order::start(&config);
+
+        let mut regtest = RegTest {
+            file_path,
+            mode,
+            buffer,
+            sections,
+            stream,
+            stream_blobs: None,
+            partial,
+            read_index: 0,
+            compared: 0,
+            key_occurrences: HashMap::new(),
+            divergence: None,
+            extra: Vec::new(),
+            pending: 0,
+            expect_empty: false,
+            max_entries: None,
+            outer_entries: 0,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            updates: Vec::new(),
+            rewritten: false,
+            xfail: None,
+            inline_count: 0,
+            #[cfg(feature = "image")]
+            image_count: 0,
+            subtest_count: 0,
+            subtest_names: HashSet::new(),
+            subtest_failures: Vec::new(),
+            section: None,
+            shape: None,
+            baseline_shape,
+            started: Instant::now(),
+            regression: OnceCell::new(),
+            backend,
+            _lock: lock,
+            config,
+            renderer,
+            normalizers: Vec::new(),
+            pretty_debug: false,
+            description: None,
+            json_paths: Vec::new(),
+        };
+        // Tells an empty baseline apart, for `assert_nothing_recorded`
+        while regtest.buffer.is_empty() && regtest.pull() {}
+        Ok(regtest)
+    }
+
+    /// Used in [Mode::Read]. Pulls the next item of a streamed baseline.
+    /// Returns `false` once the baseline was read entirely.
+    fn pull(&mut self) -> bool {
+        let Some(stream) = &self.stream else {
+            return false;
+        };
+        let dir = entry_dir(&self.file_path);
+        let item = stream.next().map(|item| {
+            let mut item = item?;
+            if let stream::Item::Entry(entry) = &mut item {
+                entry.load_apart(&dir, |path| self.backend.store().load(path))?;
+                if let Some(blobs) = &self.stream_blobs {
+                    entry.load_blob(blobs, |path| self.backend.store().load(path))?;
                 }
             }
+            Ok::<_, std::io::Error>(item)
+        });
+        match item {
+            Some(Ok(stream::Item::Shape(shape))) => self.baseline_shape = Some(shape),
+            Some(Ok(stream::Item::Blobs(blobs))) => {
+                self.stream_blobs = Some(blob_dir(&self.file_path, &blobs))
+            }
+            Some(Ok(stream::Item::Entry(entry))) if entry.section.is_none() => {
+                self.buffer.push(entry)
+            }
+            Some(Ok(stream::Item::Entry(entry))) => self.sections.push(entry),
+            Some(Err(e)) => {
+                self.stream = None;
+                panic!(
+                    "Failed to read regression test file {}: {}",
+                    self.renderer.path(&self.file_path),
+                    e
+                );
+            }
+            None => {
+                self.stream = None;
+                return false;
+            }
         }
+        true
     }
 
-    pub fn regtest<T: Display>(&mut self, value: T) {
-        self.regtest_internal(format!("{}", value), RegType::Display);
+    /// Used in [Mode::Read]. Reads the rest of a streamed baseline.
+    fn pull_all(&mut self) {
+        while self.pull() {}
     }
 
-    pub fn regtest_dbg<T: Debug>(&mut self, value: T) {
-        self.regtest_internal(format!("{:?}", value), RegType::Debug);
+    /// Used in [Mode::Write]. Appends the entries of the buffer from `from` on
+    /// to the partial file, if any.
+    fn append_partial(&mut self, from: usize) {
+        let Some(file) = &mut self.partial else {
+            return;
+        };
+
+        let mut lines = Vec::new();
+        for entry in &self.buffer[from..] {
+            serde_json::to_writer(&mut lines, entry).expect("entries are serializable");
+            lines.push(b'\n');
+        }
+        // A single write, so that a crash doesn't leave half an entry
+        if let Err(e) = file.write_all(&lines) {
+            eprintln!(
+                "warning: failed to append to partial regression data of {}: {}",
+                self.file_path.display(),
+                e
+            );
+            self.partial = None;
+        }
     }
-}
 
-impl Drop for RegTest {
-    fn drop(&mut self) {
-        if let Mode::Write = self.mode {
-            // Only create/write the file here
-            if let Ok(file) = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open(&self.file_path)
-            {
-                let mut writer = BufWriter::new(file);
-                if serde_json::to_writer_pretty(&mut writer, &self.buffer).is_ok() {
-                    let _ = writer.flush();
-                }
+    /// Index of the `nth` (from 0) baseline entry with `key`.
+    fn find_key(&self, key: &str, nth: usize) -> Option<usize> {
+        self.buffer
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.key.as_deref() == Some(key))
+            .nth(nth)
+            .map(|(i, _)| i)
+    }
+
+    /// The title of the last [header](RegTest::header) before the entry at
+    /// `index`, if any.
+    fn heading_before(&self, index: usize) -> Option<String> {
+        self.buffer[..index.min(self.buffer.len())]
+            .iter()
+            .rev()
+            .find(|e| e.reg_type == RegType::Header)
+            .map(|e| e.message.clone())
+    }
+
+    /// Whether the baseline entry at `index` is a [checkpoint](RegTest::checkpoint).
+    fn is_checkpoint(&self, index: usize) -> bool {
+        self.buffer[index].reg_type == RegType::Checkpoint
+    }
+
+    /// Index of the first baseline entry at or after `from` satisfying
+    /// `predicate`, reading more of the baseline if needed.
+    fn find_entry(&mut self, from: usize, predicate: impl Fn(&RegEntry) -> bool) -> Option<usize> {
+        let mut from = from;
+        loop {
+            if let Some(i) = self.buffer[from..].iter().position(&predicate) {
+                return Some(from + i);
+            }
+            from = self.buffer.len();
+            if !self.pull() {
+                return None;
             }
         }
     }
-}
 
-fn diff_lines(expected: &str, actual: &str) -> String {
-    let exp_lines: Vec<_> = expected.lines().collect();
-    let act_lines: Vec<_> = actual.lines().collect();
-    let max = exp_lines.len().max(act_lines.len());
+    /// Number of entries recorded (or compared) so far by this test.
+    fn recorded_count(&self) -> usize {
+        match self.mode {
+            Mode::Write => self.buffer.len() + self.inline_count,
+            Mode::Read => self.compared + self.inline_count + self.subtest_count,
+        }
+    }
 
-    let mut diff = String::new();
-    let mut minus_block = Vec::new();
-    let mut plus_block = Vec::new();
+    /// Declares that this test must not record any entries, e.g. when checking
+    /// that a code path emits no diagnostics.
+    ///
+    /// In read mode the baseline must be empty. Any later call to [regtest] or
+    /// [regtest_dbg] fails the test.
+    ///
+    /// [regtest]: RegTest::regtest
+    /// [regtest_dbg]: RegTest::regtest_dbg
+    pub fn expect_nothing_recorded(&mut self) {
+        self.assert_nothing_recorded();
+        self.expect_empty = true;
+    }
 
-    for i in 0..max {
-        let exp = exp_lines.get(i).unwrap_or(&"");
-        let act = act_lines.get(i).unwrap_or(&"");
+    /// Declares that this test is expected not to match its baseline, for
+    /// `reason` (e.g. a tracking issue), so that work known to change its
+    /// behavior can land before the baselines are regenerated, without
+    /// deleting them. Usually set with `#[regtest(xfail = "...")]`.
+    ///
+    /// In read mode, a mismatch is reported as an expected failure instead of
+    /// failing the test, and the test compares no further entries. A test
+    /// which matches its baseline fails instead, so that the marker is
+    /// removed.
+    pub fn expect_failure(&mut self, reason: &str) {
+        self.xfail = Some(reason.to_string());
+    }
 
-        if exp != act {
-            if !exp.is_empty() {
-                minus_block.push(exp);
-            }
-            if !act.is_empty() {
-                plus_block.push(act);
+    /// Limits the number of entries this test (including its subtests) may
+    /// record when writing its baseline to `max`, so that a test recording
+    /// an entry inside a loop by accident fails before its bloated baseline
+    /// is written. Usually set with `#[regtest(max_entries = 50)]`.
+    pub fn limit_entries(&mut self, max: usize) {
+        self.max_entries = Some(max);
+    }
+
+    /// Panics if any entry has been recorded so far, or if (in read mode) the
+    /// baseline contains any entries.
+    pub fn assert_nothing_recorded(&self) {
+        let recorded = self.recorded_count();
+        if recorded > 0 {
+            panic!(
+                "Expected no regression entries to be recorded, but {} were.",
+                recorded
+            );
+        }
+
+        if let Mode::Read = self.mode
+            && !self.buffer.is_empty()
+        {
+            panic!(
+                "Expected no regression entries, but the baseline {} contains {}.",
+                self.renderer.path(&self.file_path),
+                self.buffer.len()
+            );
+        }
+    }
+
+    /// Declares the structural shape of the data recorded by this test: the
+    /// number of entries, and the kinds they are recorded with (in any order).
+    ///
+    /// This catches a refactor silently dropping a snapshot point, even when
+    /// the remaining entries still match. In read mode the baseline is checked
+    /// right away; in write mode the shape is stored in the baseline's
+    /// metadata. In both modes, the entries recorded by the test are checked
+    /// when it ends. Entries of subtests and inline expectations don't count.
+    pub fn assert_shape(&mut self, entries: usize, kinds: &[RegType]) {
+        let shape = Shape::new(entries, kinds);
+
+        if let Mode::Read = self.mode {
+            self.pull_all();
+            if let Some(stored) = &self.baseline_shape
+                && *stored != shape
+            {
+                panic!(
+                    "The test declares {}, but the baseline {} was recorded with a shape of {}. Regenerate it if the change is intended.",
+                    shape,
+                    self.renderer.path(&self.file_path),
+                    stored
+                );
             }
-        } else {
-            if !minus_block.is_empty() || !plus_block.is_empty() {
-                if !minus_block.is_empty() {
-                    for line in &minus_block {
-                        diff.push_str(&format!("- {}\n", line));
-                    }
-                    minus_block.clear();
-                }
-                if !plus_block.is_empty() {
-                    for line in &plus_block {
-                        diff.push_str(&format!("+ {}\n", line));
-                    }
-                    plus_block.clear();
-                }
-            } else {
-                diff.push_str(&format!("  {}\n", exp));
+
+            let baseline = Shape::of(self.buffer.iter());
+            if baseline != shape {
+                panic!(
+                    "The test declares {}, but the baseline {} has {}.",
+                    shape,
+                    self.renderer.path(&self.file_path),
+                    baseline
+                );
             }
         }
+
+        self.shape = Some(shape);
     }
 
-    // Flush any remaining blocks
-    if !minus_block.is_empty() {
-        for line in &minus_block {
-            diff.push_str(&format!("- {}\n", line));
+    /// Checks the entries recorded by the test against the declared shape.
+    fn check_recorded_shape(&self) {
+        let Some(shape) = &self.shape else {
+            return;
+        };
+
+        let recorded = match self.mode {
+            Mode::Write => Shape::of(self.buffer.iter().filter(|e| e.section.is_none())),
+            // Kinds are compared entry by entry against the baseline
+            Mode::Read => Shape {
+                entries: self.compared,
+                kinds: shape.kinds.clone(),
+            },
+        };
+
+        if recorded != *shape {
+            panic!(
+                "The test declares {}, but recorded {} ({}).",
+                shape,
+                recorded,
+                self.renderer.path(&self.file_path)
+            );
         }
     }
-    if !plus_block.is_empty() {
-        for line in &plus_block {
-            diff.push_str(&format!("+ {}\n", line));
+
+    fn check_not_expecting_empty(&self, message: &str) {
+        if self.expect_empty {
+            panic!(
+                "Test declared that it records no regression entries, but recorded one:\n{}",
+                message
+            );
         }
     }
 
-    diff
+    fn regtest_internal(
+        &mut self,
+        message: String,
+        reg_type: RegType,
+        caller: &'static Location<'static>,
+        recording: Recording<'_>,
+    ) {
+        let Recording {
+            comparator,
+            hashed,
+            pattern,
+            note,
+            read_back,
+            value_type,
+        } = recording;
+        let location = Some(format!("{}:{}", caller.file(), caller.line()));
+        let position = Position::from_location(caller);
+        let caller = Some(position);
+        self.check_not_expecting_empty(&message);
+
+        // Digests have nothing to redact, and must stay intact, while
+        // patterns are matched against the output as is
+        let message = if reg_type.is_verbatim() {
+            message
+        } else {
+            let redacted = self.config.redact(&message);
+            placeholder::insert(&redacted, &self.config.placeholders).into_owned()
+        };
+        // Serialized values have their floats rounded too
+        let message = match self.config.float_digits {
+            Some(digits) if !reg_type.is_verbatim() || reg_type.is_serialized() => {
+                float::round(&message, digits).into_owned()
+            }
+            _ => message,
+        };
+        // Giant outputs keep their ends only
+        let message = match self.config.truncate_bytes {
+            Some(keep) if !reg_type.is_verbatim() => {
+                match truncate::truncate(&message, usize::try_from(keep).unwrap_or(usize::MAX)) {
+                    Some(truncated) => {
+                        if self.config.truncate_sidecar && matches!(self.mode, Mode::Write) {
+                            self.write_full_message(&message, &truncated.digest);
+                        }
+                        truncated.message
+                    }
+                    None => message,
+                }
+            }
+            _ => message,
+        };
+        let key = if reg_type.is_verbatim() {
+            None
+        } else {
+            self.config.entry_key(&message)
+        };
+
+        match self.mode {
+            Mode::Write => {
+                if let Some(max) = self.config.max_entry_bytes
+                    && message.len() as u64 > max
+                {
+                    panic!(
+                        "The regression entry recorded at {} is {} bytes, more than the limit of {} bytes (`max_entry_bytes`). Record a smaller part of the output, or a digest of it, instead.",
+                        location.as_deref().unwrap_or_default(),
+                        message.len(),
+                        max
+                    );
+                }
+                if let Some(max) = self.max_entries
+                    && self.outer_entries + self.buffer.len() >= max
+                {
+                    panic!(
+                        "The regression test recorded more than {} entries (`max_entries`): the one past the limit was recorded at {}. Is an entry recorded inside a loop? Record fewer entries, or raise the limit.",
+                        max,
+                        location.as_deref().unwrap_or_default()
+                    );
+                }
+                self.buffer.push(RegEntry {
+                    reg_type,
+                    message: pattern.map_or(message, str::to_string),
+                    section: None,
+                    key,
+                    location,
+                    note: note.map(str::to_string),
+                    value_type: value_type.map(str::to_string),
+                    file: None,
+                    blob: None,
+                });
+                self.append_partial(self.buffer.len() - 1);
+            }
+            Mode::Read => {
+                self.compared += 1;
+                if self.regression.get().is_some() {
+                    // Already mismatched, in advisory mode or as expected
+                    return;
+                }
+
+                if key.is_none()
+                    && let Some(divergence) = &mut self.divergence
+                {
+                    divergence.recorded.push(Recorded {
+                        reg_type,
+                        message,
+                        caller,
+                    });
+                    // With a checkpoint ahead, the divergence ends there
+                    let index = divergence.index;
+                    if divergence.recorded.len() >= RESYNC_WINDOW
+                        && self
+                            .find_entry(index, |e| e.reg_type == RegType::Checkpoint)
+                            .is_none()
+                    {
+                        self.resync();
+                    }
+                    return;
+                }
+
+                let found = match &key {
+                    Some(key) => {
+                        let occurrence = self.key_occurrences.entry(key.clone()).or_default();
+                        *occurrence += 1;
+                        let nth = *occurrence - 1;
+                        let mut found = self.find_key(key, nth);
+                        if found.is_none() && self.stream.is_some() {
+                            // The entry may be anywhere in the rest of the baseline
+                            self.pull_all();
+                            found = self.find_key(key, nth);
+                        }
+                        found
+                    }
+                    None => loop {
+                        if let Some(i) = self.buffer[self.read_index..]
+                            .iter()
+                            .position(|e| e.key.is_none())
+                        {
+                            let next = i + self.read_index;
+                            self.read_index = next + 1;
+                            break Some(next);
+                        }
+                        // Only keyed entries are left in the buffer
+                        self.read_index = self.buffer.len();
+                        if !self.pull() {
+                            break None;
+                        }
+                    },
+                };
+
+                let Some(index) = found else {
+                    self.dump_hashed(hashed, &location);
+                    if self.section.is_none() {
+                        // Collected for review, and reported when the test ends
+                        let entry = RegEntry {
+                            reg_type,
+                            message: pattern.map_or(message, str::to_string),
+                            section: None,
+                            key,
+                            location,
+                            note: note.map(str::to_string),
+                            value_type: value_type.map(str::to_string),
+                            file: None,
+                            blob: None,
+                        };
+                        self.extra.push((entry, position));
+                        return;
+                    }
+                    self.fail(Mismatch {
+                        kind: MismatchKind::MissingEntry,
+                        index: key.is_none().then_some(self.buffer.len()),
+                        key,
+                        note: note.map(str::to_string),
+                        heading: None,
+                        fields: Vec::new(),
+                        changes: Vec::new(),
+                        position: None,
+                        caller,
+                        expected: String::new(),
+                        actual: message,
+                    });
+                    return;
+                };
+
+                let expected = &self.buffer[index];
+                if let (Some(expected_type), Some(actual_type)) =
+                    (expected.value_type.as_deref(), value_type)
+                    && expected_type != actual_type
+                {
+                    eprintln!(
+                        "warning: entry #{} of {} was recorded from a `{}`, but is now recorded from a `{}` at {}. If the test records another value by mistake, record the original one again; otherwise regenerate the baseline.",
+                        index,
+                        self.renderer.path(&self.file_path),
+                        expected_type,
+                        actual_type,
+                        location.as_deref().unwrap_or_default()
+                    );
+                }
+                if expected.reg_type == reg_type
+                    && let Some(read_back) = read_back
+                    && let Err(e) = read_back(&expected.message)
+                {
+                    if self.updates_mismatched() {
+                        let message = pattern.map_or(message, str::to_string);
+                        self.update_entry(index, reg_type, message, location, note, value_type);
+                        return;
+                    }
+                    self.fail(Mismatch {
+                        kind: MismatchKind::Incompatible,
+                        index: Some(index),
+                        key,
+                        note: expected.note.clone(),
+                        heading: None,
+                        fields: Vec::new(),
+                        changes: Vec::new(),
+                        position: None,
+                        caller,
+                        expected: expected.message.clone(),
+                        actual: e,
+                    });
+                    return;
+                }
+                let expected_text = self.normalize(&reg_type, &expected.message);
+                let actual_text = self.normalize(&reg_type, &message);
+                let matches = expected.reg_type == reg_type
+                    && (self.same_text(&reg_type, &expected_text, &actual_text)
+                        || comparator.is_some_and(|c| c.equivalent(&expected_text, &actual_text)));
+                if matches {
+                    if self.config.streaming {
+                        // Matched entries are never looked at again
+                        let expected = &mut self.buffer[index];
+                        expected.message = String::new();
+                        expected.location = None;
+                        expected.note = None;
+                        expected.value_type = None;
+                    }
+                    return;
+                }
+
+                if self.updates_mismatched() {
+                    let message = pattern.map_or(message, str::to_string);
+                    self.update_entry(index, reg_type, message, location, note, value_type);
+                    return;
+                }
+
+                self.dump_hashed(hashed, &location);
+                let recorded = Recorded {
+                    reg_type,
+                    message,
+                    caller,
+                };
+                if key.is_none() {
+                    // Wait for more entries before reporting, to tell whether
+                    // entries were added or removed
+                    self.divergence = Some(Divergence {
+                        index,
+                        recorded: vec![recorded],
+                        description: self.description.clone(),
+                    });
+                } else {
+                    self.fail(self.entry_mismatch(index, key, recorded));
+                }
+            }
+        }
+    }
+
+    /// Whether baseline entries which don't match are replaced, see
+    /// [UpdatePolicy::Mismatched]. Never for baselines read from git.
+    fn updates_mismatched(&self) -> bool {
+        self.config.update == UpdatePolicy::Mismatched
+            && (self.config.baseline.is_none() || self.backend != Backend::Store)
+    }
+
+    /// Replaces the baseline entry at `index` with the recorded one, see
+    /// [UpdatePolicy::Mismatched].
+    fn update_entry(
+        &mut self,
+        index: usize,
+        reg_type: RegType,
+        message: String,
+        location: Option<String>,
+        note: Option<&str>,
+        value_type: Option<&str>,
+    ) {
+        let entry = &mut self.buffer[index];
+        entry.reg_type = reg_type;
+        entry.message = message;
+        entry.location = location;
+        entry.note = note.map(str::to_string);
+        entry.value_type = value_type.map(str::to_string);
+        self.updates.push((index, entry.clone()));
+    }
+
+    /// Writes the entries replaced with [UpdatePolicy::Mismatched] to the
+    /// data file, keeping the others as they are.
+    fn save_updates(&mut self) -> std::io::Result<()> {
+        let stored = self
+            .backend
+            .store()
+            .load(&self.file_path)?
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+        let mut data = DataFile::read(stored.as_slice(), &self.file_path)?;
+        data.load_apart(&self.file_path, |path| self.backend.store().load(path))?;
+
+        // Position of each entry in the file, by section and index in it
+        let mut counts: HashMap<Option<String>, usize> = HashMap::new();
+        let mut positions = HashMap::new();
+        for (position, entry) in data.entries.iter().enumerate() {
+            let count = counts.entry(entry.section.clone()).or_default();
+            positions.insert((entry.section.clone(), *count), position);
+            *count += 1;
+        }
+        for (index, update) in self.updates.drain(..) {
+            if let Some(&position) = positions.get(&(update.section, index)) {
+                let entry = &mut data.entries[position];
+                entry.reg_type = update.reg_type;
+                entry.message = update.message;
+                entry.location = update.location;
+                entry.note = update.note;
+                entry.value_type = update.value_type;
+            }
+        }
+        if self.config.checksums {
+            data.seal();
+        }
+        data.encrypted = self.config.encrypt;
+        self.save_data(data)
+    }
+
+    /// Saves `data` as the data file, with the configured
+    /// [layout](Config::layout), storing its large messages as
+    /// [blobs](Config::blob_bytes).
+    fn save_data(&self, mut data: DataFile) -> std::io::Result<()> {
+        if let Some(threshold) = self.config.blob_bytes {
+            data.store_blobs(&self.file_path, &self.blob_dir(), threshold, self.backend)?;
+        }
+        match self.config.layout {
+            Layout::File => data.save(&self.file_path, self.backend),
+            Layout::Directory => data.save_apart(&self.file_path, self.backend),
+        }
+    }
+
+    /// The directory holding the blobs, see [Config::blob_bytes].
+    fn blob_dir(&self) -> PathBuf {
+        let crate_root = std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let dir = self
+            .config
+            .data_dir(crate_root)
+            .join(config::BLOBS_DIR_NAME);
+        path::lfs_path(&self.config, &dir)
+    }
+
+    /// Writes the full `message` of an entry truncated to the part whose
+    /// digest is `digest` next to the data file, see
+    /// [Config::truncate_sidecar].
+    fn write_full_message(&self, message: &str, digest: &str) {
+        let path = path::lfs_path(
+            &self.config,
+            &path::full_message_path(&self.file_path, digest),
+        );
+        if let Err(e) = self.backend.store().save(&path, message.as_bytes()) {
+            eprintln!(
+                "warning: failed to write the full message {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Writes the output of [regtest_hash](RegTest::regtest_hash) recorded at
+    /// `location`, which doesn't match the baseline, to the
+    /// [dump directory](Config::hash_dump), if any.
+    fn dump_hashed(&self, hashed: Option<&[u8]>, location: &Option<String>) {
+        let (Some(content), Some(dir)) = (hashed, &self.config.hash_dump) else {
+            return;
+        };
+
+        let mut path = dir.join(file_name_part(&registry::current_test_name()));
+        for name in self.section.iter().flat_map(|section| section.split('/')) {
+            path.push(file_name_part(name));
+        }
+        path.push(format!("{}.bin", self.compared - 1));
+
+        let written = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(&path, content));
+        match written {
+            Ok(()) => eprintln!(
+                "note: the output hashed at {} doesn't match the baseline; it was written to {}",
+                location.as_deref().unwrap_or_default(),
+                self.renderer.path(&path)
+            ),
+            Err(e) => eprintln!(
+                "warning: failed to write hashed output {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// The mismatch between the baseline entry at `index` and `recorded`.
+    fn entry_mismatch(&self, index: usize, key: Option<String>, recorded: Recorded) -> Mismatch {
+        let expected = &self.buffer[index];
+        if expected.reg_type != recorded.reg_type {
+            Mismatch {
+                kind: MismatchKind::Type,
+                index: Some(index),
+                key,
+                note: expected.note.clone(),
+                heading: None,
+                fields: Vec::new(),
+                changes: Vec::new(),
+                position: None,
+                caller: recorded.caller,
+                expected: format!("{:?}", expected.reg_type),
+                actual: format!("{:?}", recorded.reg_type),
+            }
+        } else {
+            let fields = match expected.reg_type {
+                RegType::Value => fields::differing_fields(&expected.message, &recorded.message),
+                _ => Vec::new(),
+            };
+            let changes = match expected.reg_type {
+                RegType::Json => fields::json_changes(&expected.message, &recorded.message),
+                _ => Vec::new(),
+            };
+            Mismatch {
+                kind: MismatchKind::Message,
+                index: Some(index),
+                key,
+                note: expected.note.clone(),
+                heading: None,
+                fields,
+                changes,
+                position: None,
+                caller: recorded.caller,
+                expected: expected.message.clone(),
+                actual: recorded.message,
+            }
+        }
+    }
+
+    /// Reports the divergence from the baseline, if any. Looks for the closest
+    /// point where the recorded entries and the baseline agree again, to report
+    /// removed or added entries rather than a mismatch of unrelated entries.
+    fn resync(&mut self) {
+        let Some(divergence) = self.divergence.take() else {
+            return;
+        };
+
+        let unkeyed = |buffer: &[RegEntry]| {
+            buffer[divergence.index..]
+                .iter()
+                .filter(|e| e.key.is_none())
+                .count()
+        };
+        while unkeyed(&self.buffer) < RESYNC_WINDOW && self.pull() {}
+
+        // Entries past the next checkpoint are compared once it is reached
+        let baseline: Vec<usize> = (divergence.index..self.buffer.len())
+            .filter(|&i| self.buffer[i].key.is_none())
+            .take_while(|&i| i == divergence.index || !self.is_checkpoint(i))
+            .take(RESYNC_WINDOW)
+            .collect();
+        self.report_divergence(divergence, baseline);
+    }
+
+    /// Reports the divergence between the `baseline` entries (indices in the
+    /// buffer) and the entries recorded instead, as removed or added entries
+    /// if the two agree again, or else as a mismatch of the first entries.
+    fn report_divergence(&mut self, divergence: Divergence, baseline: Vec<usize>) {
+        let recorded = divergence.recorded;
+        let agree = |a: usize, b: usize| {
+            let expected = &self.buffer[baseline[a]];
+            expected.reg_type == recorded[b].reg_type
+                && self.same_text(
+                    &expected.reg_type,
+                    &self.normalize(&expected.reg_type, &expected.message),
+                    &self.normalize(&expected.reg_type, &recorded[b].message),
+                )
+        };
+
+        // The closest point, skipping as few entries as possible. Without
+        // entries on one side, all of the other side is skipped.
+        let resumed = if recorded.is_empty() || baseline.is_empty() {
+            Some((baseline.len(), recorded.len()))
+        } else {
+            (1..baseline.len() + recorded.len()).find_map(|skipped| {
+                (0..=skipped)
+                    .map(|a| (a, skipped - a))
+                    .find(|&(a, b)| a < baseline.len() && b < recorded.len() && agree(a, b))
+            })
+        };
+
+        let list = |lines: Vec<String>| lines.join("\n");
+        let mismatch = match resumed {
+            Some((removed, 0)) => Mismatch {
+                kind: MismatchKind::Removed,
+                index: Some(divergence.index),
+                key: None,
+                note: None,
+                heading: None,
+                fields: Vec::new(),
+                changes: Vec::new(),
+                position: None,
+                caller: recorded.first().and_then(|r| r.caller),
+                expected: list(
+                    baseline[..removed]
+                        .iter()
+                        .map(|&i| {
+                            let entry = &self.buffer[i];
+                            format!("#{}: {}", i, listed(&entry.reg_type, &entry.message))
+                        })
+                        .collect(),
+                ),
+                actual: String::new(),
+            },
+            Some((0, added)) => Mismatch {
+                kind: MismatchKind::Added,
+                index: Some(divergence.index),
+                key: None,
+                note: None,
+                heading: None,
+                fields: Vec::new(),
+                changes: Vec::new(),
+                position: None,
+                caller: recorded[0].caller,
+                expected: String::new(),
+                actual: list(
+                    recorded[..added]
+                        .iter()
+                        .map(|r| listed(&r.reg_type, &r.message))
+                        .collect(),
+                ),
+            },
+            _ => {
+                let first = recorded
+                    .into_iter()
+                    .next()
+                    .expect("divergence without entries");
+                self.entry_mismatch(divergence.index, None, first)
+            }
+        };
+        // Reported as of the mismatch, which may be out of the scope of its
+        // settings by now
+        self.description = divergence.description;
+        self.fail(mismatch);
+    }
+
+    /// Writes the entries recorded past the end of the baseline, if any, to the
+    /// pending file and reports them according to [Config::extra_entries].
+    fn report_extra(&mut self) {
+        let pending = path::pending_path(&self.file_path);
+        if self.config.baseline.is_some() && self.backend == Backend::Store {
+            // The working tree's baseline may differ; only report the entries
+            if let Some(&(_, caller)) = self.extra.first() {
+                let extra: Vec<String> = self.extra.drain(..).map(|(e, _)| e.message).collect();
+                self.fail(Mismatch {
+                    kind: MismatchKind::Added,
+                    index: Some(self.buffer.len()),
+                    key: None,
+                    note: None,
+                    heading: None,
+                    fields: Vec::new(),
+                    changes: Vec::new(),
+                    position: None,
+                    caller: Some(caller),
+                    expected: String::new(),
+                    actual: extra.join("\n"),
+                });
+            }
+            return;
+        }
+
+        if self.extra.is_empty() {
+            // Any pending file is from an earlier run, and now outdated
+            let _ = self.backend.store().remove(&pending);
+            return;
+        }
+
+        if self.config.extra_entries == ExtraEntries::Append {
+            let _ = self.backend.store().remove(&pending);
+            self.append_extra();
+            return;
+        }
+
+        match self.write_pending(&pending) {
+            Ok(()) =>
+            {
+                #[cfg(feature = "harness")]
+                if let Some(path) = self.backend.store().local_path(&pending) {
+                    harness::record_pending(path);
+                }
+            }
+            Err(e) => eprintln!(
+                "warning: failed to write pending regression data {}: {}",
+                pending.display(),
+                e
+            ),
+        }
+
+        let extra = std::mem::take(&mut self.extra);
+        self.pending = extra.len();
+        let messages: Vec<&str> = extra.iter().map(|(e, _)| e.message.as_str()).collect();
+        match self.config.extra_entries {
+            ExtraEntries::Warn => eprintln!(
+                "warning: regression test `{}` recorded {} entries which are not in the baseline {}. Review them in {}, and run `cargo regtest accept` to keep them.",
+                registry::current_test_name(),
+                extra.len(),
+                self.renderer.path(&self.file_path),
+                self.renderer.path(&pending)
+            ),
+            ExtraEntries::Append => unreachable!("appended above"),
+            ExtraEntries::Fail => self.fail(Mismatch {
+                kind: MismatchKind::Extra,
+                index: Some(self.buffer.len()),
+                key: None,
+                note: None,
+                heading: None,
+                fields: Vec::new(),
+                changes: Vec::new(),
+                position: None,
+                caller: Some(extra[0].1),
+                expected: String::new(),
+                actual: messages.join("\n"),
+            }),
+        }
+    }
+
+    /// Appends the extra entries to the baseline, see [ExtraEntries::Append].
+    fn append_extra(&mut self) {
+        let appended = self
+            .extended_baseline()
+            .and_then(|data| self.save_data(data));
+        let extra = std::mem::take(&mut self.extra);
+        match appended {
+            Ok(()) => {
+                self.rewritten = true;
+                let messages: Vec<String> = extra
+                    .iter()
+                    .map(|(e, _)| format!("\n  + {}", e.message.replace('\n', "\n    ")))
+                    .collect();
+                eprintln!(
+                    "note: appended {} new entries to the baseline {} of regression test `{}`:{}",
+                    extra.len(),
+                    self.renderer.path(&self.file_path),
+                    registry::current_test_name(),
+                    messages.concat()
+                );
+            }
+            Err(e) => eprintln!(
+                "warning: failed to append new entries to regression test file {}: {}",
+                self.file_path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Writes the baseline with the extra entries appended to `pending`.
+    fn write_pending(&self, pending: &Path) -> std::io::Result<()> {
+        let data = self.extended_baseline()?;
+        let mut data = DataFile::new(data.entries, data.header.shape, self.tags.clone());
+        if self.config.merge_friendly {
+            data.make_merge_friendly();
+        }
+        if self.config.checksums {
+            data.seal();
+        }
+        data.encrypted = self.config.encrypt;
+        data.save(pending, self.backend)
+    }
+
+    /// The baseline, with the extra entries appended.
+    fn extended_baseline(&self) -> std::io::Result<DataFile> {
+        let stored = self
+            .backend
+            .store()
+            .load(&self.file_path)?
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+        let mut baseline = DataFile::read(stored.as_slice(), &self.file_path)?;
+        baseline.load_apart(&self.file_path, |path| self.backend.store().load(path))?;
+
+        baseline
+            .entries
+            .extend(self.extra.iter().map(|(e, _)| e.clone()));
+        if self.config.merge_friendly {
+            baseline.make_merge_friendly();
+        }
+        if self.config.checksums {
+            baseline.seal();
+        }
+        baseline.encrypted = self.config.encrypt;
+        Ok(baseline)
+    }
+
+    /// Reports `mismatch` and fails the test, unless in
+    /// [advisory](Config::advisory) mode, where it only warns, and the test
+    /// compares no further entries.
+    fn fail(&self, mut mismatch: Mismatch) {
+        let test = registry::current_test_name();
+        if mismatch.heading.is_none() {
+            mismatch.heading = mismatch.index.and_then(|index| self.heading_before(index));
+        }
+
+        if let Some(annotation) =
+            self.renderer
+                .annotation(&test, self.section.as_deref(), &self.file_path, &mismatch)
+        {
+            println!("{}", annotation);
+        }
+
+        let json = report::to_json(
+            &test,
+            self.section.as_deref(),
+            &self.file_path,
+            &mismatch,
+            self.config.advisory,
+            self.xfail.as_deref(),
+        );
+        if nextest::is_running()
+            && let Ok(json) = &json
+        {
+            // nextest keeps the output of failed tests, e.g. in its JUnit file
+            eprintln!("regtest-mismatch: {}", json);
+        }
+
+        if let Some(report_path) = &self.config.report
+            && let Err(e) = json.and_then(|json| report::append(report_path, &json))
+        {
+            eprintln!(
+                "warning: failed to write regression report {}: {}",
+                report_path.display(),
+                e
+            );
+        }
+
+        if let Some(dir) = &self.config.html_report
+            && let Err(e) = html::record(
+                dir,
+                &test,
+                self.section.as_deref(),
+                &self.file_path,
+                &mismatch,
+            )
+        {
+            eprintln!(
+                "warning: failed to write HTML report {}: {}",
+                dir.display(),
+                e
+            );
+        }
+
+        let mut message = self.renderer.mismatch(&self.file_path, &mismatch);
+        if let Some(description) = &self.description {
+            message = format!("{}: {}", description, message);
+        }
+        let _ = self.regression.set(message.clone());
+        if let Some(reason) = &self.xfail {
+            eprintln!(
+                "note: regression test `{}` doesn't match its baseline, as expected ({}):\n{}",
+                test, reason, message
+            );
+            return;
+        }
+        if self.config.advisory {
+            eprintln!(
+                "warning: regression test `{}` doesn't match its baseline (advisory mode, not failing):\n{}",
+                test, message
+            );
+            return;
+        }
+        panic!("{}", message);
+    }
+
+    #[track_caller]
+    pub fn regtest<T: RegDisplay>(&mut self, value: T) {
+        self.regtest_internal(
+            display::render(&value),
+            RegType::Display,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records a checkpoint named `name`, splitting the entries into segments
+    /// compared separately.
+    ///
+    /// In read mode, when entries between two checkpoints don't match, the
+    /// comparison resumes at the checkpoint: the mismatch reported is the
+    /// difference between the entries of the segment, and the entries after
+    /// the checkpoint aren't misaligned by entries added or removed before
+    /// it. Checkpoints are positional entries, so adding, removing or
+    /// renaming one changes the baseline.
+    #[track_caller]
+    pub fn checkpoint(&mut self, name: &str) {
+        let caller = Location::caller();
+        if matches!(self.mode, Mode::Write) {
+            self.regtest_internal(
+                name.to_string(),
+                RegType::Checkpoint,
+                caller,
+                Recording::default(),
+            );
+            return;
+        }
+
+        if self.regression.get().is_some() {
+            // Already mismatched, in advisory mode or as expected
+            return;
+        }
+
+        // The segment of the baseline ending at this checkpoint
+        let start = self
+            .divergence
+            .as_ref()
+            .map_or(self.read_index, |divergence| divergence.index);
+        let Some(end) = self.find_entry(start, |e| {
+            e.reg_type == RegType::Checkpoint && e.message == name
+        }) else {
+            // Not in the baseline: compared like any other entry
+            self.regtest_internal(
+                name.to_string(),
+                RegType::Checkpoint,
+                caller,
+                Recording::default(),
+            );
+            return;
+        };
+
+        self.compared += 1;
+        self.read_index = end + 1;
+        let skipped: Vec<usize> = (start..end)
+            .filter(|&i| self.buffer[i].key.is_none())
+            .collect();
+        let divergence = match self.divergence.take() {
+            Some(divergence) => divergence,
+            None if skipped.is_empty() => return,
+            None => Divergence {
+                index: start,
+                recorded: Vec::new(),
+                description: self.description.clone(),
+            },
+        };
+        self.report_divergence(divergence, skipped);
+    }
+
+    /// Records a header titled `title`, giving structure to long tests with
+    /// several phases, e.g. `rt.header("Phase 2: optimization")`.
+    ///
+    /// Headers are stored as entries of their own kind and compared like
+    /// [regtest](RegTest::regtest) entries, so adding, removing or renaming
+    /// one changes the baseline. Mismatch reports name the header of the
+    /// entry which differs, and `cargo regtest docgen` renders headers as
+    /// headings.
+    #[track_caller]
+    pub fn header(&mut self, title: &str) {
+        self.regtest_internal(
+            title.to_string(),
+            RegType::Header,
+            Location::caller(),
+            Recording::default(),
+        );
+    }
+
+    /// Records `value` serialized as JSON. In read mode, the baseline is
+    /// deserialized back into `T` and compared with `value` using
+    /// `PartialEq`, so that changes to how the value is printed (e.g. the
+    /// order of its fields) don't matter. Mismatches list the fields which
+    /// differ.
+    ///
+    /// # Panics
+    /// Panics if `value` can't be serialized.
+    #[track_caller]
+    pub fn regtest_eq<T: Serialize + DeserializeOwned + PartialEq>(&mut self, value: &T) {
+        let message = match serde_json::to_string_pretty(value) {
+            Ok(message) => message,
+            Err(e) => panic!("Failed to serialize the value for regtest_eq: {}", e),
+        };
+        let equal = |expected: &str, _: &str| {
+            serde_json::from_str::<T>(expected).is_ok_and(|expected| expected == *value)
+        };
+        self.regtest_internal(
+            message,
+            RegType::Value,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                comparator: Some(&equal),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records the serialized (JSON) form of `value`, to catch changes to
+    /// how `T` is serialized. Unlike [regtest_eq](RegTest::regtest_eq), the
+    /// serialized text must match the baseline exactly. In addition:
+    ///
+    /// - `value` must deserialize back into an equal serialized form,
+    /// - in read mode, the baseline must still deserialize into `T`: if it
+    ///   doesn't, the change is reported as breaking, as data serialized by
+    ///   earlier versions could no longer be read.
+    ///
+    /// # Panics
+    /// Panics if `value` can't be serialized, or doesn't round-trip.
+    #[track_caller]
+    pub fn regtest_roundtrip<T: Serialize + DeserializeOwned>(&mut self, value: &T) {
+        let message = match serde_json::to_string_pretty(value) {
+            Ok(message) => message,
+            Err(e) => panic!("Failed to serialize the value for regtest_roundtrip: {}", e),
+        };
+        let roundtrip = serde_json::from_str::<T>(&message)
+            .map_err(|e| e.to_string())
+            .and_then(|back| serde_json::to_string_pretty(&back).map_err(|e| e.to_string()));
+        match roundtrip {
+            Ok(back) if back == message => {}
+            Ok(back) => panic!(
+                "The value recorded at {} doesn't round-trip: serialized as\n{}\nit deserializes into a value serialized as\n{}",
+                Location::caller(),
+                message,
+                back
+            ),
+            Err(e) => panic!(
+                "The value recorded at {} doesn't round-trip: {}\n{}",
+                Location::caller(),
+                e,
+                message
+            ),
+        }
+
+        let read_back = |baseline: &str| {
+            serde_json::from_str::<T>(baseline)
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        };
+        self.regtest_internal(
+            message,
+            RegType::Value,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                read_back: Some(&read_back),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records the JSON document `value` with the keys of its objects sorted,
+    /// so that their order doesn't matter.
+    ///
+    /// In read mode, mismatches list the values which changed, by JSON
+    /// pointer (e.g. `/items/1/id`), with their old and new values, rather
+    /// than a diff of the whole document.
+    ///
+    /// Volatile values can be left out or replaced with
+    /// [Settings::ignore_path] and [Settings::replace_path], or per call with
+    /// [regtest_json_ignoring](RegTest::regtest_json_ignoring). Their paths
+    /// use a subset of JSONPath: `$` is the document, followed by fields
+    /// (`.name` or `['name']`), array items (`[0]`) and wildcards (`.*` or
+    /// `[*]`), e.g. `$.items[*].meta.generated_at`.
+    #[track_caller]
+    pub fn regtest_json(&mut self, value: &serde_json::Value) {
+        self.record_json(value, &[], Location::caller());
+    }
+
+    /// Like [regtest_json](RegTest::regtest_json), also leaving out the
+    /// values at `paths`.
+    ///
+    /// # Panics
+    /// Panics if one of `paths` is not a valid path.
+    #[track_caller]
+    pub fn regtest_json_ignoring(&mut self, value: &serde_json::Value, paths: &[&str]) {
+        let rules: Vec<PathRule> = paths
+            .iter()
+            .map(|path| match JsonPath::parse(path) {
+                Ok(parsed) => PathRule {
+                    path: parsed,
+                    replacement: None,
+                },
+                Err(e) => panic!("Invalid JSON path `{}`: {}", path, e),
+            })
+            .collect();
+        self.record_json(value, &rules, Location::caller());
+    }
+
+    /// Records `value` serialized as a JSON document, like
+    /// [regtest_json](RegTest::regtest_json).
+    #[track_caller]
+    pub fn regtest_ser<T: Serialize + ?Sized>(&mut self, value: &T) {
+        let value = match serde_json::to_value(value) {
+            Ok(value) => value,
+            Err(e) => panic!("Failed to serialize the value for regtest_ser: {}", e),
+        };
+        self.record_json(&value, &[], Location::caller());
+    }
+
+    fn record_json(
+        &mut self,
+        value: &serde_json::Value,
+        rules: &[PathRule],
+        caller: &'static Location<'static>,
+    ) {
+        let message = if self.json_paths.is_empty() && rules.is_empty() {
+            fields::canonical_json(value)
+        } else {
+            let mut value = value.clone();
+            for rule in self.json_paths.iter().chain(rules) {
+                rule.apply(&mut value);
+            }
+            fields::canonical_json(&value)
+        };
+        self.regtest_internal(message, RegType::Json, caller, Recording::default());
+    }
+
+    /// Records the XML or HTML markup `value` in a canonical form, so that
+    /// semantically identical markup matches the baseline: attributes are
+    /// sorted, whitespace between tags is dropped and each tag and text is
+    /// put on a line of its own, indented by nesting depth. The markup isn't
+    /// validated.
+    ///
+    /// Requires the `markup` feature.
+    #[cfg(feature = "markup")]
+    #[track_caller]
+    pub fn regtest_markup<T: RegDisplay>(&mut self, value: T) {
+        self.regtest_internal(
+            markup::canonicalize(&display::render(&value)),
+            RegType::Markup,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Like [regtest](RegTest::regtest), recording `value` as an entry of the
+    /// custom kind `kind`, e.g. `"sql"` for queries, see [RegType::Custom].
+    ///
+    /// The kind is stored with the entry, so that diff viewers and other
+    /// tools can tell such entries apart, e.g. to highlight or pretty-print
+    /// them. Recording an entry of another kind than the baseline's is a
+    /// mismatch, like recording it with another method.
+    #[track_caller]
+    pub fn regtest_custom<T: RegDisplay>(&mut self, kind: &str, value: T) {
+        self.regtest_internal(
+            display::render(&value),
+            RegType::Custom(kind.to_string()),
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Like [regtest](RegTest::regtest), storing `note` alongside the
+    /// message to describe what the entry is about, e.g. `"tokenizer output
+    /// for empty input"`. The note is shown in mismatch reports, but never
+    /// compared.
+    #[track_caller]
+    pub fn regtest_with_note<T: RegDisplay>(&mut self, note: &str, value: T) {
+        self.regtest_internal(
+            display::render(&value),
+            RegType::Display,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                note: Some(note),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records the [`RegSnapshot`] representation of `value`, e.g. of a
+    /// struct with `#[derive(RegSnapshot)]` which leaves out or redacts some
+    /// of its fields.
+    #[track_caller]
+    pub fn regtest_snapshot<T: RegSnapshot + ?Sized>(&mut self, value: &T) {
+        self.regtest_internal(
+            value.reg_snapshot(),
+            RegType::Display,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    #[track_caller]
+    pub fn regtest_dbg<T: Debug>(&mut self, value: T) {
+        self.regtest_internal(
+            self.debug(&value),
+            RegType::Debug,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Like [regtest_dbg](RegTest::regtest_dbg), with the items of maps and
+    /// sets (e.g. `HashMap`, `HashSet`) sorted by their text, so that the
+    /// output doesn't depend on their iteration order.
+    ///
+    /// Maps and sets are recognized in the output as braces which don't
+    /// follow a name, so custom `Debug` implementations printing such braces
+    /// are sorted too.
+    #[track_caller]
+    pub fn regtest_sorted_dbg<T: Debug>(&mut self, value: T) {
+        self.regtest_internal(
+            canonical::sort_debug(&format!("{:?}", value)),
+            RegType::Debug,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records each item yielded by `items` as its own entry, like
+    /// [regtest](RegTest::regtest). A collection changing by one element then
+    /// changes a single entry, rather than all of its output.
+    #[track_caller]
+    pub fn regtest_iter<I>(&mut self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: RegDisplay,
+    {
+        let caller = Location::caller();
+        for item in items {
+            self.regtest_internal(
+                display::render(&item),
+                RegType::Display,
+                caller,
+                Recording {
+                    value_type: Some(value_type::<I::Item>()),
+                    ..Recording::default()
+                },
+            );
+        }
+    }
+
+    /// Like [regtest_iter](RegTest::regtest_iter), recording the items like
+    /// [regtest_dbg](RegTest::regtest_dbg).
+    #[track_caller]
+    pub fn regtest_iter_dbg<I>(&mut self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: Debug,
+    {
+        let caller = Location::caller();
+        for item in items {
+            self.regtest_internal(
+                self.debug(&item),
+                RegType::Debug,
+                caller,
+                Recording {
+                    value_type: Some(value_type::<I::Item>()),
+                    ..Recording::default()
+                },
+            );
+        }
+    }
+
+    /// Like [regtest_iter](RegTest::regtest_iter), recording each run of
+    /// identical consecutive items once, followed by its repetition count,
+    /// e.g. `retrying (repeated 300 times)`. Loops printing the same message
+    /// many times then make for small baselines and diffs, while a change in
+    /// the number of repetitions still changes the entry.
+    #[track_caller]
+    pub fn regtest_dedup<I>(&mut self, items: I)
+    where
+        I: IntoIterator,
+        I::Item: RegDisplay,
+    {
+        let caller = Location::caller();
+        let mut runs: Vec<(String, usize)> = Vec::new();
+        for item in items {
+            let message = display::render(&item);
+            match runs.last_mut() {
+                Some((last, count)) if *last == message => *count += 1,
+                _ => runs.push((message, 1)),
+            }
+        }
+        for (message, count) in runs {
+            let message = match count {
+                1 => message,
+                count => format!("{} (repeated {} times)", message, count),
+            };
+            self.regtest_internal(
+                message,
+                RegType::Display,
+                caller,
+                Recording {
+                    value_type: Some(value_type::<I::Item>()),
+                    ..Recording::default()
+                },
+            );
+        }
+    }
+
+    /// Records `rows` as a column-aligned text table, whose diffs are easier
+    /// to review than those of a `Vec` of tuples or structs. Each row is
+    /// serialized with serde: structs and maps get a column per field, under
+    /// a header of the field names sorted by name, and tuples and sequences
+    /// a column per item. Numbers are aligned right, and `None` is an empty
+    /// cell.
+    ///
+    /// ```text
+    /// name     note   size
+    /// -------  -----  ----
+    /// lib.rs          1200
+    /// main.rs  entry    35
+    /// ```
+    #[track_caller]
+    pub fn regtest_table<I>(&mut self, rows: I)
+    where
+        I: IntoIterator,
+        I::Item: Serialize,
+    {
+        let message = match table::render(rows) {
+            Ok(message) => message,
+            Err(e) => panic!("Failed to serialize a row for regtest_table: {}", e),
+        };
+        self.regtest_internal(
+            message,
+            RegType::Display,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<I::Item>()),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Adds a normalizer, applied to both the baseline and the recorded
+    /// output of the following entries before they are compared, after the
+    /// [configured ones](Config::normalize). Subtests inherit the normalizers
+    /// added until they start. See the [normalize] module.
+    pub fn normalize_with<N: Normalizer + 'static>(&mut self, normalizer: N) {
+        self.normalizers.push(Arc::new(normalizer));
+    }
+
+    /// `message`, as compared: normalized, unless it is
+    /// [verbatim](RegType::is_verbatim).
+    fn normalize<'a>(&self, reg_type: &RegType, message: &'a str) -> Cow<'a, str> {
+        let mut message = Cow::Borrowed(message);
+        if reg_type.is_verbatim() {
+            return message;
+        }
+        let configured = self.config.normalize.iter().map(|n| n as &dyn Normalizer);
+        for normalizer in configured.chain(self.normalizers.iter().map(|n| n.as_ref())) {
+            if let Cow::Owned(normalized) = normalizer.normalize(&message) {
+                message = Cow::Owned(normalized);
+            }
+        }
+        message
+    }
+
+    /// The `Debug` text of `value`, in the format in effect.
+    fn debug<T: Debug>(&self, value: &T) -> String {
+        if self.pretty_debug {
+            format!("{:#?}", value)
+        } else {
+            format!("{:?}", value)
+        }
+    }
+
+    /// Runs `f` with `settings` applied on top of the current ones, and
+    /// restores the current ones afterwards. Settings apply to the entries
+    /// recorded by `f`, including those of its subtests.
+    pub fn with_settings<R, F: FnOnce(&mut RegTest) -> R>(
+        &mut self,
+        settings: Settings,
+        f: F,
+    ) -> R {
+        let redactions = self.config.redactions.len();
+        let normalizers = self.normalizers.len();
+        let pretty_debug = self.pretty_debug;
+        let description = self.description.clone();
+        let json_paths = self.json_paths.len();
+        let float_digits = self.config.float_digits;
+
+        self.config.redactions.extend(settings.redactions);
+        if settings.float_digits.is_some() {
+            self.config.float_digits = settings.float_digits;
+        }
+        self.normalizers.extend(settings.normalizers);
+        self.json_paths.extend(settings.json_paths);
+        self.pretty_debug = settings.pretty_debug.unwrap_or(pretty_debug);
+        if settings.description.is_some() {
+            self.description = settings.description;
+        }
+
+        let result = f(self);
+
+        self.config.redactions.truncate(redactions);
+        self.normalizers.truncate(normalizers);
+        self.json_paths.truncate(json_paths);
+        self.config.float_digits = float_digits;
+        self.pretty_debug = pretty_debug;
+        self.description = description;
+        result
+    }
+
+    /// Whether the normalized texts `expected` and `actual` match, either
+    /// exactly or through the placeholders in `expected`. For patterns,
+    /// whether `actual` matches the `expected` pattern.
+    fn same_text(&self, reg_type: &RegType, expected: &str, actual: &str) -> bool {
+        match reg_type {
+            RegType::Pattern => Regex::new(expected).is_ok_and(|regex| regex.is_match(actual)),
+            _ if reg_type.is_verbatim() => expected == actual,
+            _ => expected == actual || placeholder::matches(expected, actual),
+        }
+    }
+
+    /// Like [regtest](RegTest::regtest), but in read mode a differing output
+    /// still matches the baseline if `comparator` deems it equivalent. See
+    /// the [compare] module for the built-in comparators.
+    #[track_caller]
+    pub fn regtest_with<T: RegDisplay, C: Comparator>(&mut self, value: T, comparator: C) {
+        self.regtest_internal(
+            display::render(&value),
+            RegType::Display,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                comparator: Some(&comparator),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Like [regtest_dbg](RegTest::regtest_dbg), with a `comparator` as in
+    /// [regtest_with](RegTest::regtest_with).
+    #[track_caller]
+    pub fn regtest_dbg_with<T: Debug, C: Comparator>(&mut self, value: T, comparator: C) {
+        self.regtest_internal(
+            self.debug(&value),
+            RegType::Debug,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                comparator: Some(&comparator),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records a SHA-256 digest and the length of `data` instead of `data`
+    /// itself, for outputs too large to store (build artifacts, rendered
+    /// images, ...). Types which are only `Display` can be hashed with
+    /// `to_string()`.
+    ///
+    /// In read mode only the digests are compared, so a mismatch can't show
+    /// what changed. With [Config::hash_dump], the mismatching output is
+    /// written to a file for inspection.
+    #[track_caller]
+    pub fn regtest_hash<T: AsRef<[u8]>>(&mut self, data: T) {
+        let data = data.as_ref();
+        let digest: String = Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.regtest_internal(
+            format!("sha256:{} ({} bytes)", digest, data.len()),
+            RegType::Hash,
+            Location::caller(),
+            Recording {
+                hashed: Some(data),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Records `image` (an [Image](image::Image), or a PNG file as bytes) as
+    /// a golden image: it is stored as a PNG file next to the data file, and
+    /// the entry holds its size and digest.
+    ///
+    /// In read mode, the image is compared with the stored one pixel by
+    /// pixel, tolerating the differences allowed by
+    /// [Config::image_threshold] and [Config::image_max_diff]. When it
+    /// doesn't match, it is written to [Config::image_diffs], along with a
+    /// diff image highlighting the differing pixels in red.
+    ///
+    /// Requires the `image` feature.
+    ///
+    /// # Panics
+    /// Panics if `image` is not a valid PNG file.
+    #[cfg(feature = "image")]
+    #[track_caller]
+    pub fn regtest_image<I: image::ImageSource + ?Sized>(&mut self, image: &I) {
+        let image = match image.to_image() {
+            Ok(image) => image,
+            Err(e) => panic!("Failed to read the image for regtest_image: {}", e),
+        };
+
+        let mut name: Vec<String> = self
+            .section
+            .iter()
+            .flat_map(|section| section.split('/'))
+            .map(file_name_part)
+            .collect();
+        name.push(self.image_count.to_string());
+        self.image_count += 1;
+        let file_path = path::lfs_path(
+            &self.config,
+            &path::image_path(&self.file_path, &name.join(".")),
+        );
+        let file_name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        let digest: String = Sha256::digest(image.pixels())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let message = format!(
+            "{}x{} sha256:{} {}",
+            image.width(),
+            image.height(),
+            digest,
+            file_name
+        );
+
+        if matches!(self.mode, Mode::Write)
+            && let Err(e) = self.backend.store().save(&file_path, &image.to_png())
+        {
+            panic!("Failed to write image {}: {}", file_path.display(), e);
+        }
+
+        let baseline = self
+            .config
+            .baseline
+            .clone()
+            .filter(|_| self.backend == Backend::Store);
+        let backend = self.backend;
+        let (threshold, max_diff) = (self.config.image_threshold, self.config.image_max_diff);
+        let mismatch = std::cell::RefCell::new(None);
+        let comparator = |expected: &str, _: &str| {
+            let mut parts = expected.split(' ');
+            let (Some(_), Some(expected_digest), Some(expected_name)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return false;
+            };
+            if expected_digest.strip_prefix("sha256:") == Some(digest.as_str()) {
+                return true;
+            }
+
+            let expected_path = file_path.with_file_name(expected_name);
+            let stored = match &baseline {
+                Some(rev) => git::show(rev, &expected_path),
+                None => backend.store().load(&expected_path),
+            };
+            let Ok(Some(stored)) = stored else {
+                return false;
+            };
+            let Ok(expected_image) = image::Image::from_png(&stored) else {
+                return false;
+            };
+            let comparison = image::compare(&expected_image, &image, threshold);
+            let pixels = image.width() as usize * image.height() as usize;
+            if let Some(comparison) = &comparison
+                && comparison.differing as f64 <= max_diff * pixels as f64
+            {
+                return true;
+            }
+            *mismatch.borrow_mut() = Some(comparison);
+            false
+        };
+        self.regtest_internal(
+            message,
+            RegType::Image,
+            Location::caller(),
+            Recording {
+                comparator: Some(&comparator),
+                ..Recording::default()
+            },
+        );
+
+        if let Some(comparison) = mismatch.into_inner() {
+            self.dump_image(&image, comparison, &name.join("."));
+        }
+    }
+
+    /// Writes the image recorded by [regtest_image](RegTest::regtest_image)
+    /// as `name`, which doesn't match the baseline, and its diff with the
+    /// baseline if they have the same size, to [Config::image_diffs].
+    #[cfg(feature = "image")]
+    fn dump_image(&self, actual: &image::Image, comparison: Option<image::Comparison>, name: &str) {
+        let dir = self
+            .config
+            .image_diffs
+            .join(file_name_part(&registry::current_test_name()));
+        let actual_path = dir.join(format!("{}.actual.png", name));
+        let diff_path = dir.join(format!("{}.diff.png", name));
+        let written = std::fs::create_dir_all(&dir)
+            .and_then(|()| std::fs::write(&actual_path, actual.to_png()))
+            .and_then(|()| match &comparison {
+                Some(comparison) => std::fs::write(&diff_path, comparison.diff.to_png()),
+                None => Ok(()),
+            });
+        match (written, comparison) {
+            (Ok(()), Some(comparison)) => eprintln!(
+                "note: {} pixels of image {} differ from the baseline; it was written to {}, and the diff to {}",
+                comparison.differing,
+                name,
+                self.renderer.path(&actual_path),
+                self.renderer.path(&diff_path)
+            ),
+            (Ok(()), None) => eprintln!(
+                "note: image {} doesn't have the size of the baseline; it was written to {}",
+                name,
+                self.renderer.path(&actual_path)
+            ),
+            (Err(e), _) => eprintln!(
+                "warning: failed to write image {}: {}",
+                actual_path.display(),
+                e
+            ),
+        }
+    }
+
+    /// A seed for the random numbers of a randomized test, which makes the
+    /// test reproducible: when generating the baseline, a random seed is
+    /// generated and recorded, and when comparing, the recorded seed is
+    /// returned, provided the test asks for it at the same point.
+    ///
+    /// ```rust
+    /// # use regression_test::RegTest;
+    /// # let path = std::env::temp_dir().join("regtest-seed-doc.json");
+    /// let mut rt = RegTest::new(path).unwrap();
+    /// let seed = rt.seed();
+    /// // e.g. `StdRng::seed_from_u64(seed)`, see also `rng` with the `rand`
+    /// // feature
+    /// rt.regtest(seed % 100);
+    /// ```
+    #[track_caller]
+    pub fn seed(&mut self) -> u64 {
+        use std::hash::{BuildHasher, Hasher};
+        let stored = match self.mode {
+            Mode::Write => None,
+            Mode::Read => self
+                .find_entry(self.read_index, |e| e.key.is_none())
+                .filter(|&i| self.buffer[i].reg_type == RegType::Seed)
+                .and_then(|i| self.buffer[i].message.parse().ok()),
+        };
+        // The keys of `RandomState` are random
+        let seed = stored.unwrap_or_else(|| std::hash::RandomState::new().build_hasher().finish());
+        self.regtest_internal(
+            seed.to_string(),
+            RegType::Seed,
+            Location::caller(),
+            Recording::default(),
+        );
+        seed
+    }
+
+    /// A [Clock](clock::Clock) frozen at a recorded time, for code whose
+    /// output depends on the current time: when generating the baseline, the
+    /// clock is set to the current time, which is recorded, and when
+    /// comparing, it is set to the recorded time, provided the test asks for
+    /// it at the same point.
+    ///
+    /// The code under test reads the time from the clock instead of the
+    /// system. Times it formats itself can be redacted too, to keep the
+    /// baseline the same when it is regenerated, see
+    /// [Settings::redact_clock].
+    #[track_caller]
+    pub fn clock(&mut self) -> clock::Clock {
+        let stored = match self.mode {
+            Mode::Write => None,
+            Mode::Read => self
+                .find_entry(self.read_index, |e| e.key.is_none())
+                .filter(|&i| self.buffer[i].reg_type == RegType::Clock)
+                .and_then(|i| self.buffer[i].message.parse().ok()),
+        };
+        let seconds = stored.unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+        let time = format_timestamp(seconds);
+        self.regtest_internal(
+            seconds.to_string(),
+            RegType::Clock,
+            Location::caller(),
+            Recording {
+                note: Some(&time),
+                ..Recording::default()
+            },
+        );
+        clock::Clock::fixed(std::time::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+    }
+
+    /// A random number generator seeded with [seed](RegTest::seed), e.g.
+    /// `rt.rng::<StdRng>()`.
+    ///
+    /// Requires the `rand` feature.
+    #[cfg(feature = "rand")]
+    #[track_caller]
+    pub fn rng<R: rand_core::SeedableRng>(&mut self) -> R {
+        R::seed_from_u64(self.seed())
+    }
+
+    /// Records how long `label` took, as a performance regression gate.
+    ///
+    /// When generating the baseline, the duration is stored. When comparing,
+    /// the entry only mismatches if `duration` exceeds the baseline by more
+    /// than `tolerance`, a fraction of it (e.g. `0.2` allows up to 20% more):
+    /// faster runs always match, and don't update the baseline.
+    ///
+    /// ```rust
+    /// # use regression_test::RegTest;
+    /// # let path = std::env::temp_dir().join("regtest-duration-doc.json");
+    /// # let mut rt = RegTest::new(path).unwrap();
+    /// let start = std::time::Instant::now();
+    /// let sorted = {
+    ///     let mut v: Vec<u32> = (0..10_000).rev().collect();
+    ///     v.sort();
+    ///     v
+    /// };
+    /// rt.regtest_duration("sort", start.elapsed(), 10.0);
+    /// # drop(sorted);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `tolerance` is negative or not a number.
+    #[track_caller]
+    pub fn regtest_duration(&mut self, label: &str, duration: Duration, tolerance: f64) {
+        let nanos = duration.as_nanos() as f64;
+        self.regtest_measurement(label, measure::Kind::Duration, nanos, tolerance);
+    }
+
+    /// Like [regtest_duration](RegTest::regtest_duration), for a throughput
+    /// in operations per second: the entry only mismatches if `per_second`
+    /// falls short of the baseline by more than `tolerance`.
+    ///
+    /// # Panics
+    /// Panics if `tolerance` is negative or not a number.
+    #[track_caller]
+    pub fn regtest_throughput(&mut self, label: &str, per_second: f64, tolerance: f64) {
+        self.regtest_measurement(label, measure::Kind::Throughput, per_second, tolerance);
+    }
+
+    /// Like [regtest_duration](RegTest::regtest_duration), for an amount of
+    /// memory in bytes: the entry only mismatches if `bytes` exceeds the
+    /// baseline by more than `tolerance`.
+    ///
+    /// # Panics
+    /// Panics if `tolerance` is negative or not a number.
+    #[track_caller]
+    pub fn regtest_memory(&mut self, label: &str, bytes: u64, tolerance: f64) {
+        self.regtest_measurement(label, measure::Kind::Memory, bytes as f64, tolerance);
+    }
+
+    /// Runs `f` and records what it allocated on the current thread, as a
+    /// regression gate on memory usage: the peak of the bytes allocated at
+    /// once, the bytes allocated in total and the number of allocations, as
+    /// three entries noted `peak allocated`, `total allocated` and
+    /// `allocations`. Returns the result of `f`.
+    ///
+    /// Like [regtest_memory](RegTest::regtest_memory), the entries only
+    /// mismatch if they exceed the baseline by more than
+    /// [Config::alloc_slack] percent.
+    ///
+    /// Requires [TrackingAllocator](alloc::TrackingAllocator) to be the
+    /// global allocator, see the [alloc] module.
+    ///
+    /// # Panics
+    /// Panics if the tracking allocator isn't installed.
+    #[track_caller]
+    pub fn regtest_alloc<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let (result, stats) = alloc::measure(f);
+        let slack = self.config.alloc_slack / 100.0;
+        for (label, kind, value) in [
+            ("peak allocated", measure::Kind::Memory, stats.peak()),
+            ("total allocated", measure::Kind::Memory, stats.total),
+            ("allocations", measure::Kind::Allocations, stats.count),
+        ] {
+            self.regtest_measurement(label, kind, value as f64, slack);
+        }
+        result
+    }
+
+    #[track_caller]
+    fn regtest_measurement(
+        &mut self,
+        label: &str,
+        kind: measure::Kind,
+        value: f64,
+        tolerance: f64,
+    ) {
+        let comparator = measure::Tolerance::new(kind, tolerance);
+        self.regtest_internal(
+            kind.format(value),
+            RegType::Measurement,
+            Location::caller(),
+            Recording {
+                comparator: Some(&comparator),
+                note: Some(label),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Runs `command` to completion and records its exit code, its standard
+    /// output and its standard error as three entries, noted `exit code`,
+    /// `stdout` and `stderr`. Returns the output, for further checks.
+    ///
+    /// The outputs are recorded as text, so the configured redactions apply
+    /// to them; others can be added for a command with
+    /// [with_settings](RegTest::with_settings), e.g. for temporary paths:
+    ///
+    /// ```rust,no_run
+    /// # use regression_test::{RegTest, Settings};
+    /// # use std::process::Command;
+    /// # let mut rt = RegTest::new("cli.json").unwrap();
+    /// let settings = Settings::new().redact(r"/tmp/\S+", "[tmp]");
+    /// rt.with_settings(settings, |rt| {
+    ///     rt.regtest_cmd(Command::new("my-cli").arg("--help"));
+    /// });
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `command` can't be run.
+    #[track_caller]
+    pub fn regtest_cmd(&mut self, command: &mut std::process::Command) -> std::process::Output {
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(e) => panic!("Failed to run {:?}: {}", command, e),
+        };
+
+        let exit = match output.status.code() {
+            Some(code) => code.to_string(),
+            None => "none (terminated by a signal)".to_string(),
+        };
+        for (note, text) in [
+            ("exit code", exit),
+            (
+                "stdout",
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+            ),
+            (
+                "stderr",
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ),
+        ] {
+            self.regtest_internal(
+                text,
+                RegType::Display,
+                Location::caller(),
+                Recording {
+                    note: Some(note),
+                    ..Recording::default()
+                },
+            );
+        }
+        output
+    }
+
+    /// Runs `f` with a local HTTP proxy standing in for the server
+    /// `upstream` (e.g. `http://localhost:8080`), and records the HTTP
+    /// exchanges made through it, in order. The code under test is pointed
+    /// at [HttpProxy::url](http::HttpProxy::url) instead of `upstream`.
+    ///
+    /// When generating the baseline, requests are forwarded to `upstream`
+    /// with their headers, but for the hop-by-hop ones and `Host`, and the
+    /// responses stored with them, with their headers but for the hop-by-hop
+    /// ones, `Content-Length` and `Date`. Request headers aren't stored, so
+    /// that credentials stay out of the data file. When comparing, no request
+    /// reaches the network: each is answered with the recorded response to
+    /// the same request (method, path and body), and the requests made are
+    /// compared with the baseline. Requests without a recorded response get
+    /// a `502 Bad Gateway` response.
+    ///
+    /// # Panics
+    /// Panics if the proxy can't be started.
+    #[track_caller]
+    pub fn with_http_replay<R, F: FnOnce(&mut RegTest, &http::HttpProxy) -> R>(
+        &mut self,
+        upstream: &str,
+        f: F,
+    ) -> R {
+        let caller = Location::caller();
+        let replay = match self.mode {
+            Mode::Write => None,
+            Mode::Read => {
+                self.pull_all();
+                let recorded = self.buffer[self.read_index..]
+                    .iter()
+                    .filter(|e| e.reg_type == RegType::Http)
+                    .filter_map(|e| serde_json::from_str::<http::Exchange>(&e.message).ok())
+                    .filter(|e| e.upstream == upstream.trim_end_matches('/'))
+                    .collect();
+                Some(recorded)
+            }
+        };
+        let proxy = match http::HttpProxy::start(upstream, replay) {
+            Ok(proxy) => proxy,
+            Err(e) => panic!("Failed to start the HTTP proxy for {}: {}", upstream, e),
+        };
+
+        let result = f(self, &proxy);
+
+        for exchange in proxy.finish() {
+            let message =
+                serde_json::to_string_pretty(&exchange).expect("HTTP exchanges serialize to JSON");
+            self.regtest_internal(message, RegType::Http, caller, Recording::default());
+        }
+        result
+    }
+
+    /// Runs `f` with a [SharedRegTest], through which the threads it spawns
+    /// (e.g. with [std::thread::scope]) record entries under a label, such as
+    /// the index of each worker:
+    ///
+    /// ```rust,no_run
+    /// # let mut rt = regression_test::RegTest::new("t.json").unwrap();
+    /// rt.shared(|shared| {
+    ///     std::thread::scope(|scope| {
+    ///         for worker in 0..4 {
+    ///             scope.spawn(move || shared.regtest(&format!("worker {}", worker), worker * 2));
+    ///         }
+    ///     });
+    /// });
+    /// ```
+    ///
+    /// The entries are recorded once `f` returns, ordered by label, each with
+    /// its label as [note](RegTest::regtest_with_note); entries under the
+    /// same label keep the order in which they were recorded.
+    pub fn shared<R, F: FnOnce(&SharedRegTest) -> R>(&mut self, f: F) -> R {
+        let shared = SharedRegTest::new(self.pretty_debug);
+        let result = f(&shared);
+        for (label, entry) in shared.into_entries() {
+            self.regtest_internal(
+                entry.message,
+                entry.reg_type,
+                entry.location,
+                Recording {
+                    note: Some(&label),
+                    value_type: Some(entry.value_type),
+                    ..Recording::default()
+                },
+            );
+        }
+        result
+    }
+
+    /// Records `pattern`, a regular expression, which in read mode the
+    /// output `value` only needs to match rather than be equal to. Suits
+    /// outputs with durations or counters, e.g. `r"took \d+ms"`. The pattern
+    /// matches anywhere in `value`, unless anchored with `^` and `$`.
+    ///
+    /// The pattern stored in the baseline is the one compared against, so
+    /// changing `pattern` requires regenerating the baseline.
+    ///
+    /// # Panics
+    /// Panics if `pattern` is not a valid regular expression, or, when
+    /// generating the baseline, if `value` doesn't match it.
+    #[track_caller]
+    pub fn regtest_matches<T: RegDisplay>(&mut self, value: T, pattern: &str) {
+        let regex = match Regex::new(pattern) {
+            Ok(regex) => regex,
+            Err(e) => panic!("Invalid regtest_matches pattern `{}`: {}", pattern, e),
+        };
+        let value = display::render(&value);
+        if matches!(self.mode, Mode::Write) && !regex.is_match(&value) {
+            panic!(
+                "The output recorded at {} doesn't match its pattern `{}`: {:?}",
+                Location::caller(),
+                pattern,
+                value
+            );
+        }
+        self.regtest_internal(
+            value,
+            RegType::Pattern,
+            Location::caller(),
+            Recording {
+                value_type: Some(value_type::<T>()),
+                pattern: Some(pattern),
+                ..Recording::default()
+            },
+        );
+    }
+
+    /// Runs `f` as a subtest named `name`, with its own section of the
+    /// regression data.
+    ///
+    /// This is meant for table-driven tests whose cases are only known at
+    /// runtime. A failing subtest doesn't stop the test: the remaining ones
+    /// still run, and all failures are reported together when this `RegTest`
+    /// is dropped.
+    ///
+    /// # Panics
+    /// Panics if `name` is empty, contains a `/`, or was already used by
+    /// another subtest of this test.
+    pub fn subtest<F: FnOnce(&mut RegTest)>(&mut self, name: &str, f: F) {
+        if name.is_empty() || name.contains('/') {
+            panic!(
+                "Invalid subtest name '{}': it must be non-empty and contain no '/'.",
+                name
+            );
+        }
+        if !self.subtest_names.insert(name.to_string()) {
+            panic!("Subtest '{}' was run more than once.", name);
+        }
+
+        // Hand over this subtest's section, including nested subtests
+        self.pull_all();
+        let nested_prefix = format!("{}/", name);
+        let (mine, others): (Vec<_>, Vec<_>) = std::mem::take(&mut self.sections)
+            .into_iter()
+            .partition(|e| {
+                e.section
+                    .as_deref()
+                    .is_some_and(|s| s == name || s.starts_with(&nested_prefix))
+            });
+        self.sections = others;
+
+        let (buffer, sections) = mine
+            .into_iter()
+            .map(|mut e| {
+                e.section = e
+                    .section
+                    .and_then(|s| s.strip_prefix(&nested_prefix).map(str::to_string));
+                e
+            })
+            .partition(|e| e.section.is_none());
+
+        let mut child = RegTest {
+            file_path: self.file_path.clone(),
+            mode: self.mode,
+            buffer,
+            sections,
+            stream: None,
+            stream_blobs: None,
+            partial: None,
+            read_index: 0,
+            compared: 0,
+            key_occurrences: HashMap::new(),
+            divergence: None,
+            extra: Vec::new(),
+            pending: 0,
+            expect_empty: self.expect_empty,
+            max_entries: self.max_entries,
+            outer_entries: self.outer_entries + self.buffer.len(),
+            tags: self.tags.clone(),
+            updates: Vec::new(),
+            rewritten: false,
+            xfail: None,
+            inline_count: 0,
+            #[cfg(feature = "image")]
+            image_count: 0,
+            subtest_count: 0,
+            subtest_names: HashSet::new(),
+            subtest_failures: Vec::new(),
+            section: Some(match &self.section {
+                Some(parent) => format!("{}/{}", parent, name),
+                None => name.to_string(),
+            }),
+            shape: None,
+            baseline_shape: None,
+            started: Instant::now(),
+            regression: OnceCell::new(),
+            backend: self.backend,
+            _lock: None,
+            config: self.config.clone(),
+            renderer: self.renderer.clone(),
+            normalizers: self.normalizers.clone(),
+            pretty_debug: self.pretty_debug,
+            description: self.description.clone(),
+            json_paths: self.json_paths.clone(),
+        };
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            f(&mut child);
+            child.resync();
+            child.check_recorded_shape();
+        }));
+
+        self.subtest_failures.extend(
+            child
+                .subtest_failures
+                .drain(..)
+                .map(|(nested, message)| (format!("{}/{}", name, nested), message)),
+        );
+        if let Err(payload) = result {
+            self.subtest_failures
+                .push((name.to_string(), render::panic_message(&*payload)));
+        }
+
+        self.inline_count += child.inline_count;
+        match self.mode {
+            Mode::Write => {
+                let from = self.buffer.len();
+                self.buffer.extend(child.buffer.drain(..).map(|mut e| {
+                    e.section = Some(match e.section {
+                        Some(nested) => format!("{}/{}", name, nested),
+                        None => name.to_string(),
+                    });
+                    e
+                }));
+                self.append_partial(from);
+            }
+            Mode::Read => {
+                self.subtest_count += child.compared + child.subtest_count;
+                self.updates
+                    .extend(child.updates.drain(..).map(|(index, mut e)| {
+                        e.section = Some(match e.section {
+                            Some(nested) => format!("{}/{}", name, nested),
+                            None => name.to_string(),
+                        });
+                        (index, e)
+                    }));
+            }
+        }
+    }
+
+    /// Compares the `Display` output of `value` with an expectation written
+    /// inline in the test, using the [`expect!`] macro.
+    ///
+    /// Empty expectations are filled in when baselines may be generated, and
+    /// mismatching ones are rewritten when baselines are regenerated (see
+    /// [`UpdatePolicy`](config::UpdatePolicy)).
+    pub fn regtest_inline<T: RegDisplay>(&mut self, value: T, expect: Expect) {
+        let redacted = self.config.redact(&display::render(&value)).into_owned();
+        let mut actual = placeholder::insert(&redacted, &self.config.placeholders).into_owned();
+        if let Some(digits) = self.config.float_digits {
+            actual = float::round(&actual, digits).into_owned();
+        }
+        self.check_not_expecting_empty(&actual);
+        self.inline_count += 1;
+
+        let expected = expect.value();
+        let expected_text = self.normalize(&RegType::Display, &expected);
+        if self.same_text(
+            &RegType::Display,
+            &expected_text,
+            &self.normalize(&RegType::Display, &actual),
+        ) {
+            return;
+        }
+
+        let update = match self.config.update {
+            UpdatePolicy::Always | UpdatePolicy::Mismatched => true,
+            UpdatePolicy::Missing | UpdatePolicy::Repair => expect.data.is_empty(),
+            UpdatePolicy::Never => false,
+        };
+
+        if update {
+            if let Err(e) = inline::update(&expect, &actual) {
+                panic!("Failed to update inline expectation: {}", e);
+            }
+        } else {
+            self.fail(Mismatch {
+                kind: MismatchKind::Inline,
+                index: None,
+                key: None,
+                note: None,
+                heading: None,
+                fields: Vec::new(),
+                changes: Vec::new(),
+                position: Some(expect.position),
+                caller: None,
+                expected,
+                actual,
+            });
+        }
+    }
+}
+
+/// Advice for when writing a data file failed with `error`.
+fn read_only_hint(error: &std::io::Error) -> &'static str {
+    match error.kind() {
+        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => {
+            " If the sources are read-only (e.g. in a sandbox), set `shadow_dir` in \
+             regtest.toml, or REGTEST_SHADOW_DIR, to write data files to another directory."
+        }
+        _ => "",
+    }
+}
+
+impl Drop for RegTest {
+    fn drop(&mut self) {
+        if self.section.is_some() {
+            return;
+        }
+
+        let (compared, recorded) = match self.mode {
+            Mode::Read => (self.compared + self.subtest_count, 0),
+            Mode::Write => (0, self.buffer.len()),
+        };
+
+        // Tells new baselines apart from regenerated ones
+        #[cfg(feature = "summary")]
+        let existed = matches!(self.mode, Mode::Write)
+            && match self.backend.store().local_path(&self.file_path) {
+                Some(path) => path.exists(),
+                None => matches!(self.backend.store().load(&self.file_path), Ok(Some(_))),
+            };
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.finish()));
+        order::finish(&self.config);
+
+        let failure = match &result {
+            Err(payload) => Some((
+                junit::FailureKind::Regression,
+                render::panic_message(&**payload),
+            )),
+            Ok(()) => match self.regression.get() {
+                Some(_) if self.config.advisory || self.xfail.is_some() => None,
+                Some(message) => Some((junit::FailureKind::Regression, message.clone())),
+                None if std::thread::panicking() => {
+                    Some((junit::FailureKind::Panic, "The test panicked.".to_string()))
+                }
+                None => None,
+            },
+        };
+
+        #[cfg(feature = "summary")]
+        summary::record(
+            &registry::current_test_name(),
+            summary::Outcome {
+                compared: matches!(self.mode, Mode::Read).then_some(compared),
+                new: matches!(self.mode, Mode::Write) && !existed,
+                updated: existed || self.rewritten,
+                pending: self.pending > 0,
+                failed: matches!(failure, Some((junit::FailureKind::Regression, _))),
+            },
+        );
+
+        if self.config.metrics.is_some() || self.config.pushgateway.is_some() {
+            let counts = metrics::Counts {
+                tests: 1,
+                compared: compared as u64,
+                recorded: recorded as u64,
+                mismatched: u64::from(
+                    matches!(failure, Some((junit::FailureKind::Regression, _)))
+                        || self.regression.get().is_some(),
+                ),
+                pending: self.pending as u64,
+                bytes: self
+                    .backend
+                    .store()
+                    .local_path(&self.file_path)
+                    .and_then(|path| std::fs::metadata(path).ok())
+                    .map_or(0, |m| m.len()),
+            };
+            if let Err(e) = metrics::record(
+                self.config.metrics.as_deref(),
+                self.config.pushgateway.as_deref(),
+                counts,
+            ) {
+                eprintln!("warning: failed to export regression test metrics: {}", e);
+            }
+        }
+
+        if let Some(path) = &self.config.junit {
+            let case = junit::Case {
+                test: registry::current_test_name(),
+                time: self.started.elapsed(),
+                failure,
+            };
+            if let Err(e) = junit::record(path, case) {
+                eprintln!(
+                    "warning: failed to write JUnit summary {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+
+        if let Err(payload) = result {
+            std::panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl RegTest {
+    /// Runs the end-of-test checks and writes the regression data, if needed.
+    fn finish(&mut self) {
+        if !std::thread::panicking() && self.regression.get().is_none() {
+            self.resync();
+            self.report_extra();
+        }
+
+        if !self.subtest_failures.is_empty() && !std::thread::panicking() {
+            let message = self
+                .renderer
+                .subtest_failures(&self.file_path, &self.subtest_failures);
+            match &self.xfail {
+                Some(reason) => {
+                    eprintln!(
+                        "note: regression test `{}` failed, as expected ({}):\n{}",
+                        registry::current_test_name(),
+                        reason,
+                        message
+                    );
+                    let _ = self.regression.set(message);
+                }
+                None => panic!("{}", message),
+            }
+        }
+
+        if let (Some(reason), Mode::Read, None) = (&self.xfail, self.mode, self.regression.get())
+            && !std::thread::panicking()
+        {
+            panic!(
+                "Regression test `{}` is expected to fail ({}), but matches its baseline {}. Remove the expected failure.",
+                registry::current_test_name(),
+                reason,
+                self.renderer.path(&self.file_path)
+            );
+        }
+
+        if !self.expect_empty && self.recorded_count() == 0 && !std::thread::panicking() {
+            let message = format!(
+                "Regression test `{}` did not record any entries ({}). Use `expect_nothing_recorded` if this is intended.",
+                registry::current_test_name(),
+                self.renderer.path(&self.file_path)
+            );
+            match self.config.empty {
+                EmptyPolicy::Allow => {}
+                EmptyPolicy::Warn => eprintln!("warning: {}", message),
+                EmptyPolicy::Fail => panic!("{}", message),
+            }
+        }
+
+        if !std::thread::panicking() {
+            self.check_recorded_shape();
+        }
+
+        // Tests which only use inline expectations don't need a data file
+        let inline_only = self.buffer.is_empty() && self.inline_count > 0;
+
+        if let Mode::Write = self.mode
+            && !inline_only
+        {
+            if !self.config.local_only
+                && self.config.shadow_dir.is_none()
+                && let Some(path) = self.backend.store().local_path(&self.file_path)
+            {
+                gitignore::warn_if_ignored(&path);
+            }
+
+            // Only create/write the file here
+            let mut data = DataFile::new(
+                std::mem::take(&mut self.buffer),
+                self.shape.take(),
+                self.tags.clone(),
+            );
+            if self.config.merge_friendly {
+                data.make_merge_friendly();
+            }
+            if self.config.checksums {
+                data.seal();
+            }
+            data.encrypted = self.config.encrypt;
+            if let Some(max) = self.config.max_file_bytes {
+                let size = data
+                    .encode(&self.file_path)
+                    .map_or(0, |data| data.len() as u64);
+                if size > max {
+                    let message = format!(
+                        "The regression data of `{}` is {} bytes, more than the limit of {} bytes (`max_file_bytes`), so {} was not written. Record less output, or a digest of it, or keep large data out of the repository with a custom store (see `regression_test::store`).",
+                        registry::current_test_name(),
+                        size,
+                        max,
+                        self.renderer.path(&self.file_path)
+                    );
+                    if std::thread::panicking() {
+                        eprintln!("warning: {}", message);
+                        return;
+                    }
+                    panic!("{}", message);
+                }
+            }
+            let saved = self.save_data(data);
+            if let Err(e) = saved {
+                // The test would pass without its baseline being saved
+                let message = format!(
+                    "Failed to write regression test file {}: {}.{}",
+                    self.renderer.path(&self.file_path),
+                    e,
+                    read_only_hint(&e)
+                );
+                if std::thread::panicking() {
+                    eprintln!("warning: {}", message);
+                    return;
+                }
+                panic!("{}", message);
+            }
+        }
+
+        if !self.updates.is_empty() && !std::thread::panicking() {
+            let count = self.updates.len();
+            match self.save_updates() {
+                Ok(()) => {
+                    self.rewritten = true;
+                    eprintln!(
+                        "note: replaced {} mismatching entries of {}",
+                        count,
+                        self.renderer.path(&self.file_path)
+                    );
+                }
+                Err(e) => panic!(
+                    "Failed to update regression test file {}: {}.{}",
+                    self.renderer.path(&self.file_path),
+                    e,
+                    read_only_hint(&e)
+                ),
+            }
+        }
+
+        // The recorded entries are safe, or there are none
+        if self.partial.take().is_some()
+            && let Some(local) = self.backend.store().local_path(&self.file_path)
+        {
+            let _ = std::fs::remove_file(path::partial_path(&local));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `f`, returning the message it panicked with, if it did.
+    fn run<R>(f: impl FnOnce() -> R + std::panic::UnwindSafe) -> Result<R, String> {
+        std::panic::catch_unwind(f).map_err(|payload| render::panic_message(&*payload))
+    }
+
+    /// A [RegTest] for `path` with the default configuration, so that the
+    /// tests don't depend on `regtest.toml` or the `REGTEST_*` variables.
+    fn regtest<P: AsRef<Path>>(path: P) -> std::io::Result<RegTest> {
+        RegTest::with_config(path, Config::default())
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("regtest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn same_test_may_reopen_file() {
+        let path = temp_file("reopen.json");
+        regtest(&path).unwrap().expect_nothing_recorded();
+        regtest(&path).unwrap().expect_nothing_recorded();
+    }
+
+    #[test]
+    fn different_tests_collide() {
+        let path = temp_file("collision.json");
+        regtest(&path).unwrap().expect_nothing_recorded();
+
+        let other = path.clone();
+        let result = std::thread::Builder::new()
+            .name("some_other_test".to_string())
+            .spawn(move || regtest(&other).map(|mut rt| rt.expect_nothing_recorded()))
+            .unwrap()
+            .join();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn nothing_recorded_requires_empty_baseline() {
+        let path = temp_file("nothing_recorded.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.expect_nothing_recorded();
+        drop(rt);
+
+        let mut rt = regtest(&path).unwrap();
+        rt.assert_nothing_recorded();
+        rt.expect_nothing_recorded();
+    }
+
+    #[test]
+    fn subtests_fail_independently() {
+        let path = temp_file("subtests.json");
+        let mut rt = regtest(&path).unwrap();
+        for case in ["a", "b", "c"] {
+            rt.subtest(case, |rt| {
+                rt.regtest(case);
+                rt.subtest("nested", |rt| rt.regtest(case.len()));
+            });
+        }
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            for case in ["a", "b", "c", "x"] {
+                rt.subtest(case, |rt| {
+                    rt.regtest(if case == "b" { "changed" } else { case });
+                    rt.subtest("nested", |rt| rt.regtest(case.len()));
+                });
+            }
+        });
+
+        let message = result.unwrap_err();
+        assert!(message.starts_with("2 subtest(s) failed"), "{}", message);
+        assert!(message.contains("--- subtest `b` ---\nRegression message mismatch"));
+        assert!(message.contains("--- subtest `x` ---\nNo more regression entries"));
+    }
+
+    #[test]
+    fn keyed_entries_match_out_of_order() {
+        let path = temp_file("keyed.json");
+        let config = Config {
+            key_pattern: Some(regex::Regex::new(r"^\[(?<key>\w+)\]").unwrap()),
+            ..Config::default()
+        };
+
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest("header");
+        rt.regtest("[a] first");
+        rt.regtest("[b] second");
+        rt.regtest("footer");
+        drop(rt);
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("[b] second");
+        rt.regtest("header");
+        rt.regtest("[a] first");
+        rt.regtest("footer");
+    }
+
+    #[test]
+    fn verbatim_entries_have_no_key() {
+        let path = temp_file("keyed_verbatim.json");
+        let config = Config {
+            key_pattern: Some(regex::Regex::new(r"^(\w+)").unwrap()),
+            ..Config::default()
+        };
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("output");
+        rt.regtest_hash("output");
+        rt.checkpoint("parsed");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let keys: Vec<_> = snapshot.entries().iter().map(|e| e.key()).collect();
+        assert_eq!(keys, [Some("output"), None, None]);
+    }
+
+    #[test]
+    fn merge_friendly_files_sort_keyed_entries() {
+        let path = temp_file("merge_friendly.json");
+        let config = Config {
+            key_pattern: Some(regex::Regex::new(r"^\[(?<key>\w+)\]").unwrap()),
+            merge_friendly: true,
+            ..Config::default()
+        };
+
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest("[c] third");
+        rt.regtest("header");
+        rt.regtest("[a] first");
+        rt.regtest("[b] second");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["[a] first", "header", "[b] second", "[c] third"]);
+        assert_eq!(snapshot.generated(), None);
+        assert_eq!(snapshot.entries()[0].location(), None);
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("[c] third");
+        rt.regtest("header");
+        rt.regtest("[a] first");
+        rt.regtest("[b] second");
+    }
+
+    #[test]
+    fn shape_is_stored_and_checked() {
+        let path = temp_file("shape.json");
+
+        let mut rt = regtest(&path).unwrap();
+        rt.assert_shape(2, &[RegType::Display, RegType::Debug]);
+        rt.regtest("a");
+        rt.regtest_dbg(1);
+        drop(rt);
+
+        let mut rt = regtest(&path).unwrap();
+        rt.assert_shape(2, &[RegType::Debug, RegType::Display]);
+        rt.regtest("a");
+        rt.regtest_dbg(1);
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.assert_shape(2, &[RegType::Display, RegType::Debug]);
+            rt.regtest("a");
+        });
+        let message = result.unwrap_err();
+        assert!(message.contains("but recorded 1 entries"), "{}", message);
+    }
+
+    #[test]
+    fn entries_record_their_location() {
+        let path = temp_file("location.json");
+
+        let mut rt = regtest(&path).unwrap();
+        let line = line!() + 1;
+        rt.regtest("here");
+        drop(rt);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            text.contains(&format!("\"location\": \"{}:{}\"", file!(), line)),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn resynchronizes_after_removed_or_added_entries() {
+        let path = temp_file("resync.json");
+        let record = |entries: &[&str]| {
+            let path = path.clone();
+            let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                for entry in entries {
+                    rt.regtest(entry);
+                }
+            })
+        };
+
+        record(&["a", "b", "c", "d"]).unwrap();
+
+        let removed = record(&["a", "c", "d"]).unwrap_err();
+        assert!(
+            removed.contains("no longer recorded:\n#1: b"),
+            "{}",
+            removed
+        );
+
+        let added = record(&["a", "x", "y", "b", "c", "d"]).unwrap_err();
+        assert!(
+            added.contains("new entries, which are not in the baseline:\nx\ny"),
+            "{}",
+            added
+        );
+
+        let changed = record(&["a", "x", "c", "d"]).unwrap_err();
+        assert!(changed.contains("message mismatch"), "{}", changed);
+    }
+
+    #[test]
+    fn checkpoints_bound_divergences() {
+        let path = temp_file("checkpoints.json");
+        let record = |entries: &[&str]| {
+            let path = path.clone();
+            let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                for entry in entries {
+                    match entry.strip_prefix("cp:") {
+                        Some(name) => rt.checkpoint(name),
+                        None => rt.regtest(entry),
+                    }
+                }
+            })
+        };
+
+        record(&["a", "cp:parsed", "b", "c", "cp:checked", "d"]).unwrap();
+        record(&["a", "cp:parsed", "b", "c", "cp:checked", "d"]).unwrap();
+
+        let removed = record(&["a", "cp:parsed", "cp:checked", "d"]).unwrap_err();
+        assert!(
+            removed.contains("no longer recorded:\n#2: b\n#3: c"),
+            "{}",
+            removed
+        );
+
+        // More added entries than the resync window holds
+        let mut added = vec!["a", "cp:parsed", "b", "c"];
+        added.extend(["x"; 2 * RESYNC_WINDOW]);
+        added.extend(["cp:checked", "d"]);
+        let added = record(&added).unwrap_err();
+        assert!(
+            added.contains(&format!(
+                "not in the baseline:\n{}",
+                ["x"; 2 * RESYNC_WINDOW].join("\n")
+            )),
+            "{}",
+            added
+        );
+
+        let changed = record(&["a", "cp:parsed", "b", "x", "cp:checked", "d"]).unwrap_err();
+        assert!(changed.contains("Expected: c"), "{}", changed);
+    }
+
+    #[test]
+    fn extra_entries_are_written_to_pending_file() {
+        let path = temp_file("extra.json");
+        let pending = pending_path(&path);
+        let record = |entries: &[&str], config: Config| {
+            let path = path.clone();
+            let entries: Vec<String> = entries.iter().map(|e| e.to_string()).collect();
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config).unwrap();
+                for entry in entries {
+                    rt.regtest(entry);
+                }
+            })
+        };
+
+        record(&["a"], Config::default()).unwrap();
+
+        let message = record(&["a", "b", "c"], Config::default()).unwrap_err();
+        assert!(
+            message.contains("past the end of the baseline:\nb\nc"),
+            "{}",
+            message
+        );
+        let text = std::fs::read_to_string(&pending).unwrap();
+        let data = DataFile::read(text.as_bytes(), &pending).unwrap();
+        let messages: Vec<_> = data.entries.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["a", "b", "c"]);
+
+        let warn = Config {
+            extra_entries: ExtraEntries::Warn,
+            ..Config::default()
+        };
+        record(&["a", "b"], warn).unwrap();
+        assert!(pending.exists());
+
+        // Matching the baseline again discards the outdated pending file
+        record(&["a"], Config::default()).unwrap();
+        assert!(!pending.exists());
+    }
+
+    #[test]
+    fn in_memory_backend_never_touches_disk() {
+        let path = temp_file("in_memory.json");
+        let open = || RegTest::with_backend(&path, Config::default(), Backend::InMemory).unwrap();
+
+        let mut rt = open();
+        rt.regtest("kept in memory");
+        drop(rt);
+        assert!(!path.exists());
+
+        let result = run(|| open().regtest("changed"));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn streaming_matches_like_loading() {
+        let path = temp_file("streaming.json");
+        let config = |streaming| Config {
+            streaming,
+            key_pattern: Some(regex::Regex::new("^key (\\d+)").unwrap()),
+            ..Config::default()
+        };
+        let record = |entries: Vec<String>, streaming| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config(streaming)).unwrap();
+                for entry in entries {
+                    rt.regtest(entry);
+                }
+                rt.assert_shape(1000, &[RegType::Display]);
+                rt.regtest("key 7");
+                rt.subtest("nested", |rt| rt.regtest("sub"));
+            })
+        };
+
+        // More entries than the stream buffers
+        let entries: Vec<String> = (0..999).map(|i| format!("entry {}", i)).collect();
+        record(entries.clone(), false).unwrap();
+        record(entries.clone(), true).unwrap();
+
+        let mut removed = entries.clone();
+        removed.remove(500);
+        let message = record(removed, true).unwrap_err();
+        assert!(message.contains("entry 500"), "{}", message);
+    }
+
+    #[test]
+    fn incremental_writes_keep_entries_until_finished() {
+        let path = temp_file("incremental.json");
+        let partial = path::partial_path(&path);
+        let config = Config {
+            incremental: true,
+            ..Config::default()
+        };
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("first");
+        rt.subtest("case", |rt| rt.regtest_dbg(2));
+        let lines: Vec<RegEntry> = std::fs::read_to_string(&partial)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].section(), Some("case"));
+
+        drop(rt);
+        assert!(!partial.exists());
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn jsonl_files_have_one_entry_per_line() {
+        let path = temp_file("lines.jsonl");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("first");
+        rt.regtest_dbg("second\nline");
+        drop(rt);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("\"version\":1"));
+        assert!(lines[2].contains("second\\\\nline"));
+
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("first");
+        rt.regtest_dbg("second\nline");
+        drop(rt);
+
+        let streaming = Config {
+            streaming: true,
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, streaming).unwrap();
+        rt.regtest("first");
+        rt.regtest_dbg("second\nline");
+    }
+
+    #[test]
+    fn malformed_entries_are_corrupt() {
+        let path = Path::new("regtest_data/t.json");
+        let file = r#"{"version": 1, "entries": [{"type": "display", "message": "a"}, {"type": "displayy"}]}"#;
+        let error = DataFile::read(file.as_bytes(), path).err().unwrap();
+        assert!(
+            CorruptSnapshot::from_error(path, &error).is_some(),
+            "{}",
+            error
+        );
+
+        // Only JSON Lines files start with a header alone
+        let header = r#"{"version": 1, "test": "t"}"#;
+        assert!(DataFile::read(header.as_bytes(), path).is_err());
+        let lines = DataFile::read(header.as_bytes(), Path::new("t.jsonl")).unwrap();
+        assert!(lines.entries.is_empty());
+        let bare = DataFile::read(&br#"[{"type": "display", "message": "a"}]"#[..], path).unwrap();
+        assert_eq!(bare.entries.len(), 1);
+    }
+
+    #[test]
+    fn directory_layout_stores_one_file_per_entry() {
+        let path = temp_file("apart.json");
+        let dir = entry_dir(&path);
+        let config = Config {
+            layout: Layout::Directory,
+            key_pattern: Some(regex::Regex::new("^key (\\S+)").unwrap()),
+            ..Config::default()
+        };
+        let record = |config: Config| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config).unwrap();
+                rt.regtest("first");
+                rt.regtest("key a/b");
+                rt.subtest("case", |rt| rt.regtest_dbg(2));
+            })
+        };
+
+        record(config.clone()).unwrap();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("0000.txt"), "first");
+        assert_eq!(read("a_b.txt"), "key a/b");
+        assert_eq!(read("case/0002.txt"), "2");
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[1].message(), "key a/b");
+
+        record(config.clone()).unwrap();
+        std::fs::write(dir.join("0000.txt"), "edited").unwrap();
+        assert!(record(config).is_err());
+    }
+
+    #[test]
+    fn size_limits_stop_large_baselines() {
+        let record = |name: &str, config: Config, message: &'static str| {
+            let path = temp_file(name);
+            let result = run({
+                let path = path.clone();
+                move || {
+                    RegTest::with_config(&path, config)
+                        .unwrap()
+                        .regtest(message)
+                }
+            });
+            (result, path)
+        };
+
+        let entry = Config {
+            max_entry_bytes: Some(4),
+            ..Config::default()
+        };
+        let (result, _) = record("large_entry.json", entry.clone(), "too long");
+        assert!(result.unwrap_err().contains("max_entry_bytes"));
+        record("small_entry.json", entry, "ok").0.unwrap();
+
+        let file = Config {
+            max_file_bytes: Some(64),
+            ..Config::default()
+        };
+        let (result, path) = record("large_file.json", file, "fits the entry limit");
+        assert!(result.unwrap_err().contains("max_file_bytes"));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn giant_messages_are_truncated() {
+        let path = temp_file("truncated.json");
+        let config = Config {
+            truncate_bytes: Some(8),
+            truncate_sidecar: true,
+            ..Config::default()
+        };
+        let record = |middle: &'static str| {
+            let (path, config) = (path.clone(), config.clone());
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config).unwrap();
+                rt.regtest(format!("BEGIN---{}---END", middle.repeat(100)));
+                rt.regtest("short");
+            })
+        };
+        record("dump").unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let message = snapshot.entries()[0].message();
+        assert!(
+            message.starts_with("BEGIN---\n[... 398 bytes truncated, sha256:"),
+            "{}",
+            message
+        );
+        assert!(message.ends_with("...]\nmp---END"), "{}", message);
+        assert_eq!(snapshot.entries()[1].message(), "short");
+
+        let digest = message.split("sha256:").nth(1).unwrap().split(' ').next();
+        let full = std::fs::read_to_string(path::full_message_path(&path, digest.unwrap()));
+        assert_eq!(full.unwrap().len(), 414);
+
+        record("dump").unwrap();
+        assert!(record("DUMP").unwrap_err().contains("truncated"));
+    }
+
+    #[test]
+    fn large_messages_are_stored_as_blobs() {
+        let root = temp_file("blob-data");
+        let path = root.join("src/lib/blobs.json");
+        let config = Config {
+            root: root.clone(),
+            blob_bytes: Some(16),
+            ..Config::default()
+        };
+        let record = |large: &'static str| {
+            let (path, config) = (path.clone(), config.clone());
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config).unwrap();
+                rt.regtest(large.repeat(10));
+                rt.regtest("small");
+            })
+        };
+        record("large").unwrap();
+
+        let stored = std::fs::read_to_string(&path).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&stored).unwrap();
+        assert_eq!(data["blobs"], "../../blobs");
+        let blob = data["entries"][0]["blob"].as_str().unwrap();
+        assert_eq!(data["entries"][0]["message"], "");
+        assert_eq!(data["entries"][1]["message"], "small");
+        let file = root.join("blobs").join(blob_file_name(blob));
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "large".repeat(10));
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "large".repeat(10));
+        record("large").unwrap();
+        assert!(record("LARGE").unwrap_err().contains("LARGE"));
+
+        let streaming = Config {
+            streaming: true,
+            ..config.clone()
+        };
+        let mut rt = RegTest::with_config(&path, streaming).unwrap();
+        rt.regtest("large".repeat(10));
+        rt.regtest("small");
+    }
+
+    #[test]
+    fn sidecar_files_are_written_to_the_lfs_dir() {
+        let root = temp_file("lfs-project");
+        let path = root.join("regtest_data/src/lib/lfs.json");
+        let config = Config {
+            source: Some(root.join("regtest.toml")),
+            root: root.join("regtest_data"),
+            lfs_dir: Some(root.join("lfs")),
+            truncate_bytes: Some(8),
+            truncate_sidecar: true,
+            blob_bytes: Some(64),
+            ..Config::default()
+        };
+        let record = |large: &'static str| {
+            let (path, config) = (path.clone(), config.clone());
+            run(move || {
+                let mut rt = RegTest::with_config(&path, config).unwrap();
+                rt.regtest(format!("BEGIN---{}---END", large.repeat(100)));
+            })
+        };
+        record("dump").unwrap();
+
+        let data: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(data["blobs"], "../../../lfs/regtest_data/blobs");
+        let blob = data["entries"][0]["blob"].as_str().unwrap();
+        assert!(
+            root.join("lfs/regtest_data/blobs")
+                .join(blob_file_name(blob))
+                .is_file()
+        );
+        let full = root.join("lfs/regtest_data/src/lib/lfs.full");
+        assert_eq!(std::fs::read_dir(full).unwrap().count(), 1);
+        assert!(
+            !path::full_message_path(&path, "")
+                .parent()
+                .unwrap()
+                .exists()
+        );
+
+        record("dump").unwrap();
+        assert!(record("DUMP").is_err());
+    }
+
+    #[test]
+    fn entry_count_is_limited_when_writing() {
+        let path = temp_file("max_entries.json");
+        let record = |count: usize| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                rt.limit_entries(3);
+                rt.regtest("first");
+                rt.subtest("loop", |rt| {
+                    for i in 1..count {
+                        rt.regtest(i);
+                    }
+                });
+            })
+        };
+        let error = record(4).unwrap_err();
+        assert!(error.contains("more than 3 entries"), "{}", error);
+        assert!(!path.exists());
+        record(3).unwrap();
+        assert_eq!(SnapshotFile::load(&path).unwrap().entries().len(), 3);
+    }
+
+    #[test]
+    fn hashes_are_compared_and_dumped() {
+        let path = temp_file("hashed.json");
+        let dump = temp_file("hashed-dump");
+        let config = Config {
+            hash_dump: Some(dump.clone()),
+            ..Config::default()
+        };
+        let record = |data: &'static [u8]| {
+            let (path, config) = (path.clone(), config.clone());
+            run(move || {
+                RegTest::with_config(&path, config)
+                    .unwrap()
+                    .regtest_hash(data)
+            })
+        };
+
+        record(b"large artifact").unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let entry = &snapshot.entries()[0];
+        assert_eq!(entry.reg_type(), RegType::Hash);
+        assert!(entry.message().starts_with("sha256:"));
+        assert!(entry.message().ends_with(" (14 bytes)"));
+
+        record(b"large artifact").unwrap();
+        assert!(record(b"changed artifact").is_err());
+        let test = file_name_part(&registry::current_test_name());
+        assert_eq!(
+            std::fs::read(dump.join(test).join("0.bin")).unwrap(),
+            b"changed artifact"
+        );
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn images_are_compared_with_tolerance() {
+        let path = temp_file("images.json");
+        let diffs = temp_file("image-diffs");
+        let config = Config {
+            image_threshold: 0.1,
+            image_diffs: diffs.clone(),
+            ..Config::default()
+        };
+        let record = |first: [u8; 4]| {
+            let (path, config) = (path.clone(), config.clone());
+            let mut pixels = first.to_vec();
+            pixels.extend_from_slice(&[0, 0, 255, 255]);
+            let png = image::Image::new(2, 1, pixels).to_png();
+            run(move || {
+                RegTest::with_config(&path, config)
+                    .unwrap()
+                    .regtest_image(&png)
+            })
+        };
+
+        record([255, 0, 0, 255]).unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let entry = &snapshot.entries()[0];
+        assert_eq!(entry.reg_type(), RegType::Image);
+        assert!(entry.message().starts_with("2x1 sha256:"));
+        assert!(entry.message().ends_with(" images.0.png"));
+        assert!(path.with_file_name("images.0.png").is_file());
+
+        record([255, 0, 0, 255]).unwrap();
+        record([253, 2, 0, 255]).unwrap();
+        assert!(record([0, 255, 0, 255]).is_err());
+        let test = diffs.join(file_name_part(&registry::current_test_name()));
+        let diff = std::fs::read(test.join("0.diff.png")).unwrap();
+        let diff = image::Image::from_png(&diff).unwrap();
+        assert_eq!(&diff.pixels()[..4], &[255, 0, 0, 255]);
+        assert!(test.join("0.actual.png").is_file());
+    }
+
+    #[test]
+    fn http_exchanges_are_replayed() {
+        use std::io::{Read, Write};
+
+        let upstream = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let upstream_url = format!("http://{}", upstream.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = upstream.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\n\r\n{\"id\": 1}")
+                .unwrap();
+        });
+
+        let path = temp_file("http.json");
+        let fetch = |rt: &mut RegTest, proxy: &http::HttpProxy| {
+            let url = format!("{}/users/1", proxy.url());
+            let response = http::request("GET", &url, "text/plain", &[], http::TIMEOUT).unwrap();
+            rt.regtest(String::from_utf8(response.body).unwrap());
+        };
+        let mut rt = regtest(&path).unwrap();
+        rt.with_http_replay(&upstream_url, fetch);
+        drop(rt);
+        server.join().unwrap();
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let types: Vec<_> = snapshot.entries().iter().map(|e| e.reg_type()).collect();
+        assert_eq!(types, [RegType::Display, RegType::Http]);
+
+        // The upstream server is gone
+        let mut rt = regtest(&path).unwrap();
+        rt.with_http_replay(&upstream_url, fetch);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commands_record_exit_code_and_outputs() {
+        let path = temp_file("cmd.json");
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", "echo out; echo err >&2; exit 3"]);
+        let output = regtest(&path).unwrap().regtest_cmd(&mut command);
+        assert_eq!(output.status.code(), Some(3));
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let entries: Vec<_> = snapshot
+            .entries()
+            .iter()
+            .map(|e| (e.note().unwrap(), e.message()))
+            .collect();
+        assert_eq!(
+            entries,
+            [("exit code", "3"), ("stdout", "out\n"), ("stderr", "err\n")]
+        );
+        regtest(&path).unwrap().regtest_cmd(&mut command);
+    }
+
+    #[test]
+    fn seeds_are_replayed() {
+        let path = temp_file("seed.json");
+        let seed = regtest(&path).unwrap().seed();
+        assert_eq!(regtest(&path).unwrap().seed(), seed);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].reg_type(), RegType::Seed);
+        assert_eq!(snapshot.entries()[0].message(), seed.to_string());
+    }
+
+    #[test]
+    fn measurements_fail_on_regressions_only() {
+        let path = temp_file("measurements.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_duration("parse", Duration::from_millis(100), 0.2);
+        rt.regtest_throughput("requests", 500.0, 0.1);
+        rt.regtest_memory("cache", 1 << 20, 0.0);
+        drop(rt);
+
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_duration("parse", Duration::from_millis(120), 0.2);
+        rt.regtest_throughput("requests", 900.0, 0.1);
+        rt.regtest_memory("cache", 1000, 0.0);
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.regtest_duration("parse", Duration::from_millis(121), 0.2);
+        });
+        let message = result.unwrap_err();
+        assert!(message.contains("121000000 ns"), "{}", message);
+    }
+
+    #[test]
+    fn allocations_are_recorded_with_slack() {
+        let path = temp_file("allocations.json");
+        let config = Config {
+            alloc_slack: 50.0,
+            ..Config::default()
+        };
+        let allocate = |bytes: usize| vec![0u8; bytes].len();
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        assert_eq!(rt.regtest_alloc(|| allocate(1000)), 1000);
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["1000 bytes", "1000 bytes", "1 allocations"]);
+
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest_alloc(|| allocate(1500));
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = RegTest::with_config(&path, config).unwrap();
+            rt.regtest_alloc(|| allocate(1501));
+        });
+        let message = result.unwrap_err();
+        assert!(message.contains("1501 bytes"), "{}", message);
+    }
+
+    #[test]
+    fn advisory_mismatches_are_reported_without_failing() {
+        let path = temp_file("advisory.json");
+        let report = temp_file("advisory-report.jsonl");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("a");
+        rt.regtest("b");
+        drop(rt);
+
+        let config = Config {
+            advisory: true,
+            report: Some(report.clone()),
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("changed");
+        rt.regtest("also changed");
+        rt.regtest("extra");
+        drop(rt);
+
+        let report = std::fs::read_to_string(&report).unwrap();
+        let lines: Vec<serde_json::Value> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1, "{}", report);
+        assert_eq!(lines[0]["advisory"], true);
+        assert_eq!(lines[0]["actual"], "changed");
+    }
+
+    #[test]
+    fn expected_failures_tolerate_mismatches_only() {
+        let path = temp_file("xfail.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("old behavior");
+        drop(rt);
+
+        let mut rt = regtest(&path).unwrap();
+        rt.expect_failure("issue-123");
+        rt.regtest("new behavior");
+        rt.regtest("more");
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.expect_failure("issue-123");
+            rt.regtest("old behavior");
+        });
+        let message = result.unwrap_err();
+        assert!(
+            message.contains("is expected to fail (issue-123), but matches its baseline"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn only_tests_with_update_tags_are_regenerated() {
+        let path = temp_file("tagged.json");
+        let mut rt = RegTest::open(&path, Config::default(), Backend::Store, &["parser"]).unwrap();
+        rt.regtest("old");
+        drop(rt);
+        assert_eq!(SnapshotFile::load(&path).unwrap().tags(), ["parser"]);
+
+        let config = Config {
+            update: UpdatePolicy::Always,
+            update_tags: vec!["lexer".to_string()],
+            ..Config::default()
+        };
+        let result = run(|| {
+            let mut rt = RegTest::open(&path, config.clone(), Backend::Store, &["parser"]).unwrap();
+            rt.regtest("new");
+        });
+        assert!(result.is_err());
+
+        let config = Config {
+            update_tags: vec!["parser".to_string()],
+            ..config
+        };
+        let mut rt = RegTest::open(&path, config, Backend::Store, &["parser", "slow"]).unwrap();
+        rt.regtest("new");
+        drop(rt);
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "new");
+        assert_eq!(snapshot.tags(), ["parser", "slow"]);
+    }
+
+    #[test]
+    fn mismatched_entries_are_replaced_in_place() {
+        let path = temp_file("update-mismatched.json");
+        let record = |rt: &mut RegTest, changed: &str| {
+            rt.regtest("first");
+            rt.regtest(changed);
+            rt.subtest("sub", |rt| rt.regtest(changed));
+            rt.regtest("last");
+        };
+        let mut rt = regtest(&path).unwrap();
+        record(&mut rt, "old");
+        drop(rt);
+        let before = std::fs::read_to_string(&path).unwrap();
+
+        let config = Config {
+            update: UpdatePolicy::Mismatched,
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        record(&mut rt, "new");
+        drop(rt);
+
+        let after = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(after, before.replace("\"old\"", "\"new\""));
+        let mut rt = regtest(&path).unwrap();
+        record(&mut rt, "new");
+    }
+
+    #[test]
+    fn extra_entries_are_appended() {
+        let path = temp_file("append.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("a");
+        drop(rt);
+
+        let config = Config {
+            extra_entries: ExtraEntries::Append,
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("a");
+        rt.regtest("b");
+        rt.regtest("c");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["a", "b", "c"]);
+        assert!(!path::pending_path(&path).exists());
+    }
+
+    #[test]
+    fn clocks_are_replayed() {
+        let path = temp_file("clock.json");
+        let now = regtest(&path).unwrap().clock().now();
+        let clock = regtest(&path).unwrap().clock();
+        assert_eq!(clock.now(), now);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].reg_type(), RegType::Clock);
+        assert_eq!(snapshot.entries()[0].note(), Some(clock.rfc3339().as_str()));
+
+        let path = temp_file("clock_redaction.json");
+        let mut rt = regtest(&path).unwrap();
+        let settings = Settings::new().redact_clock(&clock, "[now]");
+        let millis = clock.unix_seconds() * 1000 + 250;
+        let space = clock.rfc3339().replace('T', " ");
+        rt.with_settings(settings, |rt| rt.regtest(format!("{} {}", millis, space)));
+        drop(rt);
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "[now] [now]");
+    }
+
+    #[test]
+    fn iterators_record_one_entry_per_item() {
+        let path = temp_file("iter.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_iter([1, 2, 3]);
+        rt.regtest_iter_dbg(vec!["a"]);
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["1", "2", "3", "\"a\""]);
+
+        let result = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.regtest_iter([1, 2, 4]);
+            rt.regtest_iter_dbg(vec!["a"]);
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn normalizers_apply_to_both_sides() {
+        use normalize::Normalization;
+
+        let path = temp_file("normalized.json");
+        let config = Config {
+            normalize: vec![
+                Normalization::LineEndings,
+                Normalization::TrailingWhitespace,
+            ],
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest("a \r\nb\r\n");
+        drop(rt);
+        // The baseline keeps the output as recorded
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "a \r\nb\r\n");
+
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest("a\nb\n");
+        drop(rt);
+
+        let result = run(|| {
+            let mut rt = RegTest::with_config(&path, config).unwrap();
+            rt.regtest("A\nB\n");
+        });
+        assert!(result.is_err());
+
+        let mut rt = regtest(&path).unwrap();
+        rt.normalize_with(|text: &str| text.to_lowercase().replace(['\r', ' '], ""));
+        rt.regtest("A\nB\n");
+    }
+
+    #[test]
+    fn placeholders_match_dynamic_fragments() {
+        let path = temp_file("placeholders.json");
+        std::fs::write(
+            &path,
+            r#"[{"type": "display", "message": "took {{any:\\d+}}ms"}]"#,
+        )
+        .unwrap();
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("took 12ms");
+        drop(rt);
+
+        let path = temp_file("placeholders-inserted.json");
+        let config = Config {
+            placeholders: vec![config::Placeholder::Uuid],
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest("id 67e55044-10b1-426f-9247-bb680e5fe0c8");
+        drop(rt);
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "id {{uuid}}");
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest("id 00000000-0000-0000-0000-000000000000");
+    }
+
+    #[test]
+    fn floats_are_rounded_before_comparing() {
+        let path = temp_file("float-digits.json");
+        let config = Config {
+            float_digits: Some(10),
+            ..Config::default()
+        };
+        let mut rt = RegTest::with_config(&path, config.clone()).unwrap();
+        rt.regtest(0.1 + 0.2);
+        rt.regtest_eq(&vec![0.1 + 0.2]);
+        rt.regtest_json(&serde_json::json!({ "x": 0.1 + 0.2 }));
+        drop(rt);
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].message(), "0.3");
+
+        let mut rt = RegTest::with_config(&path, config).unwrap();
+        rt.regtest(0.3);
+        rt.regtest_eq(&vec![0.3]);
+        rt.regtest_json(&serde_json::json!({ "x": 0.3 }));
+    }
+
+    #[test]
+    fn patterns_only_need_to_match() {
+        let path = temp_file("pattern.json");
+        let record = |duration: u32| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                rt.regtest_matches(format!("took {}ms", duration), r"^took \d+ms$");
+            })
+        };
+
+        record(12).unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].reg_type(), RegType::Pattern);
+        assert_eq!(snapshot.entries()[0].message(), r"^took \d+ms$");
+
+        record(345).unwrap();
+        std::fs::write(
+            &path,
+            r#"[{"type": "pattern", "message": "^took 1\\d*ms$"}]"#,
+        )
+        .unwrap();
+        record(16).unwrap();
+        let error = record(99).unwrap_err();
+        assert!(error.contains("took 99ms"), "{}", error);
+
+        std::fs::remove_file(&path).unwrap();
+        let mut rt = regtest(&path).unwrap();
+        let result = run(std::panic::AssertUnwindSafe(|| {
+            rt.regtest_matches("took 1s", r"\d+ms")
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn settings_apply_within_their_scope() {
+        let path = temp_file("settings.json");
+        let settings = Settings::new()
+            .redact(r"\d+ms", "[duration]")
+            .pretty_debug(true)
+            .description("parser");
+        let mut rt = regtest(&path).unwrap();
+        rt.with_settings(settings.clone(), |rt| {
+            rt.regtest("took 12ms");
+            rt.regtest_dbg((1,));
+        });
+        rt.regtest("took 12ms");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(messages, ["took [duration]", "(\n    1,\n)", "took 12ms"]);
+
+        let error = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.with_settings(settings, |rt| rt.regtest("changed"));
+        })
+        .unwrap_err();
+        assert!(error.starts_with("parser: "), "{}", error);
+    }
+
+    #[test]
+    fn notes_are_stored_and_reported() {
+        let path = temp_file("notes.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_with_note("tokens of the empty input", "[]");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(
+            snapshot.entries()[0].note(),
+            Some("tokens of the empty input")
+        );
+
+        let error = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.regtest_with_note("tokens of the empty input", "[eof]");
+        })
+        .unwrap_err();
+        assert!(
+            error.contains("entry #0 \"tokens of the empty input\""),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn headers_name_the_phase_of_mismatches() {
+        let path = temp_file("headers.json");
+        let record = |optimized: &'static str| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                rt.header("Phase 1: parsing");
+                rt.regtest("ast");
+                rt.header("Phase 2: optimization");
+                rt.regtest(optimized);
+            })
+        };
+        record("folded").unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[2].reg_type(), RegType::Header);
+        assert_eq!(snapshot.entries()[2].message(), "Phase 2: optimization");
+
+        let error = record("inlined").unwrap_err();
+        assert!(
+            error.contains("entry #3 under \"Phase 2: optimization\""),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn repeated_messages_are_stored_once() {
+        let path = temp_file("dedup.json");
+        let record = |retries: usize| {
+            let path = path.clone();
+            run(move || {
+                let mut rt = regtest(&path).unwrap();
+                let log = ["connecting"]
+                    .into_iter()
+                    .chain(std::iter::repeat_n("retrying", retries))
+                    .chain(["connected", "retrying"]);
+                rt.regtest_dedup(log);
+            })
+        };
+        record(300).unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let messages: Vec<_> = snapshot.entries().iter().map(|e| e.message()).collect();
+        assert_eq!(
+            messages,
+            [
+                "connecting",
+                "retrying (repeated 300 times)",
+                "connected",
+                "retrying"
+            ]
+        );
+
+        record(300).unwrap();
+        assert!(record(299).unwrap_err().contains("repeated 299 times"));
+    }
+
+    #[test]
+    fn threads_record_in_label_order() {
+        let path = temp_file("shared.json");
+        let record = |delays: [u64; 3]| {
+            let mut rt = regtest(&path).unwrap();
+            rt.shared(|shared| {
+                std::thread::scope(|scope| {
+                    for (worker, delay) in delays.into_iter().enumerate() {
+                        scope.spawn(move || {
+                            std::thread::sleep(Duration::from_millis(delay));
+                            let label = format!("worker {}", worker);
+                            shared.regtest(&label, worker);
+                            shared.regtest_dbg(&label, [worker; 2]);
+                        });
+                    }
+                });
+            });
+        };
+        record([20, 10, 0]);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let entries: Vec<_> = snapshot
+            .entries()
+            .iter()
+            .map(|e| (e.note().unwrap(), e.message()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("worker 0", "0"),
+                ("worker 0", "[0, 0]"),
+                ("worker 1", "1"),
+                ("worker 1", "[1, 1]"),
+                ("worker 2", "2"),
+                ("worker 2", "[2, 2]"),
+            ]
+        );
+
+        // Threads finishing in another order still match
+        record([0, 10, 20]);
+    }
+
+    #[test]
+    fn value_types_are_stored_and_only_warn() {
+        let path = temp_file("value_types.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest(5u32);
+        rt.regtest_dbg(String::from("five"));
+        rt.regtest_json(&serde_json::json!(5));
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        let types: Vec<_> = snapshot.entries().iter().map(|e| e.value_type()).collect();
+        assert_eq!(types, [Some("u32"), Some("alloc::string::String"), None]);
+        assert_eq!(value_type::<&mut &str>(), "str");
+
+        // Values of other types with the same output still match
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest(5i64);
+        rt.regtest_dbg("five");
+        rt.regtest_json(&serde_json::json!(5));
+    }
+
+    #[test]
+    fn custom_kinds_only_match_their_own_kind() {
+        let path = temp_file("custom.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_custom("sql", "SELECT 1");
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(
+            snapshot.entries()[0].reg_type(),
+            RegType::Custom("sql".to_string())
+        );
+        regtest(&path).unwrap().regtest_custom("sql", "SELECT 1");
+
+        let error = run(|| {
+            regtest(&path)
+                .unwrap()
+                .regtest_custom("graphql", "SELECT 1");
+        })
+        .unwrap_err();
+        assert!(
+            error.contains(r#"expected Custom("sql"), got Custom("graphql")"#),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn json_mismatches_list_changed_values() {
+        let path = temp_file("json.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_json(&serde_json::json!({"name": "a", "items": [{"id": 1}]}));
+        drop(rt);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(
+            snapshot.entries()[0].message(),
+            r#"{"items":[{"id":1}],"name":"a"}"#
+        );
+
+        regtest(&path)
+            .unwrap()
+            .regtest_json(&serde_json::json!({"items": [{"id": 1}], "name": "a"}));
+
+        let error = run(|| {
+            let mut rt = regtest(&path).unwrap();
+            rt.regtest_json(&serde_json::json!({"name": "a", "items": [{"id": 2}], "new": 0}));
+        })
+        .unwrap_err();
+        assert!(error.contains("\n  /items/0/id: 1 -> 2\n"), "{}", error);
+        assert!(error.contains("\n  /new: (absent) -> 0"), "{}", error);
+    }
+
+    #[test]
+    fn json_paths_are_ignored_or_replaced() {
+        #[derive(Serialize)]
+        struct Build {
+            name: &'static str,
+            started: u64,
+        }
+
+        let path = temp_file("json_paths.json");
+        let record = |started: u64, id: u64| {
+            let mut rt = regtest(&path).unwrap();
+            let settings = Settings::new().replace_path("$.started", "[time]");
+            rt.with_settings(settings, |rt| rt.regtest_ser(&Build { name: "a", started }));
+            rt.regtest_json_ignoring(&serde_json::json!({"id": id, "ok": true}), &["$.id"]);
+        };
+        record(1, 1);
+        record(2, 2);
+
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(
+            snapshot.entries()[0].message(),
+            r#"{"name":"a","started":"[time]"}"#
+        );
+        assert_eq!(snapshot.entries()[1].message(), r#"{"ok":true}"#);
+    }
+
+    #[test]
+    fn values_are_compared_structurally() {
+        #[derive(Serialize, Deserialize, PartialEq)]
+        struct Point {
+            x: i32,
+            y: i32,
+        }
+
+        let path = temp_file("values.json");
+        let record = |point: Point| {
+            let path = path.clone();
+            run(move || regtest(&path).unwrap().regtest_eq(&point))
+        };
+
+        record(Point { x: 1, y: 2 }).unwrap();
+        let snapshot = SnapshotFile::load(&path).unwrap();
+        assert_eq!(snapshot.entries()[0].reg_type(), RegType::Value);
+
+        // Stored in another field order
+        std::fs::write(
+            &path,
+            r#"[{"type": "value", "message": "{\"y\": 2, \"x\": 1}"}]"#,
+        )
+        .unwrap();
+        record(Point { x: 1, y: 2 }).unwrap();
+
+        let error = record(Point { x: 1, y: 3 }).unwrap_err();
+        assert!(error.contains("differing fields: `y`"), "{}", error);
+    }
+
+    #[test]
+    fn roundtrips_check_the_baseline_still_deserializes() {
+        #[derive(Serialize, Deserialize)]
+        struct Message {
+            id: u32,
+            #[serde(skip_deserializing)]
+            cached: bool,
+        }
+
+        let path = temp_file("roundtrip.json");
+        let record = |id: u32, cached: bool| {
+            let path = path.clone();
+            run(move || {
+                regtest(&path)
+                    .unwrap()
+                    .regtest_roundtrip(&Message { id, cached })
+            })
+        };
+
+        record(1, false).unwrap();
+        record(1, false).unwrap();
+        let changed = record(2, false).unwrap_err();
+        assert!(changed.contains("message mismatch"), "{}", changed);
+
+        let error = record(1, true).unwrap_err();
+        assert!(error.contains("doesn't round-trip"), "{}", error);
+
+        std::fs::write(
+            &path,
+            r#"[{"type": "value", "message": "{\"id\": \"one\"}"}]"#,
+        )
+        .unwrap();
+        let breaking = record(1, false).unwrap_err();
+        assert!(
+            breaking.contains("can no longer be read back"),
+            "{}",
+            breaking
+        );
+    }
+
+    #[test]
+    fn reads_bare_entry_arrays() {
+        let path = temp_file("bare.json");
+        std::fs::write(&path, r#"[{"type": "display", "message": "old"}]"#).unwrap();
+
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest("old");
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_timestamp(1_792_153_845), "2026-10-16T12:30:45Z");
+    }
+
+    #[test]
+    fn comparators_accept_equivalent_output() {
+        let path = temp_file("compare.json");
+
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_with("built with 1.80.0", compare::Versions);
+        drop(rt);
+
+        let mut rt = regtest(&path).unwrap();
+        rt.regtest_with("built with 1.80", compare::Versions);
+    }
+
+    #[test]
+    #[should_panic(expected = "did not record any entries")]
+    fn empty_policy_fail() {
+        let path = temp_file("empty_policy_fail.json");
+        let config = Config {
+            empty: EmptyPolicy::Fail,
+            ..Config::default()
+        };
+        drop(RegTest::with_config(&path, config).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "records no regression entries")]
+    fn recording_after_expecting_nothing_fails() {
+        let path = temp_file("recording_after_nothing.json");
+        let mut rt = regtest(&path).unwrap();
+        rt.expect_nothing_recorded();
+        rt.regtest("unexpected");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    #[should_panic(expected = "Failed to write regression test file")]
+    fn unwritable_baselines_fail_the_test() {
+        // The data file can't be found, nor its directory created
+        let link = temp_file("unwritable");
+        let _ = std::fs::remove_file(&link);
+        std::os::unix::fs::symlink(link.with_file_name("missing"), &link).unwrap();
+        let mut rt = regtest(link.join("t.json")).unwrap();
+        rt.regtest("lost");
+    }
 }
@@ -0,0 +1,158 @@
+//! Advisory locks on regression data files, keeping test processes which use
+//! the same file (e.g. a retry of a test, or test binaries sharing a data
+//! directory) from reading and writing it at the same time.
+//!
+//! Tests of the same process are already kept apart by the
+//! [registry](crate::registry), and a test may open its file several times,
+//! so a lock is taken once per process and file. Locks are taken on files of
+//! their own in the temporary directory, as data files are replaced rather
+//! than written in place.
+
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release a data file.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a contended lock is tried again.
+const RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The locks held by this process, with the number of their users.
+fn held() -> &'static Mutex<HashMap<PathBuf, (File, usize)>> {
+    static HELD: OnceLock<Mutex<HashMap<PathBuf, (File, usize)>>> = OnceLock::new();
+    HELD.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The lock on a data file, released when dropped by its last user in this
+/// process.
+#[derive(Debug)]
+pub(crate) struct FileLock {
+    lock_file: PathBuf,
+}
+
+/// Locks the data file `path`, waiting for other processes to release it.
+///
+/// Fails with [`std::io::ErrorKind::WouldBlock`] if they don't release it in
+/// time.
+pub(crate) fn acquire(path: &Path) -> std::io::Result<FileLock> {
+    acquire_within(path, TIMEOUT)
+}
+
+fn acquire_within(path: &Path, timeout: Duration) -> std::io::Result<FileLock> {
+    let lock_file = lock_path(path);
+
+    // A poisoned lock only means another test panicked while holding it; the
+    // map itself is still consistent.
+    if let Some((_, users)) = held()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_mut(&lock_file)
+    {
+        *users += 1;
+        return Ok(FileLock { lock_file });
+    }
+
+    if let Some(parent) = lock_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = File::create(&lock_file)?;
+
+    let started = Instant::now();
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::Error(e)) => return Err(e),
+            Err(TryLockError::WouldBlock) if started.elapsed() >= timeout => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WouldBlock,
+                    format!(
+                        "{} is locked by another process, which is using it at the same time: \
+                         a test of another test binary resolving to the same file, or another \
+                         run of this test (e.g. a retry). Gave up after {} seconds.",
+                        path.display(),
+                        timeout.as_secs_f64()
+                    ),
+                ));
+            }
+            Err(TryLockError::WouldBlock) => {
+                if !waiting {
+                    eprintln!(
+                        "note: waiting for another process to release {}",
+                        path.display()
+                    );
+                    waiting = true;
+                }
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+        }
+    }
+
+    held()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(lock_file.clone(), (file, 1));
+    Ok(FileLock { lock_file })
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let mut held = held().lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, users)) = held.get_mut(&self.lock_file) {
+            *users -= 1;
+            if *users == 0 {
+                // Closing the file releases the lock
+                held.remove(&self.lock_file);
+            }
+        }
+    }
+}
+
+/// The file locked for the data file `path`, named after its absolute path.
+fn lock_path(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let digest: String = Sha256::digest(absolute.to_string_lossy().as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    std::env::temp_dir()
+        .join("regtest-locks")
+        .join(format!("{}.lock", digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contended_locks_time_out() {
+        let path =
+            std::env::temp_dir().join(format!("regtest-lock-test-{}.json", std::process::id()));
+
+        // Another process, as far as the lock is concerned
+        std::fs::create_dir_all(lock_path(&path).parent().unwrap()).unwrap();
+        let other = File::create(lock_path(&path)).unwrap();
+        other.lock().unwrap();
+
+        let error = acquire_within(&path, Duration::from_millis(50)).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::WouldBlock);
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("{} is locked", path.display()))
+        );
+
+        other.unlock().unwrap();
+        let first = acquire_within(&path, Duration::ZERO).unwrap();
+        // Users within the process share the lock
+        let second = acquire_within(&path, Duration::ZERO).unwrap();
+        drop(first);
+        assert!(other.try_lock().is_err());
+        drop(second);
+        other.try_lock().unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+//! Canonical form of XML and HTML markup, see
+//! [`RegTest::regtest_markup`](crate::RegTest::regtest_markup).
+//!
+//! Markup is canonicalized without validating it: tags, text and comments
+//! are put on lines of their own, indented by nesting depth, the attributes
+//! of each tag are sorted by name and double-quoted, whitespace-only text
+//! between tags is dropped and runs of whitespace in text collapsed. The
+//! contents of `<script>` and `<style>` elements are kept as is.
+
+/// HTML elements which never have an end tag.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements whose contents aren't markup.
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// The canonical form of `markup`.
+pub(crate) fn canonicalize(markup: &str) -> String {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut rest = markup;
+
+    while !rest.is_empty() {
+        let depth = open.len();
+        if let Some(after) = rest.strip_prefix("<!--") {
+            let end = after.find("-->").map_or(after.len(), |end| end + 3);
+            lines.push((depth, format!("<!--{}", &after[..end])));
+            rest = &after[end..];
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            lines.push((depth, collapse_whitespace(&rest[..end])));
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("</") {
+            let end = after.find('>').unwrap_or(after.len());
+            let name = after[..end].trim();
+            if let Some(position) = open.iter().rposition(|open| *open == name) {
+                open.truncate(position);
+            }
+            lines.push((open.len(), format!("</{}>", name)));
+            rest = after.get(end + 1..).unwrap_or("");
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_alphabetic()) {
+            let end = tag_end(rest);
+            let (name, tag, self_closing) = start_tag(&rest[1..end]);
+            lines.push((depth, tag));
+            rest = rest.get(end + 1..).unwrap_or("");
+
+            let lowercase = name.to_ascii_lowercase();
+            if RAW_TEXT_ELEMENTS.contains(&lowercase.as_str()) && !self_closing {
+                let close = format!("</{}", lowercase);
+                let end = rest.to_ascii_lowercase().find(&close).unwrap_or(rest.len());
+                let text = rest[..end].trim();
+                if !text.is_empty() {
+                    lines.push((depth + 1, text.to_string()));
+                }
+                open.push(name);
+                rest = &rest[end..];
+            } else if !self_closing && !VOID_ELEMENTS.contains(&lowercase.as_str()) {
+                open.push(name);
+            }
+        } else {
+            // A `<` not starting a tag is part of the text
+            let end = rest
+                .char_indices()
+                .skip(1)
+                .find(|&(i, c)| {
+                    c == '<'
+                        && rest[i + 1..].starts_with(|c: char| {
+                            c.is_alphabetic() || matches!(c, '/' | '!' | '?')
+                        })
+                })
+                .map_or(rest.len(), |(end, _)| end);
+            let text = collapse_whitespace(&rest[..end]);
+            if !text.is_empty() {
+                lines.push((depth, text));
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    let lines: Vec<String> = lines
+        .into_iter()
+        .map(|(depth, line)| format!("{}{}", "  ".repeat(depth), line))
+        .collect();
+    lines.join("\n")
+}
+
+/// The index of the `>` ending the tag at the start of `markup`, outside of
+/// quoted attribute values.
+fn tag_end(markup: &str) -> usize {
+    let mut quote = None;
+    for (i, c) in markup.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '>') => return i,
+            _ => {}
+        }
+    }
+    markup.len()
+}
+
+/// The name and canonical form of the start tag with the contents `tag`
+/// (without the angle brackets), and whether it is self-closing.
+fn start_tag(tag: &str) -> (&str, String, bool) {
+    let tag = tag.trim_end();
+    let (tag, self_closing) = match tag.strip_suffix('/') {
+        Some(tag) => (tag, true),
+        None => (tag, false),
+    };
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = &tag[..name_end];
+
+    let mut attributes = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let attribute = &rest[..end];
+        rest = rest[end..].trim_start();
+
+        let mut value = None;
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                    (&after[1..end], after.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = Some(raw.replace('"', "&quot;"));
+            rest = remaining.trim_start();
+        }
+        attributes.push((attribute, value));
+    }
+    attributes.sort();
+
+    let mut canonical = format!("<{}", name);
+    for (attribute, value) in attributes {
+        match value {
+            Some(value) => canonical.push_str(&format!(" {}=\"{}\"", attribute, value)),
+            None => canonical.push_str(&format!(" {}", attribute)),
+        }
+    }
+    canonical.push_str(if self_closing { "/>" } else { ">" });
+    (name, canonical, self_closing)
+}
+
+/// `text` trimmed, with each run of whitespace replaced by a space.
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equivalent_markup_is_canonicalized_alike() {
+        let a = "<ul class='list' id=\"x\">\n  <li>One  item</li><li>Two</li>\n</ul><br><img src=a.png alt=\"\"/>";
+        let b = "<ul id=\"x\"   class=\"list\"><li>\n One item\n</li>\n\n<li>Two</li></ul>\n<br>\n<img alt=\"\" src=\"a.png\" />";
+        let expected = "\
+<ul class=\"list\" id=\"x\">
+  <li>
+    One item
+  </li>
+  <li>
+    Two
+  </li>
+</ul>
+<br>
+<img alt=\"\" src=\"a.png\"/>";
+        assert_eq!(canonicalize(a), expected);
+        assert_eq!(canonicalize(b), expected);
+
+        assert_eq!(canonicalize("<p>é < ü</p>"), "<p>\n  é < ü\n</p>");
+
+        let script = "<!DOCTYPE  html><script>if (a < b) {}</script><!-- note -->";
+        assert_eq!(
+            canonicalize(script),
+            "<!DOCTYPE html>\n<script>\n  if (a < b) {}\n</script>\n<!-- note -->"
+        );
+    }
+}
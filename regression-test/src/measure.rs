@@ -0,0 +1,103 @@
+//! Performance measurements compared with a tolerance, see
+//! [`RegTest::regtest_duration`](crate::RegTest::regtest_duration).
+//!
+//! Measurements are stored as a number followed by their unit, e.g.
+//! `1520000 ns`, `2048.5 /s`, `65536 bytes` or `12 allocations`. When comparing, only a change
+//! in the direction of a regression beyond the tolerance makes them differ:
+//! faster code, higher throughput and less memory always match.
+
+use crate::compare::Comparator;
+
+/// What is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    /// A duration in nanoseconds; higher is worse.
+    Duration,
+    /// Operations per second; lower is worse.
+    Throughput,
+    /// A size in bytes; higher is worse.
+    Memory,
+    /// A number of allocations; higher is worse.
+    Allocations,
+}
+
+impl Kind {
+    fn unit(self) -> &'static str {
+        match self {
+            Kind::Duration => "ns",
+            Kind::Throughput => "/s",
+            Kind::Memory => "bytes",
+            Kind::Allocations => "allocations",
+        }
+    }
+
+    /// The message recording `value`.
+    pub(crate) fn format(self, value: f64) -> String {
+        format!("{} {}", value, self.unit())
+    }
+
+    /// The value recorded in `message`, if it is a measurement of this kind.
+    fn parse(self, message: &str) -> Option<f64> {
+        let (value, unit) = message.split_once(' ')?;
+        if unit != self.unit() {
+            return None;
+        }
+        value.parse().ok().filter(|value: &f64| value.is_finite())
+    }
+}
+
+/// Matches measurements which don't regress by more than `tolerance`, a
+/// fraction of the baseline.
+pub(crate) struct Tolerance {
+    pub(crate) kind: Kind,
+    pub(crate) tolerance: f64,
+}
+
+impl Tolerance {
+    /// # Panics
+    /// Panics if `tolerance` is negative or not a number.
+    pub(crate) fn new(kind: Kind, tolerance: f64) -> Self {
+        if tolerance.is_nan() || tolerance < 0.0 {
+            panic!("Invalid tolerance {}: must be at least 0", tolerance);
+        }
+        Tolerance { kind, tolerance }
+    }
+}
+
+impl Comparator for Tolerance {
+    fn equivalent(&self, expected: &str, actual: &str) -> bool {
+        let (Some(expected), Some(actual)) = (self.kind.parse(expected), self.kind.parse(actual))
+        else {
+            return false;
+        };
+        match self.kind {
+            Kind::Duration | Kind::Memory | Kind::Allocations => {
+                actual <= expected * (1.0 + self.tolerance)
+            }
+            Kind::Throughput => actual >= expected * (1.0 - self.tolerance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_regressions_beyond_the_tolerance_differ() {
+        let duration = Tolerance::new(Kind::Duration, 0.1);
+        assert!(duration.equivalent("1000 ns", "1100 ns"));
+        assert!(duration.equivalent("1000 ns", "10 ns"));
+        assert!(!duration.equivalent("1000 ns", "1101 ns"));
+        assert!(!duration.equivalent("1000 ns", "1000 bytes"));
+
+        let throughput = Tolerance::new(Kind::Throughput, 0.5);
+        assert!(throughput.equivalent("200.5 /s", "150 /s"));
+        assert!(!throughput.equivalent("200 /s", "99 /s"));
+
+        let memory = Tolerance::new(Kind::Memory, 0.0);
+        assert_eq!(Kind::Memory.format(4096.0), "4096 bytes");
+        assert!(memory.equivalent("4096 bytes", "4000 bytes"));
+        assert!(!memory.equivalent("4096 bytes", "4097 bytes"));
+    }
+}
@@ -0,0 +1,318 @@
+//! Three-way merges of regression data files, as done by the git merge driver
+//! `cargo regtest merge`.
+
+use crate::{DataFile, Header, RegEntry};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// A data file merged from two versions with a common ancestor, see
+/// [`merge_files`].
+pub struct MergedFile {
+    data: DataFile,
+    conflicts: Vec<MergeConflict>,
+}
+
+/// An entry which both versions of a data file changed, differently.
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    /// The entry in the common ancestor, if it has it.
+    pub base: Option<RegEntry>,
+    /// The entry in our version, unless we removed it.
+    pub ours: Option<RegEntry>,
+    /// The entry in their version, unless they removed it.
+    pub theirs: Option<RegEntry>,
+    /// Index of the entry among the entries of its subtest which have the
+    /// same key, or no key.
+    pub index: usize,
+}
+
+impl MergeConflict {
+    /// Name of the subtest which recorded the entry, if any.
+    pub fn section(&self) -> Option<&str> {
+        self.any().section()
+    }
+
+    /// The key of the entry, if any.
+    pub fn key(&self) -> Option<&str> {
+        self.any().key()
+    }
+
+    fn any(&self) -> &RegEntry {
+        self.ours
+            .as_ref()
+            .or(self.theirs.as_ref())
+            .or(self.base.as_ref())
+            .expect("conflicts have an entry on some side")
+    }
+}
+
+impl MergedFile {
+    /// The entries both versions changed differently. Our version of them is
+    /// kept in the merged file.
+    pub fn conflicts(&self) -> &[MergeConflict] {
+        &self.conflicts
+    }
+
+    /// The contents of the merged file, in the format of a data file named
+    /// `name` (JSON, or JSON Lines, compressed or not).
+    pub fn to_bytes<P: AsRef<Path>>(&self, name: P) -> std::io::Result<Vec<u8>> {
+        self.data.encode(name.as_ref())
+    }
+}
+
+/// Merges the changes made to the data file `base` by `ours` and by `theirs`.
+///
+/// Entries are identified the way a test matches them: keyed entries by their
+/// key, the others by their position, within their subtest. Each entry
+/// changed (or added, or removed) by one side only takes that side's version,
+/// and where an entry was recorded is ignored. Entries changed by both sides
+/// are [conflicts](MergedFile::conflicts). An empty `base` file stands for a
+/// file added by both sides.
+///
+/// Messages stored apart from the data file (see
+/// [`Layout::Directory`](crate::config::Layout::Directory)) aren't read: they
+/// are plain text files, merged as such. Neither are blobs (see
+/// [`Config::blob_bytes`](crate::Config::blob_bytes)), whose entries are
+/// compared by digest.
+pub fn merge_files<P: AsRef<Path>, Q: AsRef<Path>, R: AsRef<Path>>(
+    base: P,
+    ours: Q,
+    theirs: R,
+) -> std::io::Result<MergedFile> {
+    let base = read(base.as_ref())?;
+    let ours = read(ours.as_ref())?;
+    let theirs = read(theirs.as_ref())?;
+    Ok(merge(base, ours, theirs))
+}
+
+fn read(path: &Path) -> std::io::Result<DataFile> {
+    let data = std::fs::read(path)?;
+    if data.iter().all(u8::is_ascii_whitespace) {
+        return Ok(DataFile {
+            header: Header {
+                version: crate::FORMAT_VERSION,
+                crate_name: None,
+                test: None,
+                generated: None,
+                shape: None,
+                tags: Vec::new(),
+                checksum: None,
+                blobs: None,
+            },
+            entries: Vec::new(),
+            encrypted: false,
+        });
+    }
+    DataFile::read(data.as_slice(), path)
+}
+
+/// Identifies an entry across versions of a data file: its subtest, its key
+/// if any, and its index among the entries of the subtest with the same key.
+type Id = (Option<String>, Option<String>, usize);
+
+fn identified(entries: &[RegEntry]) -> Vec<(Id, &RegEntry)> {
+    let mut counts: HashMap<(Option<&str>, Option<&str>), usize> = HashMap::new();
+    entries
+        .iter()
+        .map(|entry| {
+            let count = counts
+                .entry((entry.section.as_deref(), entry.key.as_deref()))
+                .or_default();
+            *count += 1;
+            let id = (entry.section.clone(), entry.key.clone(), *count - 1);
+            (id, entry)
+        })
+        .collect()
+}
+
+/// Whether `a` and `b` hold the same entry, wherever it was recorded.
+fn same(a: Option<&RegEntry>, b: Option<&RegEntry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            a.reg_type == b.reg_type
+                && a.message == b.message
+                && a.note == b.note
+                && a.value_type == b.value_type
+                && a.file == b.file
+                && a.blob == b.blob
+        }
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// The version of something changed by at most one side, or `None` if both
+/// changed it differently.
+fn resolve<T: Copy>(base: T, ours: T, theirs: T, same: impl Fn(T, T) -> bool) -> Option<T> {
+    if same(ours, theirs) || same(base, theirs) {
+        Some(ours)
+    } else if same(base, ours) {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+fn merge(base: DataFile, ours: DataFile, theirs: DataFile) -> MergedFile {
+    let base_entries: HashMap<Id, &RegEntry> = identified(&base.entries).into_iter().collect();
+    let our_entries = identified(&ours.entries);
+    let their_entries = identified(&theirs.entries);
+    let theirs_by_id: HashMap<&Id, &RegEntry> =
+        their_entries.iter().map(|(id, e)| (id, *e)).collect();
+    let ours_by_id: HashMap<&Id, &RegEntry> = our_entries.iter().map(|(id, e)| (id, *e)).collect();
+
+    let mut conflicts = Vec::new();
+    let mut conflict = |id: &Id, ours: Option<&RegEntry>, theirs: Option<&RegEntry>| {
+        conflicts.push(MergeConflict {
+            base: base_entries.get(id).map(|&e| e.clone()),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+            index: id.2,
+        });
+    };
+
+    // Our entries, in our order
+    let mut merged: Vec<(&Id, RegEntry)> = Vec::new();
+    for (id, entry) in &our_entries {
+        let base = base_entries.get(id).copied();
+        let theirs = theirs_by_id.get(id).copied();
+        match resolve(base, Some(*entry), theirs, same) {
+            Some(Some(resolved)) => merged.push((id, resolved.clone())),
+            Some(None) => {}
+            None => {
+                conflict(id, Some(entry), theirs);
+                merged.push((id, (*entry).clone()));
+            }
+        }
+    }
+
+    // Their other entries, after the entry they follow in their order
+    let mut after = None;
+    for (id, entry) in &their_entries {
+        if let Some(position) = merged.iter().position(|(m, _)| *m == id) {
+            after = Some(position);
+            continue;
+        }
+        if ours_by_id.contains_key(id) {
+            continue;
+        }
+        let base = base_entries.get(id).copied();
+        match resolve(base, None, Some(*entry), same) {
+            Some(Some(resolved)) => {
+                let position = after.map_or(0, |after| after + 1);
+                merged.insert(position, (id, resolved.clone()));
+                after = Some(position);
+            }
+            Some(None) => {}
+            None => conflict(id, None, Some(entry)),
+        }
+    }
+
+    let generated = ours.header.generated.clone().max(theirs.header.generated);
+    let shape = resolve(
+        &base.header.shape,
+        &ours.header.shape,
+        &theirs.header.shape,
+        |a, b| a == b,
+    )
+    .unwrap_or(&ours.header.shape)
+    .clone();
+    let tags = resolve(
+        &base.header.tags,
+        &ours.header.tags,
+        &theirs.header.tags,
+        |a, b| a == b,
+    )
+    .unwrap_or(&ours.header.tags)
+    .clone();
+    let sealed = ours.header.checksum.is_some() || theirs.header.checksum.is_some();
+    let encrypted = ours.encrypted || theirs.encrypted;
+    let entries = merged.into_iter().map(|(_, entry)| entry).collect();
+
+    let mut data = DataFile {
+        header: Header {
+            generated,
+            shape,
+            tags,
+            checksum: None,
+            blobs: ours.header.blobs.clone().or(theirs.header.blobs),
+            ..ours.header
+        },
+        entries,
+        encrypted,
+    };
+    // The merge driver writes the file as the tests would have
+    if sealed {
+        data.seal();
+    }
+    MergedFile { data, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegType;
+
+    fn file(entries: &[(&str, Option<&str>)]) -> DataFile {
+        DataFile {
+            header: Header {
+                version: crate::FORMAT_VERSION,
+                crate_name: None,
+                test: None,
+                generated: None,
+                shape: None,
+                tags: Vec::new(),
+                checksum: None,
+                blobs: None,
+            },
+            entries: entries
+                .iter()
+                .map(|&(message, key)| RegEntry {
+                    reg_type: RegType::Display,
+                    message: message.to_string(),
+                    section: None,
+                    key: key.map(str::to_string),
+                    location: None,
+                    note: None,
+                    value_type: None,
+                    file: None,
+                    blob: None,
+                })
+                .collect(),
+            encrypted: false,
+        }
+    }
+
+    fn messages(merged: &MergedFile) -> Vec<&str> {
+        merged.data.entries.iter().map(|e| e.message()).collect()
+    }
+
+    #[test]
+    fn merges_changes_to_different_entries() {
+        let base = file(&[("a", None), ("k1", Some("1")), ("b", None)]);
+        // We change `a` and add a keyed entry, they change `b`, remove `k1`
+        // and add another keyed entry
+        let ours = file(&[
+            ("a2", None),
+            ("k1", Some("1")),
+            ("k2", Some("2")),
+            ("b", None),
+        ]);
+        let theirs = file(&[("a", None), ("b2", None), ("k3", Some("3"))]);
+        let merged = merge(base, ours, theirs);
+        assert!(merged.conflicts().is_empty());
+        assert_eq!(messages(&merged), ["a2", "k2", "b2", "k3"]);
+
+        // Both change `b`
+        let base = file(&[("a", None), ("b", None)]);
+        let ours = file(&[("a", None), ("b2", None)]);
+        let theirs = file(&[("a", None), ("b3", None)]);
+        let merged = merge(base, ours, theirs);
+        assert_eq!(messages(&merged), ["a", "b2"]);
+        let conflict = &merged.conflicts()[0];
+        assert_eq!(
+            (conflict.index, conflict.key(), conflict.section()),
+            (1, None, None)
+        );
+        assert_eq!(conflict.theirs.as_ref().unwrap().message(), "b3");
+    }
+}
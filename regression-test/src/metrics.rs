@@ -0,0 +1,249 @@
+//! Prometheus metrics about the regression data of a run, for tracking test
+//! health centrally.
+//!
+//! When a metrics file (see [`Config::metrics`]) or a Pushgateway (see
+//! [`Config::pushgateway`]) is configured, the counts of every
+//! [`RegTest`](crate::RegTest) are summed up process-wide. They are written
+//! (or pushed) again as each test finishes, so they are complete once the
+//! binary exits. Samples are labelled with the test binary; the metrics file
+//! keeps the samples of other binaries, so that it can be shared by all of
+//! them and collected by the node exporter's textfile collector. Under
+//! nextest, which runs every test in a process of its own, the counts of each
+//! test are added to those the file already holds for its binary.
+//!
+//! [`Config::metrics`]: crate::Config::metrics
+//! [`Config::pushgateway`]: crate::Config::pushgateway
+
+use crate::{http, nextest, registry};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Timeout of each network operation when pushing metrics, short as the
+/// tests wait for it.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Counts of one test, or summed over the tests of this process.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Counts {
+    pub(crate) tests: u64,
+    /// Entries compared against a baseline.
+    pub(crate) compared: u64,
+    /// Entries recorded into a new baseline.
+    pub(crate) recorded: u64,
+    /// Tests which failed because of their regression data.
+    pub(crate) mismatched: u64,
+    /// Entries written to pending files, waiting for review.
+    pub(crate) pending: u64,
+    /// Size of the data files used, in bytes.
+    pub(crate) bytes: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.tests += other.tests;
+        self.compared += other.compared;
+        self.recorded += other.recorded;
+        self.mismatched += other.mismatched;
+        self.pending += other.pending;
+        self.bytes += other.bytes;
+    }
+
+    /// The metrics, with their type and help text.
+    fn metrics(&self) -> [(&'static str, &'static str, &'static str, u64); 6] {
+        [
+            (
+                "regtest_tests_total",
+                "counter",
+                "Regression tests run.",
+                self.tests,
+            ),
+            (
+                "regtest_entries_compared_total",
+                "counter",
+                "Entries compared against a baseline.",
+                self.compared,
+            ),
+            (
+                "regtest_entries_recorded_total",
+                "counter",
+                "Entries recorded into a new baseline.",
+                self.recorded,
+            ),
+            (
+                "regtest_tests_mismatched_total",
+                "counter",
+                "Tests which failed because of their regression data.",
+                self.mismatched,
+            ),
+            (
+                "regtest_entries_pending_total",
+                "counter",
+                "Entries written to pending files, waiting for review.",
+                self.pending,
+            ),
+            (
+                "regtest_data_bytes",
+                "gauge",
+                "Total size of the regression data files used, in bytes.",
+                self.bytes,
+            ),
+        ]
+    }
+}
+
+/// Adds the counts of a finished test to the totals of this process, and
+/// exports them to the metrics file and/or the Pushgateway.
+pub(crate) fn record(
+    file: Option<&Path>,
+    pushgateway: Option<&str>,
+    counts: Counts,
+) -> std::io::Result<()> {
+    static TOTALS: OnceLock<Mutex<Counts>> = OnceLock::new();
+
+    let mut totals = TOTALS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    totals.add(counts);
+
+    let binary = registry::binary_name();
+    let mut written = *totals;
+    if let Some(file) = file {
+        let per_test = nextest::process_per_test();
+        nextest::rewrite(file, |existing| {
+            if per_test {
+                // The other tests of this binary ran in other processes
+                written = own_counts(&binary, existing);
+                written.add(counts);
+            }
+            render(&binary, &written, existing)
+        })?;
+    }
+
+    if let Some(url) = pushgateway {
+        push(url, &binary, &render_group(&written))?;
+    }
+    Ok(())
+}
+
+/// Renders the whole metrics file: the samples of `existing` for binaries
+/// other than `binary`, and the `totals` of this one.
+fn render(binary: &str, totals: &Counts, existing: &str) -> String {
+    let own_label = format!("{{binary=\"{}\"}}", escape_label(binary));
+    let mut out = String::new();
+
+    for (name, kind, help, value) in totals.metrics() {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+
+        let prefix = format!("{}{{", name);
+        let mut samples: Vec<String> = existing
+            .lines()
+            .filter(|line| line.starts_with(&prefix))
+            .filter(|line| !line.starts_with(&format!("{}{}", name, own_label)))
+            .map(str::to_string)
+            .collect();
+        samples.push(format!("{}{} {}", name, own_label, value));
+        samples.sort();
+
+        for sample in samples {
+            out.push_str(&sample);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The counts of `existing` for the binary `binary`.
+fn own_counts(binary: &str, existing: &str) -> Counts {
+    let own_label = format!("{{binary=\"{}\"}}", escape_label(binary));
+    let value = |name: &str| {
+        let prefix = format!("{}{} ", name, own_label);
+        existing
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix)?.parse().ok())
+            .unwrap_or(0)
+    };
+    Counts {
+        tests: value("regtest_tests_total"),
+        compared: value("regtest_entries_compared_total"),
+        recorded: value("regtest_entries_recorded_total"),
+        mismatched: value("regtest_tests_mismatched_total"),
+        pending: value("regtest_entries_pending_total"),
+        bytes: value("regtest_data_bytes"),
+    }
+}
+
+/// Renders the `totals` of this binary for the Pushgateway, where the binary
+/// is part of the grouping key rather than a label.
+fn render_group(totals: &Counts) -> String {
+    let mut out = String::new();
+    for (name, kind, help, value) in totals.metrics() {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Replaces the metrics of the group `job="regtest", binary=<binary>` on the
+/// Pushgateway at `url`.
+fn push(url: &str, binary: &str, body: &str) -> std::io::Result<()> {
+    let url = format!(
+        "{}/metrics/job/regtest/binary/{}",
+        url.trim_end_matches('/'),
+        binary
+    );
+    let response = http::request(
+        "PUT",
+        &url,
+        "text/plain; version=0.0.4",
+        body.as_bytes(),
+        PUSH_TIMEOUT,
+    )?;
+    if !response.is_success() {
+        return Err(std::io::Error::other(format!(
+            "Pushgateway rejected the metrics with status {}: {}",
+            response.status,
+            String::from_utf8_lossy(&response.body).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_own_samples_only() {
+        let counts = Counts {
+            tests: 2,
+            compared: 5,
+            ..Counts::default()
+        };
+
+        let other = render("other", &counts, "");
+        let mine = render("mine", &counts, &render("mine", &Counts::default(), &other));
+
+        assert_eq!(
+            mine.matches("# TYPE regtest_tests_total counter").count(),
+            1
+        );
+        assert!(mine.contains(
+            "regtest_entries_compared_total{binary=\"mine\"} 5\nregtest_entries_compared_total{binary=\"other\"} 5\n"
+        ));
+        assert_eq!(mine.matches("{binary=\"mine\"}").count(), 6);
+        assert_eq!(own_counts("mine", &mine), counts);
+        assert_eq!(own_counts("absent", &mine), Counts::default());
+    }
+}
@@ -0,0 +1,88 @@
+//! Support for running under [cargo-nextest](https://nexte.st).
+//!
+//! nextest runs every test in a process of its own, possibly many at once.
+//! State which libtest lets us collect process-wide, such as the JUnit file,
+//! the metrics, the HTML report and the summary, is then merged with what
+//! other processes already wrote, under a lock held across the read and the
+//! write.
+
+use std::fs::File;
+use std::path::Path;
+
+/// Whether the tests run under nextest, which sets `NEXTEST=1`.
+pub(crate) fn is_running() -> bool {
+    std::env::var_os("NEXTEST").is_some_and(|value| value == "1")
+}
+
+/// Whether every test runs in a process of its own, as nextest does by
+/// default.
+pub(crate) fn process_per_test() -> bool {
+    std::env::var_os("NEXTEST_EXECUTION_MODE").is_some_and(|mode| mode == "process-per-test")
+}
+
+/// Identifier of the nextest run, shared by all its processes.
+#[cfg(feature = "summary")]
+pub(crate) fn run_id() -> Option<String> {
+    std::env::var("NEXTEST_RUN_ID")
+        .ok()
+        .filter(|id| !id.is_empty())
+}
+
+/// Name of the test the process was started for, as set by nextest.
+pub(crate) fn test_name() -> Option<String> {
+    std::env::var("NEXTEST_TEST_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Replaces the file at `path` with `render` of its current contents (empty
+/// if it doesn't exist yet), keeping other processes from doing the same
+/// meanwhile.
+pub(crate) fn rewrite(path: &Path, render: impl FnOnce(&str) -> String) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // The file itself is replaced, so the lock is taken on a sibling
+    let lock = File::create(path.with_extension("lock"))?;
+    lock.lock()?;
+
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    let text = render(&existing);
+
+    // Replace the file atomically, so readers never see a partial file
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&tmp, text)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_rewrites_are_serialized() {
+        let dir = std::env::temp_dir().join(format!("regtest-nextest-{}", std::process::id()));
+        let path = dir.join("counter.txt");
+        let _ = std::fs::remove_file(&path);
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    rewrite(&path, |existing| {
+                        let count: u32 = existing.parse().unwrap_or(0);
+                        (count + 1).to_string()
+                    })
+                    .unwrap()
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "8");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,237 @@
+//! Normalizers applied to both the baseline and the recorded text before
+//! they are compared, so that differences which don't matter (line endings,
+//! trailing whitespace, Unicode composition) don't fail tests.
+//!
+//! The built-in normalizations are enabled in `regtest.toml`:
+//!
+//! ```toml
+//! normalize = ["line_endings", "trailing_whitespace"]
+//! ```
+//!
+//! and any other normalizer can be added to a test:
+//!
+//! ```rust
+//! use regression_test::RegTest;
+//!
+//! # let path = std::env::temp_dir().join("regtest-normalize-doc.json");
+//! let mut rt = RegTest::new(path).unwrap();
+//! rt.normalize_with(|text: &str| text.to_lowercase());
+//! rt.regtest("Hello");
+//! ```
+//!
+//! Unlike [redactions](crate::config::Redaction), normalizers don't change
+//! what is stored: the baseline keeps the output as recorded.
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::panic::RefUnwindSafe;
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::{IsNormalized, is_nfc_quick};
+
+/// Rewrites text before it is compared.
+///
+/// Implemented for closures taking the text and returning it normalized.
+/// Normalizers are unwind safe, so that tests can still be run within
+/// `catch_unwind`.
+pub trait Normalizer: Send + Sync + RefUnwindSafe {
+    /// `text`, normalized.
+    fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str>;
+}
+
+impl<F: Fn(&str) -> String + Send + Sync + RefUnwindSafe> Normalizer for F {
+    fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        Cow::Owned(self(text))
+    }
+}
+
+/// The built-in normalizations.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Turns CRLF line endings into LF.
+    LineEndings,
+    /// Removes the whitespace at the end of each line.
+    TrailingWhitespace,
+    /// Collapses each run of spaces and tabs into a single space.
+    CollapseSpaces,
+    /// Applies Unicode normalization form C, so that composed and decomposed
+    /// characters (`é` and `e` + `◌́`) compare equal.
+    Nfc,
+    /// Writes numbers formatted for any locale without thousands separators
+    /// and with a `.` as decimal separator, so that `1,234.5`, `1.234,5`,
+    /// `1 234,5` (with a non-breaking space) and `1'234.5` compare equal.
+    ///
+    /// A single separator followed by exactly three digits is taken for a
+    /// thousands separator, as in `1,234` and `1.234`, which both become
+    /// `1234`. Sequences which aren't numbers in any locale, such as versions
+    /// (`1.2.3`), are left alone.
+    Numbers,
+    /// Replaces the German, French, Spanish, Italian, Portuguese and Dutch
+    /// names of months (`März`, `décembre`, ...) with the English ones.
+    /// Abbreviations are left alone, as many are English words.
+    MonthNames,
+}
+
+impl Normalizer for Normalization {
+    fn normalize<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Normalization::LineEndings if text.contains("\r\n") => {
+                Cow::Owned(text.replace("\r\n", "\n"))
+            }
+            Normalization::TrailingWhitespace
+                if text.split('\n').any(|line| line.trim_end() != line) =>
+            {
+                let lines: Vec<&str> = text.split('\n').map(str::trim_end).collect();
+                Cow::Owned(lines.join("\n"))
+            }
+            Normalization::CollapseSpaces if text.contains("  ") || text.contains('\t') => {
+                let mut collapsed = String::with_capacity(text.len());
+                let mut blank = false;
+                for c in text.chars() {
+                    let space = c == ' ' || c == '\t';
+                    if !(space && blank) {
+                        collapsed.push(if space { ' ' } else { c });
+                    }
+                    blank = space;
+                }
+                Cow::Owned(collapsed)
+            }
+            Normalization::Nfc if is_nfc_quick(text.chars()) != IsNormalized::Yes => {
+                Cow::Owned(text.nfc().collect())
+            }
+            Normalization::Numbers => {
+                static NUMBER: OnceLock<Regex> = OnceLock::new();
+                let number = NUMBER.get_or_init(|| {
+                    Regex::new("[0-9]+(?:[.,'\u{2019}\u{a0}\u{202f}][0-9]+)+").expect("valid")
+                });
+                number.replace_all(text, |c: &Captures| {
+                    canonical_number(&c[0]).unwrap_or_else(|| c[0].to_string())
+                })
+            }
+            Normalization::MonthNames => {
+                static MONTH: OnceLock<Regex> = OnceLock::new();
+                let month = MONTH.get_or_init(|| {
+                    let names: Vec<&str> = MONTHS
+                        .iter()
+                        .flat_map(|(_, names)| *names)
+                        .copied()
+                        .collect();
+                    Regex::new(&format!(r"(?i)\b(?:{})\b", names.join("|"))).expect("valid")
+                });
+                month.replace_all(text, |c: &Captures| {
+                    let name = c[0].to_lowercase();
+                    MONTHS
+                        .iter()
+                        .find(|(_, names)| names.contains(&name.as_str()))
+                        .map_or(name, |(english, _)| english.to_string())
+                })
+            }
+            _ => Cow::Borrowed(text),
+        }
+    }
+}
+
+/// The names of the months in English, and in the other languages.
+const MONTHS: &[(&str, &[&str])] = &[
+    (
+        "January",
+        &[
+            "januar", "janvier", "enero", "gennaio", "janeiro", "januari",
+        ],
+    ),
+    (
+        "February",
+        &[
+            "februar",
+            "février",
+            "febrero",
+            "febbraio",
+            "fevereiro",
+            "februari",
+        ],
+    ),
+    ("March", &["märz", "mars", "marzo", "março", "maart"]),
+    ("April", &["avril", "abril", "aprile"]),
+    ("May", &["mai", "mayo", "maggio", "maio", "mei"]),
+    ("June", &["juni", "juin", "junio", "giugno", "junho"]),
+    ("July", &["juli", "juillet", "julio", "luglio", "julho"]),
+    ("August", &["août", "agosto", "augustus"]),
+    (
+        "September",
+        &["septembre", "septiembre", "settembre", "setembro"],
+    ),
+    (
+        "October",
+        &["oktober", "octobre", "octubre", "ottobre", "outubro"],
+    ),
+    ("November", &["novembre", "noviembre", "novembro"]),
+    (
+        "December",
+        &["dezember", "décembre", "diciembre", "dicembre", "dezembro"],
+    ),
+];
+
+/// `number` without thousands separators and with a `.` as decimal
+/// separator, if it is a number formatted for some locale: digit groups
+/// separated by thousands separators (all the same, and each group of three
+/// digits but the first of up to three), followed by an optional decimal
+/// separator (`.` or `,`) and fraction.
+fn canonical_number(number: &str) -> Option<String> {
+    let groups: Vec<&str> = number.split(|c: char| !c.is_ascii_digit()).collect();
+    let separators: Vec<char> = number.chars().filter(|c| !c.is_ascii_digit()).collect();
+    let count = separators.len();
+    let thousands = |count: usize| {
+        groups[0].len() <= 3
+            && groups[1..=count].iter().all(|group| group.len() == 3)
+            && separators[..count].iter().all(|&c| c == separators[0])
+    };
+
+    if thousands(count) {
+        // 1,234 or 1.234.567
+        return Some(groups.concat());
+    }
+    let decimal = matches!(separators[count - 1], '.' | ',')
+        && (count == 1 || separators[0] != separators[count - 1] && thousands(count - 1));
+    // 3,14, or 1,234.5 or 1 234,5
+    decimal.then(|| format!("{}.{}", groups[..count].concat(), groups[count]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn built_in_normalizations() {
+        use Normalization::*;
+        assert_eq!(LineEndings.normalize("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(TrailingWhitespace.normalize("a \t\nb  \r\nc "), "a\nb\nc");
+        assert_eq!(CollapseSpaces.normalize("a  b\t\tc \n d"), "a b c \n d");
+        assert_eq!(Nfc.normalize("e\u{301}"), "\u{e9}");
+        assert!(matches!(Nfc.normalize("plain"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn locale_normalizations() {
+        use Normalization::*;
+        for formatted in [
+            "1,234,567.5",
+            "1.234.567,5",
+            "1\u{a0}234\u{a0}567,5",
+            "1'234'567.5",
+        ] {
+            assert_eq!(Numbers.normalize(formatted), "1234567.5");
+        }
+        assert_eq!(Numbers.normalize("3,14 and 3.14"), "3.14 and 3.14");
+        assert_eq!(Numbers.normalize("1234,567"), "1234.567");
+        assert_eq!(Numbers.normalize("1,234 and 1.234"), "1234 and 1234");
+        assert_eq!(Numbers.normalize("v1.2.3, 10.0.0.1"), "v1.2.3, 10.0.0.1");
+        assert_eq!(Numbers.normalize("12'5 and 1'23"), "12'5 and 1'23");
+
+        assert_eq!(
+            MonthNames.normalize("5. März 2024, 5 décembre, 5 MAYO, Mainz"),
+            "5. March 2024, 5 December, 5 May, Mainz"
+        );
+    }
+}
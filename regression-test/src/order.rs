@@ -0,0 +1,208 @@
+//! Run-order manifests, for suites whose tests share global state affecting
+//! their output.
+//!
+//! When enabled (see [`Config::run_order`]), the order in which the tests of a
+//! binary start is recorded next to the baselines, in
+//! `<data dir>/run-order/<binary>.json`, when there is no manifest yet or
+//! when the baselines are regenerated (see [`UpdatePolicy`]). Later runs
+//! compare against it: with [`RunOrder::Warn`] a test starting out
+//! of order is reported, and with [`RunOrder::Enforce`] each test also waits
+//! until the tests recorded before it have finished.
+//!
+//! Tests recorded in the manifest but not part of the current run (e.g.
+//! because of a filter) can't be told apart from tests which didn't start yet,
+//! so they are only waited for until no test has started or finished for
+//! [`IDLE_TIMEOUT`].
+//!
+//! [`Config::run_order`]: crate::Config::run_order
+
+use crate::config::{Config, RUN_ORDER_DIR_NAME, RunOrder, UpdatePolicy};
+use crate::registry;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+/// How long a test waits for a recorded predecessor which hasn't started,
+/// while nothing else happens.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(2);
+
+struct State {
+    path: PathBuf,
+    /// The recorded order, if any.
+    manifest: Option<Vec<String>>,
+    /// Whether this run records the order, replacing the manifest.
+    recording: bool,
+    /// Tests in the order they started in this run.
+    started: Vec<String>,
+    /// Number of open `RegTest`s of each running test.
+    running: HashMap<String, usize>,
+    finished: HashSet<String>,
+    /// Recorded tests given up on, as they don't seem to be part of this run.
+    skipped: HashSet<String>,
+    /// Incremented whenever a test starts or finishes.
+    generation: u64,
+}
+
+fn state(config: &Config) -> &'static (Mutex<State>, Condvar) {
+    static STATE: OnceLock<(Mutex<State>, Condvar)> = OnceLock::new();
+    STATE.get_or_init(|| {
+        let crate_root = std::env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .or_else(|| std::env::current_dir().ok())
+            .unwrap_or_default();
+        let path = config
+            .data_dir(crate_root)
+            .join(RUN_ORDER_DIR_NAME)
+            .join(format!("{}.json", registry::binary_name()));
+
+        let manifest: Option<Vec<String>> = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok());
+        let recording = match config.update {
+            UpdatePolicy::Always => true,
+            UpdatePolicy::Missing | UpdatePolicy::Mismatched | UpdatePolicy::Repair => {
+                manifest.is_none()
+            }
+            UpdatePolicy::Never => false,
+        };
+
+        let state = State {
+            path,
+            manifest,
+            recording,
+            started: Vec::new(),
+            running: HashMap::new(),
+            finished: HashSet::new(),
+            skipped: HashSet::new(),
+            generation: 0,
+        };
+        (Mutex::new(state), Condvar::new())
+    })
+}
+
+fn lock(mutex: &Mutex<State>) -> MutexGuard<'_, State> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Called when the current test opens a `RegTest`.
+pub(crate) fn start(config: &Config) {
+    if config.run_order == RunOrder::Off {
+        return;
+    }
+
+    let test = registry::current_test_name();
+    let (mutex, condvar) = state(config);
+    let mut state = lock(mutex);
+
+    if let Some(count) = state.running.get_mut(&test) {
+        *count += 1;
+        return;
+    }
+
+    if state.recording {
+        // The first open of each test determines its position
+        if !state.started.contains(&test) {
+            state.started.push(test.clone());
+            if let Err(e) = write_manifest(&state) {
+                eprintln!(
+                    "warning: failed to write run-order manifest {}: {}",
+                    state.path.display(),
+                    e
+                );
+            }
+        }
+        state.running.insert(test, 1);
+        state.generation += 1;
+        condvar.notify_all();
+        return;
+    }
+
+    let manifest = state.manifest.clone().unwrap_or_default();
+    if let Some(index) = manifest.iter().position(|t| *t == test)
+        && !state.started.contains(&test)
+    {
+        let predecessors = &manifest[..index];
+
+        if config.run_order == RunOrder::Enforce {
+            loop {
+                let pending: Vec<&String> = predecessors
+                    .iter()
+                    .filter(|t| !state.finished.contains(*t) && !state.skipped.contains(*t))
+                    .collect();
+                if pending.is_empty() {
+                    break;
+                }
+
+                let any_running = pending.iter().any(|t| state.running.contains_key(*t));
+                let generation = state.generation;
+                let (guard, timeout) = condvar
+                    .wait_timeout(state, IDLE_TIMEOUT)
+                    .unwrap_or_else(|e| e.into_inner());
+                state = guard;
+
+                if timeout.timed_out() && !any_running && state.generation == generation {
+                    let not_started: Vec<String> = predecessors
+                        .iter()
+                        .filter(|t| !state.started.contains(t))
+                        .cloned()
+                        .collect();
+                    state.skipped.extend(not_started);
+                }
+            }
+        }
+
+        // Any test started so far which was recorded after this one is out
+        // of order
+        let late: Vec<&String> = manifest[index + 1..]
+            .iter()
+            .filter(|t| state.started.contains(t))
+            .collect();
+        if let Some(first) = late.first() {
+            let _ = writeln!(
+                std::io::stderr(),
+                "warning: test `{}` started after `{}`, unlike the order recorded in {}. \
+                 Its output may depend on state left by other tests.",
+                test,
+                first,
+                state.path.display()
+            );
+        }
+    }
+
+    state.started.push(test.clone());
+    state.running.insert(test, 1);
+    state.generation += 1;
+    condvar.notify_all();
+}
+
+/// Called when the current test drops a `RegTest`.
+pub(crate) fn finish(config: &Config) {
+    if config.run_order == RunOrder::Off {
+        return;
+    }
+
+    let test = registry::current_test_name();
+    let (mutex, condvar) = state(config);
+    let mut state = lock(mutex);
+
+    if let Some(count) = state.running.get_mut(&test) {
+        *count -= 1;
+        if *count == 0 {
+            state.running.remove(&test);
+            state.finished.insert(test);
+            state.generation += 1;
+            condvar.notify_all();
+        }
+    }
+}
+
+fn write_manifest(state: &State) -> std::io::Result<()> {
+    if let Some(parent) = state.path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut text = serde_json::to_string_pretty(&state.started)?;
+    text.push('\n');
+    std::fs::write(&state.path, text)
+}
@@ -0,0 +1,807 @@
+//! Computation of the regression data file used by a test.
+
+use crate::config::{Config, Placement, SNAPSHOTS_DIR_NAME};
+use std::path::{Component, Path, PathBuf};
+
+/// Computes the regression data file for the test `test_name` defined in
+/// `source_file`, using the [current](Config::current) configuration.
+///
+/// Data is laid out under the configured root (by default `regtest_data/`
+/// next to `src/` and `tests/`), mirroring the source tree:
+///
+/// - `src/my_module/mod.rs` → `regtest_data/src/my_module/mod/<test_name>.json`
+/// - `tests/integration.rs` → `regtest_data/tests/integration/<test_name>.json`
+/// - `examples/demo.rs` → `regtest_data/examples/demo/<test_name>.json`, and
+///   likewise for the other [target directories](Config::target_dirs)
+///
+/// unless configured otherwise, see [`Placement`].
+///
+/// This is what the `#[regtest]` macro uses, but it can be called directly
+/// when constructing a [`RegTest`](crate::RegTest) by hand.
+pub fn snapshot_path<P: AsRef<Path>>(source_file: P, test_name: &str) -> PathBuf {
+    Config::current().snapshot_path(source_file, test_name)
+}
+
+/// The source file of a `#[regtest]`, which was at `compiled` when it was
+/// compiled, at `relative` in the crate.
+///
+/// When the tests don't run where they were built, e.g. from a nextest archive
+/// extracted elsewhere, the file is looked up relative to the crate directory
+/// cargo or nextest set at runtime instead.
+#[doc(hidden)]
+pub fn __source_file(compiled: &str, relative: &str) -> PathBuf {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from);
+    normalize_path(relocate(
+        Path::new(compiled),
+        relative,
+        manifest_dir.as_deref(),
+    ))
+}
+
+/// `path` as the platform usually writes it, so that the data files computed
+/// from it are laid out alike everywhere: on Windows, without the `\\?\`
+/// prefix of the extended-length paths returned by
+/// [`canonicalize`](std::fs::canonicalize) (`\\?\C:\ws` becomes `C:\ws`),
+/// and with `\` as the only separator, since extended-length paths don't
+/// treat `/` as one. Other platforms keep their paths as they are.
+pub fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    match path.to_str() {
+        Some(text) if cfg!(windows) => PathBuf::from(normalize_windows_path(text)),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `path` made absolute, against the current directory, with its `.` and `..`
+/// components removed lexically instead of by resolving symbolic links like
+/// [`canonicalize`](std::fs::canonicalize) does, and then
+/// [normalized](normalize_path). Projects checked out through symbolic links,
+/// as with Nix or Bazel, thus keep the paths they are used with, and their
+/// data files stay in the tree the tests were given.
+pub fn logical_path<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf> {
+    let mut logical = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                logical.pop();
+            }
+            component => logical.push(component),
+        }
+    }
+    Ok(normalize_path(logical))
+}
+
+fn normalize_windows_path(path: &str) -> String {
+    let path = if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else {
+        // Only drive paths can do without the prefix, e.g. not `\\?\Volume{..}`
+        match path.strip_prefix(r"\\?\") {
+            Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest.to_string(),
+            _ => path.to_string(),
+        }
+    };
+    path.replace('/', "\\")
+}
+
+fn relocate(compiled: &Path, relative: &str, manifest_dir: Option<&Path>) -> PathBuf {
+    match manifest_dir {
+        Some(dir) if !relative.is_empty() && !compiled.exists() => dir.join(relative),
+        _ => compiled.to_path_buf(),
+    }
+}
+
+/// The regression data file of a [`regtest!`](crate::regtest) call in `file`
+/// (as given by `file!()`) of the crate at `manifest_dir`, in the module
+/// `module_path`, by the test `name` or else the test running.
+#[doc(hidden)]
+pub fn __regtest_path(
+    manifest_dir: &str,
+    file: &str,
+    module_path: &str,
+    name: Option<&str>,
+) -> PathBuf {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => {
+            let test = crate::registry::current_test_name();
+            if test == "main" || test == "<unnamed>" {
+                panic!(
+                    "regtest!() can't tell which test is running on the main thread; \
+                     name it with regtest!(\"test_name\")"
+                );
+            }
+            data_file_name(&test, module_path)
+        }
+    };
+    snapshot_path(caller_file(Path::new(manifest_dir), file), &name)
+}
+
+/// Names the data file of the test `test` after the test function, unless
+/// the test isn't in `module_path`, e.g. when `regtest!` is called by a
+/// helper shared by several modules: its module is then part of the name, as
+/// in `parser__tests__parses`.
+fn data_file_name(test: &str, module_path: &str) -> String {
+    let (module, function) = test.rsplit_once("::").unwrap_or(("", test));
+    // Test names leave out the crate, unless given by the harness
+    let local_module = module_path.split_once("::").map_or("", |(_, path)| path);
+    if module == module_path || module == local_module {
+        function.to_string()
+    } else {
+        test.replace("::", "__")
+    }
+}
+
+/// The regression data file of the test `test` (as named by libtest), run from
+/// the test binary `binary` of the crate at `manifest_dir`, see
+/// [`RegTest::for_current_test`](crate::RegTest::for_current_test).
+///
+/// The source file of the test isn't known, so the file is laid out after the
+/// test's module path, as if each module had a file of its own: the data of
+/// `parser::tests::parses` is that of a test `parses` in `src/parser/tests.rs`,
+/// or in `tests/<binary>/parser/tests.rs` for an integration test. Top-level
+/// tests use `src/lib.rs` (or `src/main.rs`), or `tests/<binary>.rs`.
+pub(crate) fn current_test_path(
+    config: &Config,
+    manifest_dir: &Path,
+    binary: &str,
+    test: &str,
+) -> PathBuf {
+    let mut modules: Vec<&str> = test.split("::").collect();
+    let function = modules.pop().unwrap_or(test);
+    let integration = manifest_dir.join("tests").join(format!("{}.rs", binary));
+    let integration_dir = manifest_dir.join("tests").join(binary);
+    let is_integration = integration.exists() || integration_dir.join("main.rs").exists();
+    // The harness names tests after their full path, crate included
+    if !is_integration && modules.first() == Some(&binary) {
+        modules.remove(0);
+    }
+
+    let source_file = match modules.split_last() {
+        None if is_integration => integration,
+        None if manifest_dir.join("src/lib.rs").exists() => manifest_dir.join("src/lib.rs"),
+        None => manifest_dir.join("src/main.rs"),
+        Some((last, parents)) => {
+            let mut file = if is_integration {
+                integration_dir
+            } else {
+                manifest_dir.join("src")
+            };
+            file.extend(parents);
+            file.push(format!("{}.rs", last));
+            file
+        }
+    };
+    config.snapshot_path(source_file, function)
+}
+
+/// The source file at `file`, as given by `file!()`, which is relative to the
+/// workspace rather than to the crate at `manifest_dir`.
+fn caller_file(manifest_dir: &Path, file: &str) -> PathBuf {
+    let file = Path::new(file);
+    if file.is_absolute() {
+        return file.to_path_buf();
+    }
+    // As in `__source_file`, for tests run where they weren't built
+    let manifest_dir = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) if !manifest_dir.exists() => PathBuf::from(dir),
+        _ => manifest_dir.to_path_buf(),
+    };
+    let components: Vec<_> = file.components().collect();
+    (0..components.len())
+        .map(|skipped| manifest_dir.join(components[skipped..].iter().collect::<PathBuf>()))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| manifest_dir.join(file))
+}
+
+pub(crate) fn snapshot_path_with(config: &Config, source_file: &Path, test_name: &str) -> PathBuf {
+    let source_file = &normalize_path(source_file);
+    // The target directory ("src", "tests", ...) tells us both the kind of
+    // target and where the crate root is. Modules may be named like target
+    // directories (`src/tests/`), so the one next to the manifest wins.
+    let kind_dirs: Vec<_> = source_file
+        .ancestors()
+        .skip(1)
+        .filter_map(|dir| {
+            let name = dir.file_name()?.to_str()?;
+            Some((dir, config.target_dirs.get(name)?))
+        })
+        .collect();
+    let kind_dir = kind_dirs
+        .iter()
+        .find(|(dir, _)| dir.with_file_name("Cargo.toml").is_file())
+        .or(kind_dirs.first());
+
+    let (crate_root, kind, relative) = match kind_dir {
+        Some((dir, kind)) => (
+            dir.parent().unwrap_or_else(|| Path::new("")),
+            kind.as_path(),
+            source_file.strip_prefix(dir).unwrap_or(source_file),
+        ),
+        None => (
+            source_file.parent().unwrap_or_else(|| Path::new("")),
+            Path::new("src"),
+            Path::new(source_file.file_name().unwrap_or_default()),
+        ),
+    };
+
+    let name = format!(
+        "{}.{}{}",
+        test_name,
+        config.format.extension(),
+        config.compression.extension()
+    );
+    let file_stem = relative.file_stem().unwrap_or_default();
+
+    match config.placement {
+        Placement::Mirrored => {
+            let mut path = data_dir(config, crate_root);
+            path.push(kind);
+            if let Some(parent) = relative.parent() {
+                path.push(parent);
+            }
+            // The file stem becomes a directory holding one file per test
+            path.push(file_stem);
+            path.push(name);
+            path
+        }
+        Placement::Central => {
+            let mut parts: Vec<_> = kind
+                .iter()
+                .chain(relative.parent().into_iter().flatten())
+                .collect();
+            parts.push(file_stem);
+            parts.push(name.as_ref());
+            let parts: Vec<_> = parts.iter().map(|part| part.to_string_lossy()).collect();
+            data_dir(config, crate_root).join(parts.join("__"))
+        }
+        Placement::NextToSource => source_file
+            .with_file_name(SNAPSHOTS_DIR_NAME)
+            .join(file_stem)
+            .join(name),
+    }
+}
+
+/// Extensions of data files: the format, then the compression, if any.
+const DATA_EXTENSIONS: [&str; 6] = [
+    "json",
+    "jsonl",
+    "json.gz",
+    "jsonl.gz",
+    "json.zst",
+    "jsonl.zst",
+];
+
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+}
+
+/// Splits the name of a data file into its stem and its extension, e.g.
+/// `t.json.zst` into `t` and `json.zst`.
+fn split_data_name(name: &str) -> Option<(&str, &str)> {
+    DATA_EXTENSIONS.iter().find_map(|extension| {
+        let stem = name.strip_suffix(extension)?.strip_suffix('.')?;
+        (!stem.is_empty()).then_some((stem, *extension))
+    })
+}
+
+/// `data_file` with its extension replaced by `extension`.
+fn with_data_extension(data_file: &Path, extension: &str) -> PathBuf {
+    match split_data_name(file_name(data_file)) {
+        Some((stem, _)) => data_file.with_file_name(format!("{}.{}", stem, extension)),
+        None => data_file.with_extension(extension),
+    }
+}
+
+/// Whether `path` is named like a regression data file, as opposed to e.g. a
+/// [pending](pending_path) or [partial](is_partial_path) file.
+pub fn is_data_path<P: AsRef<Path>>(path: P) -> bool {
+    let path = path.as_ref();
+    split_data_name(file_name(path)).is_some() && !is_pending_path(path) && !is_partial_path(path)
+}
+
+/// The directory holding the messages of the regression data file
+/// `data_file` with the [directory layout](crate::config::Layout::Directory):
+/// the same path with an `.entries` extension.
+pub fn entry_dir<P: AsRef<Path>>(data_file: P) -> PathBuf {
+    with_data_extension(data_file.as_ref(), "entries")
+}
+
+/// The image `name` recorded with
+/// [`regtest_image`](crate::RegTest::regtest_image) in the regression data
+/// file `data_file`: the same path with a `<name>.png` extension.
+#[cfg(feature = "image")]
+pub(crate) fn image_path(data_file: &Path, name: &str) -> PathBuf {
+    with_data_extension(data_file, &format!("{}.png", name))
+}
+
+/// The directory holding the full messages of the entries of the regression
+/// data file `data_file` which were truncated (see
+/// [`Config::truncate_sidecar`](crate::Config::truncate_sidecar)): the same
+/// path with a `.full` extension.
+pub fn full_message_dir<P: AsRef<Path>>(data_file: P) -> PathBuf {
+    with_data_extension(data_file.as_ref(), "full")
+}
+
+/// The file holding the full message of a truncated entry of the regression
+/// data file `data_file`, in its [full message directory](full_message_dir),
+/// named after the digest of the part left out.
+pub(crate) fn full_message_path(data_file: &Path, digest: &str) -> PathBuf {
+    full_message_dir(data_file).join(format!("{}.txt", digest))
+}
+
+/// Where the binary or sidecar file `path` of a data file (an image, a full
+/// message or a blob) is written: at its path relative to the directory of
+/// the configuration file (or else the crate root) in the
+/// [LFS directory](Config::lfs_dir), if any, and at `path` otherwise.
+pub(crate) fn lfs_path(config: &Config, path: &Path) -> PathBuf {
+    let Some(lfs) = &config.lfs_dir else {
+        return path.to_path_buf();
+    };
+    let root = config
+        .base_dir()
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from))
+        .unwrap_or_default();
+    let path = normalize_path(std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+    crate::store::ShadowStore::new(lfs, normalize_path(root)).shadow_path(&path)
+}
+
+/// `path` relative to the directory `base`, with `/` as the separator, e.g.
+/// `../../blobs`. Both are made [logical](logical_path) first.
+pub(crate) fn relative_path(path: &Path, base: &Path) -> std::io::Result<String> {
+    let (path, base) = (logical_path(path)?, logical_path(base)?);
+    let common = path
+        .components()
+        .zip(base.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let parts: Vec<String> =
+        std::iter::repeat_n("..".to_string(), base.components().count() - common)
+            .chain(
+                path.components()
+                    .skip(common)
+                    .map(|part| part.as_os_str().to_string_lossy().into_owned()),
+            )
+            .collect();
+    Ok(match parts.is_empty() {
+        true => ".".to_string(),
+        false => parts.join("/"),
+    })
+}
+
+/// `base` joined with the relative path `relative`, with its `.` and `..`
+/// components removed lexically, so that the result names a file of a
+/// [store](crate::store) the way it was saved.
+pub(crate) fn join_lexically(base: &Path, relative: &Path) -> PathBuf {
+    let mut joined = base.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                joined.pop();
+            }
+            component => joined.push(component),
+        }
+    }
+    joined
+}
+
+/// Extension of partial files, see [`partial_path`].
+const PARTIAL_EXTENSION: &str = "partial.jsonl";
+
+/// The partial file of the regression data file `data_file`, to which
+/// entries are appended as they are recorded (see
+/// [`Config::incremental`](crate::Config::incremental)).
+pub(crate) fn partial_path(data_file: &Path) -> PathBuf {
+    with_data_extension(data_file, PARTIAL_EXTENSION)
+}
+
+/// Whether `path` is the partial file of a data file, left behind by a test
+/// which didn't finish (see
+/// [`Config::incremental`](crate::Config::incremental)).
+pub fn is_partial_path<P: AsRef<Path>>(path: P) -> bool {
+    file_name(path.as_ref()).ends_with(&format!(".{}", PARTIAL_EXTENSION))
+}
+
+/// Marks pending files, before the extension of the data file.
+const PENDING_MARKER: &str = "pending";
+
+/// The name of the pending file `name` without its marker, if it is one.
+fn strip_pending_marker(name: &str) -> Option<String> {
+    let (stem, extension) = split_data_name(name)?;
+    let stem = stem.strip_suffix(PENDING_MARKER)?.strip_suffix('.')?;
+    Some(format!("{}.{}", stem, extension))
+}
+
+/// The pending file of the regression data file `data_file`: the same path
+/// with `.pending` inserted before the extension, e.g. `t.pending.json`.
+///
+/// When a test records entries past the end of its baseline, the baseline
+/// with these entries appended is written to the pending file, to be
+/// reviewed and accepted with `cargo regtest accept` (or rejected with
+/// `cargo regtest reject`).
+pub fn pending_path<P: AsRef<Path>>(data_file: P) -> PathBuf {
+    let data_file = data_file.as_ref();
+    let extension = split_data_name(file_name(data_file)).map_or("json", |(_, ext)| ext);
+    with_data_extension(data_file, &format!("{}.{}", PENDING_MARKER, extension))
+}
+
+/// Whether `path` is a [pending file](pending_path).
+pub fn is_pending_path<P: AsRef<Path>>(path: P) -> bool {
+    strip_pending_marker(file_name(path.as_ref())).is_some()
+}
+
+/// The regression data file a [pending file](pending_path) is meant to
+/// replace.
+pub fn accepted_path<P: AsRef<Path>>(pending_file: P) -> PathBuf {
+    let pending_file = pending_file.as_ref();
+    match strip_pending_marker(file_name(pending_file)) {
+        Some(name) => pending_file.with_file_name(name),
+        None => pending_file.to_path_buf(),
+    }
+}
+
+/// Separates the test name from the variant in the name of a data file.
+const VARIANT_MARKER: char = '@';
+
+/// The data file of the variant `variant` of the test whose data file is
+/// `data_file`: the same path with `@<variant>` appended to the test name,
+/// e.g. `t@with-simd.json`.
+///
+/// Variants keep separate baselines for outputs which legitimately differ,
+/// e.g. with the enabled Cargo features (see `#[regtest(variant = "...")]`).
+/// They may contain ASCII letters, digits, `-`, `_` and `+`.
+pub fn variant_path<P: AsRef<Path>>(data_file: P, variant: &str) -> PathBuf {
+    let data_file = data_file.as_ref();
+    match split_data_name(file_name(data_file)) {
+        Some((stem, extension)) => data_file.with_file_name(format!(
+            "{}{}{}.{}",
+            stem, VARIANT_MARKER, variant, extension
+        )),
+        None => data_file.with_file_name(format!(
+            "{}{}{}",
+            file_name(data_file),
+            VARIANT_MARKER,
+            variant
+        )),
+    }
+}
+
+/// The data file of the test a [variant](variant_path) data file belongs to,
+/// or `data_file` itself if it isn't one.
+pub fn without_variant<P: AsRef<Path>>(data_file: P) -> PathBuf {
+    let data_file = data_file.as_ref();
+    let Some((stem, extension)) = split_data_name(file_name(data_file)) else {
+        return data_file.to_path_buf();
+    };
+    match stem.split_once(VARIANT_MARKER) {
+        // Keeps markers following the variant, e.g. that of pending files
+        Some((test, variant)) => {
+            let rest = variant.find('.').map_or("", |dot| &variant[dot..]);
+            data_file.with_file_name(format!("{}{}.{}", test, rest, extension))
+        }
+        None => data_file.to_path_buf(),
+    }
+}
+
+/// Directory holding all regression data of the crate rooted at `crate_root`.
+pub(crate) fn data_dir(config: &Config, crate_root: &Path) -> PathBuf {
+    if config.shared {
+        let base = config
+            .base_dir()
+            .map(|base| logical_path(base).unwrap_or_else(|_| base.to_path_buf()));
+        let crate_root = &normalize_path(crate_root);
+        let crate_dir = base
+            .as_deref()
+            .and_then(|base| crate_root.strip_prefix(base).ok())
+            .filter(|rel| !rel.as_os_str().is_empty())
+            .or_else(|| crate_root.file_name().map(Path::new))
+            .unwrap_or_else(|| Path::new(""));
+
+        match base {
+            Some(base) => base.join(&config.root).join(crate_dir),
+            None => config.root.join(crate_dir),
+        }
+    } else {
+        crate_root.join(&config.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_source_tree() {
+        let config = Config::default();
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/src/my_module/mod.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/src/my_module/mod/t.json")
+        );
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/tests/it.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/tests/it/t.json")
+        );
+    }
+
+    #[test]
+    fn names_data_files_of_helpers_after_the_test_module() {
+        assert_eq!(data_file_name("tests::parses", "krate::tests"), "parses");
+        assert_eq!(
+            data_file_name("krate::tests::parses", "krate::tests"),
+            "parses"
+        );
+        assert_eq!(data_file_name("parses", "it"), "parses");
+        assert_eq!(
+            data_file_name("parser::tests::parses", "krate::helpers"),
+            "parser__tests__parses"
+        );
+    }
+
+    #[test]
+    fn lays_out_current_tests_after_their_module() {
+        let root = std::env::temp_dir().join(format!("regtest-current-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("tests")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        std::fs::write(root.join("tests/it.rs"), "").unwrap();
+
+        let config = Config::default();
+        let path = |binary: &str, test: &str| {
+            let path = current_test_path(&config, &root, binary, test);
+            path.strip_prefix(&root).unwrap().to_path_buf()
+        };
+        assert_eq!(
+            path("krate", "parses"),
+            Path::new("regtest_data/src/lib/parses.json")
+        );
+        assert_eq!(
+            path("krate", "parser::tests::parses"),
+            Path::new("regtest_data/src/parser/tests/parses.json")
+        );
+        assert_eq!(
+            path("krate", "krate::parser::parses"),
+            Path::new("regtest_data/src/parser/parses.json")
+        );
+        assert_eq!(
+            path("it", "parses"),
+            Path::new("regtest_data/tests/it/parses.json")
+        );
+        assert_eq!(
+            path("it", "cases::parses"),
+            Path::new("regtest_data/tests/it/cases/parses.json")
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn mirrors_all_target_kinds() {
+        let mut config = Config::default();
+        for (source, data) in [
+            ("src/main.rs", "src/main/t.json"),
+            ("src/bin/tool.rs", "src/bin/tool/t.json"),
+            ("examples/demo.rs", "examples/demo/t.json"),
+            ("examples/demo/main.rs", "examples/demo/main/t.json"),
+            ("benches/speed.rs", "benches/speed/t.json"),
+        ] {
+            assert_eq!(
+                snapshot_path_with(&config, &Path::new("/ws/krate").join(source), "t"),
+                Path::new("/ws/krate/regtest_data").join(data)
+            );
+        }
+
+        config
+            .target_dirs
+            .insert("tests".into(), "integration".into());
+        config.target_dirs.insert("fuzz".into(), "fuzz".into());
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/tests/it.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/integration/it/t.json")
+        );
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/fuzz/parse.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/fuzz/parse/t.json")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logical_paths_keep_symbolic_links() {
+        let root = std::env::temp_dir().join(format!("regtest-logical-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("store/krate/src")).unwrap();
+        std::os::unix::fs::symlink(root.join("store/krate"), root.join("krate")).unwrap();
+
+        assert_eq!(
+            logical_path(root.join("krate/src/./../src/lib.rs")).unwrap(),
+            root.join("krate/src/lib.rs")
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn normalizes_windows_paths() {
+        for (path, normalized) in [
+            (r"\\?\C:\ws\krate\src\lib.rs", r"C:\ws\krate\src\lib.rs"),
+            (r"\\?\UNC\server\share\src", r"\\server\share\src"),
+            (r"\\?\Volume{1}\src", r"\\?\Volume{1}\src"),
+            (r"C:\ws/krate/src\lib.rs", r"C:\ws\krate\src\lib.rs"),
+        ] {
+            assert_eq!(normalize_windows_path(path), normalized);
+        }
+    }
+
+    #[test]
+    fn places_data_files() {
+        let mut config = Config {
+            placement: Placement::Central,
+            ..Config::default()
+        };
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/src/parser/mod.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/src__parser__mod__t.json")
+        );
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/tests/it.rs"), "t"),
+            Path::new("/ws/krate/regtest_data/tests__it__t.json")
+        );
+
+        config.placement = Placement::NextToSource;
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/src/parser/mod.rs"), "t"),
+            Path::new("/ws/krate/src/parser/__snapshots__/mod/t.json")
+        );
+    }
+
+    #[test]
+    fn prefers_target_directories_next_to_the_manifest() {
+        let root = std::env::temp_dir().join(format!("regtest-targets-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("src/examples")).unwrap();
+        std::fs::write(root.join("Cargo.toml"), "").unwrap();
+
+        let config = Config::default();
+        assert_eq!(
+            snapshot_path_with(&config, &root.join("src/examples/parse.rs"), "t"),
+            root.join("regtest_data/src/examples/parse/t.json")
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn uses_nearest_source_directory() {
+        let config = Config::default();
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/home/tests/krate/src/lib.rs"), "t"),
+            Path::new("/home/tests/krate/regtest_data/src/lib/t.json")
+        );
+    }
+
+    #[test]
+    fn honors_configured_root() {
+        let config = Config {
+            root: PathBuf::from("golden"),
+            ..Config::default()
+        };
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/krate/src/lib.rs"), "t"),
+            Path::new("/ws/krate/golden/src/lib/t.json")
+        );
+    }
+
+    #[test]
+    fn shared_root_has_per_crate_directories() {
+        let config = Config {
+            shared: true,
+            source: Some(PathBuf::from("/ws/regtest.toml")),
+            ..Config::default()
+        };
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/crates/krate/src/lib.rs"), "t"),
+            Path::new("/ws/regtest_data/crates/krate/src/lib/t.json")
+        );
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/elsewhere/krate/tests/it.rs"), "t"),
+            Path::new("/ws/regtest_data/krate/tests/it/t.json")
+        );
+    }
+
+    #[test]
+    fn relates_blob_directories_to_data_files() {
+        let dir = Path::new("/ws/krate/regtest_data/blobs");
+        let parent = Path::new("/ws/krate/regtest_data/src/lib");
+        let relative = relative_path(dir, parent).unwrap();
+        assert_eq!(relative, "../../blobs");
+        assert_eq!(join_lexically(parent, Path::new(&relative)), dir);
+        assert_eq!(relative_path(parent, parent).unwrap(), ".");
+        assert_eq!(join_lexically(parent, Path::new(".")), parent);
+    }
+
+    #[test]
+    fn pending_files_sit_next_to_data_files() {
+        let data = Path::new("/ws/krate/regtest_data/src/lib/t.json");
+        let pending = pending_path(data);
+        assert_eq!(
+            pending,
+            Path::new("/ws/krate/regtest_data/src/lib/t.pending.json")
+        );
+        assert!(is_pending_path(&pending));
+        assert!(!is_pending_path(data));
+        assert_eq!(accepted_path(&pending), data);
+
+        let lines = Path::new("/ws/krate/regtest_data/src/lib/t.jsonl");
+        assert_eq!(
+            pending_path(lines),
+            Path::new("/ws/krate/regtest_data/src/lib/t.pending.jsonl")
+        );
+        assert_eq!(accepted_path(pending_path(lines)), lines);
+        assert!(!is_pending_path(partial_path(lines)));
+        assert!(is_partial_path(partial_path(lines)));
+
+        let compressed = Path::new("/ws/krate/regtest_data/src/lib/t.json.zst");
+        assert_eq!(
+            pending_path(compressed),
+            Path::new("/ws/krate/regtest_data/src/lib/t.pending.json.zst")
+        );
+        assert_eq!(accepted_path(pending_path(compressed)), compressed);
+        assert_eq!(
+            entry_dir(compressed),
+            Path::new("/ws/krate/regtest_data/src/lib/t.entries")
+        );
+        assert!(is_data_path(compressed));
+
+        let variant = variant_path(compressed, "simd+avx2");
+        assert_eq!(
+            variant,
+            Path::new("/ws/krate/regtest_data/src/lib/t@simd+avx2.json.zst")
+        );
+        assert!(is_data_path(&variant));
+        assert_eq!(without_variant(&variant), compressed);
+        assert_eq!(
+            without_variant(pending_path(&variant)),
+            pending_path(compressed)
+        );
+        assert_eq!(accepted_path(pending_path(&variant)), variant);
+        assert_eq!(without_variant(data), data);
+        assert!(!is_data_path(pending_path(compressed)));
+        assert!(!is_data_path(partial_path(compressed)));
+    }
+
+    #[test]
+    fn moved_sources_are_found_in_the_runtime_crate() {
+        let this = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/path.rs");
+        let elsewhere = Path::new("/elsewhere/krate");
+        assert_eq!(relocate(&this, "src/path.rs", Some(elsewhere)), this);
+        assert_eq!(
+            relocate(
+                Path::new("/built/krate/src/lib.rs"),
+                "src/lib.rs",
+                Some(elsewhere)
+            ),
+            Path::new("/elsewhere/krate/src/lib.rs")
+        );
+        assert_eq!(
+            relocate(Path::new("/built/krate/src/lib.rs"), "", Some(elsewhere)),
+            Path::new("/built/krate/src/lib.rs")
+        );
+    }
+
+    #[test]
+    fn shared_root_without_config_file() {
+        // This is how `REGTEST_DIR` is applied
+        let config = Config {
+            root: PathBuf::from("/tmp/regtest"),
+            shared: true,
+            ..Config::default()
+        };
+        assert_eq!(
+            snapshot_path_with(&config, Path::new("/ws/crates/krate/src/lib.rs"), "t"),
+            Path::new("/tmp/regtest/krate/src/lib/t.json")
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Placeholders in stored messages, matching any conforming fragment of the
+//! recorded output:
+//!
+//! - `{{uuid}}`: a UUID, e.g. `67e55044-10b1-426f-9247-bb680e5fe0c8`,
+//! - `{{timestamp}}`: an RFC 3339 date and time, e.g. `2024-05-01T12:00:00Z`,
+//! - `{{duration}}`: a duration as printed by `Debug` or for humans, e.g.
+//!   `12.345ms`, `1.5 s` or `3m 2s`,
+//! - `{{bytes}}`: a size in bytes, e.g. `512 B`, `1.2 MiB` or `3kB`,
+//! - `{{any}}`: any text, possibly empty,
+//! - `{{any:<regex>}}`: text matching `<regex>`, e.g. `{{any:\d+}}`. The
+//!   regex ends at the first `}}`.
+//!
+//! Anything else between double braces is literal text.
+
+use crate::config::Placeholder;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+const UUID: &str = r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}";
+const TIMESTAMP: &str = r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:?\d{2})?";
+/// One or more numbers with a unit of time, e.g. `1h 2m 3.5s`.
+const DURATION: &str = r"\b(?:\d+(?:\.\d+)? ?(?:nanoseconds?|microseconds?|milliseconds?|seconds?|secs?|minutes?|mins?|hours?|hrs?|days?|ns|[µμu]s|ms|s|m|h|d)\b ?)+";
+const BYTES: &str = r"\b\d+(?:\.\d+)? ?(?:bytes?|[kKMGTPE]i?B|B)\b";
+
+impl Placeholder {
+    /// The placeholder as written in messages.
+    fn text(self) -> &'static str {
+        match self {
+            Placeholder::Uuid => "{{uuid}}",
+            Placeholder::Timestamp => "{{timestamp}}",
+            Placeholder::Duration => "{{duration}}",
+            Placeholder::Bytes => "{{bytes}}",
+        }
+    }
+
+    /// The fragments matched by the placeholder.
+    fn regex(self) -> &'static Regex {
+        static UUID_REGEX: OnceLock<Regex> = OnceLock::new();
+        static TIMESTAMP_REGEX: OnceLock<Regex> = OnceLock::new();
+        static DURATION_REGEX: OnceLock<Regex> = OnceLock::new();
+        static BYTES_REGEX: OnceLock<Regex> = OnceLock::new();
+        match self {
+            Placeholder::Uuid => UUID_REGEX.get_or_init(|| Regex::new(UUID).unwrap()),
+            Placeholder::Timestamp => {
+                TIMESTAMP_REGEX.get_or_init(|| Regex::new(TIMESTAMP).unwrap())
+            }
+            Placeholder::Duration => DURATION_REGEX.get_or_init(|| Regex::new(DURATION).unwrap()),
+            Placeholder::Bytes => BYTES_REGEX.get_or_init(|| Regex::new(BYTES).unwrap()),
+        }
+    }
+}
+
+/// `message`, with the fragments matched by `placeholders` replaced by them.
+pub(crate) fn insert<'a>(message: &'a str, placeholders: &[Placeholder]) -> Cow<'a, str> {
+    let mut message = Cow::Borrowed(message);
+    for &placeholder in placeholders {
+        if let Cow::Owned(replaced) = placeholder
+            .regex()
+            .replace_all(&message, regex::NoExpand(placeholder.text()))
+        {
+            message = Cow::Owned(replaced);
+        }
+    }
+    message
+}
+
+/// Whether `actual` matches `expected`, read as a pattern with placeholders.
+/// Never true when `expected` has no placeholder.
+pub(crate) fn matches(expected: &str, actual: &str) -> bool {
+    if !expected.contains("{{") {
+        return false;
+    }
+
+    let mut pattern = String::from(r"\A(?s:");
+    let mut placeholders = 0;
+    let mut rest = expected;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        let fragment = match &rest[start + 2..end] {
+            "uuid" => Some(UUID.to_string()),
+            "timestamp" => Some(TIMESTAMP.to_string()),
+            "duration" => Some(DURATION.to_string()),
+            "bytes" => Some(BYTES.to_string()),
+            "any" => Some(".*".to_string()),
+            name => name
+                .strip_prefix("any:")
+                .filter(|regex| Regex::new(regex).is_ok())
+                .map(|regex| format!("(?:{})", regex)),
+        };
+        match fragment {
+            Some(fragment) => {
+                pattern.push_str(&regex::escape(&rest[..start]));
+                pattern.push_str(&fragment);
+                placeholders += 1;
+                rest = &rest[end + 2..];
+            }
+            None => {
+                // Not a placeholder: the braces are literal text
+                pattern.push_str(&regex::escape(&rest[..start + 2]));
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    pattern.push_str(&regex::escape(rest));
+    pattern.push_str(r")\z");
+
+    placeholders > 0 && Regex::new(&pattern).is_ok_and(|regex| regex.is_match(actual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_match_conforming_text() {
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let recorded = format!("created {} at 2024-05-01T12:00:00.5+02:00", id);
+        let stored = insert(&recorded, &[Placeholder::Uuid, Placeholder::Timestamp]);
+        assert_eq!(stored, "created {{uuid}} at {{timestamp}}");
+        assert!(matches(&stored, &recorded));
+        assert!(!matches(
+            &stored,
+            "created 67e55044 at 2024-05-01T12:00:00Z"
+        ));
+
+        let recorded = "parsed 1.2 MiB in 12.345ms (total 3m 2s, 512 B/s)";
+        let stored = insert(recorded, &[Placeholder::Duration, Placeholder::Bytes]);
+        assert_eq!(
+            stored,
+            "parsed {{bytes}} in {{duration}}(total {{duration}}, {{bytes}}/s)"
+        );
+        assert!(matches(
+            &stored,
+            "parsed 900 KiB in 9ms (total 1h 5s, 1 B/s)"
+        ));
+        assert_eq!(
+            insert("5 messages, 2 more", &[Placeholder::Duration]),
+            "5 messages, 2 more"
+        );
+
+        assert!(matches(r"took {{any:\d+}}ms", "took 42ms"));
+        assert!(!matches(r"took {{any:\d+}}ms", "took 42ms!"));
+        assert!(matches("a {{any}} z", "a b\nc z"));
+        assert!(matches("{{name}} is {{any}}", "{{name}} is here"));
+        assert!(!matches("{{name}} is {{any:(}}", "{{name}} is ("));
+        assert!(!matches("no placeholders", "no placeholders"));
+    }
+}
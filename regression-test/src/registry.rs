@@ -0,0 +1,73 @@
+//! Process-wide registry of opened snapshot files.
+//!
+//! Every [`RegTest`](crate::RegTest) registers the file it operates on, along
+//! with the name of the test that opened it. If a second, different test
+//! resolves to the same file, the two would silently read and overwrite each
+//! other's baselines, so [`RegTest`](crate::RegTest) refuses to continue.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+fn registry() -> &'static Mutex<HashMap<PathBuf, String>> {
+    static REGISTRY: OnceLock<Mutex<HashMap<PathBuf, String>>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Name of the test currently running on this thread.
+///
+/// libtest runs every test on a thread named after the test's path, which is
+/// the best identity we have at runtime. Tests running on the main thread,
+/// e.g. with a custom harness, are named after the test nextest started the
+/// process for, if any.
+pub(crate) fn current_test_name() -> String {
+    #[cfg(feature = "harness")]
+    if let Some(name) = crate::harness::current_test_name() {
+        return name;
+    }
+
+    match std::thread::current().name() {
+        Some(name) if name != "main" => name.to_string(),
+        name => {
+            crate::nextest::test_name().unwrap_or_else(|| name.unwrap_or("<unnamed>").to_string())
+        }
+    }
+}
+
+/// Name of the running test binary, without the hash cargo appends to it.
+pub(crate) fn binary_name() -> String {
+    let stem = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "regtest".to_string());
+
+    match stem.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            name.to_string()
+        }
+        _ => stem,
+    }
+}
+
+/// Records that the current test uses `path`.
+///
+/// Opening the same file several times from the same test is allowed. If
+/// another test has already claimed it during this run, returns that test's
+/// name.
+pub(crate) fn register(path: &Path) -> Result<(), String> {
+    let key = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let owner = current_test_name();
+
+    // A poisoned lock only means another test panicked while holding it; the
+    // map itself is still consistent.
+    let mut registry = registry().lock().unwrap_or_else(|e| e.into_inner());
+
+    match registry.get(&key) {
+        Some(previous) if *previous != owner => Err(previous.clone()),
+        Some(_) => Ok(()),
+        None => {
+            registry.insert(key, owner);
+            Ok(())
+        }
+    }
+}
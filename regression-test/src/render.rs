@@ -0,0 +1,521 @@
+//! Rendering of the messages reported to the user.
+//!
+//! All panic messages and reports go through a [Renderer], so presentation
+//! concerns (diff style, terminal hyperlinks, ...) are decided in one place.
+
+use crate::config::{Annotations, Config, DiffStyle, Hyperlinks};
+use crate::report::{Mismatch, MismatchKind};
+use std::any::Any;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// Environment variable forcing hyperlinks on (`1`) or off (`0`), following
+/// the convention of other terminal tools.
+const FORCE_HYPERLINK_ENV_VAR: &str = "FORCE_HYPERLINK";
+
+#[derive(Debug, Clone)]
+pub(crate) struct Renderer {
+    /// Whether paths are rendered as OSC 8 terminal hyperlinks.
+    hyperlinks: bool,
+    diff: DiffStyle,
+    /// Whether full diffs are printed to stdout, leaving a compact panic
+    /// message. Only done when libtest doesn't capture output.
+    echo: bool,
+    /// Whether echoed diffs use ANSI colors.
+    color: bool,
+    /// Whether mismatches are also reported as GitHub Actions annotations.
+    annotations: bool,
+}
+
+impl Renderer {
+    pub(crate) fn new(config: &Config) -> Self {
+        let hyperlinks = match config.hyperlinks {
+            Hyperlinks::Always => true,
+            Hyperlinks::Never => false,
+            Hyperlinks::Auto => terminal_supports_hyperlinks(),
+        };
+
+        Renderer {
+            hyperlinks,
+            diff: config.diff,
+            echo: config.echo && output_not_captured(),
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            annotations: match config.annotations {
+                Annotations::Always => true,
+                Annotations::Never => false,
+                Annotations::Auto => std::env::var("GITHUB_ACTIONS").is_ok_and(|v| v == "true"),
+            },
+        }
+    }
+
+    /// Renders a file path, as a clickable link when supported.
+    pub(crate) fn path(&self, path: &Path) -> String {
+        if !self.hyperlinks {
+            return path.display().to_string();
+        }
+
+        let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        format!(
+            "\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\",
+            file_url(&absolute),
+            path.display()
+        )
+    }
+
+    /// Renders the panic message for a mismatch with the data in `file`.
+    pub(crate) fn mismatch(&self, file: &Path, mismatch: &Mismatch) -> String {
+        let mut entry = match (&mismatch.key, mismatch.index) {
+            (Some(key), _) => format!("entry `{}`", key),
+            (None, index) => format!("entry #{}", index.unwrap_or_default()),
+        };
+        if let Some(note) = &mismatch.note {
+            entry.push_str(&format!(" \"{}\"", note));
+        }
+        if let Some(heading) = &mismatch.heading {
+            entry.push_str(&format!(" under \"{}\"", heading));
+        }
+        if !mismatch.fields.is_empty() {
+            let fields: Vec<String> = mismatch.fields.iter().map(|f| format!("`{}`", f)).collect();
+            entry.push_str(&format!(", differing fields: {}", fields.join(", ")));
+        }
+        if let Some(caller) = &mismatch.caller {
+            entry.push_str(&format!(", recorded at {}:{}", caller.file, caller.line));
+        }
+
+        match mismatch.kind {
+            MismatchKind::Message if !mismatch.changes.is_empty() => {
+                let mut message = format!(
+                    "Regression JSON mismatch in {} ({}):",
+                    self.path(file),
+                    entry
+                );
+                for change in &mismatch.changes {
+                    let value = |value: &Option<serde_json::Value>| match value {
+                        Some(value) => value.to_string(),
+                        None => "(absent)".to_string(),
+                    };
+                    let pointer = match change.pointer.as_str() {
+                        "" => "(document)",
+                        pointer => pointer,
+                    };
+                    message.push_str(&format!(
+                        "\n  {}: {} -> {}",
+                        pointer,
+                        value(&change.expected),
+                        value(&change.actual)
+                    ));
+                }
+                message
+            }
+            MismatchKind::Message => {
+                let location = format!("{} ({})", self.path(file), entry);
+                self.message_mismatch(&location, &mismatch.expected, &mismatch.actual)
+            }
+            MismatchKind::Inline => {
+                let location = match &mismatch.position {
+                    Some(p) => format!("{}:{}:{}", p.file, p.line, p.column),
+                    None => self.path(file),
+                };
+                self.message_mismatch(&location, &mismatch.expected, &mismatch.actual)
+            }
+            MismatchKind::Type => format!(
+                "Regression data generated in different ways in {} ({}): expected {}, got {}",
+                self.path(file),
+                entry,
+                mismatch.expected,
+                mismatch.actual
+            ),
+            MismatchKind::Incompatible => format!(
+                "Regression data incompatible in {} ({}): the baseline can no longer be read back: {}\nBaseline:\n{}",
+                self.path(file),
+                entry,
+                mismatch.actual,
+                mismatch.expected
+            ),
+            MismatchKind::Removed => format!(
+                "Regression data out of sync in {} ({}): these entries of the baseline are no longer recorded:\n{}",
+                self.path(file),
+                entry,
+                mismatch.expected
+            ),
+            MismatchKind::Added => format!(
+                "Regression data out of sync in {} ({}): the test recorded new entries, which are not in the baseline:\n{}",
+                self.path(file),
+                entry,
+                mismatch.actual
+            ),
+            MismatchKind::Extra => format!(
+                "Regression data ends early in {} ({}): the test recorded entries past the end of the baseline:\n{}\n\n\
+                 The baseline with these entries appended was written to {}. Review it, and run `cargo regtest accept` to keep it.",
+                self.path(file),
+                entry,
+                mismatch.actual,
+                self.path(&crate::path::pending_path(file))
+            ),
+            MismatchKind::MissingEntry => match &mismatch.key {
+                Some(key) => format!(
+                    "No regression entry with key `{}` in {}{}, but test recorded:\n{}",
+                    key,
+                    self.path(file),
+                    mismatch
+                        .caller
+                        .map(|c| format!(" (recorded at {}:{})", c.file, c.line))
+                        .unwrap_or_default(),
+                    mismatch.actual
+                ),
+                None => format!(
+                    "No more regression entries in {}, but test expected more ({}).",
+                    self.path(file),
+                    entry
+                ),
+            },
+        }
+    }
+
+    fn message_mismatch(&self, location: &str, expected: &str, actual: &str) -> String {
+        if self.echo {
+            println!("\n{}", self.rich_mismatch(location, expected, actual));
+            return format!(
+                "Regression message mismatch in {} (full diff printed above)",
+                location
+            );
+        }
+
+        let mut message = format!(
+            "Regression message mismatch in {}:\nExpected: {}\nActual:   {}",
+            location, expected, actual
+        );
+
+        match self.diff {
+            DiffStyle::Lines => {
+                message.push_str("\n\nDiff:\n");
+                message.push_str(&diff_lines(expected, actual));
+            }
+            DiffStyle::None => {}
+        }
+
+        message
+    }
+
+    /// A more readable, colored rendering of a mismatch, for terminals.
+    fn rich_mismatch(&self, location: &str, expected: &str, actual: &str) -> String {
+        let paint = |code: &str, text: &str| {
+            if self.color {
+                format!("\x1b[{}m{}\x1b[0m", code, text)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut out = paint("1", &format!("━━━ Regression mismatch: {} ━━━", location));
+        out.push('\n');
+        out.push_str(&paint("31", "-old snapshot"));
+        out.push('\n');
+        out.push_str(&paint("32", "+new results"));
+        out.push('\n');
+
+        let diff = match self.diff {
+            DiffStyle::Lines => diff_lines(expected, actual),
+            DiffStyle::None => {
+                let mut diff = String::new();
+                expected
+                    .lines()
+                    .for_each(|l| diff.push_str(&format!("- {}\n", l)));
+                actual
+                    .lines()
+                    .for_each(|l| diff.push_str(&format!("+ {}\n", l)));
+                diff
+            }
+        };
+
+        for line in diff.lines() {
+            let line = match line.as_bytes().first() {
+                Some(b'-') => paint("31", line),
+                Some(b'+') => paint("32", line),
+                _ => paint("2", line),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+
+        out.push_str(&paint("1", "━━━"));
+        out
+    }
+
+    /// A GitHub Actions workflow command annotating the line of the test which
+    /// recorded the mismatching entry, if annotations are enabled.
+    pub(crate) fn annotation(
+        &self,
+        test: &str,
+        subtest: Option<&str>,
+        file: &Path,
+        mismatch: &Mismatch,
+    ) -> Option<String> {
+        if !self.annotations {
+            return None;
+        }
+
+        let test = match subtest {
+            Some(subtest) => format!("{}/{}", test, subtest),
+            None => test.to_string(),
+        };
+
+        let location = match mismatch.position.or(mismatch.caller) {
+            Some(p) => format!(
+                "file={},line={},col={}",
+                escape_property(p.file),
+                p.line,
+                p.column
+            ),
+            None => format!("file={}", escape_property(&file.display().to_string())),
+        };
+
+        Some(format!(
+            "::error {},title=Regression mismatch::{}",
+            location,
+            escape_data(&format!(
+                "regression mismatch in {} ({})",
+                test,
+                file.display()
+            ))
+        ))
+    }
+
+    pub(crate) fn subtest_failures(&self, file: &Path, failures: &[(String, String)]) -> String {
+        let mut message = format!(
+            "{} subtest(s) failed in {}:",
+            failures.len(),
+            self.path(file)
+        );
+        for (name, failure) in failures {
+            message.push_str(&format!("\n\n--- subtest `{}` ---\n{}", name, failure));
+        }
+        message
+    }
+}
+
+/// Extracts the message of a caught panic.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+/// Whether libtest was asked not to capture the output of tests, in which
+/// case anything printed goes straight to the terminal.
+fn output_not_captured() -> bool {
+    static NOT_CAPTURED: OnceLock<bool> = OnceLock::new();
+    *NOT_CAPTURED.get_or_init(|| {
+        std::env::args().any(|arg| arg == "--nocapture" || arg == "--no-capture")
+            || std::env::var("RUST_TEST_NOCAPTURE").is_ok_and(|v| v != "0")
+    })
+}
+
+/// Best-effort detection of OSC 8 support in the terminal showing test output.
+fn terminal_supports_hyperlinks() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        let env = |name| std::env::var(name).unwrap_or_default();
+
+        match env(FORCE_HYPERLINK_ENV_VAR).as_str() {
+            "" => {}
+            "0" => return false,
+            _ => return true,
+        }
+
+        // libtest reports failures on stdout
+        if !std::io::stdout().is_terminal() || !env("CI").is_empty() {
+            return false;
+        }
+
+        let term = env("TERM");
+        let vte_version = env("VTE_VERSION").parse::<u32>().unwrap_or(0);
+
+        matches!(
+            env("TERM_PROGRAM").as_str(),
+            "iTerm.app" | "WezTerm" | "vscode" | "ghostty"
+        ) || vte_version >= 5000
+            || !env("WT_SESSION").is_empty()
+            || !env("KITTY_WINDOW_ID").is_empty()
+            || !env("KONSOLE_VERSION").is_empty()
+            || ["kitty", "alacritty", "foot", "wezterm", "ghostty"]
+                .iter()
+                .any(|t| term.contains(t))
+    })
+}
+
+/// Escapes the message of a GitHub Actions workflow command.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a GitHub Actions workflow command.
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// Escapes `text` for use in XML and HTML text and attribute values.
+pub(crate) fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\r' | '\t' => escaped.push(c),
+            // Not representable in XML 1.0
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds a `file://` URL, percent-encoding everything but unreserved
+/// characters and path separators.
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        url.push('/');
+    }
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' | b':' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    url
+}
+
+pub(crate) fn diff_lines(expected: &str, actual: &str) -> String {
+    let exp_lines: Vec<_> = expected.lines().collect();
+    let act_lines: Vec<_> = actual.lines().collect();
+    let max = exp_lines.len().max(act_lines.len());
+
+    let mut diff = String::new();
+    let mut minus_block = Vec::new();
+    let mut plus_block = Vec::new();
+
+    for i in 0..max {
+        let exp = exp_lines.get(i).unwrap_or(&"");
+        let act = act_lines.get(i).unwrap_or(&"");
+
+        if exp != act {
+            if !exp.is_empty() {
+                minus_block.push(exp);
+            }
+            if !act.is_empty() {
+                plus_block.push(act);
+            }
+        } else {
+            if !minus_block.is_empty() || !plus_block.is_empty() {
+                if !minus_block.is_empty() {
+                    for line in &minus_block {
+                        diff.push_str(&format!("- {}\n", line));
+                    }
+                    minus_block.clear();
+                }
+                if !plus_block.is_empty() {
+                    for line in &plus_block {
+                        diff.push_str(&format!("+ {}\n", line));
+                    }
+                    plus_block.clear();
+                }
+            } else {
+                diff.push_str(&format!("  {}\n", exp));
+            }
+        }
+    }
+
+    // Flush any remaining blocks
+    if !minus_block.is_empty() {
+        for line in &minus_block {
+            diff.push_str(&format!("- {}\n", line));
+        }
+    }
+    if !plus_block.is_empty() {
+        for line in &plus_block {
+            diff.push_str(&format!("+ {}\n", line));
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inline::Position;
+
+    #[test]
+    fn annotations_point_at_the_recording_line() {
+        let config = Config {
+            annotations: Annotations::Always,
+            ..Config::default()
+        };
+        let mismatch = Mismatch {
+            kind: MismatchKind::Message,
+            index: Some(0),
+            key: None,
+            note: None,
+            heading: None,
+            fields: Vec::new(),
+            changes: Vec::new(),
+            position: None,
+            caller: Some(Position {
+                file: "src/lib.rs",
+                line: 12,
+                column: 5,
+            }),
+            expected: "a".to_string(),
+            actual: "b".to_string(),
+        };
+
+        let annotation = Renderer::new(&config)
+            .annotation(
+                "tests::it_works",
+                None,
+                Path::new("data, 1.json"),
+                &mismatch,
+            )
+            .unwrap();
+        assert_eq!(
+            annotation,
+            "::error file=src/lib.rs,line=12,col=5,title=Regression mismatch::regression mismatch in tests::it_works (data, 1.json)"
+        );
+    }
+
+    #[test]
+    fn hyperlinks_wrap_paths() {
+        let config = Config {
+            hyperlinks: Hyperlinks::Always,
+            ..Config::default()
+        };
+        let rendered = Renderer::new(&config).path(Path::new("/data/my test.json"));
+        assert_eq!(
+            rendered,
+            "\x1b]8;;file:///data/my%20test.json\x1b\\/data/my test.json\x1b]8;;\x1b\\"
+        );
+
+        let config = Config {
+            hyperlinks: Hyperlinks::Never,
+            ..Config::default()
+        };
+        let rendered = Renderer::new(&config).path(Path::new("/data/my test.json"));
+        assert_eq!(rendered, "/data/my test.json");
+    }
+}
@@ -0,0 +1,167 @@
+//! Machine-readable reports of regression mismatches, for CI tooling.
+//!
+//! When a report file is configured (see [`Config::report`]), every mismatch
+//! is appended to it as one JSON object per line, so that concurrently running
+//! tests and test binaries can share the same file.
+//!
+//! [`Config::report`]: crate::Config::report
+
+use crate::inline::Position;
+use crate::render::diff_lines;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MismatchKind {
+    /// The recorded message differs from the stored one.
+    Message,
+    /// The entry was recorded with a different method (`Display` vs `Debug`).
+    Type,
+    /// The test recorded more entries than are stored.
+    MissingEntry,
+    /// Entries of the baseline are no longer recorded; `expected` lists them.
+    Removed,
+    /// The test recorded entries which aren't in the baseline; `actual` lists
+    /// them.
+    Added,
+    /// The test recorded entries past the end of the baseline; `actual` lists
+    /// them. They are written to the pending file.
+    Extra,
+    /// An inline expectation doesn't match.
+    Inline,
+    /// The baseline entry can no longer be read back, e.g. deserialized (see
+    /// `regtest_roundtrip`); `actual` is the error.
+    Incompatible,
+}
+
+/// A value which differs between two JSON documents.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+    /// JSON pointer to the value, empty for the whole document.
+    pub(crate) pointer: String,
+    /// The value in the baseline, unless it was added.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) expected: Option<serde_json::Value>,
+    /// The recorded value, unless it was removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) actual: Option<serde_json::Value>,
+}
+
+/// A difference between the expected and the actual output of a test.
+#[derive(Debug, Clone)]
+pub(crate) struct Mismatch {
+    pub(crate) kind: MismatchKind,
+    /// Index of the entry in the regression data file.
+    pub(crate) index: Option<usize>,
+    /// Key of the entry, if it has one.
+    pub(crate) key: Option<String>,
+    /// Note describing the entry, if it has one.
+    pub(crate) note: Option<String>,
+    /// The [header](crate::RegTest::header) the entry comes after, if any.
+    pub(crate) heading: Option<String>,
+    /// For serialized values, the paths of the fields which differ.
+    pub(crate) fields: Vec<String>,
+    /// For JSON documents, the values which differ.
+    pub(crate) changes: Vec<Change>,
+    /// Location of the inline expectation.
+    pub(crate) position: Option<Position>,
+    /// Where the test recorded the entry.
+    pub(crate) caller: Option<Position>,
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    test: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtest: Option<&'a str>,
+    kind: MismatchKind,
+    /// The regression data file, or the source file for inline expectations.
+    file: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    note: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    heading: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    fields: &'a [String],
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    changes: &'a [Change],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u32>,
+    expected: &'a str,
+    actual: &'a str,
+    diff: String,
+    /// Whether the mismatch didn't fail the test, see [`Config::advisory`].
+    ///
+    /// [`Config::advisory`]: crate::Config::advisory
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    advisory: bool,
+    /// Why the test was expected to fail, see `RegTest::expect_failure`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    xfail: Option<&'a str>,
+}
+
+/// The report line of `mismatch`, which happened in `test` (and `subtest`)
+/// while using the regression data in `file`, without the newline. `advisory`
+/// mismatches didn't fail the test, nor did those of tests expected to fail
+/// for the reason `xfail`.
+pub(crate) fn to_json(
+    test: &str,
+    subtest: Option<&str>,
+    file: &Path,
+    mismatch: &Mismatch,
+    advisory: bool,
+    xfail: Option<&str>,
+) -> std::io::Result<String> {
+    let report = Report {
+        test,
+        subtest,
+        kind: mismatch.kind,
+        file: match &mismatch.position {
+            Some(position) => position.file.to_string(),
+            None => file.display().to_string(),
+        },
+        entry: mismatch.index,
+        key: mismatch.key.as_deref(),
+        note: mismatch.note.as_deref(),
+        heading: mismatch.heading.as_deref(),
+        fields: &mismatch.fields,
+        changes: &mismatch.changes,
+        line: mismatch.position.map(|p| p.line),
+        column: mismatch.position.map(|p| p.column),
+        expected: &mismatch.expected,
+        actual: &mismatch.actual,
+        diff: diff_lines(&mismatch.expected, &mismatch.actual),
+        advisory,
+        xfail,
+    };
+
+    Ok(serde_json::to_string(&report)?)
+}
+
+/// Appends the report line `json`, see [`to_json`], to the report at
+/// `report_path`.
+pub(crate) fn append(report_path: &Path, json: &str) -> std::io::Result<()> {
+    let line = format!("{}\n", json);
+
+    if let Some(parent) = report_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // A single write in append mode keeps lines from concurrent writers intact
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(report_path)?
+        .write_all(line.as_bytes())
+}
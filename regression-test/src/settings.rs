@@ -0,0 +1,145 @@
+//! Settings applied to the entries recorded in a scope, see
+//! [`RegTest::with_settings`](crate::RegTest::with_settings).
+
+use crate::clock::Clock;
+use crate::config::Redaction;
+use crate::json_path::{JsonPath, PathRule};
+use crate::normalize::Normalizer;
+use regex::Regex;
+use std::sync::Arc;
+
+/// Settings for the entries recorded within
+/// [`RegTest::with_settings`](crate::RegTest::with_settings), on top of the
+/// configuration of the test.
+///
+/// ```rust
+/// use regression_test::{RegTest, Settings};
+/// use regression_test::normalize::Normalization;
+///
+/// # let path = std::env::temp_dir().join("regtest-settings-doc.json");
+/// let mut rt = RegTest::new(path).unwrap();
+/// let settings = Settings::new()
+///     .redact(r"\d+ms", "[duration]")
+///     .normalize_with(Normalization::TrailingWhitespace)
+///     .pretty_debug(true)
+///     .description("parser");
+/// rt.with_settings(settings, |rt| {
+///     rt.regtest("parsed in 12ms ");
+///     rt.regtest_dbg(vec![1, 2]);
+/// });
+/// ```
+#[derive(Clone, Default)]
+pub struct Settings {
+    pub(crate) redactions: Vec<Redaction>,
+    pub(crate) normalizers: Vec<Arc<dyn Normalizer>>,
+    pub(crate) pretty_debug: Option<bool>,
+    pub(crate) description: Option<String>,
+    pub(crate) json_paths: Vec<PathRule>,
+    pub(crate) float_digits: Option<u32>,
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a redaction, applied after those of the configuration.
+    ///
+    /// # Panics
+    /// Panics if `pattern` is not a valid regular expression.
+    pub fn redact(mut self, pattern: &str, replacement: &str) -> Self {
+        let pattern = match Regex::new(pattern) {
+            Ok(pattern) => pattern,
+            Err(e) => panic!("Invalid redaction pattern `{}`: {}", pattern, e),
+        };
+        self.redactions.push(Redaction {
+            pattern,
+            replacement: replacement.to_string(),
+        });
+        self
+    }
+
+    /// Adds a redaction of the current time of `clock`, see
+    /// [`RegTest::clock`](crate::RegTest::clock), for times formatted by
+    /// code which doesn't use the clock's own formats. Matches the time in
+    /// RFC 3339 format, with a `T` or a space between the date and the time
+    /// and optional fractional seconds, and as seconds or milliseconds since
+    /// the Unix epoch.
+    pub fn redact_clock(self, clock: &Clock, replacement: &str) -> Self {
+        let time = clock.rfc3339();
+        let (date, time) = (&time[..10], &time[11..19]);
+        let seconds = clock.unix_seconds();
+        self.redact(
+            &format!(r"{}[T ]{}(\.\d+)?(Z|[+-]00:?00)?", date, time),
+            replacement,
+        )
+        .redact(&format!(r"\b{}(\d{{3}})?\b", seconds), replacement)
+    }
+
+    /// Adds a normalizer, applied after those already in effect. See the
+    /// [normalize](crate::normalize) module.
+    pub fn normalize_with<N: Normalizer + 'static>(mut self, normalizer: N) -> Self {
+        self.normalizers.push(Arc::new(normalizer));
+        self
+    }
+
+    /// Whether `Debug` entries are recorded with the pretty-printed (`{:#?}`)
+    /// rather than the compact (`{:?}`) format. Doesn't apply to
+    /// [`regtest_sorted_dbg`](crate::RegTest::regtest_sorted_dbg).
+    pub fn pretty_debug(mut self, pretty: bool) -> Self {
+        self.pretty_debug = Some(pretty);
+        self
+    }
+
+    /// Leaves the values at `path` (e.g. `$.meta.generated_at`, see the
+    /// [syntax](crate::RegTest::regtest_json)) out of the JSON documents
+    /// recorded with [`regtest_json`](crate::RegTest::regtest_json) and
+    /// [`regtest_ser`](crate::RegTest::regtest_ser).
+    ///
+    /// # Panics
+    /// Panics if `path` is not a valid path.
+    pub fn ignore_path(self, path: &str) -> Self {
+        self.path_rule(path, None)
+    }
+
+    /// Like [ignore_path](Settings::ignore_path), replacing the values at
+    /// `path` with the string `replacement` rather than leaving them out.
+    ///
+    /// # Panics
+    /// Panics if `path` is not a valid path.
+    pub fn replace_path(self, path: &str, replacement: &str) -> Self {
+        self.path_rule(path, Some(replacement.to_string()))
+    }
+
+    fn path_rule(mut self, path: &str, replacement: Option<String>) -> Self {
+        let path = match JsonPath::parse(path) {
+            Ok(parsed) => parsed,
+            Err(e) => panic!("Invalid JSON path `{}`: {}", path, e),
+        };
+        self.json_paths.push(PathRule { path, replacement });
+        self
+    }
+
+    /// Rounds the decimal numbers in the recorded output to `digits`
+    /// significant digits, overriding
+    /// [`Config::float_digits`](crate::Config::float_digits).
+    ///
+    /// # Panics
+    /// Panics if `digits` is not between 1 and 17.
+    pub fn float_digits(mut self, digits: u32) -> Self {
+        assert!(
+            (1..=17).contains(&digits),
+            "float_digits must be between 1 and 17, not {}",
+            digits
+        );
+        self.float_digits = Some(digits);
+        self
+    }
+
+    /// Describes the entries, e.g. what is being tested. Mismatches found
+    /// while the settings are in effect are reported prefixed with it.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+}
@@ -0,0 +1,94 @@
+//! Recording entries from several threads, see
+//! [`RegTest::shared`](crate::RegTest::shared).
+
+use crate::RegType;
+use crate::display::{self, RegDisplay};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::panic::Location;
+use std::sync::Mutex;
+
+/// Records entries from the threads of a test, e.g. workers spawned with
+/// [`std::thread::scope`], see [`RegTest::shared`](crate::RegTest::shared).
+///
+/// Threads record under a label, such as the name or index of the worker.
+/// Entries are ordered by label, so the order in which the threads happen to
+/// run doesn't matter, as long as each label is recorded by one thread at a
+/// time.
+#[derive(Debug)]
+pub struct SharedRegTest {
+    /// Whether `Debug` entries are pretty-printed, see
+    /// [Settings::pretty_debug](crate::Settings::pretty_debug).
+    pretty_debug: bool,
+    entries: Mutex<BTreeMap<String, Vec<SharedEntry>>>,
+}
+
+/// An entry recorded through a [`SharedRegTest`].
+#[derive(Debug)]
+pub(crate) struct SharedEntry {
+    pub(crate) message: String,
+    pub(crate) reg_type: RegType,
+    pub(crate) value_type: &'static str,
+    pub(crate) location: &'static Location<'static>,
+}
+
+impl SharedRegTest {
+    pub(crate) fn new(pretty_debug: bool) -> Self {
+        SharedRegTest {
+            pretty_debug,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records `value` under `label`, like [`RegTest::regtest`](crate::RegTest::regtest).
+    #[track_caller]
+    pub fn regtest<T: RegDisplay>(&self, label: &str, value: T) {
+        self.push(
+            label,
+            display::render(&value),
+            RegType::Display,
+            crate::value_type::<T>(),
+        );
+    }
+
+    /// Records `value` under `label`, like
+    /// [`RegTest::regtest_dbg`](crate::RegTest::regtest_dbg).
+    #[track_caller]
+    pub fn regtest_dbg<T: Debug>(&self, label: &str, value: T) {
+        let message = if self.pretty_debug {
+            format!("{:#?}", value)
+        } else {
+            format!("{:?}", value)
+        };
+        self.push(label, message, RegType::Debug, crate::value_type::<T>());
+    }
+
+    #[track_caller]
+    fn push(&self, label: &str, message: String, reg_type: RegType, value_type: &'static str) {
+        let entry = SharedEntry {
+            message,
+            reg_type,
+            value_type,
+            location: Location::caller(),
+        };
+        // A poisoned lock only means another thread panicked while holding
+        // it; the entries themselves are still consistent.
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(label.to_string())
+            .or_default()
+            .push(entry);
+    }
+
+    /// The entries recorded, ordered by label, with their label.
+    pub(crate) fn into_entries(self) -> impl Iterator<Item = (String, SharedEntry)> {
+        self.entries
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .into_iter()
+            .flat_map(|(label, entries)| {
+                entries.into_iter().map(move |entry| (label.clone(), entry))
+            })
+    }
+}
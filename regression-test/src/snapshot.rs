@@ -0,0 +1,392 @@
+//! Read-only access to regression data files, for tools consuming them
+//! outside of tests (custom harnesses, documentation generators, ...).
+
+use crate::store::{FsStore, SnapshotStore};
+use crate::{DataFile, RegEntry};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// A regression data file, as written by a [`RegTest`](crate::RegTest).
+///
+/// All versions of the file format are supported; fields which an older
+/// version doesn't store are `None`.
+///
+/// ```rust
+/// use regression_test::{RegTest, SnapshotFile};
+///
+/// # let path = std::env::temp_dir().join("regtest-snapshot-doc.json");
+/// # let _ = std::fs::remove_file(&path);
+/// let mut rt = RegTest::new(&path).unwrap();
+/// rt.regtest("hello");
+/// drop(rt);
+///
+/// let snapshot = SnapshotFile::load(&path).unwrap();
+/// assert_eq!(snapshot.entries()[0].message(), "hello");
+/// ```
+pub struct SnapshotFile {
+    data: DataFile,
+}
+
+impl SnapshotFile {
+    /// Reads the data file at `path`, with the messages stored apart from it,
+    /// if any.
+    pub fn load<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+        let mut data =
+            DataFile::read(file, path).map_err(|e| crate::CorruptSnapshot::describe(path, e))?;
+        data.load_apart(path, |path| FsStore.load(path))?;
+        crate::checksum::verify(path, &data, false)?;
+        Ok(SnapshotFile { data })
+    }
+
+    /// Version of the file format the file was written with. Version 0 is a
+    /// bare array of entries, without any metadata.
+    pub fn version(&self) -> u32 {
+        self.data.header.version
+    }
+
+    /// Name of the crate owning the test.
+    pub fn crate_name(&self) -> Option<&str> {
+        self.data.header.crate_name.as_deref()
+    }
+
+    /// Full path of the test which generated the file.
+    pub fn test(&self) -> Option<&str> {
+        self.data.header.test.as_deref()
+    }
+
+    /// When the file was generated, in RFC 3339 format (UTC).
+    pub fn generated(&self) -> Option<&str> {
+        self.data.header.generated.as_deref()
+    }
+
+    /// Tags of the test, see [`RegTest::with_tags`](crate::RegTest::with_tags).
+    pub fn tags(&self) -> &[String] {
+        &self.data.header.tags
+    }
+
+    /// All entries, in the order they were recorded, including those of
+    /// subtests (see [`RegEntry::section`]).
+    pub fn entries(&self) -> &[RegEntry] {
+        &self.data.entries
+    }
+
+    /// The differences between the entries of this file and those of `other`,
+    /// see [`diff_files`].
+    pub fn diff(&self, other: &SnapshotFile) -> FileDiff {
+        diff_entries(self.entries(), other.entries())
+    }
+
+    /// Writes the message of each entry, as is, to a plain text file in
+    /// `dir`, for tools which don't read data files. Files are named like
+    /// with the [directory layout](crate::config::Layout::Directory): after
+    /// the key of the entry, or else its index (`0003.txt`), in a
+    /// subdirectory per subtest. Returns the files written.
+    pub fn export<P: AsRef<Path>>(&self, dir: P) -> std::io::Result<Vec<PathBuf>> {
+        let dir = dir.as_ref();
+        let mut written = Vec::new();
+        for (entry, name) in self.data.entries.iter().zip(self.data.entry_file_names()) {
+            let path = dir.join(name);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, entry.message())?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+}
+
+/// An entry whose output differs between two data files.
+#[derive(Debug, Clone)]
+pub struct ChangedEntry {
+    /// The entry in the first file.
+    pub before: RegEntry,
+    /// The entry in the second file.
+    pub after: RegEntry,
+}
+
+/// The differences between the entries of two data files, see [`diff_files`].
+#[derive(Debug, Clone, Default)]
+pub struct FileDiff {
+    /// Entries only in the second file.
+    pub added: Vec<RegEntry>,
+    /// Entries only in the first file.
+    pub removed: Vec<RegEntry>,
+    /// Entries in both files, recorded differently.
+    pub changed: Vec<ChangedEntry>,
+}
+
+impl FileDiff {
+    /// Whether both files hold the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the entries of the data files `a` and `b` the way a test would
+/// compare its output against a baseline, without running it.
+///
+/// Entries are compared within each subtest. Keyed entries are paired by key;
+/// the others are aligned to keep as many identical entries as possible, and
+/// differing entries at the same place are reported as changed. Where an
+/// entry was recorded is not compared.
+///
+/// ```rust,no_run
+/// let diff = regression_test::diff_files("main/test.json", "pr/test.json").unwrap();
+/// for change in &diff.changed {
+///     println!("- {}\n+ {}", change.before.message(), change.after.message());
+/// }
+/// ```
+pub fn diff_files<P: AsRef<Path>, Q: AsRef<Path>>(a: P, b: Q) -> std::io::Result<FileDiff> {
+    Ok(SnapshotFile::load(a)?.diff(&SnapshotFile::load(b)?))
+}
+
+/// The keyed entries of `entries`, identified by their key and occurrence.
+fn keyed(entries: &[RegEntry]) -> Vec<((String, usize), &RegEntry)> {
+    let mut occurrences: HashMap<&str, usize> = HashMap::new();
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let key = entry.key.as_deref()?;
+            let occurrence = occurrences.entry(key).or_default();
+            *occurrence += 1;
+            Some(((key.to_string(), *occurrence - 1), entry))
+        })
+        .collect()
+}
+
+fn same(a: &RegEntry, b: &RegEntry) -> bool {
+    a.reg_type == b.reg_type && a.message == b.message
+}
+
+fn diff_entries(a: &[RegEntry], b: &[RegEntry]) -> FileDiff {
+    let mut diff = FileDiff::default();
+
+    let mut sections: Vec<Option<&str>> = Vec::new();
+    for entry in a.iter().chain(b) {
+        if !sections.contains(&entry.section()) {
+            sections.push(entry.section());
+        }
+    }
+
+    for section in sections {
+        let in_section = |entries: &[RegEntry]| -> Vec<RegEntry> {
+            entries
+                .iter()
+                .filter(|e| e.section() == section)
+                .cloned()
+                .collect()
+        };
+        let (a, b) = (in_section(a), in_section(b));
+
+        // Keyed entries: the n-th occurrence of a key in `a` is paired with
+        // the n-th occurrence of the same key in `b`
+        let keyed_b = keyed(&b);
+        let mut unpaired: HashMap<&(String, usize), &RegEntry> =
+            keyed_b.iter().map(|(id, e)| (id, *e)).collect();
+        for (id, entry) in keyed(&a) {
+            match unpaired.remove(&id) {
+                Some(other) if same(entry, other) => {}
+                Some(other) => diff.changed.push(ChangedEntry {
+                    before: entry.clone(),
+                    after: other.clone(),
+                }),
+                None => diff.removed.push(entry.clone()),
+            }
+        }
+        for (id, entry) in &keyed_b {
+            if unpaired.contains_key(id) {
+                diff.added.push((*entry).clone());
+            }
+        }
+
+        let unkeyed = |entries: Vec<RegEntry>| -> Vec<RegEntry> {
+            entries.into_iter().filter(|e| e.key.is_none()).collect()
+        };
+        align(unkeyed(a), unkeyed(b), &mut diff);
+    }
+    diff
+}
+
+/// Aligns the entries of `a` and `b` along their longest common subsequence,
+/// reporting the rest. Differing entries at the same place are changed.
+fn align(a: Vec<RegEntry>, b: Vec<RegEntry>, diff: &mut FileDiff) {
+    // lcs[i][j]: length of the LCS of a[i..] and b[j..]. Very large inputs
+    // are compared position by position instead, to bound the cost.
+    let exhaustive = a.len().saturating_mul(b.len()) <= 4_000_000;
+    let mut lcs = vec![vec![0usize; b.len() + 1]; if exhaustive { a.len() + 1 } else { 0 }];
+    if exhaustive {
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lcs[i][j] = if same(&a[i], &b[j]) {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+    }
+
+    let (mut removed, mut added) = (Vec::new(), Vec::new());
+    let mut flush = |removed: &mut Vec<RegEntry>, added: &mut Vec<RegEntry>| {
+        let paired = removed.len().min(added.len());
+        for (before, after) in removed.drain(..paired).zip(added.drain(..paired)) {
+            diff.changed.push(ChangedEntry { before, after });
+        }
+        diff.removed.append(removed);
+        diff.added.append(added);
+    };
+
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && same(&a[i], &b[j]) {
+            flush(&mut removed, &mut added);
+            i += 1;
+            j += 1;
+        } else if !exhaustive && i < a.len() && j < b.len() {
+            removed.push(a[i].clone());
+            added.push(b[j].clone());
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            added.push(b[j].clone());
+            j += 1;
+        } else {
+            removed.push(a[i].clone());
+            i += 1;
+        }
+    }
+    flush(&mut removed, &mut added);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegType;
+
+    fn entry(message: &str, key: Option<&str>) -> RegEntry {
+        RegEntry {
+            reg_type: RegType::Display,
+            message: message.to_string(),
+            section: None,
+            key: key.map(str::to_string),
+            location: None,
+            note: None,
+            value_type: None,
+            file: None,
+            blob: None,
+        }
+    }
+
+    #[test]
+    fn loads_every_format_version() {
+        let dir = std::env::temp_dir().join(format!("regtest-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let bare = dir.join("bare.json");
+        std::fs::write(&bare, r#"[{"type": "debug", "message": "[1]"}]"#).unwrap();
+        let snapshot = SnapshotFile::load(&bare).unwrap();
+        assert_eq!(snapshot.version(), 0);
+        assert_eq!(snapshot.test(), None);
+        assert_eq!(snapshot.entries()[0].reg_type(), RegType::Debug);
+
+        let current = dir.join("current.json");
+        std::fs::write(
+            &current,
+            r#"{"version": 1, "crate": "krate", "test": "tests::t", "entries": [
+                {"type": "display", "message": "a", "section": "case", "location": "src/lib.rs:3"}
+            ]}"#,
+        )
+        .unwrap();
+        let snapshot = SnapshotFile::load(&current).unwrap();
+        assert_eq!(snapshot.crate_name(), Some("krate"));
+        assert_eq!(snapshot.test(), Some("tests::t"));
+        assert_eq!(snapshot.generated(), None);
+        let entry = &snapshot.entries()[0];
+        assert_eq!(
+            (
+                entry.message(),
+                entry.section(),
+                entry.key(),
+                entry.location()
+            ),
+            ("a", Some("case"), None, Some("src/lib.rs:3"))
+        );
+    }
+
+    #[test]
+    fn exports_messages_to_plain_files() {
+        let dir = std::env::temp_dir().join(format!("regtest-export-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut sub = entry("in subtest", None);
+        sub.section = Some("case/nested".to_string());
+        let snapshot = SnapshotFile {
+            data: DataFile {
+                header: crate::Header {
+                    version: crate::FORMAT_VERSION,
+                    crate_name: None,
+                    test: None,
+                    generated: None,
+                    shape: None,
+                    tags: Vec::new(),
+                    checksum: None,
+                    blobs: None,
+                },
+                entries: vec![entry("first", None), entry("keyed", Some("k")), sub],
+                encrypted: false,
+            },
+        };
+
+        let written = snapshot.export(&dir).unwrap();
+        let names: Vec<_> = written
+            .iter()
+            .map(|path| path.strip_prefix(&dir).unwrap().to_path_buf())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                PathBuf::from("0000.txt"),
+                PathBuf::from("k.txt"),
+                PathBuf::from("case/nested/0002.txt"),
+            ]
+        );
+        assert_eq!(std::fs::read_to_string(&written[1]).unwrap(), "keyed");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn diffs_entries() {
+        let before = [
+            entry("a", None),
+            entry("b", None),
+            entry("[k] 1", Some("k")),
+            entry("c", None),
+            entry("[gone] 2", Some("gone")),
+        ];
+        let after = [
+            entry("[k] 2", Some("k")),
+            entry("a", None),
+            entry("c2", None),
+            entry("c", None),
+            entry("d", None),
+            entry("[new] 3", Some("new")),
+        ];
+
+        let diff = diff_entries(&before, &after);
+        let messages = |entries: &[RegEntry]| -> Vec<String> {
+            entries.iter().map(|e| e.message.clone()).collect()
+        };
+        let changed: Vec<_> = diff
+            .changed
+            .iter()
+            .map(|c| (c.before.message(), c.after.message()))
+            .collect();
+        assert_eq!(changed, [("[k] 1", "[k] 2"), ("b", "c2")]);
+        assert_eq!(messages(&diff.removed), ["[gone] 2"]);
+        assert_eq!(messages(&diff.added), ["[new] 3", "d"]);
+
+        assert!(diff_entries(&before, &before).is_empty());
+    }
+}
@@ -0,0 +1,397 @@
+//! Storage backends for the regression data files.
+//!
+//! By default, data files live on the local file system ([`FsStore`]). Teams
+//! which prefer to keep large golden data out of their repository can store it
+//! elsewhere by implementing [`SnapshotStore`] and installing it with
+//! [`set_store`] before the first test runs:
+//!
+//! ```rust,no_run
+//! use regression_test::store::{self, SnapshotStore};
+//! use std::path::Path;
+//!
+//! struct Bucket;
+//!
+//! impl SnapshotStore for Bucket {
+//!     fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+//!         todo!("download {}", path.display())
+//!     }
+//!     fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+//!         todo!("upload {} bytes to {}", data.len(), path.display())
+//!     }
+//!     fn remove(&self, path: &Path) -> std::io::Result<()> {
+//!         todo!("delete {}", path.display())
+//!     }
+//! }
+//!
+//! store::set_store(Bucket);
+//! ```
+//!
+//! Data files are identified by the path they would have on the local file
+//! system (see [`snapshot_path`](crate::snapshot_path)); a remote store
+//! usually maps it to a key relative to the data directory. With the
+//! `http-store` feature, [`HttpStore`] stores data files on an HTTP server
+//! supporting `GET`, `PUT` and `DELETE`.
+//!
+//! With [`shadow_dir`](crate::Config::shadow_dir) set, the default store is a
+//! [`ShadowStore`] instead, which writes data files to that directory, e.g.
+//! for read-only checkouts.
+//!
+//! The store holds the baselines and [pending files](crate::pending_path).
+//! Run-order manifests, reports, and the `cargo regtest` commands only work
+//! with the local file system.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Where regression data files are loaded from and saved to.
+pub trait SnapshotStore: Send + Sync {
+    /// Contents of the data file `path`, or `None` if there is none.
+    fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>>;
+
+    /// Like [`load`](SnapshotStore::load), but reads the data file
+    /// incrementally, for [streaming](crate::Config::streaming) comparisons.
+    /// By default, the data file is loaded whole.
+    fn open(&self, path: &Path) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        Ok(self
+            .load(path)?
+            .map(|data| Box::new(std::io::Cursor::new(data)) as Box<dyn Read + Send>))
+    }
+
+    /// Saves `data` as the data file `path`, replacing any previous contents.
+    fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()>;
+
+    /// Removes the data file `path`. Removing a missing file is not an error.
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+
+    /// Where the data file `path` lives on the local file system, if it does.
+    /// Enables the checks which only make sense for local files, e.g.
+    /// warning about data files ignored by git.
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        let _ = path;
+        None
+    }
+}
+
+/// The default store: data files are stored as is on the local file system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsStore;
+
+impl SnapshotStore for FsStore {
+    fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn open(&self, path: &Path) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        match std::fs::File::open(path) {
+            Ok(file) => Ok(Some(Box::new(file))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, data)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(path.to_path_buf())
+    }
+}
+
+/// Keeps the data files written by tests in a shadow directory, leaving those
+/// of the source tree untouched: data files are read from the shadow
+/// directory when it has them, and from the source tree otherwise. Used by
+/// default when [`shadow_dir`](crate::Config::shadow_dir) is set.
+///
+/// The data file `path` is shadowed by the file at its path relative to
+/// `root` (usually the directory of `regtest.toml`) in the shadow directory,
+/// or at its whole path if it isn't below `root`.
+#[derive(Debug, Clone)]
+pub struct ShadowStore {
+    shadow: PathBuf,
+    root: PathBuf,
+}
+
+impl ShadowStore {
+    /// A store writing the data files below `root` to `shadow`.
+    pub fn new<S: Into<PathBuf>, R: Into<PathBuf>>(shadow: S, root: R) -> Self {
+        ShadowStore {
+            shadow: shadow.into(),
+            root: root.into(),
+        }
+    }
+
+    /// Where the data file `path` is written.
+    pub fn shadow_path(&self, path: &Path) -> PathBuf {
+        let relative: PathBuf = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect(),
+        };
+        self.shadow.join(relative)
+    }
+}
+
+impl SnapshotStore for ShadowStore {
+    fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        match FsStore.load(&self.shadow_path(path))? {
+            Some(data) => Ok(Some(data)),
+            None => FsStore.load(path),
+        }
+    }
+
+    fn open(&self, path: &Path) -> std::io::Result<Option<Box<dyn Read + Send>>> {
+        match FsStore.open(&self.shadow_path(path))? {
+            Some(data) => Ok(Some(data)),
+            None => FsStore.open(path),
+        }
+    }
+
+    fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        FsStore.save(&self.shadow_path(path), data)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        FsStore.remove(&self.shadow_path(path))
+    }
+
+    fn local_path(&self, path: &Path) -> Option<PathBuf> {
+        Some(self.shadow_path(path))
+    }
+}
+
+/// Keeps data files in memory, shared by the whole process.
+#[derive(Debug, Default)]
+struct MemoryStore {
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+}
+
+impl MemoryStore {
+    fn files(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl SnapshotStore for MemoryStore {
+    fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        Ok(self.files().get(path).cloned())
+    }
+
+    fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        self.files().insert(path.to_path_buf(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.files().remove(path);
+        Ok(())
+    }
+}
+
+/// Where a [`RegTest`](crate::RegTest) keeps its regression data, see
+/// [`RegTest::with_backend`](crate::RegTest::with_backend).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// The store installed with [`set_store`], or the file system.
+    #[default]
+    Store,
+    /// Memory shared by the whole process, never touching disk: data saved
+    /// by one `RegTest` is compared against by later ones using the same path
+    /// in the same process. Git baselines (see
+    /// [`Config::baseline`](crate::Config::baseline)) don't apply.
+    InMemory,
+}
+
+impl Backend {
+    pub(crate) fn store(self) -> &'static dyn SnapshotStore {
+        match self {
+            Backend::Store => current(),
+            Backend::InMemory => {
+                static MEMORY: OnceLock<MemoryStore> = OnceLock::new();
+                MEMORY.get_or_init(MemoryStore::default)
+            }
+        }
+    }
+}
+
+static STORE: OnceLock<Box<dyn SnapshotStore>> = OnceLock::new();
+
+/// Installs the store used by all regression tests of this process.
+///
+/// # Panics
+/// Panics if a store was already installed, or if a test already used the
+/// default [`FsStore`].
+pub fn set_store<S: SnapshotStore + 'static>(store: S) {
+    if STORE.set(Box::new(store)).is_err() {
+        panic!("The regression data store was already set, or already used.");
+    }
+}
+
+/// The store of this process.
+fn current() -> &'static dyn SnapshotStore {
+    STORE
+        .get_or_init(|| {
+            let config = crate::Config::current();
+            match &config.shadow_dir {
+                Some(shadow) => {
+                    let root = config
+                        .base_dir()
+                        .map(Path::to_path_buf)
+                        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from))
+                        .unwrap_or_default();
+                    Box::new(ShadowStore::new(shadow, root))
+                }
+                None => Box::new(FsStore),
+            }
+        })
+        .as_ref()
+}
+
+/// Stores data files on an HTTP server, under `<base URL>/<path relative to
+/// the root>`: they are read with `GET` (a 404 meaning there is no data
+/// file), written with `PUT` and removed with `DELETE`. Only plain `http://`
+/// URLs are supported.
+///
+/// ```rust,no_run
+/// use regression_test::Config;
+/// use regression_test::store::{self, HttpStore};
+///
+/// let data_dir = Config::current().data_dir(env!("CARGO_MANIFEST_DIR"));
+/// store::set_store(HttpStore::new("http://storage.internal:8080/regtest", data_dir));
+/// ```
+#[cfg(feature = "http-store")]
+#[derive(Debug, Clone)]
+pub struct HttpStore {
+    base_url: String,
+    root: PathBuf,
+}
+
+#[cfg(feature = "http-store")]
+impl HttpStore {
+    /// A store for the data files below `root` (usually the data directory),
+    /// at `base_url`.
+    pub fn new<U: Into<String>, P: Into<PathBuf>>(base_url: U, root: P) -> Self {
+        HttpStore {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            root: root.into(),
+        }
+    }
+
+    fn url(&self, path: &Path) -> std::io::Result<String> {
+        let relative = path.strip_prefix(&self.root).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not below the store root {}",
+                    path.display(),
+                    self.root.display()
+                ),
+            )
+        })?;
+        let segments: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Ok(format!("{}/{}", self.base_url, segments.join("/")))
+    }
+
+    fn failed(method: &str, url: &str, status: u16) -> std::io::Error {
+        std::io::Error::other(format!("{} {} failed with status {}", method, url, status))
+    }
+}
+
+#[cfg(feature = "http-store")]
+impl SnapshotStore for HttpStore {
+    fn load(&self, path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+        let url = self.url(path)?;
+        let response =
+            crate::http::request("GET", &url, "application/json", &[], crate::http::TIMEOUT)?;
+        match response.status {
+            404 => Ok(None),
+            _ if response.is_success() => Ok(Some(response.body)),
+            status => Err(Self::failed("GET", &url, status)),
+        }
+    }
+
+    fn save(&self, path: &Path, data: &[u8]) -> std::io::Result<()> {
+        let url = self.url(path)?;
+        let response =
+            crate::http::request("PUT", &url, "application/json", data, crate::http::TIMEOUT)?;
+        if !response.is_success() {
+            return Err(Self::failed("PUT", &url, response.status));
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        let url = self.url(path)?;
+        let response = crate::http::request(
+            "DELETE",
+            &url,
+            "application/json",
+            &[],
+            crate::http::TIMEOUT,
+        )?;
+        if !response.is_success() && response.status != 404 {
+            return Err(Self::failed("DELETE", &url, response.status));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fs_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("regtest-store-{}", std::process::id()));
+        let path = dir.join("nested/t.json");
+
+        assert!(FsStore.load(&path).unwrap().is_none());
+        FsStore.save(&path, b"[]").unwrap();
+        assert_eq!(FsStore.load(&path).unwrap().as_deref(), Some(&b"[]"[..]));
+        FsStore.remove(&path).unwrap();
+        FsStore.remove(&path).unwrap();
+        assert!(FsStore.load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn shadow_store_leaves_the_source_tree_alone() {
+        let dir = std::env::temp_dir().join(format!("regtest-shadow-{}", std::process::id()));
+        let source = dir.join("ws/krate/regtest_data/t.json");
+        FsStore.save(&source, b"[1]").unwrap();
+        let store = ShadowStore::new(dir.join("shadow"), dir.join("ws"));
+
+        assert_eq!(store.load(&source).unwrap().as_deref(), Some(&b"[1]"[..]));
+        store.save(&source, b"[2]").unwrap();
+        assert_eq!(store.load(&source).unwrap().as_deref(), Some(&b"[2]"[..]));
+        assert_eq!(
+            FsStore
+                .load(&dir.join("shadow/krate/regtest_data/t.json"))
+                .unwrap()
+                .as_deref(),
+            Some(&b"[2]"[..])
+        );
+        assert_eq!(FsStore.load(&source).unwrap().as_deref(), Some(&b"[1]"[..]));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
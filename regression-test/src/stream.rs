@@ -0,0 +1,215 @@
+//! Streaming reader for very large data files.
+//!
+//! With [`Config::streaming`](crate::Config::streaming), a baseline is not
+//! loaded whole: a background thread parses the data file and hands its
+//! entries over one at a time, through a bounded channel, as the comparison
+//! pulls them. Parsing stops as soon as the reader is dropped.
+
+use crate::{FORMAT_VERSION, RegEntry, Shape};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use std::io::{BufReader, Read};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+
+/// How many parsed entries may wait to be compared.
+const CAPACITY: usize = 256;
+
+/// An item of a data file, in the order it is read.
+pub(crate) enum Item {
+    /// The shape stored in the header.
+    Shape(Shape),
+    /// The blob directory stored in the header, which comes before the
+    /// entries referring to it.
+    Blobs(String),
+    Entry(RegEntry),
+}
+
+/// The items of a data file, read in the background.
+pub(crate) struct EntryStream {
+    receiver: Receiver<std::io::Result<Item>>,
+}
+
+impl EntryStream {
+    /// Starts reading the data file from `reader`.
+    pub(crate) fn new(reader: Box<dyn Read + Send>) -> std::io::Result<Self> {
+        let (sender, receiver) = sync_channel(CAPACITY);
+        std::thread::Builder::new()
+            .name("regtest-stream".to_string())
+            .spawn(move || {
+                let reader = match crate::encrypt::decrypter(BufReader::new(reader))
+                    .and_then(crate::compress::decoder)
+                {
+                    Ok(reader) => reader,
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        return;
+                    }
+                };
+                let mut deserializer =
+                    serde_json::Deserializer::from_reader(BufReader::new(reader));
+                let result = FileSeed { sender: &sender }
+                    .deserialize(&mut deserializer)
+                    .and_then(|complete| {
+                        if complete {
+                            return deserializer.end();
+                        }
+                        // JSON Lines: one entry per line after the header
+                        for entry in deserializer.into_iter() {
+                            send(&sender, Item::Entry(entry?))?;
+                        }
+                        Ok(())
+                    });
+                if let Err(e) = result {
+                    // Fails if the reader was dropped, which is what stopped us
+                    let _ = sender.send(Err(e.into()));
+                }
+            })?;
+        Ok(EntryStream { receiver })
+    }
+
+    /// The next item, or `None` once the file was read entirely.
+    pub(crate) fn next(&self) -> Option<std::io::Result<Item>> {
+        self.receiver.recv().ok()
+    }
+}
+
+type Sender<'a> = &'a SyncSender<std::io::Result<Item>>;
+
+fn send<E: de::Error>(sender: Sender, item: Item) -> Result<(), E> {
+    sender
+        .send(Ok(item))
+        .map_err(|_| E::custom("the reader was dropped"))
+}
+
+/// A whole data file in any supported version of the format, or the header
+/// of a JSON Lines file. Tells whether the entries were read.
+struct FileSeed<'a> {
+    sender: Sender<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for FileSeed<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FileSeed<'_> {
+    type Value = bool;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a regression data file")
+    }
+
+    /// Version 0: a bare array of entries.
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<bool, A::Error> {
+        EntriesSeed {
+            sender: self.sender,
+        }
+        .visit_seq(seq)?;
+        Ok(true)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<bool, A::Error> {
+        let mut entries = false;
+        while let Some(field) = map.next_key::<String>()? {
+            match field.as_str() {
+                "version" => {
+                    let version: u32 = map.next_value()?;
+                    if version > FORMAT_VERSION {
+                        return Err(de::Error::custom(format!(
+                            "format version {} is newer than the supported version {}; update regression-test",
+                            version, FORMAT_VERSION
+                        )));
+                    }
+                }
+                "shape" => {
+                    if let Some(shape) = map.next_value::<Option<Shape>>()? {
+                        send(self.sender, Item::Shape(shape))?;
+                    }
+                }
+                "blobs" => {
+                    if let Some(blobs) = map.next_value::<Option<String>>()? {
+                        send(self.sender, Item::Blobs(blobs))?;
+                    }
+                }
+                "entries" => {
+                    map.next_value_seed(EntriesSeed {
+                        sender: self.sender,
+                    })?;
+                    entries = true;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The array of entries.
+struct EntriesSeed<'a> {
+    sender: Sender<'a>,
+}
+
+impl<'de> DeserializeSeed<'de> for EntriesSeed<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for EntriesSeed<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array of regression entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(entry) = seq.next_element::<RegEntry>()? {
+            send(self.sender, Item::Entry(entry))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(json: &str) -> Vec<std::io::Result<Item>> {
+        let stream = EntryStream::new(Box::new(std::io::Cursor::new(json.to_string()))).unwrap();
+        std::iter::from_fn(|| stream.next()).collect()
+    }
+
+    #[test]
+    fn streams_every_format_version() {
+        let entry = r#"{"type": "display", "message": "a"}"#;
+
+        let bare = items(&format!("[{}, {}]", entry, entry));
+        assert_eq!(bare.len(), 2);
+        assert!(matches!(&bare[0], Ok(Item::Entry(e)) if e.message == "a"));
+
+        let current = items(&format!(
+            r#"{{"version": 1, "test": "t", "shape": {{"entries": 1, "kinds": ["display"]}}, "entries": [{}]}}"#,
+            entry
+        ));
+        assert!(matches!(&current[0], Ok(Item::Shape(s)) if s.entries == 1));
+        assert!(matches!(&current[1], Ok(Item::Entry(_))));
+        assert_eq!(current.len(), 2);
+
+        let newer = items(&format!(r#"{{"version": 99, "entries": [{}]}}"#, entry));
+        assert!(matches!(newer.last(), Some(Err(_))));
+
+        let lines = items(&format!("{{\"version\": 1}}\n{}\n{}\n", entry, entry));
+        assert_eq!(lines.len(), 2);
+        assert!(matches!(&lines[1], Ok(Item::Entry(e)) if e.message == "a"));
+
+        let truncated = items(&format!(r#"{{"entries": [{}, "#, entry));
+        assert!(matches!(&truncated[0], Ok(Item::Entry(_))));
+        assert!(matches!(truncated.last(), Some(Err(_))));
+    }
+}
@@ -0,0 +1,199 @@
+//! A summary of all regression tests of the process, printed to stderr when
+//! it exits, e.g.:
+//!
+//! ```text
+//! regression tests: 412
+//!   compared   398 (12034 entries)
+//!   new          3
+//!   updated      9
+//!   pending      1
+//!   failed       2: lexer::tests::unicode, parser::tests::nested
+//! ```
+//!
+//! Under nextest, which runs every test in a process of its own, the totals
+//! of each process are added to those of the run so far, kept in a file of
+//! the temporary directory, and each process prints the summary of the run
+//! so far instead of its own.
+//!
+//! Requires the `summary` feature.
+
+use crate::nextest;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// What happened to the regression data of one test.
+#[derive(Debug, Default)]
+pub(crate) struct Outcome {
+    /// Entries compared against a baseline, if there was one.
+    pub(crate) compared: Option<usize>,
+    /// Whether the baseline was generated, not having existed before.
+    pub(crate) new: bool,
+    /// Whether an existing baseline was rewritten, whole or in part.
+    pub(crate) updated: bool,
+    /// Whether entries were written to the pending file.
+    pub(crate) pending: bool,
+    /// Whether the test failed because of its regression data.
+    pub(crate) failed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    tests: usize,
+    compared: usize,
+    entries: usize,
+    new: usize,
+    updated: usize,
+    pending: usize,
+    failed: Vec<String>,
+}
+
+static TOTALS: Mutex<Totals> = Mutex::new(Totals {
+    tests: 0,
+    compared: 0,
+    entries: 0,
+    new: 0,
+    updated: 0,
+    pending: 0,
+    failed: Vec::new(),
+});
+
+/// Adds the outcome of the test `test` to the summary.
+pub(crate) fn record(test: &str, outcome: Outcome) {
+    let mut totals = TOTALS.lock().unwrap_or_else(|e| e.into_inner());
+    totals.tests += 1;
+    if let Some(entries) = outcome.compared {
+        totals.compared += 1;
+        totals.entries += entries;
+    }
+    totals.new += usize::from(outcome.new);
+    totals.updated += usize::from(outcome.updated);
+    totals.pending += usize::from(outcome.pending);
+    if outcome.failed {
+        totals.failed.push(test.to_string());
+    }
+}
+
+impl Totals {
+    /// Totals as [rendered](Totals::render), or empty ones for other text.
+    fn parse(text: &str) -> Totals {
+        let mut totals = Totals::default();
+        for line in text.lines() {
+            if let Some(tests) = line.strip_prefix("regression tests: ") {
+                totals.tests = tests.parse().unwrap_or(0);
+                continue;
+            }
+            let (counts, names) = line.split_once(": ").unwrap_or((line, ""));
+            let mut words = counts.split_whitespace();
+            let (Some(name), Some(count)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let count: usize = count.parse().unwrap_or(0);
+            match name {
+                "compared" => {
+                    totals.compared = count;
+                    totals.entries = words
+                        .next()
+                        .and_then(|entries| entries.trim_start_matches('(').parse().ok())
+                        .unwrap_or(0);
+                }
+                "new" => totals.new = count,
+                "updated" => totals.updated = count,
+                "pending" => totals.pending = count,
+                "failed" => {
+                    totals.failed = names
+                        .split(", ")
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                _ => {}
+            }
+        }
+        totals
+    }
+
+    fn add(&mut self, other: &Totals) {
+        self.tests += other.tests;
+        self.compared += other.compared;
+        self.entries += other.entries;
+        self.new += other.new;
+        self.updated += other.updated;
+        self.pending += other.pending;
+        self.failed.extend(other.failed.iter().cloned());
+    }
+
+    fn render(&self) -> String {
+        let mut failed = self.failed.clone();
+        failed.sort();
+        failed.dedup();
+        let failed = match failed.is_empty() {
+            true => String::new(),
+            false => format!(": {}", failed.join(", ")),
+        };
+        format!(
+            "regression tests: {}\n  compared {:>5} ({} entries)\n  new      {:>5}\n  updated  {:>5}\n  pending  {:>5}\n  failed   {:>5}{}\n",
+            self.tests,
+            self.compared,
+            self.entries,
+            self.new,
+            self.updated,
+            self.pending,
+            self.failed.len(),
+            failed
+        )
+    }
+}
+
+#[ctor::dtor]
+fn print_summary() {
+    let totals = TOTALS.lock().unwrap_or_else(|e| e.into_inner());
+    if totals.tests == 0 {
+        return;
+    }
+
+    let mut summary = totals.render();
+    if nextest::process_per_test()
+        && let Some(run) = nextest::run_id()
+    {
+        // The other tests of the run ran in other processes
+        let path = std::env::temp_dir().join(format!("regtest-summary-{}.txt", run));
+        let _ = nextest::rewrite(&path, |existing| {
+            let mut run = Totals::parse(existing);
+            run.add(&totals);
+            summary = run.render();
+            summary.clone()
+        });
+    }
+    // Written directly, as printing must not panic this late
+    let _ = std::io::stderr().write_all(summary.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_are_rendered() {
+        let totals = Totals {
+            tests: 4,
+            compared: 2,
+            entries: 17,
+            new: 1,
+            updated: 1,
+            pending: 0,
+            failed: vec!["b::t".to_string(), "a::t".to_string()],
+        };
+        assert_eq!(
+            totals.render(),
+            "regression tests: 4\n  compared     2 (17 entries)\n  new          1\n  updated      1\n  pending      0\n  failed       2: a::t, b::t\n"
+        );
+
+        let mut run = Totals::parse(&totals.render());
+        run.add(&totals);
+        assert_eq!(
+            run.render(),
+            "regression tests: 8\n  compared     4 (34 entries)\n  new          2\n  updated      2\n  pending      0\n  failed       4: a::t, b::t\n"
+        );
+        assert_eq!(Totals::parse("").render(), Totals::default().render());
+    }
+}
@@ -0,0 +1,134 @@
+//! Column-aligned text tables, see
+//! [`RegTest::regtest_table`](crate::RegTest::regtest_table).
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// The rows serialized with serde, rendered as a table.
+///
+/// Rows serializing to maps (e.g. structs) have a column per key, sorted by
+/// name and headed by it, and rows serializing to sequences (e.g. tuples) a
+/// column per item. Other rows are a single column. Strings are printed
+/// without quotes, `null` as an empty cell and nested values as compact
+/// JSON; numbers are aligned right, everything else left.
+pub(crate) fn render<I>(rows: I) -> Result<String, serde_json::Error>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let rows = rows
+        .into_iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<Value>, _>>()?;
+
+    let mut header: Vec<String> = Vec::new();
+    for row in &rows {
+        if let Value::Object(map) = row {
+            for key in map.keys() {
+                if !header.contains(key) {
+                    header.push(key.clone());
+                }
+            }
+        }
+    }
+    header.sort();
+
+    let mut cells: Vec<Vec<(String, bool)>> = Vec::new();
+    if !header.is_empty() {
+        cells.push(header.iter().map(|key| (key.clone(), false)).collect());
+    }
+    for row in &rows {
+        cells.push(match row {
+            Value::Object(map) => header
+                .iter()
+                .map(|key| map.get(key).map_or((String::new(), false), cell))
+                .collect(),
+            Value::Array(items) => items.iter().map(cell).collect(),
+            value => vec![cell(value)],
+        });
+    }
+
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            cells
+                .iter()
+                .filter_map(|row| row.get(column))
+                .map(|(text, _)| text.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut lines: Vec<String> = cells
+        .iter()
+        .map(|row| {
+            let padded: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|((text, numeric), &width)| match numeric {
+                    true => format!("{:>width$}", text),
+                    false => format!("{:<width$}", text),
+                })
+                .collect();
+            padded.join("  ").trim_end().to_string()
+        })
+        .collect();
+    if !header.is_empty() {
+        let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+        lines.insert(1, rule.join("  "));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// The text of a cell, and whether it is a number.
+fn cell(value: &Value) -> (String, bool) {
+    match value {
+        Value::Null => (String::new(), false),
+        Value::String(text) => (text.clone(), false),
+        Value::Number(number) => (number.to_string(), true),
+        value => (value.to_string(), false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        name: &'static str,
+        size: u64,
+        note: Option<&'static str>,
+    }
+
+    #[test]
+    fn aligns_columns() {
+        let rows = [
+            Row {
+                name: "lib.rs",
+                size: 1200,
+                note: None,
+            },
+            Row {
+                name: "main.rs",
+                size: 35,
+                note: Some("entry"),
+            },
+        ];
+        assert_eq!(
+            render(&rows).unwrap(),
+            "name     note   size\n\
+             -------  -----  ----\n\
+             lib.rs          1200\n\
+             main.rs  entry    35"
+        );
+
+        assert_eq!(
+            render([("a", 1.5, vec![1]), ("bcd", 10.0, vec![])]).unwrap(),
+            "a     1.5  [1]\n\
+             bcd  10.0  []"
+        );
+        assert_eq!(render(Vec::<u8>::new()).unwrap(), "");
+    }
+}
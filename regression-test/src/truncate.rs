@@ -0,0 +1,65 @@
+//! Truncation of giant messages, see
+//! [`Config::truncate_bytes`](crate::Config::truncate_bytes).
+
+use sha2::{Digest, Sha256};
+
+/// A message cut short.
+pub(crate) struct Truncated {
+    /// The first and last bytes of the message, around a marker saying how
+    /// much was left out and the digest of what was.
+    pub(crate) message: String,
+    /// The SHA-256 digest of the part left out, in hex.
+    pub(crate) digest: String,
+}
+
+/// `message` with all but its first and last `keep` bytes (or so, to cut at
+/// characters) left out, if it is longer than that.
+pub(crate) fn truncate(message: &str, keep: usize) -> Option<Truncated> {
+    if message.len() <= keep.saturating_mul(2) {
+        return None;
+    }
+    let head = message.floor_char_boundary(keep);
+    let tail = message.ceil_char_boundary(message.len() - keep);
+    let middle = &message[head..tail];
+    let digest: String = Sha256::digest(middle.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Some(Truncated {
+        message: format!(
+            "{}\n[... {} bytes truncated, sha256:{} ...]\n{}",
+            &message[..head],
+            middle.len(),
+            digest,
+            &message[tail..]
+        ),
+        digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_both_ends() {
+        assert!(truncate("short", 3).is_none());
+
+        let dump = format!("start{}end", "é".repeat(1000));
+        let truncated = truncate(&dump, 6).unwrap();
+        let lines: Vec<&str> = truncated.message.lines().collect();
+        // Cut at characters: 5 and 5 bytes are kept rather than 6
+        assert_eq!(lines[0], "start");
+        assert_eq!(
+            lines[1],
+            format!(
+                "[... 1998 bytes truncated, sha256:{} ...]",
+                truncated.digest
+            )
+        );
+        assert_eq!(lines[2], "éend");
+
+        let edited = dump.replacen("éé", "ee", 1);
+        assert_ne!(truncate(&edited, 6).unwrap().digest, truncated.digest);
+    }
+}